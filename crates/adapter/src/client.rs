@@ -7,17 +7,34 @@
 //! a pair of objects that implement [`Read`] and [`Write`].
 
 use std::{
-    io::{BufRead, BufReader, BufWriter, Error, Read, Write},
-    sync::mpsc::Sender,
+    io::{BufRead, BufReader, BufWriter, Error, ErrorKind, Read, Write},
+    sync::{mpsc::Sender, Arc, Mutex},
 };
 
 use dap::{
     events::{Event, EventMessage},
+    requests::{CancelArguments, Command, Request},
     responses::{Response, ResponseMessage},
 };
+use serde::Deserialize;
 
 use crate::AdapterMessage;
 
+/// The maximum number of bytes of a DAP message to write to the trace log. Longer messages
+/// (e.g. large variable or stack trace responses) are truncated.
+const MAX_TRACE_LEN: usize = 2048;
+
+/// The maximum length of a header line. Real headers are much shorter: this only stops a
+/// client that never sends a newline from making us buffer unbounded input.
+const MAX_HEADER_LEN: u64 = 1024;
+
+/// The parts of a request we can read even if we don't understand its command.
+#[derive(Deserialize)]
+struct UnknownRequest {
+    seq: i64,
+    command: String,
+}
+
 /// The primary trait for communicating with a DAP client.
 ///
 /// This defines the protocol for communicating with the client to send
@@ -39,6 +56,59 @@ pub trait Client {
     /// Returns a [`std::io::Error`] if an i/o error occurs while writing to the
     /// underlying transport.
     fn send_event(&mut self, event: Event) -> Result<(), Error>;
+
+    /// The state shared with the thread reading the client's messages for cancelling the
+    /// request being processed. Clients that can't cancel requests while one is in flight
+    /// return one that is never cancelled.
+    fn cancellation(&self) -> Cancellation {
+        Cancellation::default()
+    }
+}
+
+/// Lets the client cancel the request the adapter is processing.
+///
+/// The adapter handles requests one at a time and blocks while the interface fetches the data
+/// for one, so a cancel request waits in the queue behind the request it cancels. The thread
+/// reading the client's messages marks the request as cancelled as soon as the cancel arrives
+/// instead, and the connection waiting on the interface checks the mark. Clones share the same
+/// state.
+#[derive(Clone, Debug, Default)]
+pub struct Cancellation(Arc<Mutex<InFlight>>);
+
+#[derive(Debug, Default)]
+struct InFlight {
+    // The sequence number of the request being processed, if it can be cancelled.
+    seq: Option<i64>,
+    cancelled: bool,
+}
+
+impl Cancellation {
+    /// Start processing the request with the given sequence number.
+    pub fn start(&self, seq: i64) {
+        *self.0.lock().unwrap() = InFlight {
+            seq: Some(seq),
+            cancelled: false,
+        };
+    }
+
+    /// Finish processing the current request.
+    pub fn finish(&self) {
+        *self.0.lock().unwrap() = InFlight::default();
+    }
+
+    /// The client cancelled the request with the given sequence number. This does nothing
+    /// unless that request is being processed.
+    pub fn cancel(&self, seq: i64) {
+        let mut in_flight = self.0.lock().unwrap();
+        if in_flight.seq == Some(seq) {
+            in_flight.cancelled = true;
+        }
+    }
+
+    /// True if the client cancelled the request being processed.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.lock().unwrap().cancelled
+    }
 }
 
 /// An implementation of [`Client`] for arbitrary read/write streams.
@@ -48,6 +118,7 @@ where
 {
     output: BufWriter<W>,
     seq: i64,
+    cancellation: Cancellation,
 }
 
 impl<W> ClientImpl<W>
@@ -66,8 +137,10 @@ where
         sender: Sender<AdapterMessage>,
     ) -> Self {
         let input = BufReader::new(input);
+        let cancellation = Cancellation::default();
+        let loop_cancellation = cancellation.clone();
         std::thread::spawn(|| {
-            match client_loop(input, sender) {
+            match client_loop(input, sender, loop_cancellation) {
                 Ok(()) => (),
                 Err(e) => {
                     log::error!("Client loop exitedi with error: {e}");
@@ -77,6 +150,7 @@ where
         Self {
             output: BufWriter::new(output),
             seq: 0,
+            cancellation,
         }
     }
 
@@ -96,17 +170,10 @@ where
     //
     // Returns an io::Error if the message cannot be written to the client's output
     // stream.
-    //
-    // # Panics
-    //
-    // May panic if the given message is not valid UTF-8.
     fn send_message(&mut self, msg: &[u8]) -> Result<(), Error> {
         let len = msg.len();
         let header = format!("Content-Length: {len}\r\n\r\n");
-        log::trace!(
-            "Sending: {header}{}",
-            std::str::from_utf8(msg).expect("Message must be valid utf8")
-        );
+        trace_message("Sending", msg);
         self.output.write_all(header.as_bytes())?;
         self.output.write_all(msg)?;
         self.output.flush()?;
@@ -138,21 +205,29 @@ where
             .expect("Event messages are serializable to json");
         self.send_message(&payload)
     }
+
+    fn cancellation(&self) -> Cancellation {
+        self.cancellation.clone()
+    }
 }
 
 // The main loop for the client thread. This will read from the given input
 // stream, translate the incoming messages to DAP requests, and dispatch them
 // to the given sender. It will continue to do this until we read EOF from the
 // input stream indicating the editor has closed the connection.
+//
+// A cancel request is also passed to the given cancellation state straight away, in case the
+// adapter is already processing the request it cancels.
 fn client_loop<R: Read>(
     mut input: BufReader<R>,
     sender: Sender<AdapterMessage>,
+    cancellation: Cancellation,
 ) -> Result<(), Error> {
     let mut hdr = String::new();
     loop {
         // Read the header.
         hdr.clear();
-        match input.read_line(&mut hdr) {
+        match input.by_ref().take(MAX_HEADER_LEN).read_line(&mut hdr) {
             Ok(0) => {
                 log::info!("EOF from client: shutting down.");
                 return Ok(());
@@ -186,7 +261,7 @@ fn client_loop<R: Read>(
         };
 
         // Read the separator.
-        match input.read_line(&mut hdr) {
+        match input.by_ref().take(MAX_HEADER_LEN).read_line(&mut hdr) {
             Ok(0) => {
                 log::info!("EOF from client: shutting down.");
                 return Ok(());
@@ -195,31 +270,89 @@ fn client_loop<R: Read>(
             Err(e) => return Err(e),
         }
 
-        // Read the message body.
-        let mut buf = vec![0; len];
-        match input.read_exact(&mut buf) {
-            Ok(()) => (),
-            Err(e) => return Err(e),
-        };
+        // Read the message body. The buffer grows as the body arrives rather than being
+        // allocated up front, so a bogus length can't make us allocate more than was sent.
+        let mut buf = Vec::new();
+        input.by_ref().take(len as u64).read_to_end(&mut buf)?;
+        if buf.len() < len {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                format!("Expected a {len} byte message but got {} bytes", buf.len()),
+            ));
+        }
 
         // Convert the message to JSON
-        match serde_json::from_slice(&buf) {
+        trace_message("Received", &buf);
+        match serde_json::from_slice::<Request>(&buf) {
             Ok(request) => {
-                log::trace!("Got request: {request:?}");
+                if let Command::Cancel(CancelArguments {
+                    request_id: Some(id),
+                    ..
+                }) = request.command
+                {
+                    cancellation.cancel(id);
+                }
                 sender
                     .send(AdapterMessage::Request(request))
                     .expect("Receiver should still be alive.");
             }
-            Err(e) => {
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::InvalidData,
-                    format!("Deserialization of request failed: {e}"),
-                ));
-            }
+            Err(e) => match serde_json::from_slice::<UnknownRequest>(&buf) {
+                // A request for a command we know with arguments we can't parse. The adapter
+                // responds with an error.
+                Ok(unknown) if Command::is_known(&unknown.command) => {
+                    log::error!("Malformed request {}: {e}", unknown.command);
+                    sender
+                        .send(AdapterMessage::Request(Request {
+                            seq: unknown.seq,
+                            command: Command::Malformed(unknown.command, e.to_string()),
+                        }))
+                        .expect("Receiver should still be alive.");
+                }
+                // A request we don't understand. Let the adapter decide how to respond.
+                Ok(unknown) => {
+                    log::warn!("Unknown request {}: {e}", unknown.command);
+                    sender
+                        .send(AdapterMessage::Request(Request {
+                            seq: unknown.seq,
+                            command: Command::Unknown(unknown.command),
+                        }))
+                        .expect("Receiver should still be alive.");
+                }
+                Err(_) => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!("Deserialization of request failed: {e}"),
+                    ));
+                }
+            },
         }
     }
 }
 
+// Write a raw DAP message to the trace log, truncating it if it is very long. This does nothing
+// if trace logging is disabled, so the message is not decoded unless it is needed.
+fn trace_message(direction: &str, msg: &[u8]) {
+    if log::log_enabled!(log::Level::Trace) {
+        write_trace(direction, msg, |line| log::trace!("{line}"));
+    }
+}
+
+// Pass the trace log line for a raw DAP message to the given sink.
+fn write_trace(direction: &str, msg: &[u8], mut sink: impl FnMut(std::fmt::Arguments)) {
+    if msg.len() > MAX_TRACE_LEN {
+        sink(format_args!(
+            "{direction}: {}... ({} more bytes)",
+            String::from_utf8_lossy(&msg[..MAX_TRACE_LEN]),
+            msg.len() - MAX_TRACE_LEN
+        ));
+    } else {
+        sink(format_args!(
+            "{direction}: {}",
+            String::from_utf8_lossy(msg)
+        ));
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -250,6 +383,43 @@ mod tests {
         }
     }
 
+    #[test]
+    fn an_unknown_packet() {
+        let payload =
+            r#"{"seq": 3, "command": "setExceptionBreakpoints", "arguments": { "filters": []}}"#;
+        let str = format!("Content-Length: {}\r\n\r\n{payload}", payload.len());
+        let input = Cursor::new(str);
+        let output: Vec<u8> = vec![];
+        let (tx, rx) = channel();
+        let _ = ClientImpl::new(input, output, tx);
+        match rx.recv() {
+            Ok(AdapterMessage::Request(req)) => {
+                assert_eq!(req.seq, 3);
+                assert!(
+                    matches!(req.command, Command::Unknown(name) if name == "setExceptionBreakpoints")
+                )
+            }
+            other => panic!("Expected valid request but got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn a_malformed_packet() {
+        let payload = r#"{"seq": 4, "command": "scopes", "arguments": { "frameId": "top"}}"#;
+        let str = format!("Content-Length: {}\r\n\r\n{payload}", payload.len());
+        let input = Cursor::new(str);
+        let output: Vec<u8> = vec![];
+        let (tx, rx) = channel();
+        let _ = ClientImpl::new(input, output, tx);
+        match rx.recv() {
+            Ok(AdapterMessage::Request(req)) => {
+                assert_eq!(req.seq, 4);
+                assert!(matches!(req.command, Command::Malformed(name, _) if name == "scopes"))
+            }
+            other => panic!("Expected a malformed request but got {other:?}"),
+        }
+    }
+
     #[test]
     fn a_packet_with_extra() {
         let payload = r#"{"seq": 1, "command": "initialize", "arguments": { "clientId": "test client", "adapterID": "unrealscript"}}"#;
@@ -335,4 +505,178 @@ mod tests {
         assert_eq!(out,
         "Content-Length: 92\r\n\r\n{\"seq\":1,\"type\":\"event\",\"event\":\"output\",\"body\":{\"category\":\"stdout\",\"output\":\"A log line\"}}");
     }
+
+    // Collect the trace log line for a raw DAP message.
+    fn trace_line(direction: &str, msg: &[u8]) -> String {
+        let mut line = String::new();
+        write_trace(direction, msg, |args| line = args.to_string());
+        line
+    }
+
+    #[test]
+    fn traces_messages() {
+        let payload = r#"{"seq": 1, "command": "initialize"}"#;
+        assert_eq!(
+            trace_line("Received", payload.as_bytes()),
+            format!("Received: {payload}")
+        );
+
+        let long_output = "x".repeat(MAX_TRACE_LEN * 2);
+        let sent = trace_line("Sending", long_output.as_bytes());
+        assert_eq!(
+            sent,
+            format!(
+                "Sending: {}... ({MAX_TRACE_LEN} more bytes)",
+                &long_output[..MAX_TRACE_LEN]
+            )
+        );
+    }
+
+    // A reader that returns its data in chunks of the given sizes, in turn, to exercise
+    // messages split across reads and buffer refills.
+    struct ChunkedReader {
+        data: Vec<u8>,
+        pos: usize,
+        sizes: Vec<usize>,
+        next: usize,
+    }
+
+    impl Read for ChunkedReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let size = self.sizes[self.next % self.sizes.len()];
+            self.next += 1;
+            let len = size.min(buf.len()).min(self.data.len() - self.pos);
+            buf[..len].copy_from_slice(&self.data[self.pos..self.pos + len]);
+            self.pos += len;
+            Ok(len)
+        }
+    }
+
+    // Run the client loop over the given input, returning the requests it sent and its result.
+    fn parse(
+        data: Vec<u8>,
+        sizes: Vec<usize>,
+        capacity: usize,
+    ) -> (Vec<Request>, Result<(), Error>) {
+        let reader = ChunkedReader {
+            data,
+            pos: 0,
+            sizes,
+            next: 0,
+        };
+        let (tx, rx) = channel();
+        let result = client_loop(
+            BufReader::with_capacity(capacity, reader),
+            tx,
+            Cancellation::default(),
+        );
+        let requests = rx
+            .try_iter()
+            .map(|msg| match msg {
+                AdapterMessage::Request(req) => req,
+                other => panic!("Expected a request but got {other:?}"),
+            })
+            .collect();
+        (requests, result)
+    }
+
+    fn frame(payload: &str) -> Vec<u8> {
+        format!("Content-Length: {}\r\n\r\n{payload}", payload.len()).into_bytes()
+    }
+
+    #[test]
+    fn message_split_across_reads() {
+        let payload = r#"{"seq": 1, "command": "initialize", "arguments": { "clientId": "test client", "adapterID": "unrealscript"}}"#;
+        let (requests, result) = parse(frame(payload), vec![1, 2, 3], 4);
+        assert!(result.is_ok());
+        assert_eq!(requests.len(), 1);
+        assert!(matches!(requests[0].command, Command::Initialize(_)));
+    }
+
+    #[test]
+    fn body_larger_than_buffer() {
+        let client_id = "x".repeat(500);
+        let payload = format!(
+            r#"{{"seq": 1, "command": "initialize", "arguments": {{ "clientId": "{client_id}", "adapterID": "unrealscript"}}}}"#
+        );
+        let mut data = frame(&payload);
+        data.extend(frame(&payload));
+        let (requests, result) = parse(data, vec![7, 100], 16);
+        assert!(result.is_ok());
+        assert_eq!(requests.len(), 2);
+    }
+
+    #[test]
+    fn cancel_request_in_flight() {
+        let cancellation = Cancellation::default();
+        cancellation.start(5);
+        let mut data = frame(r#"{"seq": 6, "command": "cancel", "arguments": {"requestId": 4}}"#);
+        let (tx, rx) = channel();
+        client_loop(BufReader::new(&data[..]), tx.clone(), cancellation.clone()).unwrap();
+        assert!(!cancellation.is_cancelled());
+
+        // The cancel marks the request in flight straight away, and is still passed on.
+        data = frame(r#"{"seq": 7, "command": "cancel", "arguments": {"requestId": 5}}"#);
+        client_loop(BufReader::new(&data[..]), tx, cancellation.clone()).unwrap();
+        assert!(cancellation.is_cancelled());
+        assert_eq!(rx.try_iter().count(), 2);
+
+        cancellation.finish();
+        assert!(!cancellation.is_cancelled());
+    }
+
+    #[test]
+    fn truncated_body() {
+        let (requests, result) = parse(b"Content-Length: 100\r\n\r\n{}".to_vec(), vec![64], 64);
+        assert!(requests.is_empty());
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn huge_content_length() {
+        let data = format!("Content-Length: {}\r\n\r\n{{}}", usize::MAX).into_bytes();
+        let (requests, result) = parse(data, vec![64], 64);
+        assert!(requests.is_empty());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn random_input() {
+        // A fixed xorshift generator so failures are reproducible.
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        let payload = r#"{"seq": 1, "command": "threads"}"#;
+        let pieces: Vec<Vec<u8>> = vec![
+            frame(payload),
+            b"Content-Length: ".to_vec(),
+            b"12".to_vec(),
+            b"\r\n".to_vec(),
+            b"\n".to_vec(),
+            b"{".to_vec(),
+            payload.as_bytes().to_vec(),
+            vec![0xff, 0xfe, 0x00],
+        ];
+        for _ in 0..500 {
+            let mut data = vec![];
+            for _ in 0..next() % 12 {
+                match next() % 3 {
+                    0 => data.push(next() as u8),
+                    _ => data.extend(&pieces[next() as usize % pieces.len()]),
+                }
+            }
+            let sizes = (0..4).map(|_| 1 + next() as usize % 32).collect();
+            // Any input must give either requests or an error, never a panic.
+            let (requests, result) = parse(data, sizes, 1 + next() as usize % 64);
+            if result.is_ok() {
+                assert!(requests
+                    .iter()
+                    .all(|r| matches!(r.command, Command::Threads)));
+            }
+        }
+    }
 }
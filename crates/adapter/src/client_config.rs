@@ -3,6 +3,11 @@
 //! These settings are sent by the client to indicate which features it supports.
 //! They are used to determine the format of particular responses to the client.
 
+use std::{collections::BTreeMap, fmt::Display};
+
+use dap::requests::SessionArguments;
+use log::LevelFilter;
+
 /// A representation of the client configuration options. These will impact how
 /// we send responses. This can include both standard DAP configuration settings
 /// as well as debugger-specific ones.
@@ -12,6 +17,10 @@ pub struct ClientConfig {
     /// Otherwise they start at 0.
     pub one_based_lines: bool,
 
+    /// If true (the default) the client expects columns to start at 1. Otherwise they start
+    /// at 0.
+    pub one_based_columns: bool,
+
     /// If true then we will send type information with variables.
     pub supports_variable_type: bool,
 
@@ -28,6 +37,66 @@ pub struct ClientConfig {
 
     /// Auto resume after the first breakpoint
     pub auto_resume: bool,
+
+    /// If true (the default) normalize the encoding and line endings of text sent in output
+    /// events. See [`crate::output::decode_output`].
+    pub normalize_output: bool,
+
+    /// The maximum number of output events to send per second, or None (the default) for no
+    /// limit. See [`crate::output::OutputLimiter`].
+    pub max_output_per_second: Option<u32>,
+
+    /// A delay in milliseconds to wait after stopping from a step before telling the client,
+    /// giving Unreal time to finish sending the new state.
+    pub step_settle_delay_ms: Option<u64>,
+
+    /// If true keep the session alive when the interface disconnects and wait for the game
+    /// to connect again, instead of ending the session.
+    pub wait_for_reconnect: bool,
+
+    /// If true respond to unknown commands with an empty success response instead of an error.
+    pub acknowledge_unknown_commands: bool,
+
+    /// If true scan the source roots for classes when the session starts. See
+    /// [`crate::source::scan_classes`].
+    pub scan_source_roots: bool,
+
+    /// If true pause the game when the client disconnects, leaving the debugger running so a
+    /// new session can attach to it at the same point.
+    pub pause_on_disconnect: bool,
+
+    /// If set the interface sends its own log lines at or above this level, and they are
+    /// shown as console output alongside the game's log.
+    pub interface_log_level: Option<LevelFilter>,
+
+    /// Qualified names of classes (`Package.Class`) to break on entry to any of their
+    /// functions, as if there were a breakpoint on the first statement of each one.
+    pub break_on_enter_classes: Vec<String>,
+
+    /// If true (the default) stack frames for functions declared in a state are named with
+    /// the state, e.g. `Idle.Tick`, rather than just the function name.
+    pub show_frame_states: bool,
+
+    /// The path of a file of REPL commands to run once the session has connected, or None
+    /// (the default) for none. See [`crate::repl::read_script`].
+    pub startup_script: Option<String>,
+
+    /// A map from package names to the source root holding each one. A package listed here
+    /// is looked for in its own root before the general source roots are searched.
+    pub package_roots: BTreeMap<String, String>,
+
+    /// If true break as soon as the session has attached to the game. Only used when
+    /// attaching.
+    pub break_on_attach: bool,
+
+    /// If true leave the `self` scope out of the scopes for a frame with no current object,
+    /// e.g. a static function. Ignored if the interface can't say whether there is one.
+    pub hide_self_without_object: bool,
+
+    /// The most locals of the top frame to fetch each time execution stops, so the client's
+    /// first request for them is answered at once. None (the default) or 0 to only fetch them
+    /// when asked.
+    pub prefetch_locals: Option<usize>,
 }
 
 impl ClientConfig {
@@ -35,11 +104,27 @@ impl ClientConfig {
     pub fn new() -> Self {
         ClientConfig {
             one_based_lines: true,
+            one_based_columns: true,
             supports_variable_type: false,
             supports_invalidated_event: false,
             source_roots: vec![],
             enable_stack_hack: false,
             auto_resume: false,
+            normalize_output: true,
+            max_output_per_second: None,
+            step_settle_delay_ms: None,
+            wait_for_reconnect: false,
+            acknowledge_unknown_commands: false,
+            scan_source_roots: false,
+            pause_on_disconnect: false,
+            interface_log_level: None,
+            break_on_enter_classes: vec![],
+            show_frame_states: true,
+            startup_script: None,
+            package_roots: BTreeMap::new(),
+            break_on_attach: false,
+            hide_self_without_object: false,
+            prefetch_locals: None,
         }
     }
 }
@@ -49,3 +134,162 @@ impl Default for ClientConfig {
         Self::new()
     }
 }
+
+/// Request arguments that set options in the client configuration.
+///
+/// This is a trait only because the arguments are defined in the `dap` crate.
+pub trait ApplyToConfig {
+    /// Set the options in `config` from these arguments, using the default for any option the
+    /// client didn't give.
+    fn apply_to(&self, config: &mut ClientConfig);
+}
+
+impl ApplyToConfig for SessionArguments {
+    fn apply_to(&self, config: &mut ClientConfig) {
+        config.source_roots = self.source_roots.clone().unwrap_or_default();
+        config.enable_stack_hack = self.enable_stack_hack.unwrap_or(true);
+        config.normalize_output = self.normalize_output.unwrap_or(true);
+        config.max_output_per_second = self.max_output_per_second;
+        config.step_settle_delay_ms = self.step_settle_delay_ms;
+        config.wait_for_reconnect = self.wait_for_reconnect.unwrap_or(false);
+        config.acknowledge_unknown_commands = self.acknowledge_unknown_commands.unwrap_or(false);
+        config.scan_source_roots = self.scan_source_roots.unwrap_or(false);
+        config.interface_log_level = parse_level(self.interface_log_level.as_ref());
+        config.break_on_enter_classes = self.break_on_enter_classes.clone().unwrap_or_default();
+        config.show_frame_states = self.show_frame_states.unwrap_or(true);
+        config.startup_script = self.startup_script.clone();
+        config.package_roots = self.package_roots.clone().unwrap_or_default();
+        config.hide_self_without_object = self.hide_self_without_object.unwrap_or(false);
+        config.prefetch_locals = self.prefetch_locals;
+    }
+}
+
+/// Parse the level of interface log lines to forward, ignoring it if it isn't a valid level.
+fn parse_level(level: Option<&String>) -> Option<LevelFilter> {
+    let level = level?;
+    match level.parse() {
+        Ok(level) => Some(level),
+        Err(e) => {
+            log::error!("Ignoring bad interface log level {level}: {e}");
+            None
+        }
+    }
+}
+
+impl Display for ClientConfig {
+    /// Describe the effective configuration, one setting per line. Shown by the `.caps`
+    /// REPL command to help diagnose client interop issues.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "one_based_lines: {}", self.one_based_lines)?;
+        writeln!(f, "one_based_columns: {}", self.one_based_columns)?;
+        writeln!(f, "supports_variable_type: {}", self.supports_variable_type)?;
+        writeln!(
+            f,
+            "supports_invalidated_event: {}",
+            self.supports_invalidated_event
+        )?;
+        writeln!(f, "source_roots: {:?}", self.source_roots)?;
+        writeln!(f, "enable_stack_hack: {}", self.enable_stack_hack)?;
+        writeln!(f, "auto_resume: {}", self.auto_resume)?;
+        writeln!(f, "normalize_output: {}", self.normalize_output)?;
+        match self.max_output_per_second {
+            Some(rate) => writeln!(f, "max_output_per_second: {rate}")?,
+            None => writeln!(f, "max_output_per_second: unlimited")?,
+        }
+        match self.step_settle_delay_ms {
+            Some(ms) => writeln!(f, "step_settle_delay_ms: {ms}")?,
+            None => writeln!(f, "step_settle_delay_ms: none")?,
+        }
+        writeln!(f, "wait_for_reconnect: {}", self.wait_for_reconnect)?;
+        writeln!(
+            f,
+            "acknowledge_unknown_commands: {}",
+            self.acknowledge_unknown_commands
+        )?;
+        writeln!(f, "scan_source_roots: {}", self.scan_source_roots)?;
+        writeln!(f, "pause_on_disconnect: {}", self.pause_on_disconnect)?;
+        match &self.interface_log_level {
+            Some(level) => writeln!(f, "interface_log_level: {level}")?,
+            None => writeln!(f, "interface_log_level: none")?,
+        }
+        writeln!(
+            f,
+            "break_on_enter_classes: {:?}",
+            self.break_on_enter_classes
+        )?;
+        writeln!(f, "show_frame_states: {}", self.show_frame_states)?;
+        match &self.startup_script {
+            Some(path) => writeln!(f, "startup_script: {path}")?,
+            None => writeln!(f, "startup_script: none")?,
+        }
+        writeln!(f, "package_roots: {:?}", self.package_roots)?;
+        writeln!(f, "break_on_attach: {}", self.break_on_attach)?;
+        writeln!(
+            f,
+            "hide_self_without_object: {}",
+            self.hide_self_without_object
+        )?;
+        match self.prefetch_locals {
+            Some(count) => write!(f, "prefetch_locals: {count}"),
+            None => write!(f, "prefetch_locals: none"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_config() {
+        let config = ClientConfig {
+            one_based_lines: false,
+            one_based_columns: false,
+            supports_variable_type: true,
+            supports_invalidated_event: true,
+            source_roots: vec!["C:\\Src".to_string()],
+            enable_stack_hack: false,
+            auto_resume: true,
+            normalize_output: false,
+            max_output_per_second: Some(100),
+            step_settle_delay_ms: None,
+            wait_for_reconnect: true,
+            acknowledge_unknown_commands: false,
+            scan_source_roots: true,
+            pause_on_disconnect: true,
+            interface_log_level: Some(LevelFilter::Info),
+            break_on_enter_classes: vec!["MyPackage.MyClass".to_string()],
+            show_frame_states: false,
+            startup_script: Some("C:\\Src\\startup.txt".to_string()),
+            package_roots: BTreeMap::from([("MyPackage".to_string(), "C:\\Mod".to_string())]),
+            break_on_attach: true,
+            hide_self_without_object: true,
+            prefetch_locals: Some(20),
+        };
+        assert_eq!(
+            config.to_string(),
+            "one_based_lines: false\n\
+             one_based_columns: false\n\
+             supports_variable_type: true\n\
+             supports_invalidated_event: true\n\
+             source_roots: [\"C:\\\\Src\"]\n\
+             enable_stack_hack: false\n\
+             auto_resume: true\n\
+             normalize_output: false\n\
+             max_output_per_second: 100\n\
+             step_settle_delay_ms: none\n\
+             wait_for_reconnect: true\n\
+             acknowledge_unknown_commands: false\n\
+             scan_source_roots: true\n\
+             pause_on_disconnect: true\n\
+             interface_log_level: INFO\n\
+             break_on_enter_classes: [\"MyPackage.MyClass\"]\n\
+             show_frame_states: false\n\
+             startup_script: C:\\Src\\startup.txt\n\
+             package_roots: {\"MyPackage\": \"C:\\\\Mod\"}\n\
+             break_on_attach: true\n\
+             hide_self_without_object: true\n\
+             prefetch_locals: 20"
+        );
+    }
+}
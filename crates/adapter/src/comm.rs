@@ -5,19 +5,23 @@
 //! on top of this for managing request/response transactions.
 pub mod tcp;
 
-use std::io::{Error, ErrorKind};
+use std::{
+    io::{Error, ErrorKind},
+    time::Duration,
+};
 
 use common::{
-    Breakpoint, FrameIndex, InitializeRequest, StackTraceRequest, StackTraceResponse,
-    UnrealCommand, UnrealResponse, Variable, VariableIndex, Version, WatchKind,
+    Breakpoint, FrameIndex, InitializeRequest, InitializeResponse, StackTraceRequest,
+    StackTraceResponse, UnrealCommand, UnrealResponse, Variable, VariableIndex, Version, WatchKind,
 };
+use log::LevelFilter;
 
 macro_rules! expect_response {
     ($e:expr, $p:path) => {
         match $e {
             Ok($p(x)) => Ok(x),
             Ok(r) => Err(Error::new(
-                ErrorKind::Other,
+                ErrorKind::InvalidData,
                 format!("Protocol Error: {r:?}"),
             )),
             Err(e) => Err(e),
@@ -81,14 +85,16 @@ pub trait Connection: Send {
         version: Version,
         enable_stack_hack: bool,
         overridden_log_level: Option<&String>,
-    ) -> Result<Version, Error> {
+        forwarded_log_level: Option<LevelFilter>,
+    ) -> Result<InitializeResponse, Error> {
         self.send_command(UnrealCommand::Initialize(InitializeRequest {
             version,
             enable_stack_hack,
             overridden_log_level: overridden_log_level.cloned(),
+            forwarded_log_level: forwarded_log_level.map(|level| level.to_string()),
+            session_id: common::session_id(),
         }))?;
-        let response = expect_response!(self.next_response(), UnrealResponse::Initialize)?;
-        Ok(response.version)
+        expect_response!(self.next_response(), UnrealResponse::Initialize)
     }
 
     /// Add a breakpoint.
@@ -103,12 +109,74 @@ pub trait Connection: Send {
         expect_response!(self.next_response(), UnrealResponse::BreakpointRemoved)
     }
 
+    /// List all the breakpoints Unreal has set.
+    fn list_breakpoints(&mut self) -> Result<Vec<Breakpoint>, Error> {
+        self.send_command(UnrealCommand::ListBreakpoints)?;
+        expect_response!(self.next_response(), UnrealResponse::Breakpoints)
+    }
+
+    /// List the names of the logical threads other than the main one.
+    fn threads(&mut self) -> Result<Vec<String>, Error> {
+        self.send_command(UnrealCommand::Threads)?;
+        expect_response!(self.next_response(), UnrealResponse::Threads)
+    }
+
     /// Request a full or partial stack trace.
     fn stack_trace(&mut self, req: StackTraceRequest) -> Result<StackTraceResponse, Error> {
         self.send_command(UnrealCommand::StackTrace(req))?;
         expect_response!(self.next_response(), UnrealResponse::StackTrace)
     }
 
+    /// Request the qualified class name and current line for the given frame.
+    ///
+    /// Unreal only provides line information for the current frame, so unless the stack
+    /// hack is enabled the interface may need to switch to the requested frame to find the
+    /// line and then switch back again. This is expensive, so callers should only use this
+    /// for frames that don't already have line information.
+    fn frame_line(&mut self, frame: FrameIndex) -> Result<(String, i32), Error> {
+        self.send_command(UnrealCommand::FrameLine(frame))?;
+        match self.next_response() {
+            Ok(UnrealResponse::FrameLine(class, line)) => Ok((class, line)),
+            Ok(r) => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Protocol Error: {r:?}"),
+            )),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Ask the interface to rebuild its watch lists for the current frame. Any variable
+    /// information previously fetched for this frame may be stale after this.
+    fn refresh_watches(&mut self) -> Result<(), Error> {
+        self.send_command(UnrealCommand::RefreshWatches)?;
+        match self.next_response() {
+            Ok(UnrealResponse::WatchesRefreshed) => Ok(()),
+            Ok(r) => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Protocol Error: {r:?}"),
+            )),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Assign a value to the variable named by the given expression in the current frame. The
+    /// watch lists are rebuilt afterwards, so any variable information previously fetched for
+    /// this frame may be stale.
+    fn set_variable(&mut self, expr: &str, value: &str) -> Result<(), Error> {
+        self.send_command(UnrealCommand::SetVariable(
+            expr.to_string(),
+            value.to_string(),
+        ))?;
+        match self.next_response() {
+            Ok(UnrealResponse::WatchesRefreshed) => Ok(()),
+            Ok(r) => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Protocol Error: {r:?}"),
+            )),
+            Err(e) => Err(e),
+        }
+    }
+
     /// Request the number of children of the variable (or scope) of the given
     /// kind.
     ///
@@ -129,7 +197,7 @@ pub trait Connection: Send {
             Ok(UnrealResponse::Variables(vars)) => Ok(vars),
             Ok(UnrealResponse::DeferredVariables(vars)) => Ok(vars),
             Ok(r) => Err(Error::new(
-                ErrorKind::Other,
+                ErrorKind::InvalidData,
                 format!("Protocol Error: {r:?}"),
             )),
             Err(e) => Err(e),
@@ -141,6 +209,7 @@ pub trait Connection: Send {
     ///
     /// The request specifies the kind, frame, and variable index to identify
     /// the variable and a start and count value to allow paginated responses.
+    /// A count of 0 returns all variables from the start to the end.
     fn variables(
         &mut self,
         kind: WatchKind,
@@ -159,7 +228,7 @@ pub trait Connection: Send {
             Ok(UnrealResponse::Variables(vars)) => Ok((vars, false)),
             Ok(UnrealResponse::DeferredVariables(vars)) => Ok((vars, true)),
             Ok(r) => Err(Error::new(
-                ErrorKind::Other,
+                ErrorKind::InvalidData,
                 format!("Protocol Error: {r:?}"),
             )),
             Err(e) => Err(e),
@@ -196,10 +265,26 @@ pub trait Connection: Send {
         Ok(())
     }
 
+    /// Run the function of the given frame again from its start.
+    fn restart_frame(&mut self, frame: FrameIndex) -> Result<(), Error> {
+        self.send_command(UnrealCommand::RestartFrame(frame))?;
+        Ok(())
+    }
+
     /// Disconnect from the interface, shutting down the debugger
     /// session.
     fn disconnect(&mut self) -> Result<(), Error> {
         self.send_command(UnrealCommand::Disconnect)?;
         Ok(())
     }
+
+    /// Disconnect from the interface as with [`Connection::disconnect`], but give up if that
+    /// takes longer than the given timeout, e.g. because the interface has stopped reading.
+    ///
+    /// # Errors
+    ///
+    /// Returns an I/O error if the disconnect command cannot be sent in time.
+    fn shutdown(&mut self, _timeout: Duration) -> Result<(), Error> {
+        self.disconnect()
+    }
 }
@@ -2,23 +2,38 @@
 
 use std::{
     io::{Error, ErrorKind, Read, Write},
-    net::TcpStream,
-    sync::mpsc::{channel, Receiver, Sender},
+    net::{Shutdown, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+        Arc,
+    },
     time::Duration,
 };
 
-use common::{UnrealCommand, UnrealInterfaceMessage, UnrealResponse, DEFAULT_CONNECT_ATTEMPTS, DEFAULT_CONNECT_TIMEOUT};
+use common::{
+    UnrealCommand, UnrealInterfaceMessage, UnrealResponse, DEFAULT_CONNECT_ATTEMPTS,
+    DEFAULT_CONNECT_TIMEOUT, MORE_FRAMES_FLAG,
+};
 
-use crate::AdapterMessage;
+use crate::{client::Cancellation, AdapterMessage};
 
 use super::Connection;
 
+/// How often to check whether the client cancelled the request while waiting for a response.
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(50);
 
 /// A TCP-based connection between the debug adapter and the Unreal debugger
 /// interface.
 pub struct TcpConnection {
     tcp_stream: TcpStream,
     response_receiver: Receiver<UnrealResponse>,
+    // Set when we close the connection ourselves, so the reader thread doesn't report the
+    // closed socket as the interface shutting down.
+    closed: Arc<AtomicBool>,
+    cancellation: Cancellation,
+    // True if the interface said in the handshake that it can cancel a variable request.
+    supports_cancel: bool,
 }
 
 /// The configuration for the TCP connection timeout.
@@ -64,13 +79,38 @@ impl TcpConnection {
         let (rtx, rrx) = channel();
 
         let tcp_clone = tcp.try_clone().unwrap();
+        let closed = Arc::new(AtomicBool::new(false));
+        let closed_clone = closed.clone();
         // Spawn a new thread to manage these channels and the TCP connection.
-        std::thread::spawn(|| debuggee_tcp_loop(tcp_clone, rtx, event_sender));
+        std::thread::spawn(|| debuggee_tcp_loop(tcp_clone, rtx, event_sender, closed_clone));
         Ok(TcpConnection {
             response_receiver: rrx,
             tcp_stream: tcp,
+            closed,
+            cancellation: Cancellation::default(),
+            supports_cancel: false,
         })
     }
+
+    /// Give up waiting for a variable request's response when the client cancels the request
+    /// being processed. The response is then an [`ErrorKind::Interrupted`] error. This is only
+    /// done once the interface has said in the handshake that it supports it: older interfaces
+    /// drop the connection on a command they don't know, so their requests are left to finish.
+    pub fn with_cancellation(mut self, cancellation: Cancellation) -> Self {
+        self.cancellation = cancellation;
+        self
+    }
+}
+
+impl Drop for TcpConnection {
+    fn drop(&mut self) {
+        // The reader thread holds its own handle to the socket, so dropping ours doesn't close
+        // the connection. Shut it down explicitly so the interface can accept a new one.
+        self.closed.store(true, Ordering::SeqCst);
+        if let Err(e) = self.tcp_stream.shutdown(Shutdown::Both) {
+            log::trace!("Failed to shut down interface connection: {e}");
+        }
+    }
 }
 
 impl Connection for TcpConnection {
@@ -84,17 +124,68 @@ impl Connection for TcpConnection {
         self.tcp_stream.write_all(&buf).map(|_| ())
     }
 
+    fn shutdown(&mut self, timeout: Duration) -> Result<(), Error> {
+        // An interface that has stopped reading would block the write once the socket's buffer
+        // fills. The connection isn't used after this so the timeout can stay in place.
+        self.tcp_stream.set_write_timeout(Some(timeout))?;
+        self.disconnect()
+    }
+
     fn next_response(&mut self) -> Result<UnrealResponse, Error> {
         log::trace!("Waiting for next response...");
-        match self.response_receiver.recv() {
-            Ok(resp) => {
-                log::trace!("Got response {resp:?}");
-                Ok(resp)
+        let mut cancel_sent = false;
+        loop {
+            match self.response_receiver.recv_timeout(CANCEL_POLL_INTERVAL) {
+                Ok(UnrealResponse::Cancelled) => {
+                    log::trace!("Interface cancelled the request");
+                    return Err(Error::new(ErrorKind::Interrupted, "Request cancelled"));
+                }
+                Ok(resp) => {
+                    log::trace!("Got response {resp:?}");
+                    if let UnrealResponse::Initialize(init) = &resp {
+                        self.supports_cancel = init.supports_cancel_variable_request;
+                    }
+                    return Ok(resp);
+                }
+                // The interface may already be sending the response, in which case it ignores
+                // the cancel and we keep waiting for it.
+                Err(RecvTimeoutError::Timeout) => {
+                    if self.supports_cancel && !cancel_sent && self.cancellation.is_cancelled() {
+                        self.send_command(UnrealCommand::CancelVariableRequest)?;
+                        cancel_sent = true;
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(std::io::Error::new(
+                        ErrorKind::ConnectionReset,
+                        "Error reading next response",
+                    ))
+                }
             }
-            Err(_) => Err(std::io::Error::new(
-                ErrorKind::ConnectionReset,
-                "Error reading next response",
-            )),
+        }
+    }
+}
+
+/// Read the next message from the interface. Each frame has a 4-byte big-endian length
+/// prefix. Messages too large for one frame are split across several, with
+/// [`MORE_FRAMES_FLAG`] set in the prefix of every frame but the last.
+fn read_message(reader: &mut impl Read) -> Result<Vec<u8>, Error> {
+    let mut message = vec![];
+    loop {
+        let mut size_buf = [0u8; 4];
+        reader.read_exact(&mut size_buf)?;
+        log::trace!("Read size bytes from socket: {size_buf:?}");
+        let prefix = u32::from_be_bytes(size_buf);
+        let len = u64::from(prefix & !MORE_FRAMES_FLAG);
+        // Don't trust the length enough to allocate it up front.
+        if reader.by_ref().take(len).read_to_end(&mut message)? < len as usize {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                "Connection closed in the middle of a message",
+            ));
+        }
+        if prefix & MORE_FRAMES_FLAG == 0 {
+            return Ok(message);
         }
     }
 }
@@ -108,35 +199,36 @@ fn debuggee_tcp_loop(
     mut tcp: TcpStream,
     response_sender: Sender<UnrealResponse>,
     event_sender: Sender<AdapterMessage>,
+    closed: Arc<AtomicBool>,
 ) {
     // Adapt the tcp socket into an asymmetrical source + sink for Json objects.
     // Across this TCP socket we will send UnrealCommands to the interface, and
     // receive UnrealEvents from that interface. These will always be length-delimited.
 
     loop {
-        let mut size_buf = [0u8; 4];
-        match tcp.read_exact(&mut size_buf) {
-            Ok(()) => {
-                log::trace!("Read size bytes from socket: {size_buf:?}");
-            }
-            Err(_) => {
+        let msg_buf = match read_message(&mut tcp) {
+            Ok(buf) => buf,
+            Err(e) => {
                 // Failed to read bytes from the TCP socket. This is not necessarily
                 // an error, the interface will close the connection when it disconnects.
-                log::info!("Received EOF from interface. Closing connection.");
-                if event_sender.send(AdapterMessage::Shutdown).is_err() {
+                // But if the connection was reset rather than closed the game most likely
+                // crashed.
+                if closed.load(Ordering::SeqCst) {
+                    return;
+                }
+                let msg = if e.kind() == ErrorKind::UnexpectedEof {
+                    log::info!("Received EOF from interface. Closing connection.");
+                    AdapterMessage::Shutdown
+                } else {
+                    log::error!("Lost connection to interface: {e}");
+                    AdapterMessage::ConnectionLost
+                };
+                if event_sender.send(msg).is_err() {
                     log::error!("Failed to send shutdown event to adapter.");
                 }
                 return;
             }
         };
-        let sz = u32::from_be_bytes(size_buf);
-        let mut msg_buf = vec![0; sz as usize];
-        match tcp.read_exact(&mut msg_buf) {
-            Ok(()) => (),
-            Err(e) => {
-                log::error!("Error reading msg from interface: {e}");
-            }
-        };
         match serde_json::from_slice(&msg_buf) {
             Ok(UnrealInterfaceMessage::Event(event)) => {
                 if event_sender.send(AdapterMessage::Event(event)).is_err() {
@@ -144,6 +236,15 @@ fn debuggee_tcp_loop(
                     return;
                 }
             }
+            Ok(UnrealInterfaceMessage::InterfaceLog(line)) => {
+                if event_sender
+                    .send(AdapterMessage::InterfaceLog(line))
+                    .is_err()
+                {
+                    log::error!("Failed to send interface log to adapter.");
+                    return;
+                }
+            }
             Ok(UnrealInterfaceMessage::Response(resp)) => {
                 if response_sender.send(resp).is_err() {
                     log::error!("Failed to send response to adapter.");
@@ -178,4 +279,191 @@ impl Default for TcpConnectTimeoutConfig {
             connect_timeout: DEFAULT_CONNECT_TIMEOUT,
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use common::{FrameIndex, Variable, VariableIndex, WatchKind, MORE_FRAMES_FLAG};
+
+    use super::*;
+
+    // Split a message into frames of at most the given length, the way the interface does.
+    fn frames(message: &[u8], max_frame_len: usize) -> Vec<u8> {
+        let mut data = vec![];
+        let mut chunks = message.chunks(max_frame_len).peekable();
+        while let Some(chunk) = chunks.next() {
+            let more = if chunks.peek().is_some() {
+                MORE_FRAMES_FLAG
+            } else {
+                0
+            };
+            data.extend_from_slice(&(chunk.len() as u32 | more).to_be_bytes());
+            data.extend_from_slice(chunk);
+        }
+        data
+    }
+
+    #[test]
+    fn single_frame_message() {
+        let mut data: &[u8] = b"\0\0\0\x03abc\0\0\0\x02de";
+        assert_eq!(read_message(&mut data).unwrap(), b"abc");
+        assert_eq!(read_message(&mut data).unwrap(), b"de");
+        assert_eq!(
+            read_message(&mut data).unwrap_err().kind(),
+            ErrorKind::UnexpectedEof
+        );
+    }
+
+    #[test]
+    fn message_split_across_frames() {
+        // A watch with a huge value.
+        let value = "x".repeat(1000);
+        let var = Variable {
+            name: "Text".to_string(),
+            ty: "String".to_string(),
+            value: value.clone(),
+            index: VariableIndex::create(1).unwrap(),
+            has_children: false,
+            is_array: false,
+        };
+        let response = UnrealInterfaceMessage::Response(UnrealResponse::Variables(vec![var]));
+        let message = serde_json::to_vec(&response).unwrap();
+        let data = frames(&message, 64);
+        assert!(data.len() > message.len() + 4 * 16);
+
+        let mut reader = &data[..];
+        let read = read_message(&mut reader).unwrap();
+        assert!(reader.is_empty());
+        assert_eq!(read, message);
+        match serde_json::from_slice(&read).unwrap() {
+            UnrealInterfaceMessage::Response(UnrealResponse::Variables(vars)) => {
+                assert_eq!(vars[0].value, value);
+            }
+            other => panic!("Expected a variables response but got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn truncated_frame() {
+        let mut data: &[u8] = b"\x80\0\0\x03abc\0\0\0\x05de";
+        assert_eq!(
+            read_message(&mut data).unwrap_err().kind(),
+            ErrorKind::UnexpectedEof
+        );
+    }
+
+    // Read a command from the adapter as the interface does.
+    fn read_command(stream: &mut TcpStream) -> UnrealCommand {
+        serde_json::from_slice(&read_message(stream).unwrap()).unwrap()
+    }
+
+    // Send a response to the adapter as the interface does.
+    fn send_response(stream: &mut TcpStream, response: UnrealResponse) {
+        let message = serde_json::to_vec(&UnrealInterfaceMessage::Response(response)).unwrap();
+        stream.write_all(&frames(&message, 1024)).unwrap();
+    }
+
+    // Answer the adapter's initialize handshake, saying whether the interface supports
+    // cancelling a variable request.
+    fn answer_handshake(stream: &mut TcpStream, supports_cancel: bool) {
+        let UnrealCommand::Initialize(init) = read_command(stream) else {
+            panic!("Expected an initialize command");
+        };
+        send_response(
+            stream,
+            UnrealResponse::Initialize(common::InitializeResponse {
+                version: init.version,
+                supports_list_breakpoints: false,
+                supports_threads: false,
+                supports_restart_frame: false,
+                supports_frame_line: false,
+                supports_cancel_variable_request: supports_cancel,
+                supports_refresh_watches: false,
+                supports_set_variable: false,
+            }),
+        );
+    }
+
+    // Connect to the interface listening on the given port, performing the handshake, and
+    // start a variable request the client has already cancelled. Returns the connection and
+    // the request's result.
+    fn cancelled_variables(port: u16) -> (TcpConnection, Result<Vec<Variable>, Error>) {
+        let (tx, _rx) = channel();
+        let cancellation = Cancellation::default();
+        let mut connection = TcpConnection::connect(port, tx, TcpConnectTimeoutConfig::default())
+            .unwrap()
+            .with_cancellation(cancellation.clone());
+        connection
+            .initialize(
+                common::Version {
+                    major: 1,
+                    minor: 0,
+                    patch: 0,
+                },
+                false,
+                None,
+                None,
+            )
+            .unwrap();
+        cancellation.start(3);
+        cancellation.cancel(3);
+        let result = connection
+            .variables(
+                WatchKind::Local,
+                FrameIndex::create(1).unwrap(),
+                VariableIndex::SCOPE,
+                0,
+                0,
+            )
+            .map(|(vars, _)| vars);
+        (connection, result)
+    }
+
+    #[test]
+    fn cancel_in_flight_request() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        // An interface that waits for the cancel before answering.
+        let interface = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            answer_handshake(&mut stream, true);
+            let commands = vec![read_command(&mut stream), read_command(&mut stream)];
+            send_response(&mut stream, UnrealResponse::Cancelled);
+            commands
+        });
+
+        let (_connection, result) = cancelled_variables(port);
+        let err = result.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Interrupted);
+
+        let commands = interface.join().unwrap();
+        assert!(matches!(commands[0], UnrealCommand::Variables(..)));
+        assert!(matches!(commands[1], UnrealCommand::CancelVariableRequest));
+    }
+
+    #[test]
+    fn request_not_cancelled_without_support() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        // An older interface that takes a while to answer and doesn't know the cancel command.
+        let interface = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            answer_handshake(&mut stream, false);
+            let request = read_command(&mut stream);
+            std::thread::sleep(CANCEL_POLL_INTERVAL * 3);
+            send_response(&mut stream, UnrealResponse::Variables(vec![]));
+            vec![request, read_command(&mut stream)]
+        });
+
+        let (mut connection, result) = cancelled_variables(port);
+        assert!(result.unwrap().is_empty());
+        connection.go().unwrap();
+
+        // The fetch finished and the next command followed it, without a cancel between them.
+        let commands = interface.join().unwrap();
+        assert!(matches!(commands[0], UnrealCommand::Variables(..)));
+        assert!(matches!(commands[1], UnrealCommand::Go));
+    }
+}
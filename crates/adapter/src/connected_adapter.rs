@@ -7,39 +7,100 @@
 
 use std::{
     cmp::Ordering,
-    collections::BTreeMap,
+    collections::{hash_map::DefaultHasher, BTreeMap, BTreeSet, VecDeque},
+    hash::{Hash, Hasher},
     num::TryFromIntError,
-    path::{Component, Path},
+    path::{Component, Path, PathBuf},
     process::Child,
-    sync::mpsc::Receiver,
+    sync::mpsc::{Receiver, RecvTimeoutError, Sender},
+    time::{Duration, Instant, SystemTime},
 };
 
 use common::{
-    Breakpoint, FrameIndex, StackTraceRequest, UnrealEvent, Variable, VariableIndex, Version,
-    WatchKind,
+    Breakpoint, Frame, FrameIndex, StackTraceRequest, UnrealEvent, Variable, VariableIndex,
+    Version, WatchKind, DEFAULT_CONNECT_TIMEOUT, UNKNOWN_TYPE,
 };
 use dap::{
     events::{
-        Event, EventBody, InvalidatedAreas, InvalidatedEventBody, OutputEventBody,
-        OutputEventCategory, StoppedEventBody, StoppedEventReason,
+        CapabilitiesEventBody, Event, EventBody, InvalidatedAreas, InvalidatedEventBody,
+        OutputEventBody, OutputEventCategory, StoppedEventBody, StoppedEventReason,
+        ThreadEventBody, ThreadEventReason,
     },
     requests::{
-        Command, EvaluateArguments, Request, ScopesArguments, SetBreakpointsArguments,
-        StackTraceArguments, VariablesArguments,
+        BreakpointLocationsArguments, Command, DisconnectArguments, EvaluateArguments,
+        EvaluateContext, LaunchArguments, LocationsArguments, Request, RestartFrameArguments,
+        ScopesArguments, SetBreakpointsArguments, SetExpressionArguments,
+        SetFunctionBreakpointsArguments, SetVariableArguments, StackTraceArguments,
+        VariablesArguments,
     },
     responses::{
-        EvaluateResponseBody, Response, ResponseBody, ScopesResponseBody,
-        SetBreakpointsResponseBody, StackTraceResponseBody, ThreadsResponseBody,
-        VariablesResponseBody,
+        BreakpointLocationsResponseBody, EvaluateResponseBody, LocationsResponseBody, Response,
+        ResponseBody, ScopesResponseBody, SetBreakpointsResponseBody, SetExpressionResponseBody,
+        SetFunctionBreakpointsResponseBody, SetVariableResponseBody, StackTraceResponseBody,
+        ThreadsResponseBody, VariablesResponseBody,
+    },
+    types::{
+        BreakpointLocation, Capabilities, Scope, Source, StackFrame, Thread,
+        VariablePresentationHint, VariableReferenceInfo,
     },
-    types::{Scope, Source, StackFrame, Thread, VariableReferenceInfo},
 };
 
 use crate::{
-    client::Client, client_config::ClientConfig, comm::Connection,
-    variable_reference::VariableReference, AdapterMessage, UnrealscriptAdapterError,
+    client::Client,
+    client_config::ClientConfig,
+    comm::Connection,
+    disconnected_adapter::{capabilities, DisconnectedAdapter},
+    output::{
+        decode_output, interface_log_output, log_category, send_limited_output, OutputLimiter,
+    },
+    repl::{read_script, ReplCommand, COMMAND_PREFIX},
+    source::{
+        breakpoint_lines, function_entry_lines, line_call_column, local_declaration,
+        member_declaration, named_function_entry_lines, scan_classes, statement_start,
+    },
+    value_history::ValueHistory,
+    variable_reference::VariableReference,
+    AdapterMessage, UnrealscriptAdapterError,
 };
 
+/// The number of lines logged by the game to remember, to show if the game crashes.
+const RECENT_LOG_LINES: usize = 20;
+
+/// The most breakpoints to set for classes the user asked to break on entry to. Each one costs
+/// a round trip to the interface, and a class with many functions shouldn't swamp it.
+const MAX_ENTRY_BREAKPOINTS: usize = 200;
+
+/// The most levels deep the REPL can navigate into objects with `.cd`.
+const MAX_REPL_DEPTH: usize = 32;
+
+/// The most expressions that can be pinned with `.watch`. Each one is evaluated every time
+/// execution stops, which slows down stepping.
+const MAX_PINNED_EXPRESSIONS: usize = 16;
+
+/// How long to wait for the interface to close the connection after we disconnect from it
+/// before ending the session anyway.
+const DISCONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How long to wait for the game to break for a `.peek` or on attaching before giving up.
+const BREAK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long fetching the stack and variables for a single stop may take in total before the
+/// user is given some suggestions to speed it up.
+const SLOW_STOP_THRESHOLD: Duration = Duration::from_secs(3);
+
+/// How many times to try connecting to the interface again when the connection is lost
+/// unexpectedly, before ending the session.
+const RECONNECT_ATTEMPTS: u32 = 4;
+
+/// How many times to try connecting to the interface again while waiting for the game to
+/// reconnect, before ending the session. With the default connect timeout this is about five
+/// minutes.
+const RECONNECT_WAIT_ATTEMPTS: u32 = 150;
+
+/// How long to wait before the first attempt to connect again after the connection is lost.
+/// Each later attempt waits twice as long as the one before.
+const RECONNECT_BACKOFF: Duration = Duration::from_millis(250);
+
 /// The thread ID to use for the Unrealscript thread. The unreal debugger only supports one thread.
 const UNREAL_THREAD_ID: i64 = 1;
 const UC_KEYWORDS: [&str; 173] = [
@@ -218,8 +279,177 @@ const UC_KEYWORDS: [&str; 173] = [
     "sizeof",
 ];
 
+// Some locales write decimals with a comma rather than a period, so accept either.
 fn is_number_str(c: &str) -> bool {
-    c.chars().all(|ch| ch.is_ascii_digit() || ch == '.')
+    c.chars()
+        .all(|ch| ch.is_ascii_digit() || ch == '.' || ch == ',')
+}
+
+/// Format a watch value for the client. Unreal formats floats using the system locale, so on
+/// some systems they use a comma as the decimal separator, e.g. `3,14`. These are shown with
+/// a period for consistency. An unset object reference is always shown as `None`, however
+/// Unreal spells it. Other values, including structs whose fields are separated by commas,
+/// are left alone.
+fn display_value(value: &str) -> String {
+    if value.trim().eq_ignore_ascii_case("none") {
+        return "None".to_string();
+    }
+    let is_digits = |s: &str| !s.is_empty() && s.chars().all(|ch| ch.is_ascii_digit());
+    let number = value.strip_prefix('-').unwrap_or(value);
+    match number.split_once(',') {
+        Some((whole, fraction)) if is_digits(whole) && is_digits(fraction) => {
+            value.replacen(',', ".", 1)
+        }
+        _ => value.to_string(),
+    }
+}
+
+/// Build the output of a logpoint by replacing each `{expr}` in its message with the value
+/// given by `evaluate`. An expression that can't be evaluated is left as it is, braces and all.
+fn interpolate_log_message(
+    message: &str,
+    mut evaluate: impl FnMut(&str) -> Option<String>,
+) -> String {
+    let mut output = String::new();
+    let mut rest = message;
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        let segment = &rest[start..start + len + 1];
+        output.push_str(&rest[..start]);
+        match evaluate(segment[1..segment.len() - 1].trim()) {
+            Some(value) => output.push_str(&value),
+            None => output.push_str(segment),
+        }
+        rest = &rest[start + len + 1..];
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Check that each `{` in a logpoint message is closed before the next one opens and that each
+/// `}` closes one, returning a description of the first problem if not.
+fn check_log_message(message: &str) -> Result<(), String> {
+    let mut open = None;
+    for (column, c) in message.chars().enumerate().map(|(i, c)| (i + 1, c)) {
+        match (c, open) {
+            ('{', Some(start)) => {
+                return Err(format!(
+                    "Nested '{{' at column {column}: the '{{' at column {start} isn't closed"
+                ))
+            }
+            ('{', None) => open = Some(column),
+            ('}', Some(_)) => open = None,
+            ('}', None) => return Err(format!("Unmatched '}}' at column {column}")),
+            _ => (),
+        }
+    }
+    match open {
+        Some(start) => Err(format!("Unclosed '{{' at column {start}")),
+        None => Ok(()),
+    }
+}
+
+/// Returns true if the value of a breakpoint condition means it doesn't hold: false, zero, an
+/// unset object reference, or nothing at all.
+fn is_falsey(value: &str) -> bool {
+    let value = display_value(value.trim());
+    value.is_empty()
+        || value.eq_ignore_ascii_case("false")
+        || is_none(&value)
+        || value.parse::<f64>().is_ok_and(|number| number == 0.0)
+}
+
+/// Returns true if a value is an unset object reference.
+fn is_none(value: &str) -> bool {
+    display_value(value) == "None"
+}
+
+/// Split a breakpoint condition comparing an expression with `none`, e.g. `Target != none`,
+/// into the expression and whether it must be set for the condition to hold. Unreal can't
+/// evaluate the comparison itself, but it can evaluate the expression.
+fn none_comparison(condition: &str) -> Option<(&str, bool)> {
+    [("!=", true), ("==", false)]
+        .into_iter()
+        .find_map(|(operator, set)| {
+            let (left, right) = condition.split_once(operator)?;
+            let (left, right) = (left.trim(), right.trim());
+            if right.eq_ignore_ascii_case("none") {
+                Some((left, set))
+            } else if left.eq_ignore_ascii_case("none") {
+                Some((right, set))
+            } else {
+                None
+            }
+        })
+}
+
+/// Returns true if a variable is Unreal's answer to an expression it couldn't evaluate.
+fn is_unresolved(var: &Variable) -> bool {
+    var.ty == UNKNOWN_TYPE || display_value(var.value.trim()).is_empty()
+}
+
+/// Returns true if the line Unreal reported for a breakpoint it was asked to add means it refused
+/// to add one at all.
+fn is_rejected(line: i32) -> bool {
+    line <= 0
+}
+
+/// Returns true if the client may cancel this request. Only the data requests the client sends
+/// in bulk while the user is stepping can be cancelled.
+fn is_cancellable(command: &Command) -> bool {
+    matches!(
+        command,
+        Command::StackTrace(_) | Command::Scopes(_) | Command::Variables(_) | Command::Evaluate(_)
+    )
+}
+
+/// Ask Unreal to add a breakpoint, unless breakpoints are muted. A muted breakpoint is only
+/// recorded, as though Unreal had set it where asked, and is set when breakpoints are unmuted.
+fn add_unless_muted(
+    connection: &mut dyn Connection,
+    muted: bool,
+    bp: Breakpoint,
+) -> Result<Breakpoint, std::io::Error> {
+    if muted {
+        Ok(bp)
+    } else {
+        connection.add_breakpoint(bp)
+    }
+}
+
+/// Ask Unreal to remove a breakpoint, unless breakpoints are muted and Unreal doesn't have it
+/// anyway.
+fn remove_unless_muted(
+    connection: &mut dyn Connection,
+    muted: bool,
+    bp: Breakpoint,
+) -> Result<Breakpoint, std::io::Error> {
+    if muted {
+        Ok(bp)
+    } else {
+        connection.remove_breakpoint(bp)
+    }
+}
+
+/// Returns true if a value read back after an assignment is the value that was assigned.
+/// Unreal formats values in its own way, e.g. floats with six decimal places, so numbers are
+/// compared by value and anything else ignoring case and surrounding quotes.
+fn assigned_value_matches(assigned: &str, actual: &str) -> bool {
+    let unquote = |value: &str| {
+        let value = value.trim();
+        if value.len() >= 2 && is_string_str(value) {
+            value[1..value.len() - 1].to_string()
+        } else {
+            value.to_string()
+        }
+    };
+    let (assigned, actual) = (unquote(assigned), unquote(&display_value(actual)));
+    match (assigned.parse::<f64>(), actual.parse::<f64>()) {
+        (Ok(assigned), Ok(actual)) => assigned == actual,
+        _ => assigned.eq_ignore_ascii_case(&actual),
+    }
 }
 
 fn is_string_str(c: &str) -> bool {
@@ -230,6 +460,32 @@ fn is_invalid_expression(expression: &str) -> bool {
     expression.is_empty() || is_number_str(expression) || is_string_str(expression) || UC_KEYWORDS.contains(&expression)
 }
 
+/// True if the expression is a single identifier, such as the name of a local variable.
+fn is_identifier(expression: &str) -> bool {
+    let mut chars = expression.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Clean up the text under the cursor sent with a hover request, e.g. `Foo.` or `(Foo)`, by
+/// removing leading and trailing punctuation. A trailing `]` is kept if the expression indexes
+/// an array. Returns None if nothing evaluatable remains.
+fn hover_expression(expression: &str) -> Option<&str> {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    let expr = expression.trim_start_matches(|c: char| !is_ident(c));
+    let expr = if expr.contains('[') {
+        expr.trim_end_matches(|c: char| !is_ident(c) && c != ']')
+    } else {
+        expr.trim_end_matches(|c: char| !is_ident(c))
+    };
+
+    if is_invalid_expression(expr) || UC_KEYWORDS.contains(&expr.to_lowercase().as_str()) {
+        None
+    } else {
+        Some(expr)
+    }
+}
+
 // Information about a class.
 #[derive(Debug)]
 struct ClassInfo {
@@ -237,6 +493,24 @@ struct ClassInfo {
     pub package_name: String,
     pub class_name: String,
     pub breakpoints: Vec<i32>,
+    // The condition of each conditional breakpoint, by verified line. Unreal has no conditional
+    // breakpoints, so these are checked by the adapter when it stops on one of these lines.
+    pub conditions: BTreeMap<i32, String>,
+    // The message of each logpoint, by verified line. These are logged by the adapter, which
+    // then resumes instead of stopping.
+    pub log_messages: BTreeMap<i32, String>,
+    // The verified line for each breakpoint line requested by the client, including breakpoints
+    // that are currently disabled (removed from Unreal but may be toggled back on).
+    pub resolved_lines: BTreeMap<i32, i32>,
+    // A hash of the source the resolved lines were found in. Lines are moved to the start of
+    // their statement, so if the source changes the resolved lines are no longer right.
+    pub source_hash: Option<u64>,
+    // The lines a breakpoint can be set on, with the modification time of the file they were
+    // scanned from so they can be rescanned when the file changes.
+    pub breakpoint_lines: Option<(SystemTime, BTreeSet<i32>)>,
+    // The lines of the source, with the modification time of the file they were read from, for
+    // finding the calls stack frames are stopped at.
+    pub source_lines: Option<(SystemTime, Vec<String>)>,
 }
 
 impl ClassInfo {
@@ -247,6 +521,12 @@ impl ClassInfo {
             package_name,
             class_name,
             breakpoints: Vec::new(),
+            conditions: BTreeMap::new(),
+            log_messages: BTreeMap::new(),
+            resolved_lines: BTreeMap::new(),
+            source_hash: None,
+            breakpoint_lines: None,
+            source_lines: None,
         })
     }
 
@@ -260,6 +540,7 @@ impl ClassInfo {
         Source {
             name: Some(self.qualify()),
             path: Some(self.file_name.clone()),
+            source_reference: None,
         }
     }
 }
@@ -271,13 +552,177 @@ pub struct UnrealscriptAdapter<C: Client> {
     config: ClientConfig,
     connection: Box<dyn Connection>,
     class_map: BTreeMap<String, ClassInfo>,
-    child: Option<Child>,
+    // Source files of the classes found by scanning the source roots, by upper-case qualified
+    // class name. Empty unless scanning is enabled.
+    known_classes: BTreeMap<String, PathBuf>,
+    // The lines of the breakpoints set for classes the user asked to break on entry to, by
+    // upper-case qualified class name. These are kept separate from the client's breakpoints.
+    entry_breakpoints: BTreeMap<String, Vec<i32>>,
+    // The lines set in Unreal for the client's function breakpoints, by upper-case qualified
+    // class name and the line of the function's first statement. Unreal may have moved a
+    // breakpoint from that line. These are also kept separate from the client's source
+    // breakpoints.
+    function_breakpoints: BTreeMap<(String, i32), i32>,
+    // Evaluations that produced several variables, with the frame they were evaluated in. These
+    // are shown as a node with the variables as children. The variable reference of each node is
+    // its index + 1, which can't be confused with a `VariableReference` since those never have
+    // zero watch kind bits. Cleared when execution resumes.
+    evaluate_results: Vec<(FrameIndex, Vec<Variable>)>,
+    // The variables given a declaration location reference, as the variable reference of the
+    // scope containing them and their name. The location reference of each is its index + 1.
+    // Cleared when execution resumes.
+    locations: Vec<(i64, String)>,
+    // The number of stack frames known to exist since execution last stopped, used to detect
+    // stale frame ids: the stack depth if the interface reported it, otherwise the number of
+    // frames reported to the client. It is `None` until the stack is fetched after a stop, when
+    // any frame id is accepted, and zero while running, when none are.
+    frame_count: Option<usize>,
+    // The names leading from the selected frame to the object the REPL has navigated to with
+    // '.cd'. Names entered in the REPL are evaluated relative to this object. Empty at the top
+    // level.
+    repl_path: Vec<String>,
+    // The expressions pinned with '.watch', evaluated and shown each time execution stops.
+    pinned_expressions: Vec<String>,
+    // The frame the client last looked at or selected in the REPL. REPL evaluations use this
+    // frame. Reset to the top frame each time execution stops.
+    selected_frame: FrameIndex,
+    // Values of the variables sent to the client, to report which have changed at the next stop.
+    value_history: ValueHistory,
+    // The types of dynamic arrays including their element type, by variable reference. Finding
+    // the element type costs a request for the first element, so it is only done once per stop.
+    array_types: BTreeMap<i64, String>,
+    // The lines of the frames Unreal gave no line for, by frame index. Finding one costs two
+    // stack changes in the game, so it is only done once per frame per stop.
+    frame_lines: BTreeMap<usize, i32>,
+    // The expressions for the arrays sent to the client, by variable reference, so that their
+    // elements can be assigned to. Cleared when execution stops.
+    array_expressions: BTreeMap<i64, String>,
+    // The first level of the top frame's locals, fetched when execution stopped if the client
+    // asked for them to be prefetched. Answers the client's first request for them, and is
+    // dropped once used or if a variable is assigned.
+    prefetched_locals: Option<Vec<Variable>>,
+    child: Debuggee,
     overridden_log_level: Option<String>,
+    output_limiter: Option<OutputLimiter>,
+    // True if the interface can list the breakpoints Unreal has set.
+    supports_list_breakpoints: bool,
+    // True if the interface can list the logical threads other than the main one.
+    supports_threads: bool,
+    // True if the interface can restart a frame. The client is only told it can once we know.
+    supports_restart_frame: bool,
+    // True if the interface can find the line of a frame other than the top one.
+    supports_frame_line: bool,
+    // True if the interface can rebuild the watch lists.
+    supports_refresh_watches: bool,
+    // True if the interface can assign to variables. The client is only told it can once we
+    // know.
+    supports_set_variable: bool,
+    // The versions sent and received in the last handshake with the interface.
+    versions: Option<(Version, Version)>,
+    // True if we've asked the interface to step and haven't yet stopped again.
+    stepping: bool,
+    // True while the breakpoints are removed from Unreal with '.mute'. They're still tracked as
+    // though they were set, and are set again with '.unmute'.
+    muted: bool,
+    // True if the client has resumed or stepped since it was last told we stopped.
+    resumed: bool,
+    // The arguments we were launched with, if this is a launch session. Used to restart it.
+    launch_args: Option<LaunchArguments>,
+    // True if the client has disconnected as part of a restart.
+    restarting: bool,
+    // Used to connect to the interface again if it disconnects or the connection is lost.
+    reconnect: Option<Reconnect>,
+    timeouts: Timeouts,
+    // True if the client has asked us to end the session.
+    disconnecting: bool,
+    // When to give up waiting for the interface to close the connection after we've asked it
+    // to, if we have.
+    disconnect_deadline: Option<Instant>,
+    // The time spent fetching data from the interface since the game last stopped.
+    stop_fetch_time: Duration,
+    // True if the user has been told that fetching data for a stop is slow. This is only done
+    // once a session.
+    slow_stop_reported: bool,
+    // Messages that arrived while waiting for the game to break, to be handled before any
+    // others.
+    pending_messages: VecDeque<AdapterMessage>,
+    // True if the client has disconnected leaving the game paused and the debugger running.
+    detached: bool,
+    // The last lines logged by the game. If the connection is lost unexpectedly these are shown
+    // again since they may explain why.
+    recent_log: VecDeque<String>,
+    // What the interface is doing as far as we know from the events it has sent.
+    interface_state: InterfaceState,
+    // The actions to run when the client has finished configuring the session, in order.
+    post_configuration_actions: Vec<PostConfigurationAction>,
+    // True once the client has sent configurationDone.
+    configured: bool,
+}
+
+/// The state of the interface as seen through its events. A buggy or crashing interface may
+/// send events that make no sense in the current state, such as a stop after it disconnected or
+/// the same stop twice. These are ignored rather than confusing the client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InterfaceState {
+    /// The game is running.
+    Running,
+    /// The game is stopped in the debugger.
+    Stopped,
+    /// The interface has disconnected, or the client has asked us to disconnect from it.
+    Disconnected,
+}
+
+/// Something to do once the client has set its breakpoints and sent configurationDone. These
+/// are queued when the session starts and run in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PostConfigurationAction {
+    /// Resume the game from the breakpoint it stops at when launched, if it has stopped there
+    /// yet. If not it resumes as soon as it does.
+    ReleaseAutoResume,
+    /// Run the commands in the startup script.
+    RunStartupScript,
+}
+
+/// How long the adapter waits for the interface and the game. These are always the defaults
+/// except in tests, which shorten them so they don't have to wait.
+#[derive(Debug, Clone, Copy)]
+struct Timeouts {
+    /// How long to wait before the first attempt to connect again after the connection is lost.
+    reconnect_backoff: Duration,
+    /// How long to wait between attempts to connect again while waiting for the game to
+    /// reconnect.
+    reconnect_wait: Duration,
+    /// How long to wait for the interface to close the connection.
+    disconnect: Duration,
+    /// How long to wait for the game to break for a `.peek` or on attaching.
+    game_break: Duration,
+    /// How long fetching data for one stop may take before the user is told it's slow.
+    slow_stop: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts {
+            reconnect_backoff: RECONNECT_BACKOFF,
+            reconnect_wait: DEFAULT_CONNECT_TIMEOUT,
+            disconnect: DISCONNECT_TIMEOUT,
+            game_break: BREAK_TIMEOUT,
+            slow_stop: SLOW_STOP_THRESHOLD,
+        }
+    }
 }
 
-impl<C: Client> Drop for UnrealscriptAdapter<C> {
+/// A function to connect to the debugger interface again after it disconnects or the
+/// connection is lost. Each call makes a single attempt.
+pub type Reconnect = Box<dyn FnMut() -> Result<Box<dyn Connection>, std::io::Error>>;
+
+/// The game process launched by the adapter, if any. The process is killed when the session
+/// ends unless it has been handed over to a restarted session.
+struct Debuggee(Option<Child>);
+
+impl Drop for Debuggee {
     fn drop(&mut self) {
-        if let Some(mut child) = self.child.take() {
+        if let Some(mut child) = self.0.take() {
             log::trace!("Killing child process.");
             child.kill().unwrap_or_else(|e| {
                 log::error!("Failed to kill child process: {e:?}");
@@ -299,15 +744,109 @@ where
         child: Option<Child>,
         overridden_log_level: Option<String>,
     ) -> UnrealscriptAdapter<C> {
+        let output_limiter = config
+            .max_output_per_second
+            .filter(|&rate| rate > 0)
+            .map(|rate| OutputLimiter::new(rate, Instant::now()));
+        let mut post_configuration_actions = Vec::new();
+        if config.auto_resume {
+            post_configuration_actions.push(PostConfigurationAction::ReleaseAutoResume);
+        }
+        if config.startup_script.is_some() {
+            post_configuration_actions.push(PostConfigurationAction::RunStartupScript);
+        }
+        let known_classes = if config.scan_source_roots {
+            let classes = scan_classes(&config.source_roots);
+            log::info!("Found {} classes in source roots", classes.len());
+            classes
+        } else {
+            BTreeMap::new()
+        };
         UnrealscriptAdapter {
             class_map: BTreeMap::new(),
+            known_classes,
+            entry_breakpoints: BTreeMap::new(),
+            function_breakpoints: BTreeMap::new(),
+            evaluate_results: Vec::new(),
+            locations: Vec::new(),
+            frame_count: None,
+            repl_path: Vec::new(),
+            pinned_expressions: Vec::new(),
+            selected_frame: FrameIndex::TOP_FRAME,
+            value_history: ValueHistory::new(),
+            array_types: BTreeMap::new(),
+            frame_lines: BTreeMap::new(),
+            array_expressions: BTreeMap::new(),
+            prefetched_locals: None,
             connection,
             client,
             receiver,
             config,
-            child,
+            child: Debuggee(child),
             overridden_log_level,
+            output_limiter,
+            supports_list_breakpoints: false,
+            supports_threads: false,
+            supports_restart_frame: false,
+            supports_frame_line: false,
+            supports_refresh_watches: false,
+            supports_set_variable: false,
+            versions: None,
+            stepping: false,
+            muted: false,
+            resumed: false,
+            launch_args: None,
+            restarting: false,
+            reconnect: None,
+            timeouts: Timeouts::default(),
+            disconnecting: false,
+            disconnect_deadline: None,
+            stop_fetch_time: Duration::ZERO,
+            slow_stop_reported: false,
+            pending_messages: VecDeque::new(),
+            detached: false,
+            recent_log: VecDeque::new(),
+            interface_state: InterfaceState::Running,
+            post_configuration_actions,
+            configured: false,
+        }
+    }
+
+    /// Connect to the interface again with the given function when the connection is lost, and
+    /// if [`ClientConfig::wait_for_reconnect`] is set wait for it to connect again when it
+    /// disconnects, rather than ending the session. If `None` the session ends whenever the
+    /// interface goes away.
+    pub fn with_reconnect(mut self, reconnect: Option<Reconnect>) -> Self {
+        self.reconnect = reconnect;
+        self
+    }
+
+    /// Remember the arguments this session was launched with so it can be restarted.
+    pub fn with_launch_args(mut self, args: LaunchArguments) -> Self {
+        self.launch_args = Some(args);
+        self
+    }
+
+    /// Turn this adapter back into a disconnected adapter if the client disconnected as part
+    /// of a restart. The disconnected adapter keeps the launch configuration and the running
+    /// game, if any, for the client's next launch request. Returns `None` if the session is
+    /// over, in which case the launched game is killed.
+    pub fn into_restart(
+        mut self,
+        sender: Sender<AdapterMessage>,
+    ) -> Option<DisconnectedAdapter<C>> {
+        if !self.restarting {
+            return None;
         }
+        let args = self.launch_args.take()?;
+        Some(DisconnectedAdapter::restart(
+            self.client,
+            sender,
+            self.receiver,
+            self.config,
+            args,
+            self.child.0.take(),
+        ))
     }
 
     /// Main loop of the adapter process. This monitors the input message channel
@@ -320,86 +859,170 @@ where
     pub fn process_messages(&mut self, version: Version) -> Result<(), std::io::Error> {
         // Perform the initialization handshake with the interface to exchange version info.
         // We can't proceed if we fail to manage this initialization protocol.
-        let interface_version = self.connection.initialize(
-            version.clone(),
-            self.config.enable_stack_hack,
-            self.overridden_log_level.as_ref(),
-        )?;
-
-        // Perform some version checking and send diagnostics to the client if we have a mismatch.
-        match interface_version.cmp(&version) {
-            Ordering::Less => {
-                // Interface is out of date.
-                self.client.send_event(Event{ body: EventBody::Output(OutputEventBody {
-                    category: OutputEventCategory::Console,
-                    output: "The debugger interface version is outdated. Please re-run the installation task to update.".to_string(),
-                })})?;
-            }
-            Ordering::Greater => {
-                // The interface is newer than this adapter.
-                self.client.send_event(Event{ body: EventBody::Output(OutputEventBody {
+        if let Err(e) = self.handshake(&version) {
+            // The most common reason for this to fail is that the game we launched crashed
+            // during startup. Tell the user that rather than reporting a generic i/o error.
+            let message = match self.child_exit_status() {
+                Some(status) => format!("The game exited before debugging could start ({status})."),
+                None => return Err(e),
+            };
+            log::error!("{message} Initialization error: {e}");
+            self.client.send_event(Event {
+                body: EventBody::Output(OutputEventBody {
                     category: OutputEventCategory::Console,
-                    output: "The Unrealscript debugger extension is older than the interface version installed in Unreal. Please update the extension.".to_string(),
-                })})?;
-            }
-            Ordering::Equal => (),
-        };
+                    output: format!("{message}\n"),
+                }),
+            })?;
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::ConnectionAborted,
+                message,
+            ));
+        }
+
+        self.set_entry_breakpoints(&BTreeSet::new())?;
 
         // Now that we're connected we can tell the client that we're ready to receive breakpoint
         // info, etc. Send the 'initialized' event.
         self.client.send_event(Event {
             body: EventBody::Initialized,
         })?;
+        self.send_thread_event(ThreadEventReason::Started)?;
+
+        self.break_on_attach()?;
 
         // The main loop: monitor the input channel and handle requests and events as
         // they come in.
         loop {
-            match self.receiver.recv() {
+            match self.next_message() {
                 Ok(AdapterMessage::Request(request)) => {
                     // We received a request from the DAP client. Process it and
                     // send a response.
-                    let response = match self.accept(&request) {
-                        Ok(Some(body)) => Response::make_success(&request, body),
-                        Ok(None) => Response::make_ack(&request),
-                        // An error from the request processing code is recoverable. Send
-                        // an error response to the client so it may display it.
-                        Err(e) => {
-                            log::error!("Error processing request: {e}");
-                            Response::make_error(
-                                &request,
-                                "Request Error".to_string(),
-                                e.to_error_message(),
-                            )
+                    let cancellation = self.client.cancellation();
+                    if is_cancellable(&request.command) {
+                        cancellation.start(request.seq);
+                    }
+                    let response = if self.is_cancelled(&request) {
+                        log::info!("Skipping cancelled {} request", request.command.name());
+                        Response::make_cancelled(&request)
+                    } else {
+                        match self.accept(&request) {
+                            Ok(Some(body)) => Response::make_success(&request, body),
+                            Ok(None) => Response::make_ack(&request),
+                            // The client cancelled the request while the interface was
+                            // fetching its data, and the interface gave up.
+                            Err(_) if cancellation.is_cancelled() => {
+                                log::info!("Cancelled {} request", request.command.name());
+                                Response::make_cancelled(&request)
+                            }
+                            // An error from the request processing code is recoverable. Send
+                            // an error response to the client so it may display it.
+                            Err(e) => {
+                                log::error!("Error processing request: {e}");
+                                Response::make_error(
+                                    &request,
+                                    "Request Error".to_string(),
+                                    e.to_error_message(),
+                                )
+                            }
                         }
                     };
+                    cancellation.finish();
                     // Failing to send the response is unrecoverable. This indicates
                     // the client connection has closed so we can never send any more
                     // responses or events.
                     self.client.respond(response)?;
+
+                    // If the client disconnected to restart the session there's nothing more
+                    // to do here: the caller will restart it.
+                    if self.restarting {
+                        log::info!("Restarting debugger session.");
+                        return Ok(());
+                    }
+
+                    // Likewise if we left the game paused. Closing the connection lets the
+                    // interface wait for a new one.
+                    if self.detached {
+                        log::info!("Detached from the paused game.");
+                        return Ok(());
+                    }
                 }
                 Ok(AdapterMessage::Event(evt)) => {
                     // We received an event from the interface. Translate it to
                     // a DAP event and send to the client.
                     log::trace!("Received unreal event {evt:?}");
                     match self.process_event(evt) {
+                        Some(Event {
+                            body: EventBody::Output(body),
+                        }) => self.send_output(body)?,
+                        Some(Event {
+                            body: EventBody::Terminated,
+                        }) => self.send_terminated()?,
+                        Some(
+                            dap_event @ Event {
+                                body: EventBody::Stopped(_),
+                            },
+                        ) => {
+                            self.client.send_event(dap_event)?;
+                            self.invalidate_stack()?;
+                        }
                         Some(dap_event) => self.client.send_event(dap_event)?,
                         None => {
                             continue;
                         }
                     };
                 }
+                Ok(AdapterMessage::ProcessOutput(category, bytes)) => {
+                    let output = decode_output(&bytes, self.config.normalize_output);
+                    self.send_output(OutputEventBody { category, output })?;
+                }
+                Ok(AdapterMessage::InterfaceLog(line)) => {
+                    self.send_output(interface_log_output(line))?;
+                }
+                Ok(AdapterMessage::Shutdown | AdapterMessage::ConnectionLost)
+                    if self.waiting_for_reconnect() =>
+                {
+                    // The interface has disconnected but the client hasn't asked to end the
+                    // session, so wait for the game to come back.
+                    if !self.reconnect(&version)? {
+                        if self.restarting {
+                            log::info!("Restarting debugger session.");
+                        } else {
+                            self.send_terminated()?;
+                        }
+                        return Ok(());
+                    }
+                }
                 Ok(AdapterMessage::Shutdown) => {
                     // One of the endpoints has indicated that the session is ending. This
                     // can come from DAP when the user closes the session from the editor,
                     // or it can come from the interface if the user closes the game or
                     // uses \toggledebugger to shut down the session.
                     log::info!("Shutdown message received. Stopping adapter.");
-                    self.client.send_event(Event {
-                        body: EventBody::Terminated,
-                    })?;
+                    self.send_terminated()?;
+                    return Ok(());
+                }
+                Ok(AdapterMessage::ConnectionLost) => {
+                    // The connection was reset rather than closed. That may have been a hiccup
+                    // with the game still running, so try briefly to connect again.
+                    if self.retry_connection(&version)? {
+                        continue;
+                    }
+
+                    // Otherwise the game has probably crashed. Whatever it logged last may
+                    // say why.
+                    log::error!("Connection to interface lost. Stopping adapter.");
+                    self.client.send_event(self.connection_lost_event())?;
+                    self.send_terminated()?;
+                    return Ok(());
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    // We disconnected but the interface hasn't closed the connection. It's
+                    // probably hung, so don't keep the client waiting for it.
+                    log::warn!("Interface did not close the connection. Stopping adapter.");
+                    self.send_terminated()?;
                     return Ok(());
                 }
-                Err(_) => {
+                Err(RecvTimeoutError::Disconnected) => {
                     // Getting a RecvError means all senders have shut down. This
                     // is very unlikely and means that somehow both the TCP connection
                     // and the DAP client connection failed simultaneously. Regardless,
@@ -413,812 +1036,7375 @@ where
         }
     }
 
-    /// Process a DAP request, returning a response body.
-    pub fn accept(
-        &mut self,
-        request: &Request,
-    ) -> Result<Option<ResponseBody>, UnrealscriptAdapterError> {
-        log::trace!("Dispatching request {}", request.command.to_string());
-        match &request.command {
-            Command::SetBreakpoints(args) => Ok(Some(self.set_breakpoints(args)?)),
-            Command::Threads => Ok(Some(self.threads()?)),
-            Command::ConfigurationDone => Ok(None),
-            Command::Disconnect(_) => {
-                self.disconnect()?;
-                Ok(None)
-            }
-            Command::StackTrace(args) => Ok(Some(self.stack_trace(args)?)),
-            Command::Scopes(args) => Ok(Some(self.scopes(args)?)),
-            Command::Variables(args) => Ok(Some(self.variables(args)?)),
-            Command::Evaluate(args) => Ok(Some(self.evaluate(args)?)),
-            Command::Pause(_) => {
-                self.pause()?;
-                Ok(None)
-            }
-            Command::Continue(_) => {
-                self.go()?;
-                Ok(None)
-            }
-            Command::Next(_) => {
-                self.next()?;
-                Ok(None)
-            }
-            Command::StepIn(_) => {
-                self.step_in()?;
-                Ok(None)
-            }
-            Command::StepOut(_) => {
-                self.step_out()?;
-                Ok(None)
-            }
-            cmd => {
-                log::error!("Unhandled command: {cmd:#?}");
-                Err(UnrealscriptAdapterError::UnhandledCommand(
-                    request.command.to_string(),
-                ))
-            }
-        }
+    /// Tell the client the Unrealscript thread has started or exited.
+    fn send_thread_event(&mut self, reason: ThreadEventReason) -> Result<(), std::io::Error> {
+        self.client.send_event(Event {
+            body: EventBody::Thread(ThreadEventBody {
+                reason,
+                thread_id: UNREAL_THREAD_ID,
+            }),
+        })
     }
 
-    /// Handle a setBreakpoints request
-    fn set_breakpoints(
-        &mut self,
-        args: &SetBreakpointsArguments,
-    ) -> Result<ResponseBody, UnrealscriptAdapterError> {
-        // Break the source file out into sections and record it in our map of
-        // known classes if necessary.
-        let path = args
-            .source
-            .path
-            .as_ref()
-            .expect("Clients should provide sources as paths");
-        let class_info = ClassInfo::make(path.to_string()).or(Err(
-            UnrealscriptAdapterError::InvalidFilename(path.to_string()),
-        ))?;
-        let mut qualified_class_name = class_info.qualify();
-
-        log::trace!("setting breakpoints for {qualified_class_name}");
-        qualified_class_name.make_ascii_uppercase();
-        let class_info = self
-            .class_map
-            .entry(qualified_class_name.clone())
-            .or_insert(class_info);
+    /// Tell the client the session has ended: the thread has exited and the debuggee has
+    /// terminated.
+    fn send_terminated(&mut self) -> Result<(), std::io::Error> {
+        self.send_thread_event(ThreadEventReason::Exited)?;
+        self.client.send_event(Event {
+            body: EventBody::Terminated,
+        })
+    }
 
-        // Remove all the existing breakpoints from this class.
-        for bp in class_info.breakpoints.iter() {
-            let removed = self
-                .connection
-                .remove_breakpoint(Breakpoint::new(&qualified_class_name, *bp))?;
+    /// Perform the initialization handshake with the interface, sending diagnostics to the
+    /// client if the interface version doesn't match ours.
+    fn handshake(&mut self, version: &Version) -> Result<(), std::io::Error> {
+        let response = self.connection.initialize(
+            version.clone(),
+            self.config.enable_stack_hack,
+            self.overridden_log_level.as_ref(),
+            self.config.interface_log_level,
+        )?;
+        self.supports_list_breakpoints = response.supports_list_breakpoints;
+        self.supports_threads = response.supports_threads;
+        self.supports_frame_line = response.supports_frame_line;
+        self.supports_refresh_watches = response.supports_refresh_watches;
 
-            // The internal state of the adapter's breakpoint list should always be consistent with
-            // what unreal thinks the breakpoints are set on.
-            assert!(removed.line == *bp);
+        // The client was told in the initialize response that frames can't be restarted and
+        // variables can't be set, since that depends on the interface. Tell it again whenever
+        // that changes.
+        if response.supports_restart_frame != self.supports_restart_frame
+            || response.supports_set_variable != self.supports_set_variable
+        {
+            self.supports_restart_frame = response.supports_restart_frame;
+            self.supports_set_variable = response.supports_set_variable;
+            self.client.send_event(Event {
+                body: EventBody::Capabilities(CapabilitiesEventBody {
+                    capabilities: Capabilities {
+                        supports_restart_frame: self.supports_restart_frame,
+                        supports_set_variable: self.supports_set_variable,
+                        supports_set_expression: self.supports_set_variable,
+                        ..capabilities()
+                    },
+                }),
+            })?;
         }
 
-        class_info.breakpoints.clear();
+        // Perform some version checking and send diagnostics to the client if we have a mismatch.
+        match response.version.cmp(version) {
+            Ordering::Less => {
+                // Interface is out of date.
+                self.client.send_event(Event{ body: EventBody::Output(OutputEventBody {
+                    category: OutputEventCategory::Console,
+                    output: "The debugger interface version is outdated. Please re-run the installation task to update.".to_string(),
+                })})?;
+            }
+            Ordering::Greater => {
+                // The interface is newer than this adapter.
+                self.client.send_event(Event{ body: EventBody::Output(OutputEventBody {
+                    category: OutputEventCategory::Console,
+                    output: "The Unrealscript debugger extension is older than the interface version installed in Unreal. Please update the extension.".to_string(),
+                })})?;
+            }
+            Ordering::Equal => (),
+        };
+        self.versions = Some((version.clone(), response.version));
+        Ok(())
+    }
 
-        let mut dap_breakpoints: Vec<dap::types::Breakpoint> = Vec::new();
+    /// Wait for the next message. Once we've disconnected from the interface this times out if
+    /// the interface doesn't close the connection in time.
+    fn next_message(&mut self) -> Result<AdapterMessage, RecvTimeoutError> {
+        if let Some(message) = self.pending_messages.pop_front() {
+            return Ok(message);
+        }
+        match self.disconnect_deadline {
+            Some(deadline) => self
+                .receiver
+                .recv_timeout(deadline.saturating_duration_since(Instant::now())),
+            None => self
+                .receiver
+                .recv()
+                .map_err(|_| RecvTimeoutError::Disconnected),
+        }
+    }
 
-        // Now add the new ones (if any)
-        if let Some(breakpoints) = &args.breakpoints {
-            for bp in breakpoints {
-                // Note that Unreal only accepts 32-bit lines.
-                if let Ok(mut line) = bp.line.try_into() {
-                    // The line number received may require adjustment
-                    line += if self.config.one_based_lines { 0 } else { 1 };
+    /// True if the client has already cancelled this request.
+    ///
+    /// Messages received so far are moved to the pending queue so their order is kept, and
+    /// the request is skipped if a cancel for it is among them.
+    fn is_cancelled(&mut self, request: &Request) -> bool {
+        if !is_cancellable(&request.command) {
+            return false;
+        }
+        while let Ok(message) = self.receiver.try_recv() {
+            self.pending_messages.push_back(message);
+        }
+        self.pending_messages.iter().any(|message| {
+            matches!(message, AdapterMessage::Request(Request {
+                command: Command::Cancel(args),
+                ..
+            }) if args.request_id == Some(request.seq))
+        })
+    }
 
-                    let new_bp = self
-                        .connection
-                        .add_breakpoint(Breakpoint::new(&qualified_class_name, line))?;
+    /// True if we should wait for the interface to connect again when it disconnects.
+    fn waiting_for_reconnect(&self) -> bool {
+        self.config.wait_for_reconnect && self.reconnect.is_some() && !self.disconnecting
+    }
 
-                    // Record this breakpoint in our data structure
-                    class_info.breakpoints.push(new_bp.line);
+    /// Wait for the game to connect again after the interface disconnected, then restore the
+    /// session by repeating the initialization handshake and setting the breakpoints again.
+    ///
+    /// Requests from the client are queued until the game reconnects, except a disconnect
+    /// which ends the wait. Returns false if the session should end instead: the client
+    /// disconnected, the game we launched exited, or it didn't connect again in time.
+    ///
+    /// # Errors
+    ///
+    /// Returns an i/o error only if the client connection has closed.
+    fn reconnect(&mut self, version: &Version) -> Result<bool, std::io::Error> {
+        log::info!("Interface disconnected. Waiting for it to reconnect.");
+        self.client.send_event(Event {
+            body: EventBody::Output(OutputEventBody {
+                category: OutputEventCategory::Console,
+                output: "The game disconnected. Waiting for it to connect again...\n".to_string(),
+            }),
+        })?;
 
-                    // Record it in the response
-                    dap_breakpoints.push(dap::types::Breakpoint {
-                        verified: true,
-                        // Line number may require adjustment before sending back out to the
-                        // client.
-                        line: (new_bp.line + if self.config.one_based_lines { 0 } else { -1 })
-                            .into(),
-                        source: class_info.to_source(),
-                    });
+        for _ in 0..RECONNECT_WAIT_ATTEMPTS {
+            let Some(connect) = self.reconnect.as_mut() else {
+                return Ok(false);
+            };
+            match connect() {
+                Ok(connection) => {
+                    self.connection = connection;
+                    match self.handshake(version) {
+                        Ok(()) => {
+                            self.restore_session()?;
+                            return Ok(true);
+                        }
+                        Err(e) => log::error!("Failed to initialize reconnected interface: {e}"),
+                    }
                 }
+                Err(e) => log::trace!("Failed to reconnect: {e}"),
             }
-        }
 
-        Ok(ResponseBody::SetBreakpoints(SetBreakpointsResponseBody {
-            breakpoints: dap_breakpoints,
-        }))
+            // Between attempts see whether there's still any point waiting.
+            if self.disconnect_while_waiting()? {
+                return Ok(false);
+            }
+            if let Some(Ok(Some(status))) = self.child.0.as_mut().map(Child::try_wait) {
+                log::info!("Game exited while waiting for it to reconnect: {status}");
+                self.client.send_event(Event {
+                    body: EventBody::Output(OutputEventBody {
+                        category: OutputEventCategory::Console,
+                        output: format!("The game exited ({status}).\n"),
+                    }),
+                })?;
+                return Ok(false);
+            }
+            std::thread::sleep(self.timeouts.reconnect_wait);
+        }
+        log::warn!("Gave up waiting for the interface to reconnect.");
+        self.client.send_event(Event {
+            body: EventBody::Output(OutputEventBody {
+                category: OutputEventCategory::Console,
+                output: "The game didn't connect again. Ending the session.\n".to_string(),
+            }),
+        })?;
+        Ok(false)
+    }
+
+    /// While waiting for the game to reconnect, check whether the client has asked to end the
+    /// session. Any other messages are queued to be handled once the game is back. A
+    /// disconnect request is answered here since there's no interface to pass it on to.
+    fn disconnect_while_waiting(&mut self) -> Result<bool, std::io::Error> {
+        while let Ok(message) = self.receiver.try_recv() {
+            self.pending_messages.push_back(message);
+        }
+        let Some(position) = self.pending_messages.iter().position(|message| {
+            matches!(
+                message,
+                AdapterMessage::Shutdown
+                    | AdapterMessage::Request(Request {
+                        command: Command::Disconnect(_),
+                        ..
+                    })
+            )
+        }) else {
+            return Ok(false);
+        };
+        if let Some(AdapterMessage::Request(request)) = self.pending_messages.remove(position) {
+            if let Command::Disconnect(args) = &request.command {
+                self.restarting = matches!(args.restart, Some(true)) && self.launch_args.is_some();
+            }
+            self.client.respond(Response::make_ack(&request))?;
+        }
+        log::info!("Session ended while waiting for the interface to reconnect.");
+        self.disconnecting = true;
+        self.interface_state = InterfaceState::Disconnected;
+        Ok(true)
+    }
+
+    /// Try a few times to connect to the interface again after the connection was lost
+    /// unexpectedly, waiting longer after each failure, and restore the session if that
+    /// works. Returns false if the game couldn't be reached, most likely because it crashed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an i/o error only if the client connection has closed.
+    fn retry_connection(&mut self, version: &Version) -> Result<bool, std::io::Error> {
+        if self.disconnecting {
+            return Ok(false);
+        }
+        let mut delay = self.timeouts.reconnect_backoff;
+        for attempt in 1..=RECONNECT_ATTEMPTS {
+            let Some(connect) = self.reconnect.as_mut() else {
+                return Ok(false);
+            };
+            std::thread::sleep(delay);
+            delay *= 2;
+            match connect() {
+                Ok(connection) => self.connection = connection,
+                Err(e) => {
+                    log::info!("Reconnection attempt {attempt} failed: {e}");
+                    continue;
+                }
+            }
+            match self.handshake(version) {
+                Ok(()) => {
+                    self.restore_session()?;
+                    return Ok(true);
+                }
+                Err(e) => log::error!("Failed to initialize reconnected interface: {e}"),
+            }
+        }
+        Ok(false)
+    }
+
+    /// Restore the session after connecting to the interface again: set the breakpoints again
+    /// and tell the user the game is back.
+    ///
+    /// If the previous connection wasn't cleanly torn down Unreal may still have some
+    /// breakpoints set. When the interface can list them only the difference is sent, so
+    /// breakpoints aren't set twice and stale ones are removed.
+    fn restore_session(&mut self) -> Result<(), std::io::Error> {
+        self.stepping = false;
+        self.interface_state = InterfaceState::Running;
+
+        let already_set: BTreeSet<(String, i32)> = if self.supports_list_breakpoints {
+            match self.connection.list_breakpoints() {
+                Ok(breakpoints) => breakpoints
+                    .into_iter()
+                    .map(|bp| (bp.qualified_name.to_uppercase(), bp.line))
+                    .collect(),
+                Err(e) => {
+                    log::error!("Failed to list breakpoints: {e}");
+                    BTreeSet::new()
+                }
+            }
+        } else {
+            BTreeSet::new()
+        };
+
+        // Set the breakpoints again, skipping those Unreal still has.
+        for (class_name, class_info) in self.class_map.iter_mut() {
+            for line in std::mem::take(&mut class_info.breakpoints) {
+                if already_set.contains(&(class_name.clone(), line)) {
+                    class_info.breakpoints.push(line);
+                    continue;
+                }
+                match add_unless_muted(
+                    self.connection.as_mut(),
+                    self.muted,
+                    Breakpoint::new(class_name, line),
+                ) {
+                    Ok(bp) if is_rejected(bp.line) => {
+                        log::warn!("Unreal rejected restored breakpoint {class_name}:{line}");
+                    }
+                    Ok(bp) => class_info.breakpoints.push(bp.line),
+                    Err(e) => log::error!("Failed to restore breakpoint {class_name}:{line}: {e}"),
+                }
+            }
+        }
+        self.set_entry_breakpoints(&already_set)?;
+        for (class_name, line) in self.function_breakpoint_lines() {
+            if already_set.contains(&(class_name.clone(), line)) {
+                continue;
+            }
+            if let Err(e) = add_unless_muted(
+                self.connection.as_mut(),
+                self.muted,
+                Breakpoint::new(&class_name, line),
+            ) {
+                log::error!("Failed to restore function breakpoint {class_name}:{line}: {e}");
+            }
+        }
+
+        // Remove any breakpoints left over from the previous connection that are no longer
+        // wanted. None are while breakpoints are muted.
+        for (class_name, line) in already_set {
+            let wanted = |lines: Option<&Vec<i32>>| lines.is_some_and(|l| l.contains(&line));
+            if !self.muted
+                && (wanted(self.class_map.get(&class_name).map(|c| &c.breakpoints))
+                    || wanted(self.entry_breakpoints.get(&class_name))
+                    || self.has_function_breakpoint(&class_name, line))
+            {
+                continue;
+            }
+            if let Err(e) = self
+                .connection
+                .remove_breakpoint(Breakpoint::new(&class_name, line))
+            {
+                log::error!("Failed to remove stale breakpoint {class_name}:{line}: {e}");
+            }
+        }
+
+        log::info!("Interface reconnected.");
+        self.client.send_event(Event {
+            body: EventBody::Output(OutputEventBody {
+                category: OutputEventCategory::Console,
+                output: "The game has connected again.\n".to_string(),
+            }),
+        })
+    }
+
+    /// Set a breakpoint on the first statement of each function in the classes the user asked to
+    /// break on entry to. At most [`MAX_ENTRY_BREAKPOINTS`] are set in total, and the user is
+    /// told if some were left out. Breakpoints in `already_set` are recorded without asking
+    /// Unreal to set them again.
+    fn set_entry_breakpoints(
+        &mut self,
+        already_set: &BTreeSet<(String, i32)>,
+    ) -> Result<(), std::io::Error> {
+        self.entry_breakpoints.clear();
+        let mut remaining = MAX_ENTRY_BREAKPOINTS;
+        for name in self.config.break_on_enter_classes.clone() {
+            let Some((package, class)) = name.split_once('.') else {
+                log::error!("Not a qualified class name: {name}");
+                continue;
+            };
+            let Some(path) = self.find_source_file(package, class) else {
+                continue;
+            };
+            let source = match std::fs::read_to_string(&path) {
+                Ok(source) => source,
+                Err(e) => {
+                    log::error!("Failed to read source {path}: {e}");
+                    continue;
+                }
+            };
+
+            let qualified_class_name = name.to_uppercase();
+            for line in function_entry_lines(&source) {
+                if remaining == 0 {
+                    log::warn!("Too many entry breakpoints, stopping at {name}:{line}");
+                    return self.client.send_event(Event {
+                        body: EventBody::Output(OutputEventBody {
+                            category: OutputEventCategory::Console,
+                            output: format!(
+                                "Only the first {MAX_ENTRY_BREAKPOINTS} functions of the \
+                                 breakOnEnterClasses classes will break on entry.\n"
+                            ),
+                        }),
+                    });
+                }
+                let key = (qualified_class_name.clone(), line);
+                let line = if already_set.contains(&key) {
+                    line
+                } else {
+                    match add_unless_muted(
+                        self.connection.as_mut(),
+                        self.muted,
+                        Breakpoint::new(&qualified_class_name, line),
+                    ) {
+                        Ok(bp) => bp.line,
+                        Err(e) => {
+                            log::error!("Failed to set entry breakpoint {name}:{line}: {e}");
+                            continue;
+                        }
+                    }
+                };
+                remaining -= 1;
+                self.entry_breakpoints
+                    .entry(qualified_class_name.clone())
+                    .or_default()
+                    .push(line);
+            }
+        }
+        Ok(())
+    }
+
+    /// Return the exit status of the launched game if it has exited, or None if it is still
+    /// running or we did not launch it.
+    fn child_exit_status(&mut self) -> Option<std::process::ExitStatus> {
+        self.child.0.as_mut()?.try_wait().ok().flatten()
+    }
+
+    /// Send game output to the client, subject to the output rate limit if there is one.
+    fn send_output(&mut self, body: OutputEventBody) -> Result<(), std::io::Error> {
+        send_limited_output(&mut self.client, self.output_limiter.as_mut(), body)
+    }
+
+    /// Process a DAP request, returning a response body.
+    pub fn accept(
+        &mut self,
+        request: &Request,
+    ) -> Result<Option<ResponseBody>, UnrealscriptAdapterError> {
+        let start = Instant::now();
+        let result = self.dispatch(request);
+        if matches!(
+            request.command,
+            Command::StackTrace(_)
+                | Command::Scopes(_)
+                | Command::Variables(_)
+                | Command::Evaluate(_)
+        ) {
+            self.add_stop_fetch_time(start.elapsed());
+        }
+        result
+    }
+
+    /// Handle a DAP request with the method for its command.
+    fn dispatch(
+        &mut self,
+        request: &Request,
+    ) -> Result<Option<ResponseBody>, UnrealscriptAdapterError> {
+        log::trace!("Dispatching request {}", request.command.name());
+        match &request.command {
+            Command::SetBreakpoints(args) => Ok(Some(self.set_breakpoints(args)?)),
+            Command::BreakpointLocations(args) => Ok(Some(self.breakpoint_locations(args)?)),
+            // Cancelled requests are skipped as they come up in the queue. By the time the
+            // cancel itself is handled there's nothing left to do.
+            Command::Cancel(_) => Ok(None),
+            Command::SetFunctionBreakpoints(args) => Ok(Some(self.set_function_breakpoints(args)?)),
+            Command::Threads => Ok(Some(self.threads()?)),
+            // Clients only initialize once, before we connect, but answer a repeated request
+            // the same way rather than failing it.
+            Command::Initialize(_) => Ok(Some(ResponseBody::Initialize(Some(capabilities())))),
+            Command::ConfigurationDone => {
+                self.configuration_done();
+                Ok(None)
+            }
+            Command::Disconnect(args) => {
+                self.disconnect(args)?;
+                Ok(None)
+            }
+            Command::StackTrace(args) => Ok(Some(self.stack_trace(args)?)),
+            Command::Scopes(args) => Ok(Some(self.scopes(args)?)),
+            Command::Variables(args) => Ok(Some(self.variables(args)?)),
+            Command::Evaluate(args) => Ok(Some(self.evaluate(args)?)),
+            Command::Locations(args) => Ok(Some(self.locations(args)?)),
+            Command::SetVariable(args) if self.supports_set_variable => {
+                Ok(Some(self.set_variable(args)?))
+            }
+            Command::SetExpression(args) if self.supports_set_variable => {
+                Ok(Some(self.set_expression(args)?))
+            }
+            Command::Pause(_) => {
+                self.pause()?;
+                Ok(None)
+            }
+            Command::Continue(args) => {
+                self.check_thread(args.thread_id)?;
+                self.go()?;
+                Ok(None)
+            }
+            Command::Next(args) => {
+                self.check_thread(args.thread_id)?;
+                self.next()?;
+                Ok(None)
+            }
+            Command::StepIn(args) => {
+                self.check_thread(args.thread_id)?;
+                self.step_in()?;
+                Ok(None)
+            }
+            Command::StepOut(args) => {
+                self.check_thread(args.thread_id)?;
+                self.step_out()?;
+                Ok(None)
+            }
+            Command::RestartFrame(args) if self.supports_restart_frame => {
+                self.restart_frame(args)?;
+                Ok(None)
+            }
+            Command::Malformed(name, reason) => Err(UnrealscriptAdapterError::InvalidArguments(
+                name.clone(),
+                reason.clone(),
+            )),
+            Command::Unknown(name) if self.config.acknowledge_unknown_commands => {
+                log::warn!("Acknowledging unknown command {name}");
+                Ok(None)
+            }
+            cmd => {
+                log::error!("Unhandled command: {cmd:#?}");
+                Err(UnrealscriptAdapterError::UnhandledCommand(
+                    request.command.name(),
+                ))
+            }
+        }
+    }
+
+    /// Add to the time spent fetching data for the current stop. The first time a stop takes
+    /// longer than [`SLOW_STOP_THRESHOLD`] the user is told and given some suggestions for
+    /// speeding it up.
+    fn add_stop_fetch_time(&mut self, elapsed: Duration) {
+        if self.interface_state != InterfaceState::Stopped {
+            return;
+        }
+        self.stop_fetch_time += elapsed;
+        if self.slow_stop_reported || self.stop_fetch_time < self.timeouts.slow_stop {
+            return;
+        }
+
+        self.slow_stop_reported = true;
+        log::warn!(
+            "Fetching data for this stop took {} ms",
+            self.stop_fetch_time.as_millis()
+        );
+        let suggestion = if self.config.enable_stack_hack {
+            "expanding fewer variables"
+        } else {
+            "enabling the stack hack (`enableStackHack`) or expanding fewer variables"
+        };
+        let output = format!(
+            "Fetching the game's state when it stopped took {:.1} seconds. If stopping is slow, \
+             try {suggestion}.\n",
+            self.stop_fetch_time.as_secs_f64()
+        );
+        if let Err(e) = self.send_output(OutputEventBody {
+            category: OutputEventCategory::Console,
+            output,
+        }) {
+            log::error!("Failed to report a slow stop: {e}");
+        }
+    }
+
+    /// Handle a breakpointLocations request by listing the lines in the requested range that
+    /// start a statement or declaration, so clients don't offer blank lines or comments.
+    fn breakpoint_locations(
+        &mut self,
+        args: &BreakpointLocationsArguments,
+    ) -> Result<ResponseBody, UnrealscriptAdapterError> {
+        let path = args
+            .source
+            .path
+            .as_ref()
+            .ok_or_else(|| UnrealscriptAdapterError::InvalidFilename(String::new()))?;
+        let class_info = ClassInfo::make(path.to_string()).or(Err(
+            UnrealscriptAdapterError::InvalidFilename(path.to_string()),
+        ))?;
+        let class_info = Self::class_entry(&mut self.class_map, class_info);
+
+        // Scanning the file is cheap, but clients send this request often while the source is
+        // open, so only rescan when the file has changed.
+        let modified = std::fs::metadata(path)?.modified()?;
+        let lines = match &class_info.breakpoint_lines {
+            Some((time, lines)) if *time == modified => lines,
+            _ => {
+                let text = std::fs::read_to_string(path)?;
+                &class_info
+                    .breakpoint_lines
+                    .insert((modified, breakpoint_lines(&text)))
+                    .1
+            }
+        };
+
+        let offset = if self.config.one_based_lines { 0 } else { 1 };
+        let first = args.line + offset;
+        let last = args.end_line.unwrap_or(args.line) + offset;
+        let breakpoints = lines
+            .iter()
+            .map(|line| i64::from(*line))
+            .filter(|line| (first..=last).contains(line))
+            .map(|line| line - offset)
+            .map(|line| BreakpointLocation { line })
+            .collect();
+        Ok(ResponseBody::BreakpointLocations(
+            BreakpointLocationsResponseBody { breakpoints },
+        ))
+    }
+
+    /// Handle a setBreakpoints request
+    fn set_breakpoints(
+        &mut self,
+        args: &SetBreakpointsArguments,
+    ) -> Result<ResponseBody, UnrealscriptAdapterError> {
+        // Break the source file out into sections and record it in our map of
+        // known classes if necessary.
+        let path = args
+            .source
+            .path
+            .as_ref()
+            .expect("Clients should provide sources as paths");
+        let class_info = ClassInfo::make(path.to_string()).or(Err(
+            UnrealscriptAdapterError::InvalidFilename(path.to_string()),
+        ))?;
+        let qualified_class_name = class_info.qualify().to_uppercase();
+
+        log::trace!("setting breakpoints for {qualified_class_name}");
+        let class_info = Self::class_entry(&mut self.class_map, class_info);
+
+        // Collect the requested lines with their conditions and log messages, if any. Note that
+        // Unreal only accepts 32-bit lines, and the line number received may require adjustment.
+        // A malformed log message is reported back to the user instead of being set.
+        type LogMessage = Result<Option<String>, String>;
+        let requested: Vec<(i32, Option<String>, LogMessage)> = args
+            .breakpoints
+            .iter()
+            .flatten()
+            .filter_map(|bp| {
+                let line: i32 = bp.line.try_into().ok()?;
+                let line = line + if self.config.one_based_lines { 0 } else { 1 };
+                let condition = bp.condition.clone().filter(|c| !c.trim().is_empty());
+                let log_message = match bp.log_message.clone().filter(|m| !m.is_empty()) {
+                    Some(message) => check_log_message(&message).map(|()| Some(message)),
+                    None => Ok(None),
+                };
+                Some((line, condition, log_message))
+            })
+            .collect();
+
+        // The request replaces all the breakpoints in the source, so forget the old conditions
+        // and log messages.
+        class_info.conditions.clear();
+        class_info.log_messages.clear();
+
+        // The source is used to move new breakpoints on continuation lines of a multi-line
+        // statement to its first line, which is the only line Unreal will stop on. If it has
+        // changed since the verified lines were found, they have to be found again.
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| log::warn!("Failed to read source {path}: {e}"))
+            .ok();
+        if let Some(source) = &source {
+            let mut hasher = DefaultHasher::new();
+            source.hash(&mut hasher);
+            let hash = hasher.finish();
+            if class_info.source_hash.is_some_and(|old| old != hash) {
+                log::info!("{path} has changed: verifying its breakpoints again");
+                class_info.resolved_lines.clear();
+            }
+            class_info.source_hash = Some(hash);
+        }
+
+        // Clients toggle a breakpoint off by omitting it from the request. Rather than removing
+        // and re-adding every breakpoint in the class, diff the existing breakpoints against the
+        // requested ones: only remove those that are no longer requested, and below only add
+        // those that aren't already set. Verified lines of removed breakpoints stay cached so
+        // they can be cheaply re-enabled.
+        let wanted: BTreeSet<i32> = requested
+            .iter()
+            .filter(|(_, _, log_message)| log_message.is_ok())
+            .filter_map(|(line, _, _)| class_info.resolved_lines.get(line).copied())
+            .collect();
+        let entry_lines = self
+            .entry_breakpoints
+            .get(&qualified_class_name)
+            .cloned()
+            .unwrap_or_default();
+        let function_lines: BTreeSet<i32> = self
+            .function_breakpoints
+            .iter()
+            .filter(|((class_name, _), _)| *class_name == qualified_class_name)
+            .map(|(_, line)| *line)
+            .collect();
+        // Lines Unreal removed when asked to remove a different one.
+        let mut lost = BTreeSet::new();
+        for bp in std::mem::take(&mut class_info.breakpoints) {
+            if wanted.contains(&bp) {
+                class_info.breakpoints.push(bp);
+                continue;
+            }
+
+            // An entry or function breakpoint on the same line must stay set in Unreal.
+            if entry_lines.contains(&bp) || function_lines.contains(&bp) {
+                continue;
+            }
+
+            // Unreal already removed this one in place of another.
+            if lost.contains(&bp) {
+                continue;
+            }
+
+            let removed = remove_unless_muted(
+                self.connection.as_mut(),
+                self.muted,
+                Breakpoint::new(&qualified_class_name, bp),
+            )?;
+
+            // The internal state of the adapter's breakpoint list should always be consistent with
+            // what unreal thinks the breakpoints are set on. If Unreal removed a different line
+            // than we asked for, trust it: the line we asked for is still set so keep tracking
+            // it, and the removed line is no longer set so forget it. If that line is still
+            // wanted it will be re-enabled below.
+            if removed.line != bp {
+                log::warn!(
+                    "Asked to remove breakpoint at {qualified_class_name}:{bp} but line {} was removed.",
+                    removed.line
+                );
+                class_info.breakpoints.push(bp);
+                lost.insert(removed.line);
+
+                // Entry and function breakpoints aren't tracked with the class's breakpoints,
+                // so set them again now.
+                if entry_lines.contains(&removed.line) || function_lines.contains(&removed.line) {
+                    add_unless_muted(
+                        self.connection.as_mut(),
+                        self.muted,
+                        Breakpoint::new(&qualified_class_name, removed.line),
+                    )?;
+                }
+            }
+        }
+        class_info.breakpoints.retain(|line| !lost.contains(line));
+
+        let mut dap_breakpoints: Vec<dap::types::Breakpoint> = Vec::new();
+
+        // Now add the new or re-enabled ones (if any)
+        for (line, condition, log_message) in requested {
+            let log_message = match log_message {
+                Ok(log_message) => log_message,
+                Err(problem) => {
+                    dap_breakpoints.push(dap::types::Breakpoint {
+                        verified: false,
+                        line: (line + if self.config.one_based_lines { 0 } else { -1 }).into(),
+                        source: class_info.to_source(),
+                        message: Some(format!("Invalid log message: {problem}")),
+                    });
+                    continue;
+                }
+            };
+            let verified_line = match class_info.resolved_lines.get(&line) {
+                // Already set in Unreal.
+                Some(verified) if class_info.breakpoints.contains(verified) => *verified,
+                // Disabled: re-enable it at the line Unreal gave us last time.
+                Some(verified) => {
+                    let new_bp = add_unless_muted(
+                        self.connection.as_mut(),
+                        self.muted,
+                        Breakpoint::new(&qualified_class_name, *verified),
+                    )?;
+                    if !is_rejected(new_bp.line) {
+                        class_info.breakpoints.push(new_bp.line);
+                    }
+                    new_bp.line
+                }
+                // A new breakpoint: Unreal may move it to a different line.
+                None => {
+                    let start = source
+                        .as_deref()
+                        .map_or(line, |source| statement_start(source, line));
+                    // Another line of the same statement already set it.
+                    if class_info.breakpoints.contains(&start) {
+                        class_info.resolved_lines.insert(line, start);
+                        start
+                    } else {
+                        let new_bp = add_unless_muted(
+                            self.connection.as_mut(),
+                            self.muted,
+                            Breakpoint::new(&qualified_class_name, start),
+                        )?;
+                        if !is_rejected(new_bp.line) {
+                            class_info.resolved_lines.insert(line, new_bp.line);
+                            class_info.breakpoints.push(new_bp.line);
+                        }
+                        new_bp.line
+                    }
+                }
+            };
+
+            // Unreal refused the line outright. Nothing was recorded, so the next request for
+            // this line tries again.
+            if is_rejected(verified_line) {
+                log::warn!("Unreal rejected a breakpoint at {qualified_class_name}:{line}");
+                dap_breakpoints.push(dap::types::Breakpoint {
+                    verified: false,
+                    line: (line + if self.config.one_based_lines { 0 } else { -1 }).into(),
+                    source: class_info.to_source(),
+                    message: Some("Unreal can't break on this line.".to_string()),
+                });
+                continue;
+            }
+
+            if let Some(condition) = condition {
+                class_info.conditions.insert(verified_line, condition);
+            }
+            if let Some(log_message) = log_message {
+                class_info.log_messages.insert(verified_line, log_message);
+            }
+
+            // Record it in the response
+            dap_breakpoints.push(dap::types::Breakpoint {
+                verified: true,
+                // Line number may require adjustment before sending back out to the
+                // client.
+                line: (verified_line + if self.config.one_based_lines { 0 } else { -1 }).into(),
+                source: class_info.to_source(),
+                message: None,
+            });
+        }
+
+        Ok(ResponseBody::SetBreakpoints(SetBreakpointsResponseBody {
+            breakpoints: dap_breakpoints,
+        }))
+    }
+
+    /// Handle a setFunctionBreakpoints request.
+    ///
+    /// Unreal can only break on lines, so each function is found in its class's source and a
+    /// breakpoint is set on its first statement, as for the `breakOnEnterClasses` classes.
+    /// Functions are named as in Unreal's call stack, e.g. `MyPackage.MyClass:PostBeginPlay`,
+    /// and a function overridden in states breaks in each of them.
+    fn set_function_breakpoints(
+        &mut self,
+        args: &SetFunctionBreakpointsArguments,
+    ) -> Result<ResponseBody, UnrealscriptAdapterError> {
+        let resolved: Vec<_> = args
+            .breakpoints
+            .iter()
+            .map(|bp| self.resolve_function(&bp.name))
+            .collect();
+        let wanted: BTreeSet<(String, i32)> = resolved
+            .iter()
+            .flatten()
+            .flat_map(|(class_name, lines, _)| lines.iter().map(|line| (class_name.clone(), *line)))
+            .collect();
+
+        // As for source breakpoints, only remove the breakpoints that are no longer wanted and
+        // only add the new ones. They're keyed on the line asked for, so a breakpoint Unreal
+        // moved is left alone. Lines that also have another kind of breakpoint stay set. Each
+        // change is recorded once Unreal has made it, so an error part way through leaves the
+        // recorded breakpoints matching what's set in Unreal.
+        let old = self.function_breakpoints.clone();
+        for (bp, set_line) in old.iter().filter(|(bp, _)| !wanted.contains(*bp)) {
+            let class_name = &bp.0;
+            self.function_breakpoints.remove(bp);
+            if !self.has_other_breakpoint(class_name, *set_line)
+                && !self.has_function_breakpoint(class_name, *set_line)
+            {
+                if let Err(e) = remove_unless_muted(
+                    self.connection.as_mut(),
+                    self.muted,
+                    Breakpoint::new(class_name, *set_line),
+                ) {
+                    self.function_breakpoints.insert(bp.clone(), *set_line);
+                    return Err(e.into());
+                }
+            }
+        }
+        // The line Unreal set for each wanted line it accepted a breakpoint for.
+        let mut accepted = BTreeMap::new();
+        for (class_name, line) in wanted {
+            let set_line = if let Some(set_line) = old.get(&(class_name.clone(), line)) {
+                *set_line
+            } else if self.has_other_breakpoint(&class_name, line) {
+                line
+            } else {
+                add_unless_muted(
+                    self.connection.as_mut(),
+                    self.muted,
+                    Breakpoint::new(&class_name, line),
+                )?
+                .line
+            };
+            if is_rejected(set_line) {
+                log::warn!("Unreal rejected a function breakpoint at {class_name}:{line}");
+                continue;
+            }
+            accepted.insert((class_name.clone(), line), set_line);
+            self.function_breakpoints
+                .insert((class_name, line), set_line);
+        }
+
+        let offset = if self.config.one_based_lines { 0 } else { -1 };
+        let breakpoints = resolved
+            .into_iter()
+            .zip(&args.breakpoints)
+            .map(|(found, bp)| match found {
+                // A function with several definitions is verified if any of them could be set.
+                Some((class_name, lines, source)) => {
+                    match lines
+                        .iter()
+                        .find_map(|line| accepted.get(&(class_name.clone(), *line)))
+                    {
+                        Some(line) => dap::types::Breakpoint {
+                            verified: true,
+                            line: (line + offset).into(),
+                            source,
+                            message: None,
+                        },
+                        None => dap::types::Breakpoint {
+                            verified: false,
+                            line: (lines[0] + offset).into(),
+                            source,
+                            message: Some("Unreal can't break in this function.".to_string()),
+                        },
+                    }
+                }
+                None => dap::types::Breakpoint {
+                    verified: false,
+                    line: 0,
+                    source: Source {
+                        name: None,
+                        path: None,
+                        source_reference: None,
+                    },
+                    message: Some(format!("Function {} not found", bp.name)),
+                },
+            })
+            .collect();
+        Ok(ResponseBody::SetFunctionBreakpoints(
+            SetFunctionBreakpointsResponseBody { breakpoints },
+        ))
+    }
+
+    /// Find the function with the given name, returning its upper-case qualified class name,
+    /// the line of its first statement in each of its definitions, and its source.
+    fn resolve_function(&mut self, name: &str) -> Option<(String, Vec<i32>, Source)> {
+        let Some((class_name, function)) = name.trim().split_once(':') else {
+            log::warn!("Not a qualified function name: {name}");
+            return None;
+        };
+        let source = self.translate_source(class_name)?;
+        let path = source.path.as_ref()?;
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| log::warn!("Failed to read source {path}: {e}"))
+            .ok()?;
+        let lines = named_function_entry_lines(&text, function);
+        if lines.is_empty() {
+            log::warn!("No function {function} with a body in {path}");
+            return None;
+        }
+        Some((class_name.to_uppercase(), lines, source))
+    }
+
+    /// The lines set in Unreal for the function breakpoints, by upper-case qualified class name.
+    fn function_breakpoint_lines(&self) -> BTreeSet<(String, i32)> {
+        self.function_breakpoints
+            .iter()
+            .map(|((class_name, _), line)| (class_name.clone(), *line))
+            .collect()
+    }
+
+    /// True if a function breakpoint is set in Unreal on the given line.
+    fn has_function_breakpoint(&self, class_name: &str, line: i32) -> bool {
+        self.function_breakpoints
+            .iter()
+            .any(|((bp_class, _), set_line)| bp_class == class_name && *set_line == line)
+    }
+
+    /// True if a source or entry breakpoint is set on the given line.
+    fn has_other_breakpoint(&self, class_name: &str, line: i32) -> bool {
+        self.class_map
+            .get(class_name)
+            .is_some_and(|class_info| class_info.breakpoints.contains(&line))
+            || self
+                .entry_breakpoints
+                .get(class_name)
+                .is_some_and(|lines| lines.contains(&line))
+    }
+
+    /// Handle a threads request. Unreal runs all script on one thread, which always has id 1.
+    /// While the game is stopped interfaces that support it may also report logical threads
+    /// such as the object that's executing, which are numbered after it and have no stack of
+    /// their own.
+    fn threads(&mut self) -> Result<ResponseBody, UnrealscriptAdapterError> {
+        let mut threads = vec![Thread {
+            id: UNREAL_THREAD_ID,
+            name: "main".to_string(),
+        }];
+        if self.supports_threads && self.interface_state == InterfaceState::Stopped {
+            match self.connection.threads() {
+                Ok(names) => threads.extend(
+                    (UNREAL_THREAD_ID + 1..)
+                        .zip(names)
+                        .map(|(id, name)| Thread { id, name }),
+                ),
+                // The main thread is still there, so don't fail the whole request.
+                Err(e) => log::error!("Failed to list threads: {e}"),
+            }
+        }
+        Ok(ResponseBody::Threads(ThreadsResponseBody { threads }))
+    }
+
+    /// Check that a request to resume or step names a thread we told the client about. The
+    /// object threads are really Unreal's one thread too, so resuming any of them resumes the
+    /// game.
+    fn check_thread(&mut self, thread_id: i64) -> Result<(), UnrealscriptAdapterError> {
+        if thread_id == UNREAL_THREAD_ID {
+            return Ok(());
+        }
+        match self.threads()? {
+            ResponseBody::Threads(body) if body.threads.iter().any(|t| t.id == thread_id) => Ok(()),
+            _ => Err(UnrealscriptAdapterError::UnknownThread(thread_id)),
+        }
+    }
+
+    /// Add a class to the class map if it isn't already there, and return its entry.
+    ///
+    /// This is the only way entries are added to the map. Unreal gives us class names in upper
+    /// case and clients give us paths in their on-disk case, so the map is keyed by the upper
+    /// case qualified name to ensure both find the same entry.
+    fn class_entry(
+        class_map: &mut BTreeMap<String, ClassInfo>,
+        class_info: ClassInfo,
+    ) -> &mut ClassInfo {
+        let key = class_info.qualify().to_uppercase();
+        class_map.entry(key).or_insert(class_info)
+    }
+
+    /// Given a package and class name, search the provided source roots in order looking for the
+    /// first one that has a file that matches these names. If the package has a root of its own
+    /// that is searched first.
+    fn find_source_file(&mut self, package: &str, class: &str) -> Option<String> {
+        let package_root = self
+            .config
+            .package_roots
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(package));
+        if let Some((_, root)) = package_root {
+            let candidate = Path::new(root)
+                .join(package)
+                .join("Classes")
+                .join(format!("{class}.uc"));
+            if candidate.exists() {
+                return Self::source_path(package, class, &candidate);
+            }
+            log::warn!("{package}.{class} is not in its package root {root}");
+        }
+
+        // If we've scanned the source roots we already know where the class is, if anywhere.
+        if self.config.scan_source_roots {
+            let name = format!("{package}.{class}").to_uppercase();
+            return match self.known_classes.get(&name) {
+                Some(candidate) => Self::source_path(package, class, candidate),
+                None => {
+                    log::warn!("No source file found for {package}.{class}");
+                    None
+                }
+            };
+        }
+
+        for root in &self.config.source_roots {
+            let path = Path::new(root);
+            if !path.exists() {
+                log::error!("Invalid source root: {root}");
+                continue;
+            }
+
+            log::debug!("Searching source root {root} for {package}.{class}");
+
+            let candidate = path
+                .join(package)
+                .join("Classes")
+                .join(format!("{class}.uc"));
+            if !candidate.exists() {
+                continue;
+            }
+
+            return Self::source_path(package, class, &candidate);
+        }
+
+        log::warn!("No source file found for {package}.{class}");
+        None
+    }
+
+    /// Convert the path of a class's source file to the form we send to the client.
+    fn source_path(package: &str, class: &str, candidate: &Path) -> Option<String> {
+        let canonical = candidate
+            .canonicalize()
+            .map_err(|e| {
+                log::error!("Failed to canonicalize path {candidate:#?}");
+                e
+            })
+            .ok()?;
+
+        let path = canonical.to_str();
+        if path.is_none() {
+            log::error!("Failed to stringize path {candidate:#?}");
+            return None;
+        }
+
+        // Strip the UNC prefix canonicalize added. This is not strictly necessary but makes
+        // the pathnames look nicer in the editor.
+        let str = path.map(|s| s.strip_prefix("\\\\?\\").unwrap_or(s));
+        log::debug!("Mapped {package}.{class} -> {str:?}");
+        str.map(|s| s.to_owned())
+    }
+
+    /// Given a source file that is not known to our class map, locate the correct location on
+    /// disk for that source, add it to the class map, and return a source entry for it.
+    /// the correct path.
+    fn translate_source(&mut self, qualified_name: &str) -> Option<Source> {
+        let canonical_name = qualified_name.to_uppercase();
+
+        // If this entry does not exist then we need to try to find it by searching source roots.
+        if !self.class_map.contains_key(&canonical_name) {
+            // This entry does not exist in our map, so try to locate the source file by searching
+            // the source roots.
+            let mut split = canonical_name.split('.');
+            let package = split.next().or_else(|| {
+                log::error!("Invalid class name {canonical_name}");
+                None
+            })?;
+            let class = split.next().or_else(|| {
+                log::error!("Invalid class name {canonical_name}");
+                None
+            })?;
+
+            // Find the real source file, or return if we can't.
+            let full_path = self.find_source_file(package, class)?;
+
+            // Split the source back out from the obtained filename. Unreal will provide qualified
+            // names in all uppercase, but the full path we return will have the on-disk casing.
+            // Use that instead since it's 1) less screamy, and 2) consistent with the sources we
+            // will add when the first time we encounter a source is from a setBreakpoints request
+            // instead of in an unreal callstack since the client will also give us the filename in
+            // canonicalized case.
+            let (package, class) = split_source(&full_path).ok().or_else(|| {
+                log::error!(
+                    "Failed to split canonicalized source back into package and class: {full_path}"
+                );
+                None
+            })?;
+
+            // Put this entry in the map for later.
+            let class_info = ClassInfo {
+                file_name: full_path,
+                package_name: package,
+                class_name: class,
+                breakpoints: vec![],
+                conditions: BTreeMap::new(),
+                log_messages: BTreeMap::new(),
+                resolved_lines: BTreeMap::new(),
+                source_hash: None,
+                breakpoint_lines: None,
+                source_lines: None,
+            };
+            debug_assert_eq!(class_info.qualify().to_uppercase(), canonical_name);
+            Self::class_entry(&mut self.class_map, class_info);
+        }
+
+        // Find the entry: this should always succeed since we just added it if it wasn't there.
+        let entry = self.class_map.get(&canonical_name).unwrap();
+        Some(Source {
+            name: Some(entry.qualify()),
+            path: Some(entry.file_name.clone()),
+            source_reference: None,
+        })
+    }
+
+    fn disconnect(&mut self, args: &DisconnectArguments) -> Result<(), UnrealscriptAdapterError> {
+        if matches!(args.restart, Some(true)) {
+            if self.launch_args.is_some() {
+                // Don't stop the debugger in the game: if it's still running the restarted
+                // session will connect to it again instead of launching a new one.
+                self.restarting = true;
+                return Ok(());
+            }
+            log::warn!("Restart is only supported for launched sessions. Ending the session.");
+        }
+        self.disconnecting = true;
+        self.interface_state = InterfaceState::Disconnected;
+
+        // Pause the game instead of stopping the debugger so a new session can attach to it
+        // where it stopped. A game we launched is closed when the session ends regardless.
+        if self.config.pause_on_disconnect
+            && self.child.0.is_none()
+            && !matches!(args.terminate_debuggee, Some(true))
+        {
+            self.connection.pause()?;
+            self.detached = true;
+            return Ok(());
+        }
+
+        // Whether or not the interface responds the session ends after the timeout.
+        self.disconnect_deadline = Some(Instant::now() + self.timeouts.disconnect);
+        self.connection.shutdown(self.timeouts.disconnect)?;
+        Ok(())
+    }
+
+    /// Fetch the stack from the interface and send it to the client.
+    fn stack_trace(
+        &mut self,
+        args: &StackTraceArguments,
+    ) -> Result<ResponseBody, UnrealscriptAdapterError> {
+        // Only the main thread has a stack.
+        if args.thread_id != UNREAL_THREAD_ID {
+            return Ok(ResponseBody::StackTrace(StackTraceResponseBody {
+                stack_frames: vec![],
+                total_frames: Some(0),
+            }));
+        }
+
+        let start_frame = args
+            .start_frame
+            .unwrap_or(0)
+            .try_into()
+            .map_err(|e: TryFromIntError| UnrealscriptAdapterError::LimitExceeded(e.to_string()))?;
+
+        // A missing or zero level count asks for the whole stack, which is also what 0 means
+        // to the interface. Only a positive count limits the frames returned.
+        let levels: u32 = args
+            .levels
+            .unwrap_or(0)
+            .try_into()
+            .map_err(|e: TryFromIntError| UnrealscriptAdapterError::LimitExceeded(e.to_string()))?;
+
+        log::debug!("Stack trace request for {levels} frames starting at {start_frame}");
+
+        let mut response = self.connection.stack_trace(StackTraceRequest {
+            start_frame,
+            levels,
+        })?;
+        if levels > 0 {
+            response.frames.truncate(levels as usize);
+        }
+
+        // If the interface told us how deep the stack is, only use the frames that can exist
+        // at the requested position. A request starting at or past the end of the stack gets
+        // no frames, whatever the interface sent. Older interfaces don't report the depth, but
+        // it is still known when the rest of the stack was requested.
+        let (frames, total_frames) = match response.total_frames {
+            Some(total) => {
+                let available = total.saturating_sub(start_frame) as usize;
+                let frames = response.frames.into_iter().take(available).collect();
+                (frames, Some(total as usize))
+            }
+            None if levels == 0 && (start_frame == 0 || !response.frames.is_empty()) => {
+                let total = start_frame as usize + response.frames.len();
+                (response.frames, Some(total))
+            }
+            None => (response.frames, None),
+        };
+        let reported = start_frame as usize + frames.len();
+        self.frame_count = Some(match total_frames {
+            Some(total) => total,
+            None => self.frame_count.unwrap_or(0).max(reported),
+        });
+
+        // Remember each frame's function so variable values can be compared with those of the
+        // same function at the next stop, wherever it is in the stack then.
+        for (i, f) in frames.iter().enumerate() {
+            if let Ok(frame) = FrameIndex::create(start_frame as i64 + i as i64) {
+                let function = format!("{}.{}", f.qualified_name, f.function_name);
+                self.value_history.frame_function(frame, function);
+            }
+        }
+
+        // Each frame but the top one is stopped at a call to the function of the frame above
+        // it. That isn't known for the first frame of a later page of the stack.
+        let callees: Vec<Option<String>> = std::iter::once(None)
+            .chain(frames.iter().map(|f| Some(f.function_name.clone())))
+            .collect();
+        Ok(ResponseBody::StackTrace(StackTraceResponseBody {
+            total_frames: total_frames.map(|total| total as i64),
+            stack_frames: frames
+                .into_iter()
+                .enumerate()
+                .map(|(i, f)| {
+                    // Find the source file for this class. Frames Unreal couldn't name (e.g.
+                    // engine-internal frames) have no qualified name and no source. If the file
+                    // isn't on disk the frame still gets a name-only source so the user can tell
+                    // where it is.
+                    let source = if f.qualified_name.contains('.') {
+                        self.translate_source(&f.qualified_name)
+                            .or_else(|| Some(Self::unfound_source(&f.qualified_name)))
+                    } else {
+                        None
+                    };
+
+                    // We'll use the index into the stack frame vector as the id
+                    let id = i as i64 + start_frame as i64;
+
+                    // Unreal only gives us the line for the current frame unless the stack hack
+                    // resolved them all. Ask the interface for the others so the client can
+                    // show the right line as soon as the user selects the frame. Only the frames
+                    // the client asked for are resolved, so a client loading the stack a page at
+                    // a time only pays for the frames it shows.
+                    let line = if f.line == 0 && f.qualified_name.contains('.') {
+                        self.resolve_frame_line(id)
+                    } else {
+                        f.line
+                    };
+
+                    // Unreal has no column information, so point at the call this frame is making
+                    // if it can be found in the source, otherwise at the start of the line.
+                    // Clients ignore the column of frames without a source.
+                    let column = match &source {
+                        Some(_) => {
+                            let column = callees[i].as_deref().and_then(|callee| {
+                                self.frame_call_column(&f.qualified_name, line, callee)
+                            });
+                            self.client_column(column.unwrap_or(1).into())
+                        }
+                        None => 0,
+                    };
+
+                    let name = match f.state_name {
+                        Some(state) if self.config.show_frame_states => {
+                            format!("{state}.{}", f.function_name)
+                        }
+                        _ => f.function_name,
+                    };
+
+                    StackFrame {
+                        id,
+                        name,
+                        source,
+                        line: line as i64,
+                        column,
+                    }
+                })
+                .collect(),
+        }))
+    }
+
+    /// Find the column of a call to `callee` on the given line of a class's source, if the source
+    /// is on disk and the call can be found on that line. The lines are kept with the class so
+    /// that each stack trace doesn't read the file again, and reread when the file changes.
+    fn frame_call_column(&mut self, qualified_name: &str, line: i32, callee: &str) -> Option<i32> {
+        let class_info = self.class_map.get_mut(&qualified_name.to_uppercase())?;
+        let modified = std::fs::metadata(&class_info.file_name)
+            .and_then(|metadata| metadata.modified())
+            .ok()?;
+        let lines = match &class_info.source_lines {
+            Some((time, lines)) if *time == modified => lines,
+            _ => {
+                let text = std::fs::read_to_string(&class_info.file_name).ok()?;
+                let lines = text.lines().map(str::to_string).collect();
+                &class_info.source_lines.insert((modified, lines)).1
+            }
+        };
+        line_call_column(lines.get(usize::try_from(line - 1).ok()?)?, callee)
+    }
+
+    /// Build a source for a class whose file couldn't be found, with only the class's qualified
+    /// name. It has no source reference since there's no content to give the client for it.
+    fn unfound_source(qualified_name: &str) -> Source {
+        Source {
+            name: Some(qualified_name.to_string()),
+            path: None,
+            source_reference: None,
+        }
+    }
+
+    /// Convert a one-based column number to the client's convention.
+    fn client_column(&self, column: i64) -> i64 {
+        if self.config.one_based_columns {
+            column
+        } else {
+            column - 1
+        }
+    }
+
+    /// Find the line for a stack frame that had no line information in the stack trace.
+    /// Returns 0 if the line can't be determined, including when the interface can't find it.
+    fn resolve_frame_line(&mut self, frame: i64) -> i32 {
+        let frame = match FrameIndex::create(frame) {
+            Ok(frame) if self.supports_frame_line => frame,
+            _ => return 0,
+        };
+        if let Some(&line) = self.frame_lines.get(&frame.into()) {
+            return line;
+        }
+        match self.connection.frame_line(frame) {
+            Ok((_, line)) => {
+                self.frame_lines.insert(frame.into(), line);
+                line
+            }
+            Err(e) => {
+                log::error!("Failed to resolve the line for frame {frame}: {e}");
+                0
+            }
+        }
+    }
+
+    /// Validate a frame id received from the client. Ids beyond what Unreal can represent are
+    /// out of range, and ids for frames that weren't in the stack reported since execution last
+    /// stopped are stale.
+    fn frame_index(&self, frame_id: i64) -> Result<FrameIndex, UnrealscriptAdapterError> {
+        let frame = FrameIndex::create(frame_id).or(Err(
+            UnrealscriptAdapterError::LimitExceeded("Frame index out of range".to_string()),
+        ))?;
+        if self
+            .frame_count
+            .is_some_and(|count| usize::from(frame) >= count)
+        {
+            return Err(UnrealscriptAdapterError::StaleFrame(frame_id));
+        }
+        Ok(frame)
+    }
+
+    /// Return the scopes available in this suspended state. Unreal only supports two scopes: Local
+    /// and Global (the third watch kind for user watches is handled by DAP and we don't need
+    /// native support for it).
+    fn scopes(&mut self, args: &ScopesArguments) -> Result<ResponseBody, UnrealscriptAdapterError> {
+        let frame_index = self.frame_index(args.frame_id)?;
+        self.select_frame(frame_index)?;
+
+        let globals_ref =
+            VariableReference::new(WatchKind::Global, frame_index, VariableIndex::SCOPE);
+        let locals_ref =
+            VariableReference::new(WatchKind::Local, frame_index, VariableIndex::SCOPE);
+
+        // For the top-most frame (0) only, fetch all the watch data from the debugger.
+        let local_var_info = if args.frame_id == 0 {
+            let child_count = self
+                .connection
+                .watch_count(WatchKind::Local, VariableIndex::SCOPE)?
+                .try_into()
+                .or(Err(UnrealscriptAdapterError::LimitExceeded(
+                    "Too many variables".to_string(),
+                )))?;
+            VariableReferenceInfo::new(locals_ref.to_int(), child_count, false)
+        } else {
+            VariableReferenceInfo::new_childless(locals_ref.to_int())
+        };
+
+        let locals = Scope {
+            name: "locals".to_string(),
+            variable_info: local_var_info,
+            expensive: false,
+        };
+
+        // With no current object, e.g. in a static function, the `self` scope is always empty.
+        if self.config.hide_self_without_object && !self.has_current_object(frame_index) {
+            return Ok(ResponseBody::Scopes(ScopesResponseBody {
+                scopes: vec![locals],
+            }));
+        }
+
+        let global_var_info = if args.frame_id == 0 {
+            let child_count = self
+                .connection
+                .watch_count(WatchKind::Global, VariableIndex::SCOPE)?
+                .try_into()
+                .or(Err(UnrealscriptAdapterError::LimitExceeded(
+                    "Too many variables".to_string(),
+                )))?;
+            VariableReferenceInfo::new(globals_ref.to_int(), child_count, false)
+        } else {
+            VariableReferenceInfo::new_childless(globals_ref.to_int())
+        };
+
+        Ok(ResponseBody::Scopes(ScopesResponseBody {
+            scopes: vec![
+                Scope {
+                    name: "self".to_string(),
+                    variable_info: global_var_info,
+                    expensive: false,
+                },
+                locals,
+            ],
+        }))
+    }
+
+
+
+    fn evaluate(
+        &mut self,
+        args: &EvaluateArguments,
+    ) -> Result<ResponseBody, UnrealscriptAdapterError> {
+        // The REPL always uses the selected frame, which the user may have changed with a
+        // command, rather than whatever frame the client thinks is current.
+        let frame_index = match args.frame_id {
+            _ if args.context == Some(EvaluateContext::Repl) => self.selected_frame,
+            Some(f) => self.frame_index(f)?,
+            None => self.selected_frame,
+        };
+
+        // Expressions entered in the REPL may be commands for the adapter itself.
+        if args.context == Some(EvaluateContext::Repl) {
+            if let Some(command) = args.expression.trim().strip_prefix(COMMAND_PREFIX) {
+                let result = self.repl_command(ReplCommand::parse(command)?)?;
+                return Ok(ResponseBody::Evaluate(EvaluateResponseBody {
+                    result,
+                    ty: None,
+                    variable_info: VariableReferenceInfo::default(),
+                }));
+            }
+        }
+
+        // Hovers send whatever token is under the cursor. Evaluate the identifier within it if
+        // there is one, otherwise fail quietly so the editor simply shows no hover.
+        let hover = args.context == Some(EvaluateContext::Hover);
+        let relative;
+        let expression = if hover {
+            hover_expression(&args.expression)
+                .ok_or_else(|| UnrealscriptAdapterError::NotEvaluatable(args.expression.clone()))?
+        } else if args.context == Some(EvaluateContext::Repl) {
+            relative = self.repl_expression(&args.expression);
+            relative.as_str()
+        } else {
+            args.expression.as_str()
+        };
+
+        // `none` is a keyword, but it's also the value of an object reference that isn't set.
+        // Show it the way Unreal shows such a reference rather than echoing the keyword back.
+        if !hover && expression.trim().eq_ignore_ascii_case("none") {
+            return Ok(ResponseBody::Evaluate(EvaluateResponseBody {
+                result: "None".to_string(),
+                ty: None,
+                variable_info: VariableReferenceInfo::default(),
+            }));
+        }
+
+        if is_invalid_expression(expression) {
+            return Ok(ResponseBody::Evaluate(EvaluateResponseBody {
+                result: args.expression.clone(),
+                ty: None,
+                variable_info: VariableReferenceInfo::default(),
+            }));
+        }
+
+        let mut var = self.connection.evaluate(frame_index, expression)?;
+        let mut kind = WatchKind::User;
+
+        // Unreal's evaluator can't always see loop variables, though they're still listed among
+        // the frame's locals. Look a name that evaluated to nothing up there instead.
+        if var.is_empty() && is_identifier(expression) {
+            if let Some(local) = self.find_local(frame_index, expression)? {
+                var.push(local);
+                kind = WatchKind::Local;
+            }
+        }
+
+        // Several variables are shown as a single node with all of them as children.
+        if var.len() > 1 {
+            let count = var.len();
+            self.evaluate_results.push((frame_index, var));
+            let reference = self.evaluate_results.len().try_into().or(Err(
+                UnrealscriptAdapterError::LimitExceeded("Too many evaluations".to_string()),
+            ))?;
+            return Ok(ResponseBody::Evaluate(EvaluateResponseBody {
+                result: format!("{count} results"),
+                ty: None,
+                variable_info: VariableReferenceInfo::new(reference, count as i64, false),
+            }));
+        }
+
+        // We may get back a vector of length 0, which means that something has gone wrong with evaluating this
+        // expression. This is not a typical error, passing an invalid expression will usually
+        // still provide a valid response with a value indicating that the expression can't be
+        // resolved. Send an error back to the client in this case.
+        let var = var.pop().ok_or_else(|| {
+            if hover {
+                UnrealscriptAdapterError::NotEvaluatable(expression.to_string())
+            } else {
+                UnrealscriptAdapterError::WatchError(args.expression.clone())
+            }
+        })?;
+
+        // Only a structured value gets a reference the client can expand with a variables
+        // request. A leaf gets 0 like any other variable without children.
+        let child_count = self.get_child_count(kind, &var);
+        let reference = if var.has_children {
+            VariableReference::new(kind, frame_index, var.index).to_int()
+        } else {
+            0
+        };
+        if var.is_array && reference != 0 {
+            self.array_expressions
+                .insert(reference, expression.to_string());
+        }
+
+        Ok(ResponseBody::Evaluate(EvaluateResponseBody {
+            result: display_value(&var.value),
+            ty: Some(var.ty),
+            variable_info: VariableReferenceInfo::new(reference, child_count, var.is_array),
+        }))
+    }
+
+    /// Returns false only if the frame is known to have no current object. The interface only
+    /// reports an object thread when the frame Unreal stopped in has a current object, so this is
+    /// only known for the top frame.
+    fn has_current_object(&mut self, frame: FrameIndex) -> bool {
+        if !self.supports_threads || frame != FrameIndex::TOP_FRAME {
+            return true;
+        }
+        match self.connection.threads() {
+            Ok(threads) => !threads.is_empty(),
+            Err(e) => {
+                log::error!("Failed to find whether there is a current object: {e}");
+                true
+            }
+        }
+    }
+
+    /// Return the variables requested.
+    fn variables(
+        &mut self,
+        args: &VariablesArguments,
+    ) -> Result<ResponseBody, UnrealscriptAdapterError> {
+        let var = match VariableReference::from_int(args.variables_reference) {
+            Some(var) => var,
+            None => return self.evaluate_result_variables(args.variables_reference),
+        };
+        self.frame_index(var.frame().into())?;
+
+        // Note: filtering is not implemented. In Unreal any given variable can have either named
+        // or indexed children, but not both. We will never send a variables/scopes response that
+        // has a non-zero count for both of these types, so we should also never receive a request
+        // for one of the types. Even if the client requested a particular filtering we would
+        // either send the whole list (if the filter matched) or nothing (if it didn't).
+        //
+        // A client that doesn't give a count wants everything from the start, which is what a
+        // count of 0 means to the interface.
+        let out_of_range =
+            |what: &str| UnrealscriptAdapterError::LimitExceeded(format!("{what} out of range"));
+        let start =
+            usize::try_from(args.start.unwrap_or(0)).or(Err(out_of_range("Start index")))?;
+        let count = usize::try_from(args.count.unwrap_or(0)).or(Err(out_of_range("Count")))?;
+        let (vars, invalidated) = match self.take_prefetched_locals(&var, start, count) {
+            Some(vars) => (vars, false),
+            None => {
+                self.connection
+                    .variables(var.kind(), var.frame(), var.variable(), start, count)?
+            }
+        };
+        self.selected_frame = var.frame();
+
+        // If this response involved changing stacks, we aren't using the stack hack, the client
+        // supports the feature, and the frame was sent without its line, send an invalidated
+        // stack event for this frame.
+        //
+        // Unreal only gives line information for the top-most stack frame until we actually
+        // switch to another frame. The stack trace normally resolves the other frames' lines up
+        // front, but an interface that can't do that leaves them at 0. This event instructs the
+        // client to refresh this single stack frame, which now gets its line since Unreal has
+        // switched to it. This is not perfect: the client goes to the source file and line 0
+        // before asking for the variables, so the first time you select that frame it jumps to
+        // the file but the wrong line. Clicking on it again goes to the correct line.
+        //
+        // When the stack hack is enabled we don't need this because we did fetch line number info
+        // for all frames when we received the callstack from Unreal and these were all returned
+        // in the original StackTraceResponse.
+        if invalidated
+            && self.config.supports_invalidated_event
+            && !self.config.enable_stack_hack
+            && !self.frame_lines.contains_key(&var.frame().into())
+        {
+            log::trace!("Invalidating frame {}", var.frame());
+            self.client.send_event(Event {
+                body: EventBody::Invalidated(InvalidatedEventBody {
+                    areas: vec![InvalidatedAreas::Stacks],
+                    frame_id: Some(var.frame().into()),
+                }),
+            })?;
+        }
+        Ok(ResponseBody::Variables(VariablesResponseBody {
+            variables: vars
+                .iter()
+                .map(|v| {
+                    let evaluate_name = Self::evaluate_name(&var, &v.name);
+                    let has_declaration = evaluate_name.is_some();
+                    let mut dap_var = self.dap_variable(var.kind(), var.frame(), v, evaluate_name);
+                    if has_declaration {
+                        dap_var.declaration_location_reference =
+                            Some(self.location_reference(var.to_int(), &v.name));
+                    }
+                    let reference = dap_var.variable_info.variables_reference;
+                    if v.is_array && reference != 0 {
+                        if let Some(expr) = &dap_var.evaluate_name {
+                            self.array_expressions.insert(reference, expr.clone());
+                        }
+                    }
+                    if self.value_history.record(&var, v, reference) {
+                        dap_var.presentation_hint = Some(VariablePresentationHint {
+                            attributes: vec!["changed".to_string()],
+                        });
+                    }
+                    dap_var
+                })
+                .collect(),
+        }))
+    }
+
+    /// Assign a new value to a variable, returning its value as read back from Unreal. Only
+    /// variables directly in the locals or globals scopes and elements of arrays with such an
+    /// expression can be set, since these are the only ones we have an expression for.
+    fn set_variable(
+        &mut self,
+        args: &SetVariableArguments,
+    ) -> Result<ResponseBody, UnrealscriptAdapterError> {
+        let not_settable = || UnrealscriptAdapterError::WatchError(args.name.clone());
+        let var = VariableReference::from_int(args.variables_reference).ok_or_else(not_settable)?;
+        let frame = self.frame_index(var.frame().into())?;
+        let expr = match Self::evaluate_name(&var, &args.name) {
+            Some(expr) => expr,
+            None => self.element_expression(&var, &args.name)?,
+        };
+        let dap_var = self.assign(frame, &expr, &args.value)?;
+        Ok(ResponseBody::SetVariable(SetVariableResponseBody {
+            value: dap_var.value,
+            ty: dap_var.ty,
+            variable_info: dap_var.variable_info,
+        }))
+    }
+
+    /// Get an expression for an element of an array from its name, which Unreal gives as the
+    /// array's name followed by the index, e.g. `Counts[3]`. A bare `[3]` is accepted too. Fails
+    /// if we have no expression for the array or the index is past its end.
+    fn element_expression(
+        &mut self,
+        array: &VariableReference,
+        name: &str,
+    ) -> Result<String, UnrealscriptAdapterError> {
+        let not_settable = || UnrealscriptAdapterError::WatchError(name.to_string());
+        let array_expr = self
+            .array_expressions
+            .get(&array.to_int())
+            .cloned()
+            .ok_or_else(not_settable)?;
+        let index = name
+            .strip_suffix(']')
+            .and_then(|name| name.rsplit_once('['))
+            .and_then(|(_, index)| index.parse::<usize>().ok())
+            .ok_or_else(not_settable)?;
+
+        // Check the index first so that a bad one gets a clearer error than a failed read back.
+        self.select_frame(array.frame())?;
+        let len = self
+            .connection
+            .watch_count(array.kind(), array.variable())?;
+        if index >= len {
+            return Err(UnrealscriptAdapterError::LimitExceeded(format!(
+                "Index {index} is out of range for {array_expr}, which has {len} elements"
+            )));
+        }
+        Ok(format!("{array_expr}[{index}]"))
+    }
+
+    /// Assign a new value to the variable named by a watch expression, returning its value as
+    /// read back from Unreal. The expression must name a single variable.
+    fn set_expression(
+        &mut self,
+        args: &SetExpressionArguments,
+    ) -> Result<ResponseBody, UnrealscriptAdapterError> {
+        let frame = match args.frame_id {
+            Some(f) => self.frame_index(f)?,
+            None => self.selected_frame,
+        };
+        let expr = args.expression.trim();
+        if is_invalid_expression(expr) {
+            return Err(UnrealscriptAdapterError::WatchError(expr.to_string()));
+        }
+
+        // Unreal ignores an assignment to something that isn't a variable, so make sure there
+        // is one to assign to first.
+        let vars = self.connection.evaluate(frame, expr)?;
+        if vars.len() != 1 {
+            log::warn!("Can't assign to {expr}: it has {} values", vars.len());
+            return Err(UnrealscriptAdapterError::WatchError(expr.to_string()));
+        }
+
+        let dap_var = self.assign(frame, expr, &args.value)?;
+        Ok(ResponseBody::SetExpression(SetExpressionResponseBody {
+            value: dap_var.value,
+            ty: dap_var.ty,
+            variable_info: dap_var.variable_info,
+        }))
+    }
+
+    /// Assign a value to the variable named by the expression in the given frame, and read it
+    /// back. Fails if Unreal didn't accept the value.
+    fn assign(
+        &mut self,
+        frame: FrameIndex,
+        expr: &str,
+        value: &str,
+    ) -> Result<dap::types::Variable, UnrealscriptAdapterError> {
+        self.select_frame(frame)?;
+        self.prefetched_locals = None;
+        self.connection.set_variable(expr, value)?;
+
+        // Unreal doesn't say whether it accepted the value, so read it back to find out.
+        let mut vars = self.connection.evaluate(frame, expr)?;
+        if vars.len() != 1 || !assigned_value_matches(value, &vars[0].value) {
+            log::warn!(
+                "Unreal did not assign {value} to {expr}: it is {:?}",
+                vars.iter().map(|v| &v.value).collect::<Vec<_>>()
+            );
+            return Err(UnrealscriptAdapterError::WatchError(format!(
+                "{expr} = {value}"
+            )));
+        }
+        Ok(self.dap_variable(WatchKind::User, frame, &vars.remove(0), None))
+    }
+
+    /// Return the variables of an evaluation that produced several of them.
+    fn evaluate_result_variables(
+        &mut self,
+        reference: i64,
+    ) -> Result<ResponseBody, UnrealscriptAdapterError> {
+        let (frame, vars) = usize::try_from(reference - 1)
+            .ok()
+            .and_then(|index| self.evaluate_results.get(index))
+            .cloned()
+            .ok_or(UnrealscriptAdapterError::LimitExceeded(
+                "Variable reference out of range".to_string(),
+            ))?;
+        Ok(ResponseBody::Variables(VariablesResponseBody {
+            variables: vars
+                .iter()
+                .map(|v| self.dap_variable(WatchKind::User, frame, v, None))
+                .collect(),
+        }))
+    }
+
+    /// Convert a variable of the given kind in the given frame to a DAP variable.
+    fn dap_variable(
+        &mut self,
+        kind: WatchKind,
+        frame: FrameIndex,
+        v: &Variable,
+        evaluate_name: Option<String>,
+    ) -> dap::types::Variable {
+        // If this variable is structured get the child count so we can put it in
+        // the appropriate field of the response.
+        let cnt = self.get_child_count(kind, v);
+        let variable_reference = if v.has_children {
+            VariableReference::new(kind, frame, v.index).to_int()
+        } else {
+            0
+        };
+
+        dap::types::Variable {
+            name: v.name.clone(),
+            value: display_value(&v.value),
+            ty: if self.config.supports_variable_type {
+                Some(self.variable_type(kind, frame, v, variable_reference, cnt))
+            } else {
+                None
+            },
+            evaluate_name,
+            presentation_hint: None,
+            declaration_location_reference: None,
+            variable_info: VariableReferenceInfo::new(variable_reference, cnt, v.is_array),
+        }
+    }
+
+    /// Get the location reference for the declaration of the named variable in the scope with the
+    /// given variable reference, giving it one if it doesn't have one yet.
+    fn location_reference(&mut self, scope: i64, name: &str) -> i64 {
+        let index = match self
+            .locations
+            .iter()
+            .position(|(s, n)| *s == scope && n == name)
+        {
+            Some(index) => index,
+            None => {
+                self.locations.push((scope, name.to_string()));
+                self.locations.len() - 1
+            }
+        };
+        index as i64 + 1
+    }
+
+    /// Find the source location for a location reference. Locals and parameters are found in
+    /// the function of their frame, and members in the source of that frame's class.
+    fn locations(
+        &mut self,
+        args: &LocationsArguments,
+    ) -> Result<ResponseBody, UnrealscriptAdapterError> {
+        let not_locatable = || UnrealscriptAdapterError::NotLocatable(args.location_reference);
+        let (scope, name) = usize::try_from(args.location_reference - 1)
+            .ok()
+            .and_then(|index| self.locations.get(index))
+            .cloned()
+            .ok_or_else(not_locatable)?;
+        let var = VariableReference::from_int(scope).ok_or_else(not_locatable)?;
+
+        let mut response = self.connection.stack_trace(StackTraceRequest {
+            start_frame: var.frame().into(),
+            levels: 1,
+        })?;
+        let frame = response.frames.pop().ok_or_else(not_locatable)?;
+        let source = self
+            .translate_source(&frame.qualified_name)
+            .ok_or_else(not_locatable)?;
+        let text = source
+            .path
+            .as_ref()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .ok_or_else(not_locatable)?;
+
+        let declaration = match var.kind() {
+            WatchKind::Local => {
+                let line = if frame.line > 0 {
+                    frame.line
+                } else {
+                    self.resolve_frame_line(i64::from(var.frame()))
+                };
+                local_declaration(&text, line, &name)
+            }
+            WatchKind::Global => member_declaration(&text, &name),
+            WatchKind::User => None,
+        };
+        let (line, column) = declaration.ok_or_else(not_locatable)?;
+        Ok(ResponseBody::Locations(LocationsResponseBody {
+            source,
+            line: (line + if self.config.one_based_lines { 0 } else { -1 }).into(),
+            column: Some(self.client_column(column.into())),
+        }))
+    }
+
+    /// Build an expression the client can evaluate independently to get the given variable, if
+    /// we know one. This is only done for variables directly in the locals or globals scopes:
+    /// locals are evaluated by name and globals as members of `self`.
+    fn evaluate_name(parent: &VariableReference, name: &str) -> Option<String> {
+        if parent.variable() != VariableIndex::SCOPE {
+            return None;
+        }
+        match parent.kind() {
+            WatchKind::Local => Some(name.to_string()),
+            WatchKind::Global => Some(format!("self.{name}")),
+            WatchKind::User => None,
+        }
+    }
+
+    /// Handle a configurationDone request: run the queued post-configuration actions in
+    /// order. A failed action is logged and the rest still run.
+    fn configuration_done(&mut self) {
+        if std::mem::replace(&mut self.configured, true) {
+            log::warn!("Ignoring repeated configurationDone request");
+            return;
+        }
+        for action in std::mem::take(&mut self.post_configuration_actions) {
+            log::info!("Running post-configuration action {action:?}");
+            if let Err(e) = self.run_post_configuration_action(action) {
+                log::error!("Post-configuration action {action:?} failed: {e}");
+            }
+        }
+    }
+
+    /// Run one of the actions queued until configuration is done.
+    fn run_post_configuration_action(
+        &mut self,
+        action: PostConfigurationAction,
+    ) -> Result<(), UnrealscriptAdapterError> {
+        match action {
+            PostConfigurationAction::ReleaseAutoResume => self.release_auto_resume(),
+            PostConfigurationAction::RunStartupScript => Ok(self.run_startup_script()?),
+        }
+    }
+
+    /// Resume the game if it's being held at the breakpoint it stopped at when launched. If it
+    /// hasn't stopped there yet it's resumed when it does.
+    fn release_auto_resume(&mut self) -> Result<(), UnrealscriptAdapterError> {
+        if !self.config.auto_resume || self.interface_state != InterfaceState::Stopped {
+            return Ok(());
+        }
+        log::info!("auto-resuming from initial breakpoint");
+        self.config.auto_resume = false;
+        self.connection.go()?;
+        self.interface_state = InterfaceState::Running;
+        Ok(())
+    }
+
+    /// Run the commands in the startup script, if there is one, showing each command and its
+    /// result in the debug console. If the script can't be read none of it is run and the
+    /// error is shown instead.
+    ///
+    /// # Errors
+    ///
+    /// Returns an i/o error only if the client connection has closed.
+    fn run_startup_script(&mut self) -> Result<(), std::io::Error> {
+        let Some(path) = self.config.startup_script.clone() else {
+            return Ok(());
+        };
+        let commands = match read_script(&path) {
+            Ok(commands) => commands,
+            Err(e) => {
+                log::error!("{e}");
+                return self.client.send_event(Event {
+                    body: EventBody::Output(OutputEventBody {
+                        category: OutputEventCategory::Console,
+                        output: format!("{e}\n"),
+                    }),
+                });
+            }
+        };
+        for (line, command) in commands {
+            log::info!("Running startup command {line}");
+            let result = match self.repl_command(command) {
+                Ok(result) => result,
+                Err(e) => e.to_string(),
+            };
+            self.client.send_event(Event {
+                body: EventBody::Output(OutputEventBody {
+                    category: OutputEventCategory::Console,
+                    output: format!("> {line}\n{result}\n"),
+                }),
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Run an adapter command entered in the REPL, returning the text to display.
+    fn repl_command(&mut self, command: ReplCommand) -> Result<String, UnrealscriptAdapterError> {
+        match command {
+            ReplCommand::About => Ok(self.about()),
+            ReplCommand::Caps => Ok(self.config.to_string()),
+            ReplCommand::Frame(None) => Ok(format!("Frame {}", self.selected_frame)),
+            ReplCommand::Frame(Some(frame_id)) => {
+                let frame = self.frame_index(frame_id)?;
+                self.select_frame(frame)?;
+                Ok(format!("Selected frame {frame}"))
+            }
+            ReplCommand::Cd(path) => self.change_repl_path(path.as_deref()),
+            ReplCommand::Ls => self.list_repl_path(),
+            ReplCommand::Watch(None) if self.pinned_expressions.is_empty() => {
+                Ok("No pinned expressions".to_string())
+            }
+            ReplCommand::Watch(None) => Ok(self.pinned_expressions.join("\n")),
+            ReplCommand::Watch(Some(expr)) => {
+                if !self.pinned_expressions.contains(&expr) {
+                    if self.pinned_expressions.len() == MAX_PINNED_EXPRESSIONS {
+                        return Err(UnrealscriptAdapterError::LimitExceeded(format!(
+                            "Can't pin more than {MAX_PINNED_EXPRESSIONS} expressions"
+                        )));
+                    }
+                    self.pinned_expressions.push(expr.clone());
+                }
+                Ok(format!("Watching {expr}"))
+            }
+            ReplCommand::Unwatch(None) => {
+                let count = std::mem::take(&mut self.pinned_expressions).len();
+                Ok(format!("Unpinned {count} expressions"))
+            }
+            ReplCommand::Unwatch(Some(expr)) => {
+                let count = self.pinned_expressions.len();
+                self.pinned_expressions.retain(|pinned| *pinned != expr);
+                if self.pinned_expressions.len() == count {
+                    Ok(format!("{expr} is not pinned"))
+                } else {
+                    Ok(format!("Stopped watching {expr}"))
+                }
+            }
+            ReplCommand::Peek => self.peek(),
+            ReplCommand::Mute => self.mute(),
+            ReplCommand::Unmute => self.unmute(),
+            ReplCommand::Refresh => self.refresh_watches(),
+        }
+    }
+
+    /// Break, describe where the game stopped and the values of the pinned expressions, then
+    /// resume. The client isn't told about the stop so its pause UI never engages. If the game
+    /// is already stopped it's just described.
+    ///
+    /// Messages that arrive while waiting for the game to break are handled afterwards. If it
+    /// doesn't break in time the pause is still pending, and when it does break the client
+    /// is told about the stop as usual.
+    fn peek(&mut self) -> Result<String, UnrealscriptAdapterError> {
+        if self.interface_state == InterfaceState::Stopped {
+            return self.snapshot();
+        }
+        // The stop that ends the step could be mistaken for the one we ask for.
+        if self.stepping {
+            return Ok("Can't peek while stepping".to_string());
+        }
+
+        self.pause_and_wait()?;
+        let at_breakpoint = self.stopped_at_breakpoint();
+        let snapshot = self.snapshot();
+        if at_breakpoint {
+            // The game hit a breakpoint before it paused for us. Leave it stopped there and
+            // handle the stop as usual.
+            self.pending_messages
+                .push_back(AdapterMessage::Event(UnrealEvent::Stopped));
+            return snapshot;
+        }
+        self.go()?;
+        snapshot
+    }
+
+    /// True if the game is stopped on a line with a breakpoint.
+    fn stopped_at_breakpoint(&mut self) -> bool {
+        let breakpoints = self.all_breakpoints();
+        if breakpoints.is_empty() {
+            return false;
+        }
+        match self.connection.stack_trace(StackTraceRequest {
+            start_frame: 0,
+            levels: 1,
+        }) {
+            Ok(response) => response.frames.last().is_some_and(|frame| {
+                breakpoints.contains(&(frame.qualified_name.to_uppercase(), frame.line))
+            }),
+            Err(e) => {
+                log::error!("Failed to find where we stopped: {e}");
+                false
+            }
+        }
+    }
+
+    /// Every breakpoint the adapter has set, by upper-case qualified class name and line. A
+    /// line with several kinds of breakpoint is only included once.
+    fn all_breakpoints(&self) -> BTreeSet<(String, i32)> {
+        let source = self.class_map.iter().flat_map(|(class_name, class_info)| {
+            class_info
+                .breakpoints
+                .iter()
+                .map(move |line| (class_name.clone(), *line))
+        });
+        let entry = self
+            .entry_breakpoints
+            .iter()
+            .flat_map(|(class_name, lines)| {
+                lines.iter().map(move |line| (class_name.clone(), *line))
+            });
+        source
+            .chain(entry)
+            .chain(self.function_breakpoint_lines())
+            .collect()
+    }
+
+    /// Remove every breakpoint from Unreal so the game runs freely. The breakpoints are still
+    /// tracked as though they were set, including any changes the client makes to them, and
+    /// are set again by [`Self::unmute`]. The client isn't told.
+    fn mute(&mut self) -> Result<String, UnrealscriptAdapterError> {
+        if self.muted {
+            return Ok("Breakpoints are already muted".to_string());
+        }
+        let mut count = 0;
+        for (class_name, line) in self.all_breakpoints() {
+            match self
+                .connection
+                .remove_breakpoint(Breakpoint::new(&class_name, line))
+            {
+                Ok(_) => count += 1,
+                Err(e) => log::error!("Failed to mute breakpoint {class_name}:{line}: {e}"),
+            }
+        }
+        self.muted = true;
+        Ok(format!("Muted {count} breakpoints"))
+    }
+
+    /// Set the breakpoints removed by [`Self::mute`] again, as they are now.
+    fn unmute(&mut self) -> Result<String, UnrealscriptAdapterError> {
+        if !self.muted {
+            return Ok("Breakpoints are not muted".to_string());
+        }
+        self.muted = false;
+        let mut count = 0;
+        for (class_name, line) in self.all_breakpoints() {
+            match self
+                .connection
+                .add_breakpoint(Breakpoint::new(&class_name, line))
+            {
+                Ok(bp) => {
+                    if bp.line != line {
+                        log::warn!(
+                            "Unreal moved unmuted breakpoint {class_name}:{line} to {}",
+                            bp.line
+                        );
+                    }
+                    count += 1;
+                }
+                Err(e) => log::error!("Failed to unmute breakpoint {class_name}:{line}: {e}"),
+            }
+        }
+        Ok(format!("Unmuted {count} breakpoints"))
+    }
+
+    /// Break if the user asked to on attaching. The client is told about the stop as usual.
+    /// If the game doesn't break in time, most likely because it's running native code, the
+    /// user is told so and the pause is left pending.
+    ///
+    /// # Errors
+    ///
+    /// Returns an i/o error only if the client connection has closed.
+    fn break_on_attach(&mut self) -> Result<(), std::io::Error> {
+        if !self.config.break_on_attach {
+            return Ok(());
+        }
+        match self.pause_and_wait() {
+            Ok(()) => {
+                self.pending_messages
+                    .push_back(AdapterMessage::Event(UnrealEvent::Stopped));
+                Ok(())
+            }
+            Err(e) => {
+                log::warn!("Failed to break on attach: {e}");
+                let output = match e {
+                    UnrealscriptAdapterError::IoError(e)
+                        if e.kind() == std::io::ErrorKind::TimedOut =>
+                    {
+                        format!(
+                            "{e}. It may be running native code, and will break when it next \
+                             runs script.\n"
+                        )
+                    }
+                    e => format!("Failed to break on attach: {e}\n"),
+                };
+                self.client.send_event(Event {
+                    body: EventBody::Output(OutputEventBody {
+                        category: OutputEventCategory::Console,
+                        output,
+                    }),
+                })
+            }
+        }
+    }
+
+    /// Ask the game to break and wait for it to stop. The stop is consumed: the caller decides
+    /// whether the client hears about it. Messages that arrive while waiting are handled
+    /// afterwards.
+    fn pause_and_wait(&mut self) -> Result<(), UnrealscriptAdapterError> {
+        self.connection.pause()?;
+        let deadline = Instant::now() + self.timeouts.game_break;
+        loop {
+            match self
+                .receiver
+                .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            {
+                Ok(AdapterMessage::Event(UnrealEvent::Stopped)) => return Ok(()),
+                Ok(
+                    message @ (AdapterMessage::Shutdown
+                    | AdapterMessage::ConnectionLost
+                    | AdapterMessage::Event(UnrealEvent::Disconnect)),
+                ) => {
+                    self.pending_messages.push_back(message);
+                    return Err(UnrealscriptAdapterError::IoError(std::io::Error::new(
+                        std::io::ErrorKind::ConnectionAborted,
+                        "The game disconnected before it could break",
+                    )));
+                }
+                Ok(message) => self.pending_messages.push_back(message),
+                Err(RecvTimeoutError::Timeout) => {
+                    return Err(UnrealscriptAdapterError::IoError(std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        format!(
+                            "The game did not break within {} ms",
+                            self.timeouts.game_break.as_millis()
+                        ),
+                    )))
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(UnrealscriptAdapterError::IoError(std::io::Error::new(
+                        std::io::ErrorKind::ConnectionReset,
+                        "Lost the connections to the client and the interface",
+                    )))
+                }
+            }
+        }
+    }
+
+    /// Describe where the game is stopped and the values of the pinned expressions.
+    fn snapshot(&mut self) -> Result<String, UnrealscriptAdapterError> {
+        let mut response = self.connection.stack_trace(StackTraceRequest {
+            start_frame: 0,
+            levels: 1,
+        })?;
+        let mut output = match response.frames.pop() {
+            Some(frame) => format!(
+                "Stopped in {}.{} line {}\n",
+                frame.qualified_name, frame.function_name, frame.line
+            ),
+            None => "Stopped\n".to_string(),
+        };
+        output.push_str(&self.pinned_values());
+        output.truncate(output.trim_end().len());
+        Ok(output)
+    }
+
+    /// Evaluate the pinned expressions in the top frame and show their values in the debug
+    /// console.
+    fn show_pinned_expressions(&mut self) {
+        if self.pinned_expressions.is_empty() {
+            return;
+        }
+        let output = self.pinned_values();
+        if let Err(e) = self.send_output(OutputEventBody {
+            category: OutputEventCategory::Console,
+            output,
+        }) {
+            log::error!("Failed to send pinned expressions: {e}");
+        }
+    }
+
+    /// Fetch the first level of the top frame's locals, up to the configured number, so the
+    /// client's request for them can be answered without waiting for the interface.
+    fn prefetch_locals(&mut self) {
+        self.prefetched_locals = None;
+        // A count of 0 would ask the interface for all of them, so it turns prefetching off.
+        let Some(count) = self.config.prefetch_locals.filter(|&count| count > 0) else {
+            return;
+        };
+        match self.connection.variables(
+            WatchKind::Local,
+            FrameIndex::TOP_FRAME,
+            VariableIndex::SCOPE,
+            0,
+            count,
+        ) {
+            Ok((vars, _)) => self.prefetched_locals = Some(vars),
+            Err(e) => log::error!("Failed to prefetch locals: {e}"),
+        }
+    }
+
+    /// Take the prefetched locals if they answer a request for the given window of a variable's
+    /// children, as the interface would.
+    fn take_prefetched_locals(
+        &mut self,
+        var: &VariableReference,
+        start: usize,
+        count: usize,
+    ) -> Option<Vec<Variable>> {
+        if !matches!(var.kind(), WatchKind::Local)
+            || var.frame() != FrameIndex::TOP_FRAME
+            || var.variable() != VariableIndex::SCOPE
+            || start != 0
+        {
+            return None;
+        }
+        let mut locals = self.prefetched_locals.take()?;
+        // A count of 0 asks for all of them, which we only know we have if there were fewer
+        // than the most we asked for.
+        let fetched = self.config.prefetch_locals.unwrap_or(0);
+        if count == 0 && locals.len() < fetched {
+            Some(locals)
+        } else if count != 0 && count <= locals.len() {
+            locals.truncate(count);
+            Some(locals)
+        } else {
+            None
+        }
+    }
+
+    /// Evaluate the pinned expressions in the top frame, one `expr = value` line each.
+    fn pinned_values(&mut self) -> String {
+        let mut output = String::new();
+        for expr in self.pinned_expressions.clone() {
+            let value = match self.connection.evaluate(FrameIndex::TOP_FRAME, &expr) {
+                Ok(vars) if vars.len() == 1 => display_value(&vars[0].value),
+                Ok(vars) => format!("<{} results>", vars.len()),
+                Err(e) => {
+                    log::error!("Failed to evaluate pinned expression {expr}: {e}");
+                    format!("<{e}>")
+                }
+            };
+            output.push_str(&format!("{expr} = {value}\n"));
+        }
+        output
+    }
+
+    /// Make a name entered in the REPL relative to the object it has navigated to. Expressions
+    /// that don't start with a name, or start with a keyword such as `self`, are left alone.
+    fn repl_expression(&self, expression: &str) -> String {
+        let expression = expression.trim();
+        let first = expression
+            .split(|c: char| !c.is_alphanumeric() && c != '_')
+            .next()
+            .unwrap_or("");
+        if self.repl_path.is_empty()
+            || !first.starts_with(|c: char| c.is_alphabetic() || c == '_')
+            || UC_KEYWORDS.contains(&first.to_lowercase().as_str())
+        {
+            return expression.to_string();
+        }
+        format!("{}.{expression}", self.repl_path.join("."))
+    }
+
+    /// Handle the '.cd' REPL command: navigate to the given path relative to the current
+    /// object, or back to the top level. The path is only changed if it leads to an object
+    /// with children.
+    fn change_repl_path(&mut self, path: Option<&str>) -> Result<String, UnrealscriptAdapterError> {
+        let mut new_path = self.repl_path.clone();
+        match path {
+            None => new_path.clear(),
+            Some(path) => {
+                for name in path.split('/').filter(|name| !name.is_empty()) {
+                    if name == ".." {
+                        new_path.pop();
+                    } else {
+                        new_path.push(name.to_string());
+                    }
+                }
+            }
+        }
+        if new_path.len() > MAX_REPL_DEPTH {
+            return Err(UnrealscriptAdapterError::LimitExceeded(format!(
+                "Can't navigate more than {MAX_REPL_DEPTH} levels deep"
+            )));
+        }
+
+        if new_path.is_empty() {
+            self.repl_path.clear();
+            return Ok("At the top level".to_string());
+        }
+        let joined = new_path.join(".");
+        if self.repl_object(&joined)?.is_none() {
+            return Ok(format!("{joined} is not an object"));
+        }
+        self.repl_path = new_path;
+        Ok(joined)
+    }
+
+    /// Handle the '.ls' REPL command: list the children of the current object, or the locals
+    /// of the selected frame at the top level. Children that can be navigated into are marked
+    /// with a trailing `/`.
+    fn list_repl_path(&mut self) -> Result<String, UnrealscriptAdapterError> {
+        let (kind, parent) = if self.repl_path.is_empty() {
+            (WatchKind::Local, VariableIndex::SCOPE)
+        } else {
+            let joined = self.repl_path.join(".");
+            match self.repl_object(&joined)? {
+                Some(var) => (WatchKind::User, var.index),
+                None => return Ok(format!("{joined} is no longer an object")),
+            }
+        };
+        let (children, _) = self
+            .connection
+            .variables(kind, self.selected_frame, parent, 0, 0)?;
+        if children.is_empty() {
+            return Ok("No children".to_string());
+        }
+        Ok(children
+            .iter()
+            .map(|v| {
+                let marker = if v.has_children { "/" } else { "" };
+                format!("{}{marker} = {}", v.name, display_value(&v.value))
+            })
+            .collect::<Vec<_>>()
+            .join("\n"))
+    }
+
+    /// Evaluate an object path from the REPL in the selected frame, returning the object if
+    /// it is one with children.
+    fn repl_object(&mut self, path: &str) -> Result<Option<Variable>, UnrealscriptAdapterError> {
+        let mut vars = self.connection.evaluate(self.selected_frame, path)?;
+        Ok(match vars.pop() {
+            Some(var) if vars.is_empty() && var.has_children => Some(var),
+            _ => None,
+        })
+    }
+
+    /// Describe the adapter, the interface it is connected to and the environment, to be
+    /// copied into bug reports.
+    fn about(&self) -> String {
+        let (protocol, interface) = match &self.versions {
+            Some((sent, received)) => (sent.to_string(), received.to_string()),
+            None => ("unknown".to_string(), "unknown".to_string()),
+        };
+        let session = if self.launch_args.is_some() {
+            "launch (-autoDebug)"
+        } else {
+            "attach"
+        };
+        format!(
+            "adapter: {}\n\
+             interface: {interface}\n\
+             protocol: {protocol}\n\
+             os: {} ({})\n\
+             session: {session}\n\
+             enable_stack_hack: {}",
+            env!("CARGO_PKG_VERSION"),
+            std::env::consts::OS,
+            std::env::consts::ARCH,
+            self.config.enable_stack_hack,
+        )
+    }
+
+    /// Make the given frame the selected one, switching the interface to it if it isn't
+    /// already.
+    fn select_frame(&mut self, frame: FrameIndex) -> Result<(), UnrealscriptAdapterError> {
+        if frame != self.selected_frame {
+            // The interface changes Unreal's current frame when asked for its variables.
+            self.connection
+                .variables(WatchKind::Local, frame, VariableIndex::SCOPE, 0, 1)?;
+            self.selected_frame = frame;
+        }
+        Ok(())
+    }
+
+    /// Find the local variable with the given name in the given frame, if there is one.
+    fn find_local(
+        &mut self,
+        frame: FrameIndex,
+        name: &str,
+    ) -> Result<Option<Variable>, UnrealscriptAdapterError> {
+        // The interface changes Unreal's current frame when asked for its variables.
+        let (locals, _) =
+            self.connection
+                .variables(WatchKind::Local, frame, VariableIndex::SCOPE, 0, 0)?;
+        self.selected_frame = frame;
+        Ok(locals
+            .into_iter()
+            .find(|local| local.name.eq_ignore_ascii_case(name)))
+    }
+
+    /// Ask the interface to rebuild its watch lists, e.g. after the game's state was changed
+    /// from outside the debugger. Everything we remember about the variables may be stale after
+    /// this, so it's all forgotten and the client is told to refetch the variables it shows.
+    fn refresh_watches(&mut self) -> Result<String, UnrealscriptAdapterError> {
+        if !self.supports_refresh_watches {
+            return Ok("Refreshing the variables is not supported by this interface".to_string());
+        }
+        self.connection.refresh_watches()?;
+        self.array_types.clear();
+        self.frame_lines.clear();
+        self.array_expressions.clear();
+        self.evaluate_results.clear();
+        self.locations.clear();
+        self.prefetched_locals = None;
+        if self.config.supports_invalidated_event {
+            self.client.send_event(Event {
+                body: EventBody::Invalidated(InvalidatedEventBody {
+                    areas: vec![InvalidatedAreas::Variables],
+                    frame_id: None,
+                }),
+            })?;
+        }
+        Ok("Refreshed the variables".to_string())
+    }
+
+    /// Get the type to show for a variable. Unreal only reports dynamic arrays as `Array`, so
+    /// for non-empty ones the element type is taken from the first element to give e.g.
+    /// `Array<Int>`. If the element type isn't known the plain type is used.
+    fn variable_type(
+        &mut self,
+        kind: WatchKind,
+        frame: FrameIndex,
+        v: &Variable,
+        reference: i64,
+        count: i64,
+    ) -> String {
+        if !v.is_array || v.ty != "Array" || count == 0 {
+            return v.ty.clone();
+        }
+        if let Some(ty) = self.array_types.get(&reference) {
+            return ty.clone();
+        }
+        let ty = match self.connection.variables(kind, frame, v.index, 0, 1) {
+            Ok((elements, _)) => match elements.first() {
+                // Elements Unreal doesn't give a type for are just reported as "array element".
+                Some(element) if !element.ty.is_empty() && element.ty != "array element" => {
+                    format!("{}<{}>", v.ty, element.ty)
+                }
+                _ => v.ty.clone(),
+            },
+            Err(e) => {
+                log::error!("Failed to retrieve the first element of {}: {e:?}", v.name);
+                v.ty.clone()
+            }
+        };
+        // User watch references are reused for each new evaluation, so don't cache those.
+        if !matches!(kind, WatchKind::User) {
+            self.array_types.insert(reference, ty.clone());
+        }
+        ty
+    }
+
+    fn get_child_count(&mut self, kind: WatchKind, var: &Variable) -> i64 {
+        if var.has_children {
+            match self.connection.watch_count(kind, var.index) {
+                Ok(count) => count.try_into().unwrap_or_else(|_| {
+                    log::error!("Child count for var {} too large", var.name);
+                    0
+                }),
+                Err(e) => {
+                    log::error!("Failed to retrieve watch count for {var:?}: {e:?}");
+                    0
+                }
+            }
+        } else {
+            0
+        }
+    }
+
+    /// After telling the client we stopped, have it refetch the whole stack if it has resumed
+    /// since it last saw it, since the stack may have changed shape rather than just the frames
+    /// it asks about. This must follow the stopped event: until then the client has no stack to
+    /// refetch.
+    fn invalidate_stack(&mut self) -> Result<(), std::io::Error> {
+        if std::mem::take(&mut self.resumed) && self.config.supports_invalidated_event {
+            self.client.send_event(Event {
+                body: EventBody::Invalidated(InvalidatedEventBody {
+                    areas: vec![InvalidatedAreas::Stacks],
+                    frame_id: None,
+                }),
+            })?;
+        }
+        Ok(())
+    }
+
+    /// "Pause": Tell the debugger to break as soon as possible.
+    fn pause(&mut self) -> Result<(), UnrealscriptAdapterError> {
+        self.connection.pause()?;
+        Ok(())
+    }
+
+    fn go(&mut self) -> Result<(), UnrealscriptAdapterError> {
+        self.connection.go()?;
+        self.resumed = true;
+        self.evaluate_results.clear();
+        self.locations.clear();
+        self.frame_count = Some(0);
+        self.interface_state = InterfaceState::Running;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<(), UnrealscriptAdapterError> {
+        self.connection.next()?;
+        self.resumed = true;
+        self.stepping = true;
+        self.evaluate_results.clear();
+        self.locations.clear();
+        self.frame_count = Some(0);
+        self.interface_state = InterfaceState::Running;
+        Ok(())
+    }
+
+    fn step_in(&mut self) -> Result<(), UnrealscriptAdapterError> {
+        self.connection.step_in()?;
+        self.resumed = true;
+        self.stepping = true;
+        self.evaluate_results.clear();
+        self.locations.clear();
+        self.frame_count = Some(0);
+        self.interface_state = InterfaceState::Running;
+        Ok(())
+    }
+
+    fn step_out(&mut self) -> Result<(), UnrealscriptAdapterError> {
+        self.connection.step_out()?;
+        self.resumed = true;
+        self.stepping = true;
+        self.evaluate_results.clear();
+        self.locations.clear();
+        self.frame_count = Some(0);
+        self.interface_state = InterfaceState::Running;
+        Ok(())
+    }
+
+    /// Run the function of a frame again from its start. The game stops there again as it does
+    /// after a step.
+    fn restart_frame(
+        &mut self,
+        args: &RestartFrameArguments,
+    ) -> Result<(), UnrealscriptAdapterError> {
+        let frame = self.frame_index(args.frame_id)?;
+        self.connection.restart_frame(frame)?;
+        self.resumed = true;
+        self.stepping = true;
+        self.evaluate_results.clear();
+        self.locations.clear();
+        self.frame_count = Some(0);
+        self.interface_state = InterfaceState::Running;
+        Ok(())
+    }
+
+    /// Decide whether to stop at the breakpoint we've stopped on. A breakpoint with a condition
+    /// only stops if the condition holds, or if it can't be evaluated, in which case the user is
+    /// told why so they can fix it. A logpoint logs its message and never stops.
+    fn stops_at_breakpoint(&mut self) -> bool {
+        if self
+            .class_map
+            .values()
+            .all(|c| c.conditions.is_empty() && c.log_messages.is_empty())
+        {
+            return true;
+        }
+
+        let frame = match self.connection.stack_trace(StackTraceRequest {
+            start_frame: 0,
+            levels: 1,
+        }) {
+            Ok(mut response) => match response.frames.pop() {
+                Some(frame) => frame,
+                None => return true,
+            },
+            Err(e) => {
+                log::error!("Failed to find where we stopped: {e}");
+                return true;
+            }
+        };
+        let (condition, log_message) =
+            match self.class_map.get(&frame.qualified_name.to_uppercase()) {
+                Some(class_info) => (
+                    class_info.conditions.get(&frame.line).cloned(),
+                    class_info.log_messages.get(&frame.line).cloned(),
+                ),
+                None => return true,
+            };
+
+        if let Some(condition) = condition {
+            if !self.condition_holds(&condition, &frame) {
+                return false;
+            }
+        }
+
+        let Some(log_message) = log_message else {
+            return true;
+        };
+        let mut output = interpolate_log_message(&log_message, |expr| {
+            match self.connection.evaluate(FrameIndex::TOP_FRAME, expr) {
+                Ok(mut vars) if vars.len() == 1 => Some(display_value(&vars.remove(0).value)),
+                Ok(vars) => {
+                    log::warn!("Logpoint expression '{expr}' gave {} results", vars.len());
+                    None
+                }
+                Err(e) => {
+                    log::warn!("Failed to evaluate logpoint expression '{expr}': {e}");
+                    None
+                }
+            }
+        });
+        output.push('\n');
+        if let Err(e) = self.send_output(OutputEventBody {
+            category: OutputEventCategory::Console,
+            output,
+        }) {
+            log::error!("Failed to send logpoint output: {e}");
+        }
+        false
+    }
+
+    /// Check a breakpoint condition at the given frame. Returns true if it holds, and also if it
+    /// can't be evaluated, in which case the user is told why so they can fix it.
+    fn condition_holds(&mut self, condition: &str, frame: &Frame) -> bool {
+        let (expression, must_be_set) = match none_comparison(condition) {
+            Some((expression, set)) => (expression, Some(set)),
+            None => (condition, None),
+        };
+        let error = match self.connection.evaluate(FrameIndex::TOP_FRAME, expression) {
+            // Unreal only evaluates variable names. Anything else comes back with no type, or
+            // with no value at all, and must not be mistaken for a condition that holds.
+            Ok(vars) if vars.len() == 1 && is_unresolved(&vars[0]) => {
+                "Unreal can only evaluate the names of variables".to_string()
+            }
+            Ok(vars) if vars.len() == 1 => {
+                log::debug!(
+                    "Breakpoint condition '{condition}': {expression} is {}",
+                    vars[0].value
+                );
+                return match must_be_set {
+                    Some(set) => is_none(&vars[0].value) != set,
+                    None => !is_falsey(&vars[0].value),
+                };
+            }
+            Ok(vars) => format!("it gave {} results", vars.len()),
+            Err(e) => e.to_string(),
+        };
+        let output = format!(
+            "Failed to evaluate the breakpoint condition '{condition}' at {}:{}: {error}\n",
+            frame.qualified_name, frame.line
+        );
+        log::error!("{}", output.trim_end());
+        if let Err(e) = self.send_output(OutputEventBody {
+            category: OutputEventCategory::Console,
+            output,
+        }) {
+            log::error!("Failed to report breakpoint condition error: {e}");
+        }
+        true
+    }
+
+    /// Build the output event telling the user the connection to the game was lost, with the
+    /// last lines it logged.
+    fn connection_lost_event(&self) -> Event {
+        let mut output = "The connection to the game was lost unexpectedly.".to_string();
+        if !self.recent_log.is_empty() {
+            output.push_str(" The last lines it logged were:\n");
+            for line in &self.recent_log {
+                output.push_str(line.trim_end());
+                output.push('\n');
+            }
+        } else {
+            output.push('\n');
+        }
+        Event {
+            body: EventBody::Output(OutputEventBody {
+                category: OutputEventCategory::Console,
+                output,
+            }),
+        }
+    }
+
+    /// Process an event received from the interface, turning it into an event
+    /// to send to the client.
+    fn process_event(&mut self, evt: UnrealEvent) -> Option<Event> {
+        match evt {
+            UnrealEvent::Log(msg) => {
+                let output = decode_output(msg.as_bytes(), self.config.normalize_output);
+                if self.recent_log.len() == RECENT_LOG_LINES {
+                    self.recent_log.pop_front();
+                }
+                self.recent_log.push_back(output.clone());
+                Some(Event {
+                    body: EventBody::Output(OutputEventBody {
+                        category: log_category(&output),
+                        output,
+                    }),
+                })
+            }
+            UnrealEvent::Stopped if self.interface_state != InterfaceState::Running => {
+                log::warn!(
+                    "Ignoring stop from interface in state {:?}",
+                    self.interface_state
+                );
+                None
+            }
+            UnrealEvent::Stopped => {
+                self.interface_state = InterfaceState::Stopped;
+                self.stop_fetch_time = Duration::ZERO;
+                self.frame_count = None;
+                self.selected_frame = FrameIndex::TOP_FRAME;
+                self.value_history.stopped();
+                self.array_types.clear();
+                self.frame_lines.clear();
+                self.array_expressions.clear();
+                // The object the REPL navigated to may not be there in the new context.
+                self.repl_path.clear();
+                // If we stopped after a step give Unreal time to finish sending the new state
+                // before we tell the client, or it may fetch stale stack or watch data.
+                let stepped = std::mem::take(&mut self.stepping);
+                if stepped {
+                    if let Some(ms) = self.config.step_settle_delay_ms {
+                        std::thread::sleep(Duration::from_millis(ms));
+                    }
+                }
+
+                // Hold the game at its initial breakpoint until the client has set its own
+                // breakpoints. It's released when configuration is done.
+                if self.config.auto_resume && !self.configured {
+                    log::info!("Holding at initial breakpoint until configuration is done");
+                    return None;
+                }
+                if self.config.auto_resume {
+                    log::info!("auto-resuming from initial breakpoint");
+                    self.config.auto_resume = false;
+                    match self.connection.go() {
+                        Ok(()) => {
+                            self.interface_state = InterfaceState::Running;
+                            return None;
+                        }
+                        Err(e) => {
+                            log::error!("Error auto-resuming after initial breakpoint: {e}");
+                        }
+                    }
+                }
+
+                // A step stops wherever it ends up, but a breakpoint only stops if its condition
+                // holds and it isn't a logpoint.
+                if !stepped && !self.stops_at_breakpoint() {
+                    match self.connection.go() {
+                        Ok(()) => {
+                            self.interface_state = InterfaceState::Running;
+                            return None;
+                        }
+                        Err(e) => {
+                            log::error!("Error resuming after breakpoint: {e}");
+                        }
+                    }
+                }
+
+                self.show_pinned_expressions();
+                self.prefetch_locals();
+                Some(Event {
+                    body: EventBody::Stopped(StoppedEventBody {
+                        reason: StoppedEventReason::Breakpoint,
+                        thread_id: UNREAL_THREAD_ID,
+                    }),
+                })
+            }
+            UnrealEvent::Disconnect if self.waiting_for_reconnect() => {
+                // The interface is shutting down, but we'll wait for it to come back when the
+                // connection closes.
+                log::info!("Interface is disconnecting.");
+                self.interface_state = InterfaceState::Disconnected;
+                None
+            }
+            UnrealEvent::Disconnect if self.interface_state == InterfaceState::Disconnected => {
+                // The client has already been told, or will be when the connection closes.
+                log::warn!("Ignoring repeated disconnect from interface");
+                None
+            }
+            UnrealEvent::Disconnect => {
+                self.interface_state = InterfaceState::Disconnected;
+                // We've received a disconnect event from interface. This means
+                // the connection is shutting down. Send a terminated event to the
+                // client.
+                Some(Event {
+                    body: EventBody::Terminated,
+                })
+            }
+        }
+    }
+}
+
+/// The filename does not conform to the Unreal path conventions for class naming.
+#[derive(Debug)]
+pub struct BadFilenameError;
+
+/// Process a Source entry to obtain information about a class.
+///
+/// For Unrealscript the details of a class can be determined from its source file.
+/// Given a Source entry with a full path to a source file we expect the path to always
+/// be of the form:
+///
+/// &lt;arbitrary leading directories&gt;\Src\PackageName\Classes\ClassName.uc
+///
+/// From a path of this form we can isolate the package and class names. This naming
+/// scheme is mandatory: the Unreal debugger only talks about package and class names,
+/// and the client only talks about source files. The Unrealscript compiler uses these
+/// same conventions.
+pub fn split_source(path_str: &str) -> Result<(String, String), BadFilenameError> {
+    let path = Path::new(&path_str);
+    let mut iter = path.components().rev();
+
+    // Isolate the filename. This is the last component of the path and should have an extension to
+    // strip.
+    let component = iter.next().ok_or(BadFilenameError)?;
+    let class_name = match component {
+        Component::Normal(file_name) => Path::new(file_name).file_stem().ok_or(BadFilenameError),
+        _ => Err(BadFilenameError),
+    }?
+    .to_str()
+    .expect("Source path should be valid utf-8")
+    .to_owned();
+
+    // Skip the parent
+    iter.next();
+
+    // the package name should be the next component.
+    let component = iter.next().ok_or(BadFilenameError)?;
+    let package_name = match component {
+        Component::Normal(file_name) => Ok(file_name),
+        _ => Err(BadFilenameError),
+    }?
+    .to_str()
+    .expect("Source path should be vaild utf-8")
+    .to_owned();
+    Ok((package_name, class_name))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::{
+        io::{Error, Stdout},
+        sync::{
+            mpsc::{channel, Sender},
+            Arc, Mutex,
+        },
+    };
+
+    use common::{UnrealCommand, UnrealResponse};
+    use dap::types::{FunctionBreakpoint, Source, SourceBreakpoint};
+
+    use crate::client::{Cancellation, ClientImpl};
+
+    use super::*;
+
+    const GOOD_PATH: &str = if cfg!(windows) {
+        "C:\\foo\\src\\MyPackage\\classes\\SomeClass.uc"
+    } else {
+        "/home/somebody/src/MyPackage/classes/SomeClass.uc"
+    };
+
+    fn make_client(sender: Sender<AdapterMessage>) -> ClientImpl<Stdout> {
+        ClientImpl::new(std::io::stdin(), std::io::stdout(), sender)
+    }
+
+    // A log of the commands sent to a mock connection, shared with the test. Each command is
+    // described by a short string such as "add 10" or "evaluate 0 Foo": see `describe`.
+    #[derive(Clone, Default)]
+    struct CommandLog(Arc<Mutex<Vec<String>>>);
+
+    impl CommandLog {
+        // Add an entry for something other than a command, e.g. a reconnection.
+        fn push(&self, entry: impl Into<String>) {
+            self.0.lock().unwrap().push(entry.into());
+        }
+
+        fn entries(&self) -> Vec<String> {
+            self.0.lock().unwrap().clone()
+        }
+
+        // The entries whose first word is one of the given ones, e.g. `["add", "remove"]` for
+        // the breakpoint changes.
+        fn of(&self, kinds: &[&str]) -> Vec<String> {
+            self.0
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|entry| kinds.contains(&entry.split(' ').next().unwrap()))
+                .cloned()
+                .collect()
+        }
+
+        fn clear(&self) {
+            self.0.lock().unwrap().clear();
+        }
+    }
+
+    // Describe a command for a command log.
+    fn describe(command: &UnrealCommand) -> String {
+        match command {
+            UnrealCommand::Initialize(_) => "initialize".to_string(),
+            UnrealCommand::AddBreakpoint(bp) => format!("add {}", bp.line),
+            UnrealCommand::RemoveBreakpoint(bp) => format!("remove {}", bp.line),
+            UnrealCommand::ListBreakpoints => "list".to_string(),
+            UnrealCommand::Threads => "threads".to_string(),
+            UnrealCommand::StackTrace(req) => format!("stack {} {}", req.start_frame, req.levels),
+            UnrealCommand::WatchCount(kind, variable) => format!("count {kind:?} {variable}"),
+            UnrealCommand::Variables(kind, frame, variable, start, count) => {
+                format!("variables {kind:?} {frame} {variable} {start} {count}")
+            }
+            UnrealCommand::Evaluate(frame, expr) => format!("evaluate {frame} {expr}"),
+            UnrealCommand::FrameLine(frame) => format!("line {frame}"),
+            UnrealCommand::RefreshWatches => "refresh".to_string(),
+            UnrealCommand::SetVariable(expr, value) => format!("set {expr} {value}"),
+            UnrealCommand::CancelVariableRequest => "cancel".to_string(),
+            UnrealCommand::Pause => "pause".to_string(),
+            UnrealCommand::Go => "go".to_string(),
+            UnrealCommand::Next => "next".to_string(),
+            UnrealCommand::StepIn => "step_in".to_string(),
+            UnrealCommand::StepOut => "step_out".to_string(),
+            UnrealCommand::RestartFrame(frame) => format!("restart {frame}"),
+            UnrealCommand::Disconnect => "disconnect".to_string(),
+        }
+    }
+
+    type MockHandler =
+        Box<dyn FnMut(&UnrealCommand) -> Option<Result<UnrealResponse, Error>> + Send>;
+
+    // A configurable mock connection for testing. Each command sent to it is passed to a
+    // handler, which returns the interface's response to it or an error, or None to leave the
+    // command to the mock. The mock adds and removes breakpoints as asked and accepts the
+    // initialize handshake with no optional features. Any other command is ignored, and
+    // waiting for a response to it panics. If it's recording, each command is added to the
+    // log before it's handled.
+    struct MockConnection {
+        handler: MockHandler,
+        response: Option<UnrealResponse>,
+        log: Option<CommandLog>,
+    }
+
+    impl MockConnection {
+        fn new(
+            handler: impl FnMut(&UnrealCommand) -> Option<Result<UnrealResponse, Error>>
+                + Send
+                + 'static,
+        ) -> Self {
+            MockConnection {
+                handler: Box::new(handler),
+                response: None,
+                log: None,
+            }
+        }
+
+        // Record the commands sent to this connection in the given log.
+        fn recording(mut self, log: &CommandLog) -> Self {
+            self.log = Some(log.clone());
+            self
+        }
+    }
+
+    impl Default for MockConnection {
+        fn default() -> Self {
+            MockConnection::new(|_| None)
+        }
+    }
+
+    impl Connection for MockConnection {
+        fn send_command(&mut self, command: UnrealCommand) -> Result<(), Error> {
+            if let Some(log) = &self.log {
+                log.push(describe(&command));
+            }
+            self.response = match (self.handler)(&command) {
+                Some(response) => Some(response?),
+                None => match command {
+                    UnrealCommand::Initialize(req) => {
+                        Some(UnrealResponse::Initialize(common::InitializeResponse {
+                            version: req.version,
+                            supports_list_breakpoints: false,
+                            supports_threads: false,
+                            supports_restart_frame: false,
+                            supports_frame_line: false,
+                            supports_cancel_variable_request: false,
+                            supports_refresh_watches: false,
+                            supports_set_variable: false,
+                        }))
+                    }
+                    UnrealCommand::AddBreakpoint(bp) => Some(UnrealResponse::BreakpointAdded(bp)),
+                    UnrealCommand::RemoveBreakpoint(bp) => {
+                        Some(UnrealResponse::BreakpointRemoved(bp))
+                    }
+                    _ => None,
+                },
+            };
+            Ok(())
+        }
+
+        fn next_response(&mut self) -> Result<UnrealResponse, Error> {
+            Ok(self
+                .response
+                .take()
+                .expect("The mock has no response to the last command"))
+        }
+    }
+
+    // Build an adapter with the client made by the given function and the given config,
+    // talking to the given mock connection. Returns the adapter and a sender for queueing
+    // messages to it as if they came from the client or the interface.
+    fn make_adapter<C: Client>(
+        client: impl FnOnce(Sender<AdapterMessage>) -> C,
+        config: ClientConfig,
+        connection: MockConnection,
+    ) -> (UnrealscriptAdapter<C>, Sender<AdapterMessage>) {
+        let (tx, rx) = channel();
+        let adapter = UnrealscriptAdapter::new(
+            client(tx.clone()),
+            rx,
+            config,
+            Box::new(connection),
+            None,
+            None,
+        );
+        (adapter, tx)
+    }
+
+    fn make_test_adapter() -> UnrealscriptAdapter<ClientImpl<Stdout>> {
+        make_adapter(make_client, ClientConfig::new(), MockConnection::default()).0
+    }
+
+    #[test]
+    fn can_split_source() {
+        let (package, class) = split_source(GOOD_PATH).unwrap();
+        assert_eq!(package, "MyPackage");
+        assert_eq!(class, "SomeClass");
+    }
+
+    #[test]
+    fn split_source_bad_classname() {
+        let path = if cfg!(windows) {
+            "C:\\MyMod\\BadClass.uc"
+        } else {
+            "/MyMod/BadClass.uc"
+        };
+        let info = split_source(path);
+        assert!(matches!(info, Err(BadFilenameError)));
+    }
+
+    #[test]
+    fn split_source_forward_slashes() {
+        let (package, class) = split_source(GOOD_PATH).unwrap();
+        assert_eq!(package, "MyPackage");
+        assert_eq!(class, "SomeClass");
+    }
+
+    #[test]
+    fn qualify_name() {
+        let class = ClassInfo::make(GOOD_PATH.to_string()).unwrap();
+        let qual = class.qualify();
+        assert_eq!(qual, "MyPackage.SomeClass")
+    }
+
+    #[test]
+    fn log_warnings_are_stderr() {
+        let mut adapter = make_test_adapter();
+        let mut category = |line: &str| match adapter.process_event(UnrealEvent::Log(line.into())) {
+            Some(Event {
+                body: EventBody::Output(body),
+            }) => body.category,
+            other => panic!("Expected an output event but got {other:?}"),
+        };
+        assert!(matches!(
+            category("ScriptWarning: Accessed None 'Pawn'\r\n"),
+            OutputEventCategory::Stderr
+        ));
+        assert!(matches!(
+            category("ScriptLog: Health: 0\r\n"),
+            OutputEventCategory::Stdout
+        ));
+    }
+
+    #[test]
+    fn breakpoint_and_stack_share_class_entry() {
+        let mut adapter = make_test_adapter();
+        let args = SetBreakpointsArguments {
+            source: Source {
+                name: None,
+                path: Some(GOOD_PATH.to_string()),
+                source_reference: None,
+            },
+            breakpoints: Some(vec![SourceBreakpoint::new(10)]),
+        };
+        adapter.set_breakpoints(&args).unwrap();
+
+        // Unreal names classes in upper case, but they should find the entry added for the
+        // breakpoint regardless.
+        for name in ["MYPACKAGE.SOMECLASS", "MyPackage.SomeClass"] {
+            let source = adapter.translate_source(name).unwrap();
+            assert_eq!(source.path.as_deref(), Some(GOOD_PATH));
+        }
+        assert_eq!(adapter.class_map.len(), 1);
+    }
+
+    #[test]
+    fn add_breakpoint_registers_class() {
+        let mut adapter = make_test_adapter();
+        let args = SetBreakpointsArguments {
+            source: Source {
+                name: None,
+                path: Some(GOOD_PATH.to_string()),
+                source_reference: None,
+            },
+            breakpoints: Some(vec![SourceBreakpoint::new(10)]),
+        };
+        let _response = adapter.set_breakpoints(&args).unwrap();
+        // Class cache should be keyed on UPCASED qualified names.
+        assert!(adapter.class_map.contains_key("MYPACKAGE.SOMECLASS"));
+
+        // The entry in this map should have 1 breakpoint
+        assert_eq!(
+            adapter.class_map["MYPACKAGE.SOMECLASS"].breakpoints,
+            vec![10]
+        );
+    }
+
+    #[test]
+    fn add_multiple_breakpoints() {
+        let mut adapter = make_test_adapter();
+        let args = SetBreakpointsArguments {
+            source: Source {
+                name: None,
+                path: Some(GOOD_PATH.to_string()),
+                source_reference: None,
+            },
+            breakpoints: Some(vec![SourceBreakpoint::new(10), SourceBreakpoint::new(105)]),
+        };
+        let _response = adapter.set_breakpoints(&args).unwrap();
+        // The entry in this map should have 2 breakpoints
+        assert_eq!(
+            adapter.class_map["MYPACKAGE.SOMECLASS"].breakpoints,
+            vec![10, 105]
+        );
+    }
+
+    #[test]
+    fn breakpoint_on_continuation_line() {
+        let mut adapter = make_test_adapter();
+        let args = SetBreakpointsArguments {
+            source: Source {
+                name: None,
+                path: Some(
+                    concat!(
+                        env!("CARGO_MANIFEST_DIR"),
+                        "/testdata/MyPackage/Classes/Multiline.uc"
+                    )
+                    .to_string(),
+                ),
+                source_reference: None,
+            },
+            breakpoints: Some(vec![SourceBreakpoint::new(9)]),
+        };
+        match adapter.set_breakpoints(&args).unwrap() {
+            ResponseBody::SetBreakpoints(body) => assert_eq!(body.breakpoints[0].line, 8),
+            _ => panic!("Expected a set breakpoints response"),
+        }
+        assert_eq!(
+            adapter.class_map["MYPACKAGE.MULTILINE"].breakpoints,
+            vec![8]
+        );
+    }
+
+    #[test]
+    fn breakpoints_on_one_statement() {
+        let log = CommandLog::default();
+        let (mut adapter, _) = make_adapter(
+            make_client,
+            ClientConfig::new(),
+            listing_mock(vec![]).recording(&log),
+        );
+        let mut set = |lines: &[i64]| {
+            let args = SetBreakpointsArguments {
+                source: Source {
+                    name: None,
+                    path: Some(
+                        concat!(
+                            env!("CARGO_MANIFEST_DIR"),
+                            "/testdata/MyPackage/Classes/Multiline.uc"
+                        )
+                        .to_string(),
+                    ),
+                    source_reference: None,
+                },
+                breakpoints: Some(
+                    lines
+                        .iter()
+                        .map(|&line| SourceBreakpoint::new(line))
+                        .collect(),
+                ),
+            };
+            match adapter.set_breakpoints(&args).unwrap() {
+                ResponseBody::SetBreakpoints(body) => body
+                    .breakpoints
+                    .iter()
+                    .map(|bp| bp.line)
+                    .collect::<Vec<_>>(),
+                _ => panic!("Expected a set breakpoints response"),
+            }
+        };
+
+        // Both lines are on the statement starting at line 8, which is only set once.
+        assert_eq!(set(&[9, 10]), vec![8, 8]);
+        assert_eq!(set(&[8, 9]), vec![8, 8]);
+        assert_eq!(set(&[]), Vec::<i64>::new());
+        assert_eq!(log.entries(), vec!["add 8", "remove 8"]);
+    }
+
+    #[test]
+    fn breakpoint_locations() {
+        let mut adapter = make_test_adapter();
+        let args = BreakpointLocationsArguments {
+            source: Source {
+                name: None,
+                path: Some(
+                    concat!(
+                        env!("CARGO_MANIFEST_DIR"),
+                        "/testdata/MyPackage/Classes/Multiline.uc"
+                    )
+                    .to_string(),
+                ),
+                source_reference: None,
+            },
+            line: 7,
+            end_line: Some(13),
+        };
+        for _ in 0..2 {
+            match adapter.breakpoint_locations(&args).unwrap() {
+                ResponseBody::BreakpointLocations(body) => {
+                    let lines: Vec<i64> = body.breakpoints.iter().map(|bp| bp.line).collect();
+                    assert_eq!(lines, vec![8, 12, 13]);
+                }
+                _ => panic!("Expected a breakpoint locations response"),
+            }
+        }
+        assert!(adapter.class_map["MYPACKAGE.MULTILINE"]
+            .breakpoint_lines
+            .is_some());
+    }
+
+    #[test]
+    fn reset_breakpoints() {
+        let mut adapter = make_test_adapter();
+        let mut args = SetBreakpointsArguments {
+            source: Source {
+                name: None,
+                path: Some(GOOD_PATH.to_string()),
+                source_reference: None,
+            },
+            breakpoints: Some(vec![SourceBreakpoint::new(10), SourceBreakpoint::new(105)]),
+        };
+        adapter.set_breakpoints(&args).unwrap();
+
+        // Set breakpoints in this class again.
+        args = SetBreakpointsArguments {
+            source: Source {
+                name: None,
+                path: Some(GOOD_PATH.to_string()),
+                source_reference: None,
+            },
+            breakpoints: Some(vec![SourceBreakpoint::new(26)]),
+        };
+        // this should delete the two existing breakpoints and replace them
+        // with the new one.
+        adapter.set_breakpoints(&args).unwrap();
+        assert_eq!(
+            adapter.class_map["MYPACKAGE.SOMECLASS"].breakpoints,
+            vec![26]
+        );
+    }
+
+    // A mock connection with a fixed call stack. Only the top-most frame has line information
+    // in the stack trace, the others must be resolved with frame_line.
+    fn stack_mock(frames: Vec<Frame>, lines: Vec<i32>) -> MockConnection {
+        MockConnection::new(move |command| match command {
+            UnrealCommand::StackTrace(req) => {
+                Some(Ok(UnrealResponse::StackTrace(common::StackTraceResponse {
+                    frames: frames
+                        .iter()
+                        .skip(req.start_frame as usize)
+                        .cloned()
+                        .collect(),
+                    total_frames: Some(frames.len() as u32),
+                })))
+            }
+            UnrealCommand::FrameLine(frame) => {
+                let idx: usize = (*frame).into();
+                Some(Ok(UnrealResponse::FrameLine(
+                    frames[idx].qualified_name.clone(),
+                    lines[idx],
+                )))
+            }
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn stack_trace_resolves_frame_lines() {
+        let make_frame = |function: &str, line| Frame {
+            qualified_name: "MyPackage.SomeClass".to_string(),
+            function_name: function.to_string(),
+            line,
+            state_name: None,
+        };
+        let log = CommandLog::default();
+        let (mut adapter, _) = make_adapter(
+            make_client,
+            ClientConfig::new(),
+            stack_mock(
+                vec![
+                    make_frame("Top", 10),
+                    make_frame("Middle", 0),
+                    make_frame("Bottom", 0),
+                ],
+                vec![10, 25, 42],
+            )
+            .recording(&log),
+        );
+        adapter.supports_frame_line = true;
+        fn lines(adapter: &mut UnrealscriptAdapter<impl Client>, levels: Option<i64>) -> Vec<i64> {
+            match adapter.stack_trace(&StackTraceArguments {
+                thread_id: UNREAL_THREAD_ID,
+                start_frame: None,
+                levels,
+            }) {
+                Ok(ResponseBody::StackTrace(body)) => {
+                    body.stack_frames.iter().map(|f| f.line).collect()
+                }
+                other => panic!("Expected a stack trace response but got {other:?}"),
+            }
+        }
+
+        // Only the frames asked for are resolved, and each only once.
+        assert_eq!(lines(&mut adapter, Some(2)), vec![10, 25]);
+        assert_eq!(lines(&mut adapter, None), vec![10, 25, 42]);
+        assert_eq!(log.of(&["line"]), vec!["line 1", "line 2"]);
+
+        // They're resolved again after the next stop.
+        adapter.process_event(UnrealEvent::Stopped);
+        assert_eq!(lines(&mut adapter, None), vec![10, 25, 42]);
+        assert_eq!(
+            log.of(&["line"]),
+            vec!["line 1", "line 2", "line 1", "line 2"]
+        );
+
+        // An older interface can't resolve them, so they're left for the client to fetch again
+        // when the frame's variables invalidate it.
+        adapter.supports_frame_line = false;
+        adapter.process_event(UnrealEvent::Stopped);
+        assert_eq!(lines(&mut adapter, None), vec![10, 0, 0]);
+        assert_eq!(
+            log.of(&["line"]),
+            vec!["line 1", "line 2", "line 1", "line 2"]
+        );
+    }
+
+    #[test]
+    fn stack_trace_missing_source() {
+        let make_frame = |class: &str| Frame {
+            qualified_name: class.to_string(),
+            function_name: "Func".to_string(),
+            line: 10,
+            state_name: None,
+        };
+        let (mut adapter, _) = make_adapter(
+            make_client,
+            ClientConfig::new(),
+            stack_mock(
+                vec![
+                    make_frame("MyPackage.Missing"),
+                    make_frame("MyPackage.Other"),
+                    make_frame("MYPACKAGE.MISSING"),
+                ],
+                vec![10, 10, 10],
+            ),
+        );
+        let response = adapter
+            .stack_trace(&StackTraceArguments {
+                thread_id: UNREAL_THREAD_ID,
+                start_frame: None,
+                levels: None,
+            })
+            .unwrap();
+        match response {
+            ResponseBody::StackTrace(body) => {
+                let sources: Vec<_> = body
+                    .stack_frames
+                    .iter()
+                    .map(|f| f.source.as_ref().unwrap())
+                    .map(|s| (s.name.as_deref(), s.path.as_deref(), s.source_reference))
+                    .collect();
+                // Each frame is named after its class, with no reference the client could
+                // follow to content we don't have.
+                assert_eq!(
+                    sources,
+                    vec![
+                        (Some("MyPackage.Missing"), None, None),
+                        (Some("MyPackage.Other"), None, None),
+                        (Some("MYPACKAGE.MISSING"), None, None),
+                    ]
+                );
+            }
+            _ => panic!("Expected a stack trace response"),
+        }
+    }
+
+    fn frame_names(show_frame_states: bool) -> Vec<String> {
+        let make_frame = |state: Option<&str>| Frame {
+            qualified_name: "MyPackage.SomeClass".to_string(),
+            function_name: "Tick".to_string(),
+            line: 10,
+            state_name: state.map(str::to_string),
+        };
+        let mut config = ClientConfig::new();
+        config.show_frame_states = show_frame_states;
+        let (mut adapter, _) = make_adapter(
+            make_client,
+            config,
+            stack_mock(
+                vec![make_frame(Some("Idle")), make_frame(None)],
+                vec![10, 10],
+            ),
+        );
+        match adapter.stack_trace(&StackTraceArguments {
+            thread_id: UNREAL_THREAD_ID,
+            start_frame: None,
+            levels: None,
+        }) {
+            Ok(ResponseBody::StackTrace(body)) => {
+                body.stack_frames.into_iter().map(|f| f.name).collect()
+            }
+            other => panic!("Expected a stack trace response but got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn stack_frame_states() {
+        assert_eq!(frame_names(true), vec!["Idle.Tick", "Tick"]);
+        assert_eq!(frame_names(false), vec!["Tick", "Tick"]);
+    }
+
+    fn stack_frame_column(one_based_columns: bool) -> i64 {
+        let mut config = ClientConfig::new();
+        config.one_based_columns = one_based_columns;
+        let (mut adapter, _) = make_adapter(
+            make_client,
+            config,
+            stack_mock(
+                vec![Frame {
+                    qualified_name: "MyPackage.SomeClass".to_string(),
+                    function_name: "Top".to_string(),
+                    line: 10,
+                    state_name: None,
+                }],
+                vec![10],
+            ),
+        );
+        UnrealscriptAdapter::<ClientImpl<Stdout>>::class_entry(
+            &mut adapter.class_map,
+            ClassInfo::make(GOOD_PATH.to_string()).unwrap(),
+        );
+        match adapter.stack_trace(&StackTraceArguments {
+            thread_id: UNREAL_THREAD_ID,
+            start_frame: None,
+            levels: None,
+        }) {
+            Ok(ResponseBody::StackTrace(body)) => {
+                assert!(body.stack_frames[0].source.is_some());
+                body.stack_frames[0].column
+            }
+            other => panic!("Expected a stack trace response but got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn stack_frame_columns() {
+        assert_eq!(stack_frame_column(true), 1);
+        assert_eq!(stack_frame_column(false), 0);
+    }
+
+    fn frame_columns(
+        adapter: &mut UnrealscriptAdapter<ClientImpl<Stdout>>,
+        start_frame: Option<i64>,
+    ) -> Vec<i64> {
+        match adapter.stack_trace(&StackTraceArguments {
+            thread_id: UNREAL_THREAD_ID,
+            start_frame,
+            levels: None,
+        }) {
+            Ok(ResponseBody::StackTrace(body)) => {
+                body.stack_frames.into_iter().map(|f| f.column).collect()
+            }
+            other => panic!("Expected a stack trace response but got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn stack_frame_call_columns() {
+        let make_frame = |function: &str, line| Frame {
+            qualified_name: "MyPackage.Multiline".to_string(),
+            function_name: function.to_string(),
+            line,
+            state_name: None,
+        };
+        let mut config = ClientConfig::new();
+        config.source_roots = vec![concat!(env!("CARGO_MANIFEST_DIR"), "/testdata").to_string()];
+        config.scan_source_roots = true;
+        let (mut adapter, _) = make_adapter(
+            make_client,
+            config,
+            stack_mock(
+                vec![
+                    make_frame("Bar", 20),
+                    make_frame("Foo", 8),
+                    make_frame("Foo", 15),
+                ],
+                vec![20, 8, 15],
+            ),
+        );
+        // The top frame isn't making a call, and the last frame's line doesn't call Foo.
+        assert_eq!(frame_columns(&mut adapter, None), vec![1, 11, 1]);
+        // The callee of the first frame of a later page isn't known.
+        assert_eq!(frame_columns(&mut adapter, Some(1)), vec![1, 1]);
+
+        // The source was read once and kept with the class.
+        let class_info = &adapter.class_map["MYPACKAGE.MULTILINE"];
+        assert!(class_info
+            .source_lines
+            .as_ref()
+            .is_some_and(|(_, lines)| lines[7].contains("Bar(")));
+    }
+
+    // Request a page of a three frame stack, returning the frame ids and total frame count.
+    fn stack_page(start_frame: i64) -> (Vec<i64>, Option<i64>) {
+        let make_frame = |function: &str| Frame {
+            qualified_name: String::new(),
+            function_name: function.to_string(),
+            line: 1,
+            state_name: None,
+        };
+        let (mut adapter, _) = make_adapter(
+            make_client,
+            ClientConfig::new(),
+            stack_mock(
+                vec![
+                    make_frame("Top"),
+                    make_frame("Middle"),
+                    make_frame("Bottom"),
+                ],
+                vec![1, 1, 1],
+            ),
+        );
+        match adapter.stack_trace(&StackTraceArguments {
+            thread_id: UNREAL_THREAD_ID,
+            start_frame: Some(start_frame),
+            levels: Some(20),
+        }) {
+            Ok(ResponseBody::StackTrace(body)) => (
+                body.stack_frames.iter().map(|f| f.id).collect(),
+                body.total_frames,
+            ),
+            other => panic!("Expected a stack trace response but got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn stack_page_within_depth() {
+        assert_eq!(stack_page(1), (vec![1, 2], Some(3)));
+    }
+
+    #[test]
+    fn stack_page_at_depth() {
+        assert_eq!(stack_page(3), (vec![], Some(3)));
+    }
+
+    #[test]
+    fn stack_page_beyond_depth() {
+        assert_eq!(stack_page(20), (vec![], Some(3)));
+    }
+
+    // A mock connection that always sends a three frame stack whatever was asked for.
+    fn levels_mock() -> MockConnection {
+        MockConnection::new(|command| match command {
+            UnrealCommand::StackTrace(_) => {
+                Some(Ok(UnrealResponse::StackTrace(common::StackTraceResponse {
+                    frames: (1..=3)
+                        .map(|line| Frame {
+                            qualified_name: String::new(),
+                            function_name: "Foo".to_string(),
+                            line,
+                            state_name: None,
+                        })
+                        .collect(),
+                    total_frames: None,
+                })))
+            }
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn stack_trace_levels() {
+        let log = CommandLog::default();
+        let (mut adapter, _) = make_adapter(
+            make_client,
+            ClientConfig::new(),
+            levels_mock().recording(&log),
+        );
+        let mut frame_count = |levels| match adapter.stack_trace(&StackTraceArguments {
+            thread_id: UNREAL_THREAD_ID,
+            start_frame: None,
+            levels,
+        }) {
+            Ok(ResponseBody::StackTrace(body)) => body.stack_frames.len(),
+            other => panic!("Expected a stack trace response but got {other:?}"),
+        };
+
+        // No count or a count of 0 asks for the whole stack. A positive count limits it even
+        // if the interface sends more.
+        assert_eq!(frame_count(None), 3);
+        assert_eq!(frame_count(Some(0)), 3);
+        assert_eq!(frame_count(Some(2)), 2);
+        assert_eq!(log.entries(), vec!["stack 0 0", "stack 0 0", "stack 0 2"]);
+    }
+
+    #[test]
+    fn total_frames_without_interface_depth() {
+        // This interface doesn't report the stack depth, but the whole stack was requested.
+        let (mut adapter, _) = make_adapter(make_client, ClientConfig::new(), scope_mock());
+        match adapter.stack_trace(&StackTraceArguments {
+            thread_id: UNREAL_THREAD_ID,
+            start_frame: None,
+            levels: None,
+        }) {
+            Ok(ResponseBody::StackTrace(body)) => assert_eq!(body.total_frames, Some(2)),
+            other => panic!("Expected a stack trace response but got {other:?}"),
+        }
+    }
+
+    // A mock connection that evaluates every expression to a variable whose value is the
+    // expression itself.
+    fn eval_mock() -> MockConnection {
+        MockConnection::new(|command| match command {
+            UnrealCommand::Evaluate(_, expr) => {
+                Some(Ok(UnrealResponse::Variables(vec![Variable {
+                    name: expr.clone(),
+                    ty: "int".to_string(),
+                    value: expr.clone(),
+                    index: VariableIndex::create(1).unwrap(),
+                    has_children: false,
+                    is_array: false,
+                }])))
+            }
+            _ => None,
+        })
+    }
+
+    fn disconnect_commands(pause_on_disconnect: bool, terminate: Option<bool>) -> Vec<String> {
+        let log = CommandLog::default();
+        let mut config = ClientConfig::new();
+        config.pause_on_disconnect = pause_on_disconnect;
+        let (mut adapter, _) = make_adapter(
+            make_client,
+            config,
+            MockConnection::default().recording(&log),
+        );
+        adapter
+            .disconnect(&DisconnectArguments {
+                restart: None,
+                terminate_debuggee: terminate,
+            })
+            .unwrap();
+        log.entries()
+    }
+
+    #[test]
+    fn pause_on_disconnect() {
+        assert_eq!(disconnect_commands(true, None), vec!["pause"]);
+        assert_eq!(disconnect_commands(false, None), vec!["disconnect"]);
+        assert_eq!(disconnect_commands(true, Some(true)), vec!["disconnect"]);
+    }
+
+    // A mock connection where fetching variables always changes frames, and every expression
+    // evaluates to 1.
+    fn frame_mock() -> MockConnection {
+        MockConnection::new(|command| match command {
+            UnrealCommand::Variables(..) => Some(Ok(UnrealResponse::DeferredVariables(vec![]))),
+            UnrealCommand::Evaluate(_, expr) => {
+                Some(Ok(UnrealResponse::Variables(vec![Variable {
+                    name: expr.clone(),
+                    ty: "int".to_string(),
+                    value: "1".to_string(),
+                    index: VariableIndex::create(1).unwrap(),
+                    has_children: false,
+                    is_array: false,
+                }])))
+            }
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn repl_frame_command() {
+        let log = CommandLog::default();
+        let (mut adapter, _) = make_adapter(
+            make_client,
+            ClientConfig::new(),
+            frame_mock().recording(&log),
+        );
+        let mut repl = |expression: &str, frame_id| match adapter.evaluate(&EvaluateArguments {
+            expression: expression.to_string(),
+            frame_id,
+            context: Some(EvaluateContext::Repl),
+        }) {
+            Ok(ResponseBody::Evaluate(body)) => body.result,
+            other => panic!("Expected an evaluate response but got {other:?}"),
+        };
+
+        assert_eq!(repl(".frame 2", None), "Selected frame 2");
+        assert_eq!(repl(".frame", None), "Frame 2");
+        // The selected frame is used even if the client sends a different one.
+        repl("Foo", Some(0));
+        assert_eq!(
+            log.entries(),
+            vec!["variables Local 2 0 0 1", "evaluate 2 Foo"]
+        );
+
+        // Execution stopping selects the top frame again.
+        log.clear();
+        adapter.process_event(UnrealEvent::Stopped);
+        adapter
+            .evaluate(&EvaluateArguments {
+                expression: "Bar".to_string(),
+                frame_id: None,
+                context: Some(EvaluateContext::Repl),
+            })
+            .unwrap();
+        assert_eq!(log.entries(), vec!["evaluate 0 Bar"]);
+    }
+
+    fn tree_variable(name: &str, value: &str, index: u32, has_children: bool) -> Variable {
+        Variable {
+            name: name.to_string(),
+            ty: "Object".to_string(),
+            value: value.to_string(),
+            index: VariableIndex::create(index).unwrap(),
+            has_children,
+            is_array: false,
+        }
+    }
+
+    // A mock connection with a local object 'Pawn' holding an int 'Health' and an object
+    // 'Weapon'.
+    fn tree_mock() -> MockConnection {
+        MockConnection::new(|command| match command {
+            UnrealCommand::Variables(kind, _, variable, _, _) => {
+                let vars = match (kind, u32::from(*variable)) {
+                    (WatchKind::Local, 0) => vec![tree_variable("Pawn", "MyPawn", 1, true)],
+                    (WatchKind::User, 1) => vec![
+                        tree_variable("Health", "100", 2, false),
+                        tree_variable("Weapon", "MyWeapon", 3, true),
+                    ],
+                    (WatchKind::User, 3) => vec![],
+                    other => panic!("Unexpected variables request {other:?}"),
+                };
+                Some(Ok(UnrealResponse::Variables(vars)))
+            }
+            UnrealCommand::Evaluate(_, expr) => {
+                let var = match expr.as_str() {
+                    "Pawn" => tree_variable(expr, "MyPawn", 1, true),
+                    "Pawn.Health" => tree_variable(expr, "100", 2, false),
+                    "Pawn.Weapon" => tree_variable(expr, "MyWeapon", 3, true),
+                    _ => tree_variable(expr, "Unknown", 4, false),
+                };
+                Some(Ok(UnrealResponse::Variables(vec![var])))
+            }
+            UnrealCommand::WatchCount(_, _) => Some(Ok(UnrealResponse::WatchCount(2))),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn repl_navigation() {
+        let log = CommandLog::default();
+        let (mut adapter, _) = make_adapter(
+            make_client,
+            ClientConfig::new(),
+            tree_mock().recording(&log),
+        );
+        let mut repl = |expression: &str| match adapter.evaluate(&EvaluateArguments {
+            expression: expression.to_string(),
+            frame_id: None,
+            context: Some(EvaluateContext::Repl),
+        }) {
+            Ok(ResponseBody::Evaluate(body)) => body.result,
+            other => panic!("Expected an evaluate response but got {other:?}"),
+        };
+
+        assert_eq!(repl(".ls"), "Pawn/ = MyPawn");
+        assert_eq!(repl(".cd Pawn"), "Pawn");
+        assert_eq!(repl(".ls"), "Health = 100\nWeapon/ = MyWeapon");
+
+        // Names are relative to the current object, but keywords aren't.
+        assert_eq!(repl("Health"), "100");
+        repl("self.Health");
+        assert_eq!(
+            log.of(&["evaluate"]).last().unwrap(),
+            "evaluate 0 self.Health"
+        );
+
+        // Only objects can be navigated into.
+        assert_eq!(repl(".cd Health"), "Pawn.Health is not an object");
+        assert_eq!(repl(".cd Weapon"), "Pawn.Weapon");
+        assert_eq!(repl(".ls"), "No children");
+        assert_eq!(repl(".cd .."), "Pawn");
+        assert_eq!(repl(".cd Weapon/../.."), "At the top level");
+        assert_eq!(repl("Pawn"), "MyPawn");
+
+        // Literals and keywords are echoed back without asking Unreal, wherever the REPL is.
+        repl(".cd Pawn");
+        let evaluated = log.entries().len();
+        assert_eq!(repl("3.5"), "3.5");
+        assert_eq!(repl("'Hello'"), "'Hello'");
+        assert_eq!(repl("true"), "true");
+        assert_eq!(log.entries().len(), evaluated);
+    }
+
+    #[test]
+    fn repl_navigation_depth() {
+        let log = CommandLog::default();
+        let (mut adapter, _) = make_adapter(
+            make_client,
+            ClientConfig::new(),
+            tree_mock().recording(&log),
+        );
+        let path = vec!["Outer"; MAX_REPL_DEPTH + 1].join("/");
+        assert!(matches!(
+            adapter.change_repl_path(Some(&path)),
+            Err(UnrealscriptAdapterError::LimitExceeded(_))
+        ));
+        assert!(adapter.repl_path.is_empty());
+        assert!(log.entries().is_empty());
+    }
+
+    #[test]
+    fn repl_navigation_resets_on_stop() {
+        let (mut adapter, _) = make_adapter(make_client, ClientConfig::new(), tree_mock());
+        assert_eq!(adapter.change_repl_path(Some("Pawn")).unwrap(), "Pawn");
+        adapter.process_event(UnrealEvent::Stopped);
+        assert!(adapter.repl_path.is_empty());
+    }
+
+    // A mock connection that evaluates every expression to two variables.
+    fn multi_eval_mock() -> MockConnection {
+        MockConnection::new(|command| match command {
+            UnrealCommand::Evaluate(_, expr) => Some(Ok(UnrealResponse::Variables(
+                (1..=2)
+                    .map(|i| Variable {
+                        name: format!("{expr}{i}"),
+                        ty: "int".to_string(),
+                        value: i.to_string(),
+                        index: VariableIndex::create(i).unwrap(),
+                        has_children: false,
+                        is_array: false,
+                    })
+                    .collect(),
+            ))),
+            _ => None,
+        })
+    }
+
+    fn watch(adapter: &mut UnrealscriptAdapter<ClientImpl<Stdout>>, expression: &str) -> String {
+        match adapter.evaluate(&EvaluateArguments {
+            expression: expression.to_string(),
+            frame_id: None,
+            context: Some(EvaluateContext::Watch),
+        }) {
+            Ok(ResponseBody::Evaluate(body)) => body.result,
+            other => panic!("Expected an evaluate response but got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn evaluate_none() {
+        let (mut adapter, _) = make_adapter(make_client, ClientConfig::new(), eval_mock());
+        assert_eq!(watch(&mut adapter, "none"), "None");
+        assert_eq!(watch(&mut adapter, " NONE "), "None");
+        // Other keywords are still echoed back.
+        assert_eq!(watch(&mut adapter, "true"), "true");
+    }
+
+    #[test]
+    fn evaluate_multiple_results() {
+        let (mut adapter, _) = make_adapter(make_client, ClientConfig::new(), multi_eval_mock());
+        let reference = match adapter.evaluate(&EvaluateArguments {
+            expression: "Foo".to_string(),
+            frame_id: None,
+            context: Some(EvaluateContext::Watch),
+        }) {
+            Ok(ResponseBody::Evaluate(body)) => {
+                assert_eq!(body.result, "2 results");
+                body.variable_info.variables_reference
+            }
+            other => panic!("Expected an evaluate response but got {other:?}"),
+        };
+
+        match adapter.variables(&VariablesArguments {
+            variables_reference: reference,
+            start: None,
+            count: None,
+        }) {
+            Ok(ResponseBody::Variables(body)) => {
+                let values: Vec<(&str, &str)> = body
+                    .variables
+                    .iter()
+                    .map(|v| (v.name.as_str(), v.value.as_str()))
+                    .collect();
+                assert_eq!(values, vec![("Foo1", "1"), ("Foo2", "2")]);
+            }
+            other => panic!("Expected a variables response but got {other:?}"),
+        }
+    }
+
+    // A mock connection that evaluates `Pos` to a struct with two children and anything else
+    // to an int.
+    fn struct_eval_mock() -> MockConnection {
+        MockConnection::new(|command| match command {
+            UnrealCommand::Evaluate(_, expr) => {
+                let structured = expr == "Pos";
+                Some(Ok(UnrealResponse::Variables(vec![Variable {
+                    name: expr.clone(),
+                    ty: if structured { "Vector" } else { "Int" }.to_string(),
+                    value: if structured { "" } else { "3" }.to_string(),
+                    index: VariableIndex::create(if structured { 1 } else { 4 }).unwrap(),
+                    has_children: structured,
+                    is_array: false,
+                }])))
+            }
+            UnrealCommand::Variables(..) => Some(Ok(UnrealResponse::Variables(
+                ["X", "Y"]
+                    .iter()
+                    .zip(2..)
+                    .map(|(name, i)| Variable {
+                        name: name.to_string(),
+                        ty: "Float".to_string(),
+                        value: format!("{i}.0"),
+                        index: VariableIndex::create(i).unwrap(),
+                        has_children: false,
+                        is_array: false,
+                    })
+                    .collect(),
+            ))),
+            UnrealCommand::WatchCount(_, _) => Some(Ok(UnrealResponse::WatchCount(2))),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn evaluate_structured_value() {
+        let log = CommandLog::default();
+        let (mut adapter, _) = make_adapter(
+            make_client,
+            ClientConfig::new(),
+            struct_eval_mock().recording(&log),
+        );
+        let mut evaluate = |expression: &str| match adapter.evaluate(&EvaluateArguments {
+            expression: expression.to_string(),
+            frame_id: Some(0),
+            context: Some(EvaluateContext::Watch),
+        }) {
+            Ok(ResponseBody::Evaluate(body)) => body.variable_info,
+            other => panic!("Expected an evaluate response but got {other:?}"),
+        };
+
+        // A leaf value can't be expanded.
+        assert_eq!(evaluate("Count").variables_reference, 0);
+
+        let info = evaluate("Pos");
+        assert_ne!(info.variables_reference, 0);
+        assert_eq!(info.named_variables, Some(2));
+        match adapter.variables(&VariablesArguments {
+            variables_reference: info.variables_reference,
+            start: None,
+            count: None,
+        }) {
+            Ok(ResponseBody::Variables(body)) => {
+                let values: Vec<(&str, &str)> = body
+                    .variables
+                    .iter()
+                    .map(|v| (v.name.as_str(), v.value.as_str()))
+                    .collect();
+                assert_eq!(values, vec![("X", "2.0"), ("Y", "3.0")]);
+            }
+            other => panic!("Expected a variables response but got {other:?}"),
+        }
+
+        // The children are fetched as user watches in the frame the expression was evaluated in.
+        assert_eq!(log.of(&["variables"]), vec!["variables User 0 1 0 0"]);
+    }
+
+    // A mock connection whose evaluator can't see anything, with a loop variable among the
+    // locals.
+    fn loop_var_mock() -> MockConnection {
+        MockConnection::new(|command| match command {
+            UnrealCommand::Evaluate(_, _) => Some(Ok(UnrealResponse::Variables(vec![]))),
+            UnrealCommand::Variables(kind, _, variable, _, _) => {
+                assert!(matches!(kind, WatchKind::Local));
+                assert_eq!(*variable, VariableIndex::SCOPE);
+                Some(Ok(UnrealResponse::Variables(vec![Variable {
+                    name: "I".to_string(),
+                    ty: "Int".to_string(),
+                    value: "3".to_string(),
+                    index: VariableIndex::create(2).unwrap(),
+                    has_children: false,
+                    is_array: false,
+                }])))
+            }
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn evaluate_loop_variable() {
+        let (mut adapter, _) = make_adapter(make_client, ClientConfig::new(), loop_var_mock());
+        match adapter.evaluate(&EvaluateArguments {
+            expression: "i".to_string(),
+            frame_id: None,
+            context: Some(EvaluateContext::Watch),
+        }) {
+            Ok(ResponseBody::Evaluate(body)) => {
+                assert_eq!(body.result, "3");
+                assert_eq!(body.ty.as_deref(), Some("Int"));
+                // An int has nothing to expand.
+                assert_eq!(body.variable_info.variables_reference, 0);
+            }
+            other => panic!("Expected an evaluate response but got {other:?}"),
+        }
+
+        // Other expressions still fail when they evaluate to nothing.
+        assert!(matches!(
+            adapter.evaluate(&EvaluateArguments {
+                expression: "J + 1".to_string(),
+                frame_id: None,
+                context: Some(EvaluateContext::Watch),
+            }),
+            Err(UnrealscriptAdapterError::WatchError(_))
+        ));
+    }
+
+    fn hover(expression: &str) -> Result<ResponseBody, UnrealscriptAdapterError> {
+        let (mut adapter, _) = make_adapter(make_client, ClientConfig::new(), eval_mock());
+        adapter.evaluate(&EvaluateArguments {
+            expression: expression.to_string(),
+            frame_id: None,
+            context: Some(EvaluateContext::Hover),
+        })
+    }
+
+    fn hover_result(expression: &str) -> String {
+        match hover(expression) {
+            Ok(ResponseBody::Evaluate(body)) => body.result,
+            other => panic!("Expected an evaluate response but got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn hover_trailing_dot() {
+        assert_eq!(hover_result("Foo."), "Foo");
+    }
+
+    #[test]
+    fn hover_parens() {
+        assert_eq!(hover_result("(Foo)"), "Foo");
+    }
+
+    #[test]
+    fn hover_array_element() {
+        assert_eq!(hover_result("Foo[1];"), "Foo[1]");
+    }
+
+    #[test]
+    fn numbers_not_evaluated() {
+        assert!(is_invalid_expression("3.14"));
+        assert!(is_invalid_expression("3,14"));
+    }
+
+    #[test]
+    fn comma_decimal_value() {
+        assert_eq!(display_value("3,14"), "3.14");
+        assert_eq!(display_value("-0,500000"), "-0.500000");
+    }
+
+    #[test]
+    fn period_decimal_value() {
+        assert_eq!(display_value("3.14"), "3.14");
+        assert_eq!(display_value("-0.500000"), "-0.500000");
+    }
+
+    #[test]
+    fn non_decimal_values_unchanged() {
+        for value in ["'a,b'", "(X=1,00,Y=2,00,Z=3,00)", "1,", ",5", "None"] {
+            assert_eq!(display_value(value), value);
+        }
+    }
+
+    #[test]
+    fn none_values() {
+        for value in ["None", "none", " NONE "] {
+            assert_eq!(display_value(value), "None");
+        }
+        assert_eq!(display_value("'none'"), "'none'");
+    }
+
+    #[test]
+    fn none_comparisons() {
+        assert_eq!(none_comparison("Target != none"), Some(("Target", true)));
+        assert_eq!(none_comparison("None == Target"), Some(("Target", false)));
+        assert_eq!(none_comparison("Count == 3"), None);
+        assert_eq!(none_comparison("Target"), None);
+    }
+
+    #[test]
+    fn falsey_values() {
+        for value in ["False", "false", "0", "0.00", "-0,000000", "None", ""] {
+            assert!(is_falsey(value), "{value}");
+        }
+        for value in ["True", "1", "0,5", "'0'", "MyPackage.MyClass"] {
+            assert!(!is_falsey(value), "{value}");
+        }
+    }
+
+    #[test]
+    fn assigned_values() {
+        assert!(assigned_value_matches("5", "5"));
+        assert!(assigned_value_matches("1.5", "1,500000"));
+        assert!(assigned_value_matches("true", "True"));
+        assert!(assigned_value_matches("\"Hi\"", "Hi"));
+        assert!(!assigned_value_matches("5", "4"));
+        assert!(!assigned_value_matches("Foo", "None"));
+    }
+
+    // A mock connection where Unreal accepts any assignment to a variable starting with
+    // 'Count' and ignores the rest. 'Items' evaluates to more than one value. The only local is
+    // 'Counts', an array of two elements.
+    fn set_mock() -> MockConnection {
+        let mut values = BTreeMap::new();
+        MockConnection::new(move |command| match command {
+            UnrealCommand::SetVariable(expr, value) => {
+                if expr.contains("Count") {
+                    values.insert(expr.clone(), format!("{value}.000000"));
+                }
+                Some(Ok(UnrealResponse::WatchesRefreshed))
+            }
+            UnrealCommand::Evaluate(_, expr) => {
+                let value = values.get(expr).map_or("0.000000", String::as_str);
+                let var = Variable {
+                    name: expr.clone(),
+                    ty: "Float".to_string(),
+                    value: value.to_string(),
+                    index: VariableIndex::create(1).unwrap(),
+                    has_children: false,
+                    is_array: false,
+                };
+                let vars = if expr == "Items" {
+                    vec![var.clone(), var]
+                } else {
+                    vec![var]
+                };
+                Some(Ok(UnrealResponse::Variables(vars)))
+            }
+            UnrealCommand::Variables(..) => Some(Ok(UnrealResponse::Variables(vec![Variable {
+                name: "Counts".to_string(),
+                ty: "Array".to_string(),
+                value: "".to_string(),
+                index: VariableIndex::create(1).unwrap(),
+                has_children: true,
+                is_array: true,
+            }]))),
+            UnrealCommand::WatchCount(_, _) => Some(Ok(UnrealResponse::WatchCount(2))),
+            UnrealCommand::RefreshWatches => Some(Ok(UnrealResponse::WatchesRefreshed)),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn set_variable() {
+        let log = CommandLog::default();
+        let mut config = ClientConfig::new();
+        config.supports_variable_type = true;
+        let (mut adapter, _) = make_adapter(make_client, config, set_mock().recording(&log));
+        let mut set = |kind, variable, name: &str| {
+            adapter.set_variable(&SetVariableArguments {
+                variables_reference: VariableReference::new(kind, FrameIndex::TOP_FRAME, variable)
+                    .to_int(),
+                name: name.to_string(),
+                value: "5".to_string(),
+            })
+        };
+
+        match set(WatchKind::Local, VariableIndex::SCOPE, "Count") {
+            Ok(ResponseBody::SetVariable(body)) => {
+                assert_eq!(body.value, "5.000000");
+                assert_eq!(body.ty.as_deref(), Some("Float"));
+            }
+            other => panic!("Expected a set variable response but got {other:?}"),
+        }
+        assert!(set(WatchKind::Global, VariableIndex::SCOPE, "Count").is_ok());
+
+        // Unreal ignored the assignment.
+        assert!(matches!(
+            set(WatchKind::Local, VariableIndex::SCOPE, "Total"),
+            Err(UnrealscriptAdapterError::WatchError(_))
+        ));
+
+        // There's no expression for a child of a structured variable, so it isn't set at all.
+        assert!(matches!(
+            set(WatchKind::Local, VariableIndex::create(1).unwrap(), "Count"),
+            Err(UnrealscriptAdapterError::WatchError(_))
+        ));
+        assert_eq!(
+            log.of(&["set"]),
+            vec!["set Count 5", "set self.Count 5", "set Total 5"]
+        );
+    }
+
+    #[test]
+    fn set_expression() {
+        let log = CommandLog::default();
+        let (mut adapter, _) =
+            make_adapter(make_client, ClientConfig::new(), set_mock().recording(&log));
+        let mut set = |expression: &str| {
+            adapter.set_expression(&SetExpressionArguments {
+                expression: expression.to_string(),
+                value: "5".to_string(),
+                frame_id: Some(0),
+            })
+        };
+
+        match set("Count.Total ") {
+            Ok(ResponseBody::SetExpression(body)) => assert_eq!(body.value, "5.000000"),
+            other => panic!("Expected a set expression response but got {other:?}"),
+        }
+
+        // Unreal ignored the assignment.
+        assert!(matches!(
+            set("Total"),
+            Err(UnrealscriptAdapterError::WatchError(_))
+        ));
+
+        // Nothing is assigned unless the expression names a single variable.
+        for expression in ["Items", "3", ""] {
+            assert!(matches!(
+                set(expression),
+                Err(UnrealscriptAdapterError::WatchError(_))
+            ));
+        }
+        assert_eq!(log.of(&["set"]), vec!["set Count.Total 5", "set Total 5"]);
+    }
+
+    #[test]
+    fn set_expression_needs_interface_support() {
+        let log = CommandLog::default();
+        let (mut adapter, _) =
+            make_adapter(make_client, ClientConfig::new(), set_mock().recording(&log));
+        let request: Request = serde_json::from_value(serde_json::json!({
+            "type": "request",
+            "seq": 1,
+            "command": "setExpression",
+            "arguments": {"expression": "Count", "value": "5", "frameId": 0},
+        }))
+        .unwrap();
+
+        // An older interface can't assign, so nothing is sent to it.
+        assert!(matches!(
+            adapter.accept(&request),
+            Err(UnrealscriptAdapterError::UnhandledCommand(c)) if c == "setExpression"
+        ));
+        assert!(log.entries().is_empty());
+
+        adapter.supports_set_variable = true;
+        assert!(matches!(
+            adapter.accept(&request),
+            Ok(Some(ResponseBody::SetExpression(_)))
+        ));
+        assert_eq!(log.of(&["set"]), vec!["set Count 5"]);
+    }
+
+    #[test]
+    fn set_array_element() {
+        let log = CommandLog::default();
+        let (mut adapter, _) =
+            make_adapter(make_client, ClientConfig::new(), set_mock().recording(&log));
+        let scope = VariableReference::new(
+            WatchKind::Local,
+            FrameIndex::TOP_FRAME,
+            VariableIndex::SCOPE,
+        );
+        let array = match adapter.variables(&VariablesArguments {
+            variables_reference: scope.to_int(),
+            start: None,
+            count: None,
+        }) {
+            Ok(ResponseBody::Variables(body)) => {
+                body.variables[0].variable_info.variables_reference
+            }
+            other => panic!("Expected a variables response but got {other:?}"),
+        };
+        let mut set = |name: &str| {
+            adapter.set_variable(&SetVariableArguments {
+                variables_reference: array,
+                name: name.to_string(),
+                value: "5".to_string(),
+            })
+        };
+
+        for name in ["Counts[0]", "[1]"] {
+            match set(name) {
+                Ok(ResponseBody::SetVariable(body)) => assert_eq!(body.value, "5.000000"),
+                other => panic!("Expected a set variable response but got {other:?}"),
+            }
+        }
+
+        // An index past the end is refused without trying to assign it.
+        assert!(matches!(
+            set("Counts[2]"),
+            Err(UnrealscriptAdapterError::LimitExceeded(_))
+        ));
+        assert!(matches!(
+            set("Counts"),
+            Err(UnrealscriptAdapterError::WatchError(_))
+        ));
+        assert_eq!(log.of(&["set"]), vec!["set Counts[0] 5", "set Counts[1] 5"]);
+    }
+
+    #[test]
+    fn refresh_forgets_variables() {
+        let log = CommandLog::default();
+        let events = Arc::new(Mutex::new(vec![]));
+        let mut config = ClientConfig::new();
+        config.supports_invalidated_event = true;
+        let (mut adapter, _) = make_adapter(
+            |_| EventLogClient {
+                events: events.clone(),
+            },
+            config,
+            set_mock().recording(&log),
+        );
+        let scope = VariableReference::new(
+            WatchKind::Local,
+            FrameIndex::TOP_FRAME,
+            VariableIndex::SCOPE,
+        );
+        let array = match adapter.variables(&VariablesArguments {
+            variables_reference: scope.to_int(),
+            start: None,
+            count: None,
+        }) {
+            Ok(ResponseBody::Variables(body)) => {
+                body.variables[0].variable_info.variables_reference
+            }
+            other => panic!("Expected a variables response but got {other:?}"),
+        };
+
+        // An older interface can't rebuild the watch lists, so nothing is forgotten.
+        assert_eq!(
+            adapter.repl_command(ReplCommand::Refresh).unwrap(),
+            "Refreshing the variables is not supported by this interface"
+        );
+        assert!(events.lock().unwrap().is_empty());
+
+        adapter.supports_refresh_watches = true;
+        assert_eq!(
+            adapter.repl_command(ReplCommand::Refresh).unwrap(),
+            "Refreshed the variables"
+        );
+        assert!(matches!(
+            events.lock().unwrap().as_slice(),
+            [Event {
+                body: EventBody::Invalidated(InvalidatedEventBody { frame_id: None, .. }),
+            }]
+        ));
+
+        // The array's expression is forgotten until the client fetches the variables again.
+        assert!(matches!(
+            adapter.set_variable(&SetVariableArguments {
+                variables_reference: array,
+                name: "[1]".to_string(),
+                value: "5".to_string(),
+            }),
+            Err(UnrealscriptAdapterError::WatchError(_))
+        ));
+        assert_eq!(log.of(&["set", "refresh"]), vec!["refresh"]);
+    }
+
+    #[test]
+    fn hover_keyword() {
+        match hover("function") {
+            Err(e @ UnrealscriptAdapterError::NotEvaluatable(_)) => {
+                assert!(!e.to_error_message().show_user)
+            }
+            other => panic!("Expected a quiet error but got {other:?}"),
+        }
+    }
+
+    // A mock connection with a single variable in each scope, stopped in a native function in
+    // a two frame stack.
+    fn scope_mock() -> MockConnection {
+        MockConnection::new(|command| match command {
+            UnrealCommand::Variables(kind, ..) => {
+                let name = match kind {
+                    WatchKind::Local => "Count",
+                    _ => "Health",
+                };
+                Some(Ok(UnrealResponse::Variables(vec![Variable {
+                    name: name.to_string(),
+                    ty: "int".to_string(),
+                    value: "1".to_string(),
+                    index: VariableIndex::create(1).unwrap(),
+                    has_children: false,
+                    is_array: false,
+                }])))
+            }
+            UnrealCommand::WatchCount(_, _) => Some(Ok(UnrealResponse::WatchCount(1))),
+            UnrealCommand::StackTrace(_) => {
+                let frame = Frame {
+                    qualified_name: String::new(),
+                    function_name: "Native".to_string(),
+                    line: 1,
+                    state_name: None,
+                };
+                Some(Ok(UnrealResponse::StackTrace(common::StackTraceResponse {
+                    frames: vec![frame.clone(), frame],
+                    total_frames: None,
+                })))
+            }
+            // Stopped in a native function, so there is no current object.
+            UnrealCommand::Threads => Some(Ok(UnrealResponse::Threads(vec![]))),
+            _ => None,
+        })
+    }
+
+    fn scope_evaluate_names(kind: WatchKind) -> Vec<Option<String>> {
+        let (mut adapter, _) = make_adapter(make_client, ClientConfig::new(), scope_mock());
+        let scope = VariableReference::new(kind, FrameIndex::TOP_FRAME, VariableIndex::SCOPE);
+        match adapter.variables(&VariablesArguments {
+            variables_reference: scope.to_int(),
+            start: None,
+            count: None,
+        }) {
+            Ok(ResponseBody::Variables(body)) => body
+                .variables
+                .into_iter()
+                .map(|v| v.evaluate_name)
+                .collect(),
+            other => panic!("Expected a variables response but got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn scope_variable_evaluate_names() {
+        assert_eq!(
+            scope_evaluate_names(WatchKind::Local),
+            vec![Some("Count".to_string())]
+        );
+        assert_eq!(
+            scope_evaluate_names(WatchKind::Global),
+            vec![Some("self.Health".to_string())]
+        );
+    }
+
+    fn fetch_stack(adapter: &mut UnrealscriptAdapter<impl Client>) {
+        adapter
+            .stack_trace(&StackTraceArguments {
+                thread_id: UNREAL_THREAD_ID,
+                start_frame: None,
+                levels: None,
+            })
+            .unwrap();
+    }
+
+    fn scopes(
+        adapter: &mut UnrealscriptAdapter<impl Client>,
+        frame_id: i64,
+    ) -> Result<ResponseBody, UnrealscriptAdapterError> {
+        adapter.scopes(&ScopesArguments { frame_id })
+    }
+
+    fn frame_variables(
+        adapter: &mut UnrealscriptAdapter<impl Client>,
+        frame: i64,
+    ) -> Result<ResponseBody, UnrealscriptAdapterError> {
+        let scope = VariableReference::new(
+            WatchKind::Local,
+            FrameIndex::create(frame).unwrap(),
+            VariableIndex::SCOPE,
+        );
+        adapter.variables(&VariablesArguments {
+            variables_reference: scope.to_int(),
+            start: None,
+            count: None,
+        })
+    }
+
+    fn scope_names(adapter: &mut UnrealscriptAdapter<impl Client>) -> Vec<String> {
+        match scopes(adapter, 0) {
+            Ok(ResponseBody::Scopes(body)) => body.scopes.into_iter().map(|s| s.name).collect(),
+            other => panic!("Expected a scopes response but got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn self_scope_without_object() {
+        let (mut adapter, _) = make_adapter(make_client, ClientConfig::new(), scope_mock());
+        adapter.supports_threads = true;
+        // The scope is shown unless the user asked to hide it.
+        assert_eq!(scope_names(&mut adapter), vec!["self", "locals"]);
+        adapter.config.hide_self_without_object = true;
+        assert_eq!(scope_names(&mut adapter), vec!["locals"]);
+        // Only the top frame is known to have no object.
+        fetch_stack(&mut adapter);
+        match scopes(&mut adapter, 1) {
+            Ok(ResponseBody::Scopes(body)) => assert_eq!(body.scopes[0].name, "self"),
+            other => panic!("Expected a scopes response but got {other:?}"),
+        }
+        // An interface that can't say whether there is an object always shows it.
+        adapter.supports_threads = false;
+        assert_eq!(scope_names(&mut adapter), vec!["self", "locals"]);
+    }
+
+    #[test]
+    fn out_of_range_frame() {
+        let (mut adapter, _) = make_adapter(make_client, ClientConfig::new(), scope_mock());
+        for frame_id in [-1, 1000] {
+            assert!(matches!(
+                scopes(&mut adapter, frame_id),
+                Err(UnrealscriptAdapterError::LimitExceeded(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn frame_beyond_stack_is_stale() {
+        let (mut adapter, _) = make_adapter(make_client, ClientConfig::new(), scope_mock());
+        fetch_stack(&mut adapter);
+        assert!(scopes(&mut adapter, 1).is_ok());
+        assert!(frame_variables(&mut adapter, 1).is_ok());
+        assert!(matches!(
+            scopes(&mut adapter, 2),
+            Err(UnrealscriptAdapterError::StaleFrame(2))
+        ));
+        assert!(matches!(
+            frame_variables(&mut adapter, 2),
+            Err(UnrealscriptAdapterError::StaleFrame(2))
+        ));
+    }
+
+    #[test]
+    fn frame_after_resume_is_stale() {
+        let (mut adapter, _) = make_adapter(make_client, ClientConfig::new(), scope_mock());
+        fetch_stack(&mut adapter);
+        adapter.go().unwrap();
+        assert!(matches!(
+            scopes(&mut adapter, 0),
+            Err(UnrealscriptAdapterError::StaleFrame(0))
+        ));
+        assert!(matches!(
+            frame_variables(&mut adapter, 0),
+            Err(UnrealscriptAdapterError::StaleFrame(0))
+        ));
+
+        // Frames are valid again once we stop.
+        adapter.process_event(UnrealEvent::Stopped);
+        assert!(scopes(&mut adapter, 0).is_ok());
+        fetch_stack(&mut adapter);
+        assert!(frame_variables(&mut adapter, 1).is_ok());
+    }
+
+    // A mock connection with a single dynamic array of ints in the locals scope.
+    fn array_mock() -> MockConnection {
+        MockConnection::new(|command| match command {
+            UnrealCommand::Variables(_, _, variable, _, _) => {
+                let var = if *variable == VariableIndex::SCOPE {
+                    Variable {
+                        name: "Counts".to_string(),
+                        ty: "Array".to_string(),
+                        value: "".to_string(),
+                        index: VariableIndex::create(1).unwrap(),
+                        has_children: true,
+                        is_array: true,
+                    }
+                } else {
+                    Variable {
+                        name: "Counts[0]".to_string(),
+                        ty: "Int".to_string(),
+                        value: "3".to_string(),
+                        index: VariableIndex::create(2).unwrap(),
+                        has_children: false,
+                        is_array: false,
+                    }
+                };
+                Some(Ok(UnrealResponse::Variables(vec![var])))
+            }
+            UnrealCommand::WatchCount(_, _) => Some(Ok(UnrealResponse::WatchCount(1))),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn array_element_type() {
+        let log = CommandLog::default();
+        let mut config = ClientConfig::new();
+        config.supports_variable_type = true;
+        let (mut adapter, _) = make_adapter(make_client, config, array_mock().recording(&log));
+        for _ in 0..2 {
+            match frame_variables(&mut adapter, 0).unwrap() {
+                ResponseBody::Variables(body) => {
+                    assert_eq!(body.variables[0].ty.as_deref(), Some("Array<Int>"));
+                }
+                other => panic!("Expected a variables response but got {other:?}"),
+            }
+        }
+
+        // The element type is only fetched once.
+        assert_eq!(
+            log.of(&["variables"]),
+            vec![
+                "variables Local 0 0 0 0",
+                "variables Local 0 1 0 1",
+                "variables Local 0 0 0 0"
+            ]
+        );
+    }
+
+    // A mock connection with three locals.
+    fn prefetch_mock() -> MockConnection {
+        MockConnection::new(|command| match command {
+            UnrealCommand::Variables(_, _, _, start, count) => {
+                let vars = ["A", "B", "C"]
+                    .iter()
+                    .zip(1..)
+                    .map(|(name, i)| Variable {
+                        name: name.to_string(),
+                        ty: "Int".to_string(),
+                        value: i.to_string(),
+                        index: VariableIndex::create(i).unwrap(),
+                        has_children: false,
+                        is_array: false,
+                    })
+                    .skip(*start)
+                    .take(if *count == 0 { usize::MAX } else { *count })
+                    .collect();
+                Some(Ok(UnrealResponse::Variables(vars)))
+            }
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn prefetch_locals_on_stop() {
+        let names = |response: Result<ResponseBody, UnrealscriptAdapterError>| match response {
+            Ok(ResponseBody::Variables(body)) => body
+                .variables
+                .into_iter()
+                .map(|v| v.name)
+                .collect::<Vec<_>>(),
+            other => panic!("Expected a variables response but got {other:?}"),
+        };
+        for prefetch in [None, Some(0), Some(5), Some(2)] {
+            let log = CommandLog::default();
+            let mut config = ClientConfig::new();
+            config.prefetch_locals = prefetch;
+            let (mut adapter, _) =
+                make_adapter(make_client, config, prefetch_mock().recording(&log));
+            adapter.process_event(UnrealEvent::Stopped);
+            assert_eq!(names(frame_variables(&mut adapter, 0)), vec!["A", "B", "C"]);
+            // Only the first request can be answered from the prefetched locals.
+            assert_eq!(names(frame_variables(&mut adapter, 0)), vec!["A", "B", "C"]);
+
+            let expected = match prefetch {
+                None | Some(0) => vec!["variables Local 0 0 0 0", "variables Local 0 0 0 0"],
+                Some(5) => vec!["variables Local 0 0 0 5", "variables Local 0 0 0 0"],
+                // Too few locals were fetched to answer a request for all of them.
+                _ => vec![
+                    "variables Local 0 0 0 2",
+                    "variables Local 0 0 0 0",
+                    "variables Local 0 0 0 0",
+                ],
+            };
+            assert_eq!(log.of(&["variables"]), expected);
+        }
+    }
+
+    // A mock connection that moves each new breakpoint on an even line to the following line.
+    fn breakpoint_mock() -> MockConnection {
+        MockConnection::new(|command| match command {
+            UnrealCommand::AddBreakpoint(bp) => {
+                let line = if bp.line % 2 == 0 {
+                    bp.line + 1
+                } else {
+                    bp.line
+                };
+                Some(Ok(UnrealResponse::BreakpointAdded(Breakpoint::new(
+                    &bp.qualified_name,
+                    line,
+                ))))
+            }
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn toggle_breakpoint_reuses_line() {
+        let log = CommandLog::default();
+        let (mut adapter, _) = make_adapter(
+            make_client,
+            ClientConfig::new(),
+            breakpoint_mock().recording(&log),
+        );
+        let make_args = |lines: &[i64]| SetBreakpointsArguments {
+            source: Source {
+                name: None,
+                path: Some(GOOD_PATH.to_string()),
+                source_reference: None,
+            },
+            breakpoints: Some(lines.iter().map(|l| SourceBreakpoint::new(*l)).collect()),
+        };
+
+        adapter.set_breakpoints(&make_args(&[10, 21])).unwrap();
+        // Toggle off the breakpoint on line 10, then back on again.
+        adapter.set_breakpoints(&make_args(&[21])).unwrap();
+        let response = adapter.set_breakpoints(&make_args(&[10, 21])).unwrap();
+
+        // The breakpoint on 21 is never touched, and 10 is re-enabled at its verified line.
+        assert_eq!(
+            log.entries(),
+            vec!["add 10", "add 21", "remove 11", "add 11"]
+        );
+        match response {
+            ResponseBody::SetBreakpoints(body) => {
+                let lines: Vec<i64> = body.breakpoints.iter().map(|bp| bp.line).collect();
+                assert_eq!(lines, vec![11, 21]);
+            }
+            _ => panic!("Expected a set breakpoints response"),
+        }
+    }
+
+    #[test]
+    fn mute_breakpoints() {
+        let log = CommandLog::default();
+        let (mut adapter, _) = make_adapter(
+            make_client,
+            ClientConfig::new(),
+            breakpoint_mock().recording(&log),
+        );
+        let make_args = |lines: &[i64]| SetBreakpointsArguments {
+            source: Source {
+                name: None,
+                path: Some(GOOD_PATH.to_string()),
+                source_reference: None,
+            },
+            breakpoints: Some(lines.iter().map(|l| SourceBreakpoint::new(*l)).collect()),
+        };
+
+        adapter.set_breakpoints(&make_args(&[10, 21])).unwrap();
+        log.clear();
+        assert_eq!(
+            adapter.repl_command(ReplCommand::Mute).unwrap(),
+            "Muted 2 breakpoints"
+        );
+        assert_eq!(
+            adapter.repl_command(ReplCommand::Mute).unwrap(),
+            "Breakpoints are already muted"
+        );
+        assert_eq!(
+            adapter.repl_command(ReplCommand::Unmute).unwrap(),
+            "Unmuted 2 breakpoints"
+        );
+        // Unmuting sets exactly the breakpoints that were removed, at their verified lines.
+        assert_eq!(
+            log.entries(),
+            vec!["remove 11", "remove 21", "add 11", "add 21"]
+        );
+
+        // Changes made while muted are only recorded, and take effect on unmuting.
+        log.clear();
+        adapter.repl_command(ReplCommand::Mute).unwrap();
+        let response = adapter.set_breakpoints(&make_args(&[21, 31])).unwrap();
+        match response {
+            ResponseBody::SetBreakpoints(body) => {
+                assert!(body.breakpoints.iter().all(|bp| bp.verified));
+            }
+            _ => panic!("Expected a set breakpoints response"),
+        }
+        adapter.repl_command(ReplCommand::Unmute).unwrap();
+        assert_eq!(
+            log.entries(),
+            vec!["remove 11", "remove 21", "add 21", "add 31"]
+        );
+        assert_eq!(
+            adapter.repl_command(ReplCommand::Unmute).unwrap(),
+            "Breakpoints are not muted"
+        );
+    }
+
+    #[test]
+    fn changed_source_verifies_again() {
+        let log = CommandLog::default();
+        let (mut adapter, _) = make_adapter(
+            make_client,
+            ClientConfig::new(),
+            breakpoint_mock().recording(&log),
+        );
+        let root = std::env::temp_dir().join(format!("changed_source_{}", std::process::id()));
+        let classes = root.join("MyPackage").join("Classes");
+        std::fs::create_dir_all(&classes).unwrap();
+        let file = classes.join("SomeClass.uc");
+        let source: String = (1..=30).map(|i| format!("// Line {i}\n")).collect();
+        std::fs::write(&file, &source).unwrap();
+        let args = SetBreakpointsArguments {
+            source: Source {
+                name: None,
+                path: Some(file.to_string_lossy().to_string()),
+                source_reference: None,
+            },
+            breakpoints: Some(vec![SourceBreakpoint::new(10)]),
+        };
+
+        adapter.set_breakpoints(&args).unwrap();
+        // Nothing has changed, so the breakpoint stays where Unreal put it.
+        adapter.set_breakpoints(&args).unwrap();
+        std::fs::write(&file, format!("// Edited\n{source}")).unwrap();
+        adapter.set_breakpoints(&args).unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(log.entries(), vec!["add 10", "remove 11", "add 10"]);
+    }
+
+    #[test]
+    fn add_breakpoint_to_existing_set() {
+        let log = CommandLog::default();
+        let (mut adapter, _) = make_adapter(
+            make_client,
+            ClientConfig::new(),
+            breakpoint_mock().recording(&log),
+        );
+        let make_args = |lines: &[i64]| SetBreakpointsArguments {
+            source: Source {
+                name: None,
+                path: Some(GOOD_PATH.to_string()),
+                source_reference: None,
+            },
+            breakpoints: Some(lines.iter().map(|l| SourceBreakpoint::new(*l)).collect()),
+        };
+
+        adapter.set_breakpoints(&make_args(&[10, 21])).unwrap();
+        log.clear();
+        let response = adapter.set_breakpoints(&make_args(&[10, 21, 33])).unwrap();
+
+        // Only the new breakpoint is sent to Unreal.
+        assert_eq!(log.entries(), vec!["add 33"]);
+        match response {
+            ResponseBody::SetBreakpoints(body) => {
+                let lines: Vec<i64> = body.breakpoints.iter().map(|bp| bp.line).collect();
+                assert_eq!(lines, vec![11, 21, 33]);
+            }
+            _ => panic!("Expected a set breakpoints response"),
+        }
+    }
+
+    // A mock connection that always removes the line before the one asked for.
+    fn mismatched_remove_mock() -> MockConnection {
+        MockConnection::new(|command| match command {
+            UnrealCommand::RemoveBreakpoint(bp) => Some(Ok(UnrealResponse::BreakpointRemoved(
+                Breakpoint::new(&bp.qualified_name, bp.line - 1),
+            ))),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn mismatched_removed_line() {
+        let log = CommandLog::default();
+        let (mut adapter, _) = make_adapter(
+            make_client,
+            ClientConfig::new(),
+            mismatched_remove_mock().recording(&log),
+        );
+        let make_args = |lines: &[i64]| SetBreakpointsArguments {
+            source: Source {
+                name: None,
+                path: Some(GOOD_PATH.to_string()),
+                source_reference: None,
+            },
+            breakpoints: Some(lines.iter().map(|l| SourceBreakpoint::new(*l)).collect()),
+        };
+
+        adapter.set_breakpoints(&make_args(&[20, 21])).unwrap();
+        let response = adapter.set_breakpoints(&make_args(&[20])).unwrap();
+
+        // Removing 21 removed 20 instead, so it must be set again.
+        assert_eq!(
+            log.entries(),
+            vec!["add 20", "add 21", "remove 21", "add 20"]
+        );
+        match response {
+            ResponseBody::SetBreakpoints(body) => {
+                let lines: Vec<i64> = body.breakpoints.iter().map(|bp| bp.line).collect();
+                assert_eq!(lines, vec![20]);
+            }
+            _ => panic!("Expected a set breakpoints response"),
+        }
+
+        // 21 is still set in Unreal, so it's still tracked and removed again next time.
+        log.clear();
+        adapter.set_breakpoints(&make_args(&[20])).unwrap();
+        assert_eq!(log.entries(), vec!["remove 21", "add 20"]);
+    }
+
+    #[test]
+    fn repl_about_command() {
+        let mut config = ClientConfig::new();
+        config.enable_stack_hack = true;
+        let (mut adapter, _) = make_adapter(make_client, config, MockConnection::default());
+        adapter
+            .handshake(&Version {
+                major: 1,
+                minor: 2,
+                patch: 3,
+            })
+            .unwrap();
+        let about = match adapter.evaluate(&EvaluateArguments {
+            expression: ".about".to_string(),
+            frame_id: None,
+            context: Some(EvaluateContext::Repl),
+        }) {
+            Ok(ResponseBody::Evaluate(body)) => body.result,
+            other => panic!("Expected an evaluate response but got {other:?}"),
+        };
+
+        let lines: Vec<&str> = about.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                format!("adapter: {}", env!("CARGO_PKG_VERSION")).as_str(),
+                "interface: 1.2.3",
+                "protocol: 1.2.3",
+                format!("os: {} ({})", std::env::consts::OS, std::env::consts::ARCH).as_str(),
+                "session: attach",
+                "enable_stack_hack: true",
+            ]
+        );
+    }
+
+    // A mock connection that records the session id sent with an initialize command.
+    fn session_mock(session_id: Arc<Mutex<Option<String>>>) -> MockConnection {
+        MockConnection::new(move |command| {
+            if let UnrealCommand::Initialize(init) = command {
+                *session_id.lock().unwrap() = init.session_id.clone();
+            }
+            None
+        })
+    }
+
+    #[test]
+    fn handshake_sends_session_id() {
+        let session_id = Arc::new(Mutex::new(None));
+        let (mut adapter, _) = make_adapter(
+            make_client,
+            ClientConfig::new(),
+            session_mock(session_id.clone()),
+        );
+        let id = common::new_session_id();
+        common::set_session_id(Some(&id));
+        adapter
+            .handshake(&Version {
+                major: 1,
+                minor: 2,
+                patch: 3,
+            })
+            .unwrap();
+        assert_eq!(*session_id.lock().unwrap(), Some(id));
+    }
+
+    #[test]
+    fn entry_breakpoints() {
+        let log = CommandLog::default();
+        let mut config = ClientConfig::new();
+        config.source_roots = vec![concat!(env!("CARGO_MANIFEST_DIR"), "/testdata").to_string()];
+        config.break_on_enter_classes = vec![
+            "MyPackage.Multiline".to_string(),
+            "MyPackage.Missing".to_string(),
+            "Unqualified".to_string(),
+        ];
+        let (mut adapter, _) = make_adapter(
+            make_client,
+            config,
+            MockConnection::default().recording(&log),
+        );
+        adapter.set_entry_breakpoints(&BTreeSet::new()).unwrap();
+        assert_eq!(log.entries(), vec!["add 8", "add 20"]);
+        assert_eq!(
+            adapter.entry_breakpoints["MYPACKAGE.MULTILINE"],
+            vec![8, 20]
+        );
+    }
+
+    #[test]
+    fn function_breakpoints() {
+        let log = CommandLog::default();
+        let mut config = ClientConfig::new();
+        config.source_roots = vec![concat!(env!("CARGO_MANIFEST_DIR"), "/testdata").to_string()];
+        config.scan_source_roots = true;
+        let (mut adapter, _) =
+            make_adapter(make_client, config, listing_mock(vec![]).recording(&log));
+        let set_function_breakpoints = |adapter: &mut UnrealscriptAdapter<_>, names: &[&str]| {
+            let args = SetFunctionBreakpointsArguments {
+                breakpoints: names
+                    .iter()
+                    .map(|name| FunctionBreakpoint {
+                        name: name.to_string(),
+                    })
+                    .collect(),
+            };
+            match adapter.set_function_breakpoints(&args).unwrap() {
+                ResponseBody::SetFunctionBreakpoints(body) => body
+                    .breakpoints
+                    .iter()
+                    .map(|bp| (bp.verified, bp.line))
+                    .collect::<Vec<_>>(),
+                _ => panic!("Expected a set function breakpoints response"),
+            }
+        };
+
+        let found = set_function_breakpoints(
+            &mut adapter,
+            &[
+                "MyPackage.Multiline:foo",
+                "MyPackage.Multiline:Missing",
+                "Foo",
+            ],
+        );
+        assert_eq!(found, vec![(true, 8), (false, 0), (false, 0)]);
+
+        // A source breakpoint on the first line of 'Bar' shares that line with a function
+        // breakpoint, so it is only set once and stays set when either is removed.
+        adapter
+            .set_breakpoints(&SetBreakpointsArguments {
+                source: Source {
+                    name: None,
+                    path: Some(
+                        concat!(
+                            env!("CARGO_MANIFEST_DIR"),
+                            "/testdata/MyPackage/Classes/Multiline.uc"
+                        )
+                        .to_string(),
+                    ),
+                    source_reference: None,
+                },
+                breakpoints: Some(vec![SourceBreakpoint::new(20)]),
+            })
+            .unwrap();
+        let found = set_function_breakpoints(&mut adapter, &["MyPackage.Multiline:Bar"]);
+        assert_eq!(found, vec![(true, 20)]);
+        adapter
+            .set_breakpoints(&SetBreakpointsArguments {
+                source: Source {
+                    name: None,
+                    path: Some(
+                        concat!(
+                            env!("CARGO_MANIFEST_DIR"),
+                            "/testdata/MyPackage/Classes/Multiline.uc"
+                        )
+                        .to_string(),
+                    ),
+                    source_reference: None,
+                },
+                breakpoints: Some(vec![]),
+            })
+            .unwrap();
+        assert_eq!(log.entries(), vec!["add 8", "add 20", "remove 8"]);
+        assert!(adapter
+            .function_breakpoints
+            .contains_key(&("MYPACKAGE.MULTILINE".to_string(), 20)));
+    }
+
+    #[test]
+    fn moved_function_breakpoint_kept() {
+        let log = CommandLog::default();
+        let mut config = ClientConfig::new();
+        config.source_roots = vec![concat!(env!("CARGO_MANIFEST_DIR"), "/testdata").to_string()];
+        config.scan_source_roots = true;
+        let (mut adapter, _) = make_adapter(make_client, config, breakpoint_mock().recording(&log));
+        let mut set_function_breakpoints = |names: &[&str]| {
+            let args = SetFunctionBreakpointsArguments {
+                breakpoints: names
+                    .iter()
+                    .map(|name| FunctionBreakpoint {
+                        name: name.to_string(),
+                    })
+                    .collect(),
+            };
+            match adapter.set_function_breakpoints(&args).unwrap() {
+                ResponseBody::SetFunctionBreakpoints(body) => body
+                    .breakpoints
+                    .iter()
+                    .map(|bp| (bp.verified, bp.line))
+                    .collect::<Vec<_>>(),
+                _ => panic!("Expected a set function breakpoints response"),
+            }
+        };
+
+        // Unreal moves the breakpoint in 'foo' from line 8 to 9. Setting it again leaves it
+        // alone, and removing it removes the line Unreal set.
+        assert_eq!(
+            set_function_breakpoints(&["MyPackage.Multiline:foo"]),
+            vec![(true, 9)]
+        );
+        assert_eq!(
+            set_function_breakpoints(&["MyPackage.Multiline:foo"]),
+            vec![(true, 9)]
+        );
+        assert!(set_function_breakpoints(&[]).is_empty());
+        assert_eq!(log.entries(), vec!["add 8", "remove 9"]);
+    }
+
+    // A mock connection where Unreal refuses a breakpoint on line 20, which it reports as a
+    // breakpoint on line 0, and fails to remove any breakpoint.
+    fn function_reject_mock() -> MockConnection {
+        MockConnection::new(|command| match command {
+            UnrealCommand::AddBreakpoint(bp) => {
+                let line = if bp.line == 20 { 0 } else { bp.line };
+                Some(Ok(UnrealResponse::BreakpointAdded(Breakpoint::new(
+                    &bp.qualified_name,
+                    line,
+                ))))
+            }
+            UnrealCommand::RemoveBreakpoint(_) => Some(Err(Error::new(
+                std::io::ErrorKind::ConnectionReset,
+                "Connection reset",
+            ))),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn function_breakpoints_match_unreal() {
+        let mut config = ClientConfig::new();
+        config.source_roots = vec![concat!(env!("CARGO_MANIFEST_DIR"), "/testdata").to_string()];
+        config.scan_source_roots = true;
+        let (mut adapter, _) = make_adapter(make_client, config, function_reject_mock());
+        let args = |names: &[&str]| SetFunctionBreakpointsArguments {
+            breakpoints: names
+                .iter()
+                .map(|name| FunctionBreakpoint {
+                    name: name.to_string(),
+                })
+                .collect(),
+        };
+        let expected = BTreeMap::from([(("MYPACKAGE.MULTILINE".to_string(), 8), 8)]);
+
+        // Unreal refused the breakpoint in 'Bar', so it isn't verified or recorded.
+        match adapter
+            .set_function_breakpoints(&args(&[
+                "MyPackage.Multiline:foo",
+                "MyPackage.Multiline:Bar",
+            ]))
+            .unwrap()
+        {
+            ResponseBody::SetFunctionBreakpoints(body) => {
+                let found: Vec<_> = body
+                    .breakpoints
+                    .iter()
+                    .map(|bp| (bp.verified, bp.line, bp.message.is_some()))
+                    .collect();
+                assert_eq!(found, vec![(true, 8, false), (false, 20, true)]);
+            }
+            other => panic!("Expected a set function breakpoints response but got {other:?}"),
+        }
+        assert_eq!(adapter.function_breakpoints, expected);
+
+        // Failing to remove the breakpoint in 'foo' leaves it recorded, since it's still set.
+        assert!(adapter.set_function_breakpoints(&args(&[])).is_err());
+        assert_eq!(adapter.function_breakpoints, expected);
+    }
+
+    // A mock connection stopped in 'MyPackage.Multiline' with a local 'Sum' and a member that
+    // isn't declared in that class's source.
+    fn location_mock() -> MockConnection {
+        MockConnection::new(|command| match command {
+            UnrealCommand::Variables(kind, ..) => {
+                let name = match kind {
+                    WatchKind::Local => "Sum",
+                    _ => "Inherited",
+                };
+                Some(Ok(UnrealResponse::Variables(vec![Variable {
+                    name: name.to_string(),
+                    ty: "Int".to_string(),
+                    value: "0".to_string(),
+                    index: VariableIndex::create(1).unwrap(),
+                    has_children: false,
+                    is_array: false,
+                }])))
+            }
+            UnrealCommand::StackTrace(_) => {
+                Some(Ok(UnrealResponse::StackTrace(common::StackTraceResponse {
+                    frames: vec![Frame {
+                        qualified_name: "MyPackage.Multiline".to_string(),
+                        function_name: "Foo".to_string(),
+                        line: 15,
+                        state_name: None,
+                    }],
+                    total_frames: None,
+                })))
+            }
+            _ => None,
+        })
+    }
+
+    fn declaration_reference(
+        adapter: &mut UnrealscriptAdapter<impl Client>,
+        kind: WatchKind,
+    ) -> i64 {
+        let scope = VariableReference::new(kind, FrameIndex::TOP_FRAME, VariableIndex::SCOPE);
+        match adapter.variables(&VariablesArguments {
+            variables_reference: scope.to_int(),
+            start: None,
+            count: None,
+        }) {
+            Ok(ResponseBody::Variables(body)) => {
+                body.variables[0].declaration_location_reference.unwrap()
+            }
+            other => panic!("Expected a variables response but got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn declaration_locations() {
+        let mut config = ClientConfig::new();
+        config.source_roots = vec![concat!(env!("CARGO_MANIFEST_DIR"), "/testdata").to_string()];
+        config.scan_source_roots = true;
+        let (mut adapter, _) = make_adapter(make_client, config, location_mock());
+        let local = declaration_reference(&mut adapter, WatchKind::Local);
+        assert_eq!(declaration_reference(&mut adapter, WatchKind::Local), local);
+        match adapter.locations(&LocationsArguments {
+            location_reference: local,
+        }) {
+            Ok(ResponseBody::Locations(body)) => {
+                assert!(body.source.path.unwrap().ends_with("Multiline.uc"));
+                assert_eq!(body.line, 5);
+                assert_eq!(body.column, Some(15));
+            }
+            other => panic!("Expected a locations response but got {other:?}"),
+        }
+
+        let member = declaration_reference(&mut adapter, WatchKind::Global);
+        assert!(matches!(
+            adapter.locations(&LocationsArguments {
+                location_reference: member
+            }),
+            Err(UnrealscriptAdapterError::NotLocatable(_))
+        ));
+
+        // References don't survive resuming execution.
+        adapter.go().unwrap();
+        assert!(matches!(
+            adapter.locations(&LocationsArguments {
+                location_reference: local
+            }),
+            Err(UnrealscriptAdapterError::NotLocatable(_))
+        ));
+    }
+
+    #[test]
+    fn package_root_wins() {
+        let mut config = ClientConfig::new();
+        config.source_roots = vec![concat!(env!("CARGO_MANIFEST_DIR"), "/testdata").to_string()];
+        config.scan_source_roots = true;
+        config.package_roots = BTreeMap::from([(
+            "mypackage".to_string(),
+            concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/PackageRoot").to_string(),
+        )]);
+        let (mut adapter, _) = make_adapter(make_client, config, MockConnection::default());
+
+        // The general source root also has MyPackage.Multiline, but the package's own root
+        // is used.
+        let path = adapter.find_source_file("MyPackage", "Multiline").unwrap();
+        assert!(Path::new(&path).ends_with("PackageRoot/MyPackage/Classes/Multiline.uc"));
+
+        // Packages without a root of their own are still found in the source roots.
+        let path = adapter
+            .find_source_file("OtherPackage", "OtherClass")
+            .unwrap();
+        assert!(Path::new(&path).ends_with("testdata/OtherPackage/Classes/OtherClass.uc"));
+    }
+
+    #[test]
+    fn reconnect_restores_breakpoints() {
+        let log = CommandLog::default();
+        let mut config = ClientConfig::new();
+        config.wait_for_reconnect = true;
+        let reconnect_log = log.clone();
+        let reconnect: Reconnect = Box::new(move || {
+            reconnect_log.push("connect");
+            Ok(Box::new(
+                MockConnection::default().recording(&reconnect_log),
+            ))
+        });
+        let (adapter, tx) = make_adapter(
+            make_client,
+            config,
+            MockConnection::default().recording(&log),
+        );
+        let mut adapter = adapter.with_reconnect(Some(reconnect));
+        adapter
+            .set_breakpoints(&SetBreakpointsArguments {
+                source: Source {
+                    name: None,
+                    path: Some(GOOD_PATH.to_string()),
+                    source_reference: None,
+                },
+                breakpoints: Some(vec![SourceBreakpoint::new(10), SourceBreakpoint::new(21)]),
+            })
+            .unwrap();
+
+        // The game disconnects and connects again, then the user ends the session.
+        tx.send(AdapterMessage::Event(UnrealEvent::Disconnect))
+            .unwrap();
+        tx.send(AdapterMessage::Shutdown).unwrap();
+        let disconnect: Request = serde_json::from_str(
+            r#"{"type": "request", "seq": 1, "command": "disconnect", "arguments": {}}"#,
+        )
+        .unwrap();
+        tx.send(AdapterMessage::Request(disconnect)).unwrap();
+        tx.send(AdapterMessage::Shutdown).unwrap();
+        adapter
+            .process_messages(Version {
+                major: 1,
+                minor: 0,
+                patch: 0,
+            })
+            .unwrap();
+
+        assert_eq!(
+            log.entries(),
+            vec![
+                "add 10",
+                "add 21",
+                "initialize",
+                "connect",
+                "initialize",
+                "add 10",
+                "add 21",
+                "disconnect"
+            ]
+        );
+    }
+
+    // Wait for a game that never connects again, with the given messages from the client
+    // queued after the interface disconnects. Returns everything sent to the client.
+    fn wait_for_lost_game(messages: Vec<AdapterMessage>) -> Vec<serde_json::Value> {
+        let transcript = Arc::new(Mutex::new(vec![]));
+        let log = CommandLog::default();
+        let mut config = ClientConfig::new();
+        config.wait_for_reconnect = true;
+        let reconnect: Reconnect = Box::new(|| {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::ConnectionRefused,
+                "no game",
+            ))
+        });
+        let (adapter, tx) = make_adapter(
+            |_| TranscriptClient {
+                messages: transcript.clone(),
+                cancellation: Cancellation::default(),
+            },
+            config,
+            MockConnection::default().recording(&log),
+        );
+        let mut adapter = adapter.with_reconnect(Some(reconnect));
+        adapter.timeouts.reconnect_wait = Duration::ZERO;
+        tx.send(AdapterMessage::Shutdown).unwrap();
+        for message in messages {
+            tx.send(message).unwrap();
+        }
+        adapter
+            .process_messages(Version {
+                major: 1,
+                minor: 0,
+                patch: 0,
+            })
+            .unwrap();
+        let messages = transcript.lock().unwrap().clone();
+        messages
+    }
+
+    #[test]
+    fn disconnect_while_waiting_for_reconnect() {
+        let transcript = wait_for_lost_game(vec![
+            request(1, "threads", serde_json::Value::Null),
+            request(2, "disconnect", serde_json::json!({})),
+        ]);
+        let kinds = transcript.iter().map(message_kind).collect::<Vec<_>>();
+        assert_eq!(
+            kinds[kinds.len() - 3..],
+            ["response disconnect", "event thread", "event terminated"]
+        );
+        assert_eq!(transcript[transcript.len() - 3]["success"], true);
+    }
+
+    #[test]
+    fn stop_waiting_for_reconnect_eventually() {
+        let transcript = wait_for_lost_game(vec![]);
+        let kinds = transcript.iter().map(message_kind).collect::<Vec<_>>();
+        assert_eq!(
+            kinds[kinds.len() - 3..],
+            ["event output", "event thread", "event terminated"]
+        );
+        assert_eq!(
+            transcript[transcript.len() - 3]["body"]["output"],
+            "The game didn't connect again. Ending the session.\n"
+        );
+    }
+
+    // Lose the connection to the interface, then end the session. Connecting again fails the
+    // given number of times before it succeeds. Returns the commands sent to the interface,
+    // and each connection attempt.
+    fn lose_connection(failures: usize) -> Vec<String> {
+        let log = CommandLog::default();
+        let reconnect_log = log.clone();
+        let mut attempts = 0;
+        let reconnect: Reconnect = Box::new(move || {
+            reconnect_log.push("connect");
+            attempts += 1;
+            if attempts <= failures {
+                return Err(Error::new(std::io::ErrorKind::ConnectionRefused, "refused"));
+            }
+            Ok(Box::new(
+                MockConnection::default().recording(&reconnect_log),
+            ))
+        });
+        let (adapter, tx) = make_adapter(
+            make_client,
+            ClientConfig::new(),
+            MockConnection::default().recording(&log),
+        );
+        let mut adapter = adapter.with_reconnect(Some(reconnect));
+        adapter.timeouts.reconnect_backoff = Duration::from_millis(1);
+        adapter
+            .set_breakpoints(&SetBreakpointsArguments {
+                source: Source {
+                    name: None,
+                    path: Some(GOOD_PATH.to_string()),
+                    source_reference: None,
+                },
+                breakpoints: Some(vec![SourceBreakpoint::new(10)]),
+            })
+            .unwrap();
+
+        tx.send(AdapterMessage::ConnectionLost).unwrap();
+        tx.send(AdapterMessage::Shutdown).unwrap();
+        adapter
+            .process_messages(Version {
+                major: 1,
+                minor: 0,
+                patch: 0,
+            })
+            .unwrap();
+        log.entries()
     }
 
-    /// Handle a threads request
-    fn threads(&mut self) -> Result<ResponseBody, UnrealscriptAdapterError> {
-        Ok(ResponseBody::Threads(ThreadsResponseBody {
-            threads: vec![Thread {
-                id: 1,
-                name: "main".to_string(),
-            }],
-        }))
+    #[test]
+    fn connection_lost_reconnects() {
+        assert_eq!(
+            lose_connection(2),
+            vec![
+                "add 10",
+                "initialize",
+                "connect",
+                "connect",
+                "connect",
+                "initialize",
+                "add 10"
+            ]
+        );
     }
 
-    /// Given a package and class name, search the provided source roots in order looking for the
-    /// first one that has a file that matches these names.
-    fn find_source_file(&mut self, package: &str, class: &str) -> Option<String> {
-        for root in &self.config.source_roots {
-            let path = Path::new(root);
-            if !path.exists() {
-                log::error!("Invalid source root: {root}");
-                continue;
+    #[test]
+    fn connection_lost_gives_up() {
+        let connects = lose_connection(usize::MAX)
+            .into_iter()
+            .filter(|command| command == "connect")
+            .count();
+        assert_eq!(connects, RECONNECT_ATTEMPTS as usize);
+    }
+
+    // A mock connection for a reconnected interface where Unreal still has some breakpoints set
+    // from the previous connection.
+    fn listing_mock(already_set: Vec<i32>) -> MockConnection {
+        MockConnection::new(move |command| match command {
+            UnrealCommand::Initialize(init) => {
+                Some(Ok(UnrealResponse::Initialize(common::InitializeResponse {
+                    version: init.version.clone(),
+                    supports_list_breakpoints: true,
+                    supports_threads: false,
+                    supports_restart_frame: false,
+                    supports_frame_line: false,
+                    supports_cancel_variable_request: false,
+                    supports_refresh_watches: false,
+                    supports_set_variable: false,
+                })))
             }
+            UnrealCommand::ListBreakpoints => Some(Ok(UnrealResponse::Breakpoints(
+                already_set
+                    .iter()
+                    .map(|line| Breakpoint::new("MYPACKAGE.SOMECLASS", *line))
+                    .collect(),
+            ))),
+            _ => None,
+        })
+    }
 
-            log::debug!("Searching source root {root} for {package}.{class}");
+    #[test]
+    fn reconnect_sends_breakpoint_diff() {
+        let log = CommandLog::default();
+        let mut config = ClientConfig::new();
+        config.wait_for_reconnect = true;
+        let reconnect_log = log.clone();
+        let reconnect: Reconnect = Box::new(move || {
+            reconnect_log.push("connect");
+            Ok(Box::new(
+                listing_mock(vec![21, 30]).recording(&reconnect_log),
+            ))
+        });
+        let (adapter, tx) = make_adapter(
+            make_client,
+            config,
+            MockConnection::default().recording(&log),
+        );
+        let mut adapter = adapter.with_reconnect(Some(reconnect));
+        adapter
+            .set_breakpoints(&SetBreakpointsArguments {
+                source: Source {
+                    name: None,
+                    path: Some(GOOD_PATH.to_string()),
+                    source_reference: None,
+                },
+                breakpoints: Some(vec![SourceBreakpoint::new(10), SourceBreakpoint::new(21)]),
+            })
+            .unwrap();
 
-            let candidate = path
-                .join(package)
-                .join("Classes")
-                .join(format!("{class}.uc"));
-            if !candidate.exists() {
-                continue;
+        tx.send(AdapterMessage::Event(UnrealEvent::Disconnect))
+            .unwrap();
+        tx.send(AdapterMessage::Shutdown).unwrap();
+        let disconnect: Request = serde_json::from_str(
+            r#"{"type": "request", "seq": 1, "command": "disconnect", "arguments": {}}"#,
+        )
+        .unwrap();
+        tx.send(AdapterMessage::Request(disconnect)).unwrap();
+        tx.send(AdapterMessage::Shutdown).unwrap();
+        adapter
+            .process_messages(Version {
+                major: 1,
+                minor: 0,
+                patch: 0,
+            })
+            .unwrap();
+
+        // Only the breakpoint Unreal lost is set again, and the one no longer wanted is removed.
+        assert_eq!(
+            log.entries(),
+            vec![
+                "add 10",
+                "add 21",
+                "initialize",
+                "connect",
+                "initialize",
+                "list",
+                "add 10",
+                "remove 30",
+                "disconnect"
+            ]
+        );
+        assert_eq!(
+            adapter.class_map["MYPACKAGE.SOMECLASS"].breakpoints,
+            vec![10, 21]
+        );
+    }
+
+    // A mock connection where Unreal refuses a breakpoint on line 21, which it reports as a
+    // breakpoint on line 0.
+    fn reject_mock() -> MockConnection {
+        MockConnection::new(|command| match command {
+            UnrealCommand::AddBreakpoint(bp) => {
+                let line = if bp.line == 21 { 0 } else { bp.line };
+                Some(Ok(UnrealResponse::BreakpointAdded(Breakpoint::new(
+                    &bp.qualified_name,
+                    line,
+                ))))
             }
+            _ => None,
+        })
+    }
 
-            let canonical = candidate
-                .canonicalize()
-                .map_err(|e| {
-                    log::error!("Failed to canonicalize path {candidate:#?}");
-                    e
-                })
-                .ok()?;
+    #[test]
+    fn rejected_breakpoint_unverified() {
+        let log = CommandLog::default();
+        let (mut adapter, _) = make_adapter(
+            make_client,
+            ClientConfig::new(),
+            reject_mock().recording(&log),
+        );
+        let mut set = || match adapter
+            .set_breakpoints(&SetBreakpointsArguments {
+                source: Source {
+                    name: None,
+                    path: Some(GOOD_PATH.to_string()),
+                    source_reference: None,
+                },
+                breakpoints: Some(vec![SourceBreakpoint::new(10), SourceBreakpoint::new(21)]),
+            })
+            .unwrap()
+        {
+            ResponseBody::SetBreakpoints(body) => body
+                .breakpoints
+                .into_iter()
+                .map(|bp| (bp.verified, bp.line, bp.message.is_some()))
+                .collect::<Vec<_>>(),
+            other => panic!("Expected a set breakpoints response but got {other:?}"),
+        };
 
-            let path = canonical.to_str();
-            if path.is_none() {
-                log::error!("Failed to stringize path {candidate:#?}");
-                return None;
+        assert_eq!(set(), vec![(true, 10, false), (false, 21, true)]);
+        // The rejected breakpoint isn't recorded, so it's tried again.
+        assert_eq!(set(), vec![(true, 10, false), (false, 21, true)]);
+        assert_eq!(log.entries(), vec!["add 10", "add 21", "add 21"]);
+        assert_eq!(
+            adapter.class_map["MYPACKAGE.SOMECLASS"].breakpoints,
+            vec![10]
+        );
+    }
+
+    // A mock connection stopped at line 10 of 'MyPackage.SomeClass'. 'Broken' can't be
+    // evaluated and any other expression evaluates to itself.
+    fn condition_mock() -> MockConnection {
+        MockConnection::new(|command| match command {
+            UnrealCommand::StackTrace(_) => {
+                Some(Ok(UnrealResponse::StackTrace(common::StackTraceResponse {
+                    frames: vec![Frame {
+                        qualified_name: "MYPACKAGE.SOMECLASS".to_string(),
+                        function_name: "Tick".to_string(),
+                        line: 10,
+                        state_name: None,
+                    }],
+                    total_frames: None,
+                })))
+            }
+            UnrealCommand::Evaluate(_, expr) => {
+                if expr == "Broken" {
+                    return Some(Err(Error::other("Protocol Error")));
+                }
+                let (ty, value) = match expr.as_str() {
+                    "Count > 3" => (UNKNOWN_TYPE, expr.as_str()),
+                    "Empty" => ("Bool", ""),
+                    "Target" => ("Object", "none"),
+                    "Owner" => ("Object", "MyPawn_0"),
+                    _ => ("Bool", expr.as_str()),
+                };
+                Some(Ok(UnrealResponse::Variables(vec![Variable {
+                    name: expr.clone(),
+                    ty: ty.to_string(),
+                    value: value.to_string(),
+                    index: VariableIndex::create(1).unwrap(),
+                    has_children: false,
+                    is_array: false,
+                }])))
             }
+            _ => None,
+        })
+    }
 
-            // Strip the UNC prefix canonicalize added. This is not strictly necessary but makes
-            // the pathnames look nicer in the editor.
-            let str = path.and_then(|s| s.strip_prefix("\\\\?\\"));
-            log::debug!("Mapped {package}.{class} -> {str:?}");
-            return str.map(|s| s.to_owned());
+    // Set a breakpoint on line 10 with the given condition, then stop there. Returns true if
+    // the client is told about the stop.
+    fn stop_at_conditional_breakpoint(
+        adapter: &mut UnrealscriptAdapter<impl Client>,
+        condition: Option<&str>,
+    ) -> bool {
+        adapter
+            .set_breakpoints(&SetBreakpointsArguments {
+                source: Source {
+                    name: None,
+                    path: Some(GOOD_PATH.to_string()),
+                    source_reference: None,
+                },
+                breakpoints: Some(vec![SourceBreakpoint {
+                    condition: condition.map(str::to_string),
+                    ..SourceBreakpoint::new(10)
+                }]),
+            })
+            .unwrap();
+        let stopped = reports_stop(adapter);
+        if stopped {
+            adapter.go().unwrap();
         }
+        stopped
+    }
 
-        log::warn!("No source file found for {package}.{class}");
-        None
+    #[test]
+    fn conditional_breakpoints() {
+        let log = CommandLog::default();
+        let (mut adapter, _) = make_adapter(
+            make_client,
+            ClientConfig::new(),
+            condition_mock().recording(&log),
+        );
+        assert!(!stop_at_conditional_breakpoint(&mut adapter, Some("False")));
+        assert!(stop_at_conditional_breakpoint(&mut adapter, Some("True")));
+
+        // A condition that can't be evaluated stops so the user can see what's wrong.
+        assert!(stop_at_conditional_breakpoint(&mut adapter, Some("Broken")));
+        assert!(stop_at_conditional_breakpoint(
+            &mut adapter,
+            Some("Count > 3")
+        ));
+        assert!(stop_at_conditional_breakpoint(&mut adapter, Some("Empty")));
+
+        // Resetting the breakpoints clears the condition.
+        assert!(stop_at_conditional_breakpoint(&mut adapter, None));
+        assert_eq!(
+            log.of(&["evaluate", "go"]),
+            vec![
+                "evaluate 0 False",
+                "go",
+                "evaluate 0 True",
+                "go",
+                "evaluate 0 Broken",
+                "go",
+                "evaluate 0 Count > 3",
+                "go",
+                "evaluate 0 Empty",
+                "go",
+                "go"
+            ]
+        );
     }
 
-    /// Given a source file that is not known to our class map, locate the correct location on
-    /// disk for that source, add it to the class map, and return a source entry for it.
-    /// the correct path.
-    fn translate_source(&mut self, canonical_name: String) -> Option<Source> {
-        // If this entry does not exist then we need to try to find it by searching source roots.
-        if !self.class_map.contains_key(&canonical_name) {
-            // This entry does not exist in our map, so try to locate the source file by searching
-            // the source roots.
-            let mut split = canonical_name.split('.');
-            let package = split.next().or_else(|| {
-                log::error!("Invalid class name {canonical_name}");
-                None
-            })?;
-            let class = split.next().or_else(|| {
-                log::error!("Invalid class name {canonical_name}");
-                None
-            })?;
+    #[test]
+    fn conditions_compare_with_none() {
+        let log = CommandLog::default();
+        let (mut adapter, _) = make_adapter(
+            make_client,
+            ClientConfig::new(),
+            condition_mock().recording(&log),
+        );
+        assert!(!stop_at_conditional_breakpoint(
+            &mut adapter,
+            Some("Target != none")
+        ));
+        assert!(stop_at_conditional_breakpoint(
+            &mut adapter,
+            Some("Target == None")
+        ));
+        assert!(stop_at_conditional_breakpoint(
+            &mut adapter,
+            Some("Owner != none")
+        ));
+        assert!(!stop_at_conditional_breakpoint(
+            &mut adapter,
+            Some("Target")
+        ));
+        assert_eq!(
+            log.of(&["evaluate", "go"]),
+            vec![
+                "evaluate 0 Target",
+                "go",
+                "evaluate 0 Target",
+                "go",
+                "evaluate 0 Owner",
+                "go",
+                "evaluate 0 Target",
+                "go",
+            ]
+        );
+    }
 
-            // Find the real source file, or return if we can't.
-            let full_path = self.find_source_file(package, class)?;
+    #[test]
+    fn step_ignores_breakpoint_condition() {
+        let log = CommandLog::default();
+        let (mut adapter, _) = make_adapter(
+            make_client,
+            ClientConfig::new(),
+            condition_mock().recording(&log),
+        );
+        adapter.stepping = true;
+        assert!(stop_at_conditional_breakpoint(&mut adapter, Some("False")));
+        assert_eq!(log.of(&["evaluate", "go"]), vec!["go"]);
+    }
 
-            // Split the source back out from the obtained filename. Unreal will provide qualified
-            // names in all uppercase, but the full path we return will have the on-disk casing.
-            // Use that instead since it's 1) less screamy, and 2) consistent with the sources we
-            // will add when the first time we encounter a source is from a setBreakpoints request
-            // instead of in an unreal callstack since the client will also give us the filename in
-            // canonicalized case.
-            let (package, class) = split_source(&full_path).ok().or_else(|| {
-                log::error!(
-                    "Failed to split canonicalized source back into package and class: {full_path}"
+    #[test]
+    fn log_message_interpolation() {
+        let evaluate = |expr: &str| (expr != "Broken").then(|| expr.to_lowercase());
+        assert_eq!(
+            interpolate_log_message("Hit {Count} times", evaluate),
+            "Hit count times"
+        );
+        assert_eq!(
+            interpolate_log_message("{ A }{B} and {Broken}", evaluate),
+            "ab and {Broken}"
+        );
+        assert_eq!(
+            interpolate_log_message("Unclosed {A} {B", evaluate),
+            "Unclosed a {B"
+        );
+        assert_eq!(interpolate_log_message("No braces", evaluate), "No braces");
+    }
+
+    #[test]
+    fn log_message_syntax() {
+        assert!(check_log_message("Hit {Count} times, {Pawn.Health}").is_ok());
+        assert!(check_log_message("No braces").is_ok());
+        assert_eq!(
+            check_log_message("Unclosed {Count"),
+            Err("Unclosed '{' at column 10".to_string())
+        );
+        assert_eq!(
+            check_log_message("Count}"),
+            Err("Unmatched '}' at column 6".to_string())
+        );
+        assert_eq!(
+            check_log_message("{A{B}}"),
+            Err("Nested '{' at column 3: the '{' at column 1 isn't closed".to_string())
+        );
+    }
+
+    #[test]
+    fn malformed_logpoint() {
+        let mut adapter = make_test_adapter();
+        let args = SetBreakpointsArguments {
+            source: Source {
+                name: None,
+                path: Some(GOOD_PATH.to_string()),
+                source_reference: None,
+            },
+            breakpoints: Some(vec![
+                SourceBreakpoint {
+                    log_message: Some("X is {X".to_string()),
+                    ..SourceBreakpoint::new(10)
+                },
+                SourceBreakpoint {
+                    log_message: Some("X is {X}".to_string()),
+                    ..SourceBreakpoint::new(20)
+                },
+            ]),
+        };
+        match adapter.set_breakpoints(&args).unwrap() {
+            ResponseBody::SetBreakpoints(body) => {
+                assert!(!body.breakpoints[0].verified);
+                assert_eq!(body.breakpoints[0].line, 10);
+                assert_eq!(
+                    body.breakpoints[0].message.as_deref(),
+                    Some("Invalid log message: Unclosed '{' at column 6")
                 );
-                None
-            })?;
+                assert!(body.breakpoints[1].verified);
+                assert!(body.breakpoints[1].message.is_none());
+            }
+            _ => panic!("Expected a set breakpoints response"),
+        }
 
-            // Put this entry in the map for later.
-            let class_info = ClassInfo {
-                file_name: full_path,
-                package_name: package,
-                class_name: class,
-                breakpoints: vec![],
-            };
-            self.class_map.insert(canonical_name.clone(), class_info);
+        // The malformed logpoint isn't set at all.
+        let class_info = &adapter.class_map["MYPACKAGE.SOMECLASS"];
+        assert_eq!(class_info.breakpoints, vec![20]);
+        assert!(class_info.log_messages.contains_key(&20));
+        assert!(!class_info.log_messages.contains_key(&10));
+    }
+
+    #[test]
+    fn logpoints() {
+        let log = CommandLog::default();
+        let (mut adapter, _) = make_adapter(
+            make_client,
+            ClientConfig::new(),
+            condition_mock().recording(&log),
+        );
+        let mut set_logpoint = |condition: &str| {
+            adapter
+                .set_breakpoints(&SetBreakpointsArguments {
+                    source: Source {
+                        name: None,
+                        path: Some(GOOD_PATH.to_string()),
+                        source_reference: None,
+                    },
+                    breakpoints: Some(vec![SourceBreakpoint {
+                        condition: Some(condition.to_string()),
+                        log_message: Some("X is {X}, {Broken}".to_string()),
+                        ..SourceBreakpoint::new(10)
+                    }]),
+                })
+                .unwrap();
+            !reports_stop(&mut adapter)
+        };
+
+        // A logpoint never stops, and only logs if its condition holds.
+        assert!(set_logpoint("True"));
+        assert!(set_logpoint("False"));
+        assert_eq!(
+            log.of(&["evaluate", "go"]),
+            vec![
+                "evaluate 0 True",
+                "evaluate 0 X",
+                "evaluate 0 Broken",
+                "go",
+                "evaluate 0 False",
+                "go"
+            ]
+        );
+    }
+
+    #[test]
+    fn pinned_expressions() {
+        let log = CommandLog::default();
+        let (mut adapter, _) = make_adapter(
+            make_client,
+            ClientConfig::new(),
+            condition_mock().recording(&log),
+        );
+        for expr in ["Count", "Pawn.Health", "Count"] {
+            adapter
+                .repl_command(ReplCommand::Watch(Some(expr.to_string())))
+                .unwrap();
         }
+        assert_eq!(
+            adapter.repl_command(ReplCommand::Watch(None)).unwrap(),
+            "Count\nPawn.Health"
+        );
 
-        // Find the entry: this should always succeed since we just added it if it wasn't there.
-        let entry = self.class_map.get(&canonical_name).unwrap();
-        Some(Source {
-            name: Some(entry.qualify()),
-            path: Some(entry.file_name.clone()),
-        })
+        // Each stop evaluates all the pinned expressions.
+        assert!(reports_stop(&mut adapter));
+        adapter.go().unwrap();
+        adapter
+            .repl_command(ReplCommand::Unwatch(Some("Count".to_string())))
+            .unwrap();
+        assert!(reports_stop(&mut adapter));
+        adapter.go().unwrap();
+        adapter.repl_command(ReplCommand::Unwatch(None)).unwrap();
+        assert!(reports_stop(&mut adapter));
+        assert_eq!(
+            log.of(&["evaluate", "go"]),
+            vec![
+                "evaluate 0 Count",
+                "evaluate 0 Pawn.Health",
+                "go",
+                "evaluate 0 Pawn.Health",
+                "go"
+            ]
+        );
+
+        for i in 0..MAX_PINNED_EXPRESSIONS {
+            adapter
+                .repl_command(ReplCommand::Watch(Some(format!("Var{i}"))))
+                .unwrap();
+        }
+        assert!(matches!(
+            adapter.repl_command(ReplCommand::Watch(Some("OneMore".to_string()))),
+            Err(UnrealscriptAdapterError::LimitExceeded(_))
+        ));
     }
 
-    fn disconnect(&mut self) -> Result<(), UnrealscriptAdapterError> {
-        self.connection.disconnect()?;
-        Ok(())
+    // A mock connection for an interface that has stopped responding. Sending the disconnect
+    // command gives up after a while, as a real connection does once its timeout expires.
+    fn hung_mock(timeout: Duration) -> MockConnection {
+        MockConnection::new(move |command| match command {
+            UnrealCommand::Disconnect => {
+                std::thread::sleep(timeout);
+                Some(Err(Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    "Timed out sending disconnect",
+                )))
+            }
+            _ => None,
+        })
     }
 
-    /// Fetch the stack from the interface and send it to the client.
-    fn stack_trace(
-        &mut self,
-        args: &StackTraceArguments,
-    ) -> Result<ResponseBody, UnrealscriptAdapterError> {
-        let start_frame = args
-            .start_frame
-            .unwrap_or(0)
-            .try_into()
-            .map_err(|e: TryFromIntError| UnrealscriptAdapterError::LimitExceeded(e.to_string()))?;
+    #[test]
+    fn disconnect_from_hung_interface() {
+        let timeout = Duration::from_millis(50);
+        let (mut adapter, tx) = make_adapter(make_client, ClientConfig::new(), hung_mock(timeout));
+        adapter.timeouts.disconnect = timeout;
 
-        let levels = args
-            .levels
-            .unwrap_or(0)
-            .try_into()
-            .map_err(|e: TryFromIntError| UnrealscriptAdapterError::LimitExceeded(e.to_string()))?;
+        // The interface never closes the connection.
+        let disconnect: Request = serde_json::from_str(
+            r#"{"type": "request", "seq": 1, "command": "disconnect", "arguments": {}}"#,
+        )
+        .unwrap();
+        tx.send(AdapterMessage::Request(disconnect)).unwrap();
+        let start = Instant::now();
+        adapter
+            .process_messages(Version {
+                major: 1,
+                minor: 0,
+                patch: 0,
+            })
+            .unwrap();
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
 
-        log::debug!("Stack trace request for {levels} frames starting at {start_frame}");
+    #[test]
+    fn connection_lost_shows_recent_log() {
+        let log = CommandLog::default();
+        let mut messages: Vec<AdapterMessage> = (0..25)
+            .map(|i| AdapterMessage::Event(UnrealEvent::Log(format!("line {i}\r\n"))))
+            .collect();
+        messages.push(AdapterMessage::ConnectionLost);
+        let transcript = run_session(MockConnection::default().recording(&log), messages);
 
-        let response = self.connection.stack_trace(StackTraceRequest {
-            start_frame,
-            levels,
-        })?;
-        Ok(ResponseBody::StackTrace(StackTraceResponseBody {
-            stack_frames: response
-                .frames
-                .into_iter()
-                .enumerate()
-                .map(|(i, f)| {
-                    let canonical_name = f.qualified_name.to_uppercase();
-                    // Find the source file for this class.
-                    let source = self.translate_source(canonical_name);
+        // The lines are shown before the session ends, or the client may not show them.
+        let lost = transcript
+            .iter()
+            .position(|message| {
+                message["event"] == "output"
+                    && message["body"]["output"]
+                        .as_str()
+                        .unwrap()
+                        .starts_with("The connection to the game was lost")
+            })
+            .expect("Expected a connection lost message");
+        let terminated = transcript
+            .iter()
+            .position(|message| message["event"] == "terminated")
+            .expect("Expected a terminated event");
+        assert!(lost < terminated);
 
-                    StackFrame {
-                        // We'll use the index into the stack frame vector as the id
-                        id: i as i64 + start_frame as i64,
-                        name: f.function_name,
-                        source,
-                        line: f.line as i64,
-                        column: 0,
-                    }
-                })
-                .collect(),
-        }))
+        // Only the most recent lines are kept.
+        let output = transcript[lost]["body"]["output"].as_str().unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        let expected: Vec<String> = (5..25).map(|i| format!("line {i}")).collect();
+        assert_eq!(lines[1..], expected);
     }
 
-    /// Return the scopes available in this suspended state. Unreal only supports two scopes: Local
-    /// and Global (the third watch kind for user watches is handled by DAP and we don't need
-    /// native support for it).
-    fn scopes(&mut self, args: &ScopesArguments) -> Result<ResponseBody, UnrealscriptAdapterError> {
-        let frame_index = FrameIndex::create(args.frame_id).or(Err(
-            UnrealscriptAdapterError::LimitExceeded("Frame index out of range".to_string()),
-        ))?;
+    fn accept_with_config(
+        config: ClientConfig,
+        command: Command,
+    ) -> Result<Option<ResponseBody>, UnrealscriptAdapterError> {
+        let (mut adapter, _) = make_adapter(make_client, config, MockConnection::default());
+        adapter.accept(&Request { seq: 1, command })
+    }
 
-        let globals_ref =
-            VariableReference::new(WatchKind::Global, frame_index, VariableIndex::SCOPE);
-        let locals_ref =
-            VariableReference::new(WatchKind::Local, frame_index, VariableIndex::SCOPE);
+    fn unknown_command(
+        acknowledge: bool,
+    ) -> Result<Option<ResponseBody>, UnrealscriptAdapterError> {
+        let mut config = ClientConfig::new();
+        config.acknowledge_unknown_commands = acknowledge;
+        accept_with_config(
+            config,
+            Command::Unknown("setExceptionBreakpoints".to_string()),
+        )
+    }
 
-        // For the top-most frame (0) only, fetch all the watch data from the debugger.
-        let local_var_info = if args.frame_id == 0 {
-            let child_count = self
-                .connection
-                .watch_count(WatchKind::Local, VariableIndex::SCOPE)?
-                .try_into()
-                .or(Err(UnrealscriptAdapterError::LimitExceeded(
-                    "Too many variables".to_string(),
-                )))?;
-            VariableReferenceInfo::new(locals_ref.to_int(), child_count, false)
-        } else {
-            VariableReferenceInfo::new_childless(locals_ref.to_int())
-        };
+    #[test]
+    fn unknown_command_is_error() {
+        match unknown_command(false) {
+            Err(UnrealscriptAdapterError::UnhandledCommand(name)) => {
+                assert_eq!(name, "setExceptionBreakpoints")
+            }
+            other => panic!("Expected an unhandled command error but got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn unknown_command_is_acknowledged() {
+        assert!(matches!(unknown_command(true), Ok(None)));
+    }
 
-        let global_var_info = if args.frame_id == 0 {
-            let child_count = self
-                .connection
-                .watch_count(WatchKind::Global, VariableIndex::SCOPE)?
-                .try_into()
-                .or(Err(UnrealscriptAdapterError::LimitExceeded(
-                    "Too many variables".to_string(),
-                )))?;
-            VariableReferenceInfo::new(globals_ref.to_int(), child_count, false)
+    #[test]
+    fn malformed_command_is_error() {
+        // A known command isn't acknowledged just because its arguments couldn't be parsed.
+        let mut config = ClientConfig::new();
+        config.acknowledge_unknown_commands = true;
+        let command = Command::Malformed("scopes".to_string(), "invalid type".to_string());
+        assert!(matches!(
+            accept_with_config(config, command),
+            Err(UnrealscriptAdapterError::InvalidArguments(name, _)) if name == "scopes"
+        ));
+    }
+
+    #[test]
+    fn initialize_reports_capabilities() {
+        let mut adapter = make_test_adapter();
+        let request: Request = serde_json::from_str(
+            r#"{"type": "request", "seq": 1, "command": "initialize", "arguments": {}}"#,
+        )
+        .unwrap();
+        match adapter.accept(&request) {
+            Ok(Some(ResponseBody::Initialize(Some(capabilities)))) => {
+                assert!(capabilities.supports_configuration_done_request);
+                assert!(capabilities.supports_log_points);
+                // That depends on the interface, so the client is told after the handshake.
+                assert!(!capabilities.supports_set_variable);
+            }
+            other => panic!("Expected an initialize response but got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn game_exits_during_initialize() {
+        let mut child = if cfg!(windows) {
+            std::process::Command::new("cmd")
+                .args(["/C", "exit 3"])
+                .spawn()
+                .unwrap()
         } else {
-            VariableReferenceInfo::new_childless(globals_ref.to_int())
+            std::process::Command::new("sh")
+                .args(["-c", "exit 3"])
+                .spawn()
+                .unwrap()
         };
+        child.wait().unwrap();
 
-        Ok(ResponseBody::Scopes(ScopesResponseBody {
-            scopes: vec![
-                Scope {
-                    name: "self".to_string(),
-                    variable_info: global_var_info,
-                    expensive: false,
-                },
-                Scope {
-                    name: "locals".to_string(),
-                    variable_info: local_var_info,
-                    expensive: false,
-                },
-            ],
-        }))
+        // The initialize handshake fails because the game has gone.
+        let connection = MockConnection::new(|command| match command {
+            UnrealCommand::Initialize(_) => Some(Err(Error::new(
+                std::io::ErrorKind::ConnectionReset,
+                "Connection reset",
+            ))),
+            _ => None,
+        });
+        let (tx, rx) = channel();
+        let mut adapter = UnrealscriptAdapter::new(
+            make_client(tx),
+            rx,
+            ClientConfig::new(),
+            Box::new(connection),
+            Some(child),
+            None,
+        );
+        let err = adapter
+            .process_messages(Version {
+                major: 1,
+                minor: 0,
+                patch: 0,
+            })
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::ConnectionAborted);
+        assert!(err
+            .to_string()
+            .starts_with("The game exited before debugging could start"));
+        assert!(err.to_string().contains('3'));
     }
 
+    #[test]
+    fn stopped_after_step_waits_to_settle() {
+        let mut config = ClientConfig::new();
+        config.step_settle_delay_ms = Some(50);
+        let (mut adapter, _) = make_adapter(make_client, config, MockConnection::default());
 
+        adapter.next().unwrap();
+        let start = Instant::now();
+        assert!(adapter.process_event(UnrealEvent::Stopped).is_some());
+        assert!(start.elapsed() >= Duration::from_millis(50));
 
-    fn evaluate(
-        &mut self,
-        args: &EvaluateArguments,
-    ) -> Result<ResponseBody, UnrealscriptAdapterError> {
-        let frame_index = match args.frame_id {
-            Some(f) => FrameIndex::create(f).or(Err(UnrealscriptAdapterError::LimitExceeded(
-                "Frame index out of range".to_string(),
-            )))?,
-            None => FrameIndex::TOP_FRAME,
-        };
+        // Stopping again without a step (e.g. a breakpoint) doesn't wait.
+        adapter.go().unwrap();
+        assert!(!adapter.stepping);
+        assert!(adapter.process_event(UnrealEvent::Stopped).is_some());
+    }
 
-        if is_invalid_expression(args.expression.as_str()) {
-            return Ok(ResponseBody::Evaluate(EvaluateResponseBody {
-                result: args.expression.clone(),
-                ty: None,
-                variable_info: VariableReferenceInfo::default(),
-            }));
-        }
+    // A client that records the events sent to it, for tests that need to see events the
+    // adapter sends directly rather than returning from process_event.
+    struct EventLogClient {
+        events: Arc<Mutex<Vec<Event>>>,
+    }
 
-        let mut var = self.connection.evaluate(frame_index, &args.expression)?;
+    impl Client for EventLogClient {
+        fn respond(&mut self, _response: Response) -> Result<(), Error> {
+            Ok(())
+        }
 
-        // We may get back a vector of length 0, which means that something has gone wrong with evaluating this
-        // expression. This is not a typical error, passing an invalid expression will usually
-        // still provide a valid response with a value indicating that the expression can't be
-        // resolved. Send an error back to the client in this case.
-        let var = var.pop().ok_or(UnrealscriptAdapterError::WatchError(
-            args.expression.clone(),
-        ))?;
+        fn send_event(&mut self, event: Event) -> Result<(), Error> {
+            self.events.lock().unwrap().push(event);
+            Ok(())
+        }
+    }
 
-        let child_count = self.get_child_count(WatchKind::User, &var);
+    #[test]
+    fn stopped_after_step_invalidates_stack() {
+        let mut config = ClientConfig::new();
+        config.supports_invalidated_event = true;
+        let transcript = run_configured_session(
+            config,
+            MockConnection::default(),
+            vec![
+                // The first stop has no earlier stack to invalidate.
+                AdapterMessage::Event(UnrealEvent::Stopped),
+                request(1, "next", serde_json::json!({"threadId": 1})),
+                AdapterMessage::Event(UnrealEvent::Stopped),
+            ],
+        );
+        assert_eq!(
+            transcript.iter().map(message_kind).collect::<Vec<_>>(),
+            [
+                "event initialized",
+                "event thread",
+                "event stopped",
+                "response next",
+                "event stopped",
+                "event invalidated",
+                "event thread",
+                "event terminated"
+            ]
+        );
+        assert_eq!(
+            transcript[5]["body"]["areas"],
+            serde_json::json!(["stacks"])
+        );
+        assert!(transcript[5]["body"].get("frameId").is_none());
+    }
 
-        Ok(ResponseBody::Evaluate(EvaluateResponseBody {
-            result: var.value,
-            ty: Some(var.ty),
-            variable_info: VariableReferenceInfo::new(
-                VariableReference::new(WatchKind::User, frame_index, var.index).to_int(),
-                child_count,
-                var.is_array,
-            ),
-        }))
+    // A mock connection with a large array of ints in every frame. Fetching variables from a
+    // frame other than the top one requires switching frames.
+    fn paging_mock() -> MockConnection {
+        MockConnection::new(|command| match command {
+            UnrealCommand::Variables(_, frame, _, start, count) => {
+                let (start, count) = (*start, *count);
+                let end = if count == 0 { 1000 } else { start + count };
+                let vars = (start..end.min(1000))
+                    .map(|i| Variable {
+                        name: format!("Counts[{i}]"),
+                        ty: "Int".to_string(),
+                        value: "0".to_string(),
+                        index: VariableIndex::create(i as u32 + 2).unwrap(),
+                        has_children: false,
+                        is_array: false,
+                    })
+                    .collect();
+                Some(Ok(if *frame == FrameIndex::TOP_FRAME {
+                    UnrealResponse::Variables(vars)
+                } else {
+                    UnrealResponse::DeferredVariables(vars)
+                }))
+            }
+            _ => None,
+        })
     }
 
-    /// Return the variables requested.
-    fn variables(
-        &mut self,
-        args: &VariablesArguments,
-    ) -> Result<ResponseBody, UnrealscriptAdapterError> {
-        let var = VariableReference::from_int(args.variables_reference).ok_or(
-            UnrealscriptAdapterError::LimitExceeded("Variable reference out of range".to_string()),
-        )?;
+    #[test]
+    fn variables_window() {
+        let events = Arc::new(Mutex::new(vec![]));
+        let log = CommandLog::default();
+        let mut config = ClientConfig::new();
+        config.supports_invalidated_event = true;
+        let (mut adapter, _) = make_adapter(
+            |_| EventLogClient {
+                events: events.clone(),
+            },
+            config,
+            paging_mock().recording(&log),
+        );
+        // The stack trace resolved the line of frame 1, but not frame 2.
+        adapter.frame_lines.insert(1, 25);
+        let mut window = |frame: i64, start: Option<i64>, count: Option<i64>| {
+            let array = VariableReference::new(
+                WatchKind::Local,
+                FrameIndex::create(frame).unwrap(),
+                VariableIndex::create(1).unwrap(),
+            );
+            match adapter.variables(&VariablesArguments {
+                variables_reference: array.to_int(),
+                start,
+                count,
+            }) {
+                Ok(ResponseBody::Variables(body)) => body
+                    .variables
+                    .into_iter()
+                    .map(|var| var.name)
+                    .collect::<Vec<_>>(),
+                other => panic!("Expected a variables response but got {other:?}"),
+            }
+        };
 
-        // Note: filtering is not implemented. In Unreal any given variable can have either named
-        // or indexed children, but not both. We will never send a variables/scopes response that
-        // has a non-zero count for both of these types, so we should also never receive a request
-        // for one of the types. Even if the client requested a particular filtering we would
-        // either send the whole list (if the filter matched) or nothing (if it didn't).
-        let (vars, invalidated) =
-            self.connection.variables(
-                var.kind(),
-                var.frame(),
-                var.variable(),
-                args.start.unwrap_or(0).try_into().or(Err(
-                    UnrealscriptAdapterError::LimitExceeded("Start index out of range".to_string()),
-                ))?,
-                args.count.unwrap_or(0).try_into().or(Err(
-                    UnrealscriptAdapterError::LimitExceeded("Count out of range".to_string()),
-                ))?,
-            )?;
+        assert_eq!(
+            window(0, Some(500), Some(2)),
+            vec!["Counts[500]", "Counts[501]"]
+        );
+        // Without a count the client gets everything from the start.
+        assert_eq!(
+            window(0, Some(998), None),
+            vec!["Counts[998]", "Counts[999]"]
+        );
+        assert_eq!(window(0, None, None).len(), 1000);
+        assert!(events.lock().unwrap().is_empty());
 
-        // If this response involved changing stacks, we aren't using the stack hack, and the client
-        // supports the feature, send an invalidated stack event for this frame.
-        //
-        // This is useful for Unreal because we don't have line information for anything except the
-        // top-most stack frame until we actually switch to that other frame. This event will
-        // instruct the client to refresh this single stack frame, which will allow us to provide a
-        // useful line number. This is not perfect: the client tries to switch to the source file
-        // and incorrect (0) line number before asking for the variables and before we send this
-        // event, so it will jump to the file but the wrong line the first time you switch to that
-        // stack frame. Clicking on it again will go to the correct line.
-        //
-        // When the stack hack is enabled we don't need this because we did fetch line number info
-        // for all frames when we received the callstack from Unreal and these were all returned
-        // in the original StackTraceResponse.
-        if invalidated && self.config.supports_invalidated_event && !self.config.enable_stack_hack {
-            log::trace!("Invalidating frame {}", var.frame());
-            self.client.send_event(Event {
+        // Only the window is fetched from the interface, with a count of 0 for everything.
+        assert_eq!(
+            log.entries(),
+            vec![
+                "variables Local 0 1 500 2",
+                "variables Local 0 1 998 0",
+                "variables Local 0 1 0 0"
+            ]
+        );
+
+        // A window in another frame invalidates that frame.
+        assert_eq!(window(2, Some(10), Some(1)), vec!["Counts[10]"]);
+        assert_eq!(log.entries().last().unwrap(), "variables Local 2 1 10 1");
+        assert!(matches!(
+            events.lock().unwrap().as_slice(),
+            [Event {
                 body: EventBody::Invalidated(InvalidatedEventBody {
-                    areas: vec![InvalidatedAreas::Stacks],
-                    frame_id: var.frame().into(),
+                    frame_id: Some(2),
+                    ..
                 }),
-            })?;
-        }
-        Ok(ResponseBody::Variables(VariablesResponseBody {
-            variables: vars
-                .iter()
-                .map(|v| {
-                    // If this variable is structured get the child count so we can put it in
-                    // the appropriate field of the response.
-                    let cnt = self.get_child_count(var.kind(), v);
-                    let variable_reference = if v.has_children {
-                        VariableReference::new(var.kind(), var.frame(), v.index).to_int()
-                    } else {
-                        0
-                    };
+            }]
+        ));
 
-                    dap::types::Variable {
-                        name: v.name.clone(),
-                        value: v.value.clone(),
-                        ty: if self.config.supports_variable_type {
-                            Some(v.ty.clone())
-                        } else {
-                            None
-                        },
-                        variable_info: VariableReferenceInfo::new(
-                            variable_reference,
-                            cnt,
-                            v.is_array,
-                        ),
-                    }
-                })
-                .collect(),
-        }))
+        // A frame whose line the stack trace already resolved has nothing to refresh.
+        assert_eq!(window(1, Some(10), Some(1)), vec!["Counts[10]"]);
+        assert_eq!(events.lock().unwrap().len(), 1);
     }
 
-    fn get_child_count(&mut self, kind: WatchKind, var: &Variable) -> i64 {
-        if var.has_children {
-            match self.connection.watch_count(kind, var.index) {
-                Ok(count) => count.try_into().unwrap_or_else(|_| {
-                    log::error!("Child count for var {} too large", var.name);
-                    0
-                }),
-                Err(e) => {
-                    log::error!("Failed to retrieve watch count for {var:?}: {e:?}");
-                    0
-                }
+    // A mock connection for an interface that takes a while to fetch the stack.
+    fn slow_mock() -> MockConnection {
+        MockConnection::new(|command| match command {
+            UnrealCommand::StackTrace(_) => {
+                std::thread::sleep(Duration::from_millis(40));
+                Some(Ok(UnrealResponse::StackTrace(common::StackTraceResponse {
+                    frames: vec![],
+                    total_frames: None,
+                })))
             }
-        } else {
-            0
-        }
+            _ => None,
+        })
     }
 
-    /// "Pause": Tell the debugger to break as soon as possible.
-    fn pause(&mut self) -> Result<(), UnrealscriptAdapterError> {
-        self.connection.pause()?;
-        Ok(())
+    #[test]
+    fn slow_stop_is_reported_once() {
+        let events = Arc::new(Mutex::new(vec![]));
+        let (mut adapter, _) = make_adapter(
+            |_| EventLogClient {
+                events: events.clone(),
+            },
+            ClientConfig::new(),
+            slow_mock(),
+        );
+        adapter.timeouts.slow_stop = Duration::from_millis(100);
+        let request: Request = serde_json::from_str(
+            r#"{"type": "request", "seq": 1, "command": "stackTrace", "arguments": {"threadId": 1}}"#,
+        )
+        .unwrap();
+        fn advisories(events: &[Event]) -> usize {
+            events
+                .iter()
+                .filter(|event| {
+                    matches!(&event.body, EventBody::Output(body) if body.output.contains("stack hack"))
+                })
+                .count()
+        }
+
+        // The time for each stop is counted separately.
+        adapter.process_event(UnrealEvent::Stopped);
+        adapter.accept(&request).unwrap();
+        adapter.go().unwrap();
+        adapter.process_event(UnrealEvent::Stopped);
+        adapter.accept(&request).unwrap();
+        assert_eq!(advisories(&events.lock().unwrap()), 0);
+
+        adapter.accept(&request).unwrap();
+        adapter.accept(&request).unwrap();
+        assert_eq!(advisories(&events.lock().unwrap()), 1);
+
+        // The user is only told once.
+        adapter.accept(&request).unwrap();
+        adapter.accept(&request).unwrap();
+        assert_eq!(advisories(&events.lock().unwrap()), 1);
     }
 
-    fn go(&mut self) -> Result<(), UnrealscriptAdapterError> {
-        self.connection.go()?;
-        Ok(())
+    // Send a stop from the interface, returning true if the client is told about it.
+    fn reports_stop(adapter: &mut UnrealscriptAdapter<impl Client>) -> bool {
+        matches!(
+            adapter.process_event(UnrealEvent::Stopped),
+            Some(Event {
+                body: EventBody::Stopped(_)
+            })
+        )
     }
 
-    fn next(&mut self) -> Result<(), UnrealscriptAdapterError> {
-        self.connection.next()?;
-        Ok(())
+    #[test]
+    fn duplicate_stop_is_ignored() {
+        let (mut adapter, _) = make_adapter(make_client, ClientConfig::new(), scope_mock());
+        assert!(reports_stop(&mut adapter));
+        assert!(adapter.process_event(UnrealEvent::Stopped).is_none());
+
+        // Once resumed the next stop is reported.
+        adapter.go().unwrap();
+        assert!(reports_stop(&mut adapter));
     }
 
-    fn step_in(&mut self) -> Result<(), UnrealscriptAdapterError> {
-        self.connection.step_in()?;
-        Ok(())
+    #[test]
+    fn events_after_disconnect_are_ignored() {
+        let (mut adapter, _) = make_adapter(make_client, ClientConfig::new(), scope_mock());
+        assert!(matches!(
+            adapter.process_event(UnrealEvent::Disconnect),
+            Some(Event {
+                body: EventBody::Terminated
+            })
+        ));
+        assert!(adapter.process_event(UnrealEvent::Stopped).is_none());
+        assert!(adapter.process_event(UnrealEvent::Disconnect).is_none());
     }
 
-    fn step_out(&mut self) -> Result<(), UnrealscriptAdapterError> {
-        self.connection.step_out()?;
-        Ok(())
+    #[test]
+    fn stop_after_client_disconnect_is_ignored() {
+        let log = CommandLog::default();
+        let (mut adapter, _) = make_adapter(
+            make_client,
+            ClientConfig::new(),
+            MockConnection::default().recording(&log),
+        );
+        adapter
+            .disconnect(&DisconnectArguments {
+                restart: None,
+                terminate_debuggee: None,
+            })
+            .unwrap();
+        assert!(adapter.process_event(UnrealEvent::Stopped).is_none());
     }
 
-    /// Process an event received from the interface, turning it into an event
-    /// to send to the client.
-    fn process_event(&mut self, evt: UnrealEvent) -> Option<Event> {
-        match evt {
-            UnrealEvent::Log(msg) => Some(Event {
-                body: EventBody::Output(OutputEventBody {
-                    category: OutputEventCategory::Stdout,
-                    output: msg,
-                }),
-            }),
-            UnrealEvent::Stopped => {
-                if self.config.auto_resume {
-                    log::info!("auto-resuming from initial breakpoint");
-                    self.config.auto_resume = false;
-                    match self.connection.go() {
-                        Ok(()) => return None,
-                        Err(e) => {
-                            log::error!("Error auto-resuming after initial breakpoint: {e}");
-                        }
-                    }
-                }
+    #[test]
+    fn startup_script_runs_after_configuration() {
+        let log = CommandLog::default();
+        let mut config = ClientConfig::new();
+        config.startup_script =
+            Some(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/startup.txt").to_string());
+        let (mut adapter, tx) = make_adapter(
+            make_client,
+            config,
+            MockConnection::default().recording(&log),
+        );
+        tx.send(request(1, "configurationDone", serde_json::Value::Null))
+            .unwrap();
+        tx.send(AdapterMessage::Shutdown).unwrap();
+        adapter
+            .process_messages(Version {
+                major: 1,
+                minor: 0,
+                patch: 0,
+            })
+            .unwrap();
 
-                Some(Event {
-                    body: EventBody::Stopped(StoppedEventBody {
-                        reason: StoppedEventReason::Breakpoint,
-                        thread_id: UNREAL_THREAD_ID,
-                    }),
-                })
-            }
-            UnrealEvent::Disconnect => {
-                // We've received a disconnect event from interface. This means
-                // the connection is shutting down. Send a terminated event to the
-                // client.
-                Some(Event {
-                    body: EventBody::Terminated,
-                })
-            }
-        }
+        // Both commands ran, in order, once configuration was done.
+        assert_eq!(log.entries(), vec!["initialize"]);
+        assert_eq!(adapter.pinned_expressions, vec!["Count", "Pawn.Health"]);
     }
-}
 
-/// The filename does not conform to the Unreal path conventions for class naming.
-#[derive(Debug)]
-pub struct BadFilenameError;
+    #[test]
+    fn break_on_attach() {
+        let log = CommandLog::default();
+        let mut config = ClientConfig::new();
+        config.break_on_attach = true;
+        let (mut adapter, tx) = make_adapter(
+            make_client,
+            config,
+            MockConnection::default().recording(&log),
+        );
+        tx.send(AdapterMessage::Event(UnrealEvent::Stopped))
+            .unwrap();
+        tx.send(AdapterMessage::Shutdown).unwrap();
+        adapter
+            .process_messages(Version {
+                major: 1,
+                minor: 0,
+                patch: 0,
+            })
+            .unwrap();
 
-/// Process a Source entry to obtain information about a class.
-///
-/// For Unrealscript the details of a class can be determined from its source file.
-/// Given a Source entry with a full path to a source file we expect the path to always
-/// be of the form:
-///
-/// &lt;arbitrary leading directories&gt;\Src\PackageName\Classes\ClassName.uc
-///
-/// From a path of this form we can isolate the package and class names. This naming
-/// scheme is mandatory: the Unreal debugger only talks about package and class names,
-/// and the client only talks about source files. The Unrealscript compiler uses these
-/// same conventions.
-pub fn split_source(path_str: &str) -> Result<(String, String), BadFilenameError> {
-    let path = Path::new(&path_str);
-    let mut iter = path.components().rev();
+        // The pause is sent once the handshake is done, and the stop is handled as usual.
+        assert_eq!(log.entries(), vec!["initialize", "pause"]);
+        assert_eq!(adapter.interface_state, InterfaceState::Stopped);
+    }
 
-    // Isolate the filename. This is the last component of the path and should have an extension to
-    // strip.
-    let component = iter.next().ok_or(BadFilenameError)?;
-    let class_name = match component {
-        Component::Normal(file_name) => Path::new(file_name).file_stem().ok_or(BadFilenameError),
-        _ => Err(BadFilenameError),
-    }?
-    .to_str()
-    .expect("Source path should be valid utf-8")
-    .to_owned();
+    // A mock connection for a game that breaks when paused.
+    fn peek_mock() -> MockConnection {
+        MockConnection::new(|command| match command {
+            UnrealCommand::StackTrace(_) => {
+                Some(Ok(UnrealResponse::StackTrace(common::StackTraceResponse {
+                    frames: vec![Frame {
+                        function_name: "Tick".to_string(),
+                        qualified_name: "MyPackage.SomeClass".to_string(),
+                        line: 10,
+                        state_name: None,
+                    }],
+                    total_frames: None,
+                })))
+            }
+            UnrealCommand::Evaluate(_, expr) => {
+                Some(Ok(UnrealResponse::Variables(vec![Variable {
+                    name: expr.clone(),
+                    ty: "Int".to_string(),
+                    value: "3".to_string(),
+                    index: VariableIndex::create(1).unwrap(),
+                    has_children: false,
+                    is_array: false,
+                }])))
+            }
+            _ => None,
+        })
+    }
 
-    // Skip the parent
-    iter.next();
+    #[test]
+    fn peek() {
+        let log = CommandLog::default();
+        let (mut adapter, tx) = make_adapter(
+            make_client,
+            ClientConfig::new(),
+            peek_mock().recording(&log),
+        );
+        adapter.pinned_expressions.push("Count".to_string());
+        tx.send(AdapterMessage::Event(UnrealEvent::Log("hello".to_string())))
+            .unwrap();
+        tx.send(AdapterMessage::Event(UnrealEvent::Stopped))
+            .unwrap();
 
-    // the package name should be the next component.
-    let component = iter.next().ok_or(BadFilenameError)?;
-    let package_name = match component {
-        Component::Normal(file_name) => Ok(file_name),
-        _ => Err(BadFilenameError),
-    }?
-    .to_str()
-    .expect("Source path should be vaild utf-8")
-    .to_owned();
-    Ok((package_name, class_name))
-}
+        assert_eq!(
+            adapter.repl_command(ReplCommand::Peek).unwrap(),
+            "Stopped in MyPackage.SomeClass.Tick line 10\nCount = 3"
+        );
+        assert_eq!(
+            log.of(&["pause", "stack", "evaluate", "go"]),
+            vec!["pause", "stack 0 1", "evaluate 0 Count", "go"]
+        );
+        assert_eq!(adapter.interface_state, InterfaceState::Running);
 
-#[cfg(test)]
-mod tests {
+        // The log line that arrived while waiting is still handled.
+        assert!(matches!(
+            adapter.next_message(),
+            Ok(AdapterMessage::Event(UnrealEvent::Log(line))) if line == "hello"
+        ));
+    }
 
-    use std::{
-        io::{Error, Stdout},
-        sync::mpsc::{channel, Sender},
-    };
+    #[test]
+    fn peek_at_breakpoint() {
+        let log = CommandLog::default();
+        let (mut adapter, tx) = make_adapter(
+            make_client,
+            ClientConfig::new(),
+            peek_mock().recording(&log),
+        );
+        adapter
+            .set_breakpoints(&SetBreakpointsArguments {
+                source: Source {
+                    name: None,
+                    path: Some(GOOD_PATH.to_string()),
+                    source_reference: None,
+                },
+                breakpoints: Some(vec![SourceBreakpoint::new(10)]),
+            })
+            .unwrap();
+        tx.send(AdapterMessage::Event(UnrealEvent::Stopped))
+            .unwrap();
 
-    use common::{UnrealCommand, UnrealResponse};
-    use dap::types::{Source, SourceBreakpoint};
+        // The game stopped at the breakpoint rather than for the peek, so it isn't resumed and
+        // the stop is handled next.
+        assert_eq!(
+            adapter.repl_command(ReplCommand::Peek).unwrap(),
+            "Stopped in MyPackage.SomeClass.Tick line 10"
+        );
+        assert!(!log.entries().contains(&"go".to_string()));
+        assert!(matches!(
+            adapter.next_message(),
+            Ok(AdapterMessage::Event(UnrealEvent::Stopped))
+        ));
+    }
 
-    use crate::client::ClientImpl;
+    #[test]
+    fn peek_while_stepping() {
+        let log = CommandLog::default();
+        let (mut adapter, _) = make_adapter(
+            make_client,
+            ClientConfig::new(),
+            peek_mock().recording(&log),
+        );
+        adapter.stepping = true;
+        assert_eq!(
+            adapter.repl_command(ReplCommand::Peek).unwrap(),
+            "Can't peek while stepping"
+        );
+        assert!(log.entries().is_empty());
+    }
 
-    use super::*;
+    #[test]
+    fn peek_disconnected() {
+        let log = CommandLog::default();
+        let (mut adapter, tx) = make_adapter(
+            make_client,
+            ClientConfig::new(),
+            peek_mock().recording(&log),
+        );
+        tx.send(AdapterMessage::ConnectionLost).unwrap();
+        match adapter.repl_command(ReplCommand::Peek) {
+            Err(UnrealscriptAdapterError::IoError(e)) => {
+                assert_eq!(e.kind(), std::io::ErrorKind::ConnectionAborted)
+            }
+            _ => panic!("Expected a connection error"),
+        }
+        // The lost connection is still handled.
+        assert!(matches!(
+            adapter.next_message(),
+            Ok(AdapterMessage::ConnectionLost)
+        ));
+    }
 
-    const GOOD_PATH: &str = if cfg!(windows) {
-        "C:\\foo\\src\\MyPackage\\classes\\SomeClass.uc"
-    } else {
-        "/home/somebody/src/MyPackage/classes/SomeClass.uc"
-    };
+    #[test]
+    fn peek_timeout() {
+        let log = CommandLog::default();
+        let (mut adapter, _) = make_adapter(
+            make_client,
+            ClientConfig::new(),
+            peek_mock().recording(&log),
+        );
+        adapter.timeouts.game_break = Duration::from_millis(50);
+        assert!(matches!(
+            adapter.repl_command(ReplCommand::Peek),
+            Err(e) if e.origin() == crate::ErrorOrigin::Interface
+        ));
+        assert_eq!(log.entries(), vec!["pause"]);
+    }
 
-    fn make_client(sender: Sender<AdapterMessage>) -> ClientImpl<Stdout> {
-        ClientImpl::new(std::io::stdin(), std::io::stdout(), sender)
+    // A mock connection for an interface that either drops the connection or answers every
+    // command with the wrong response.
+    fn broken_mock(reset: bool) -> MockConnection {
+        MockConnection::new(move |_| {
+            Some(if reset {
+                Err(Error::new(
+                    std::io::ErrorKind::ConnectionReset,
+                    "Connection reset",
+                ))
+            } else {
+                Ok(UnrealResponse::WatchCount(0))
+            })
+        })
+    }
+
+    fn stack_trace_error(reset: bool) -> UnrealscriptAdapterError {
+        let (mut adapter, _) = make_adapter(make_client, ClientConfig::new(), broken_mock(reset));
+        adapter
+            .stack_trace(&StackTraceArguments {
+                thread_id: UNREAL_THREAD_ID,
+                start_frame: None,
+                levels: None,
+            })
+            .unwrap_err()
     }
 
-    struct MockConnection {}
+    #[test]
+    fn error_origins() {
+        let e = stack_trace_error(true);
+        assert_eq!(e.origin(), crate::ErrorOrigin::Interface);
+        assert_eq!(e.to_error_message().format, "[interface] Connection reset");
 
-    // A mock connection for testing. This version does not use the low-level required
-    // trait methods: they all panic. It reimplements the high-level API to return mocked
-    // values instead.
-    impl Connection for MockConnection {
-        fn send_command(&mut self, _command: UnrealCommand) -> Result<(), Error> {
-            unreachable!();
-        }
+        let e = stack_trace_error(false);
+        assert_eq!(e.origin(), crate::ErrorOrigin::Protocol);
+        assert_eq!(
+            e.to_error_message().variables.unwrap()["origin"],
+            "protocol"
+        );
 
-        fn next_response(&mut self) -> Result<UnrealResponse, Error> {
-            unreachable!()
-        }
+        let mut adapter = make_test_adapter();
+        let e = adapter
+            .evaluate(&EvaluateArguments {
+                expression: ".bogus".to_string(),
+                frame_id: None,
+                context: Some(EvaluateContext::Repl),
+            })
+            .unwrap_err();
+        assert_eq!(e.origin(), crate::ErrorOrigin::Adapter);
+        assert_eq!(
+            e.to_error_message().format,
+            "[adapter] Unhandled command: .bogus"
+        );
+    }
 
-        fn add_breakpoint(&mut self, bp: Breakpoint) -> Result<Breakpoint, Error> {
-            Ok(bp)
-        }
+    // A mock connection for an interface that reports the object Unreal stopped in as a thread.
+    fn threads_mock() -> MockConnection {
+        MockConnection::new(|command| match command {
+            UnrealCommand::Threads => Some(Ok(UnrealResponse::Threads(vec![
+                "MyGame.MyPawn_0".to_string()
+            ]))),
+            _ => None,
+        })
+    }
 
-        fn remove_breakpoint(&mut self, bp: Breakpoint) -> Result<Breakpoint, Error> {
-            Ok(bp)
+    fn thread_names(adapter: &mut UnrealscriptAdapter<ClientImpl<Stdout>>) -> Vec<(i64, String)> {
+        match adapter.threads().unwrap() {
+            ResponseBody::Threads(body) => {
+                body.threads.into_iter().map(|t| (t.id, t.name)).collect()
+            }
+            other => panic!("Expected a threads response but got {other:?}"),
         }
+    }
 
-        fn stack_trace(
-            &mut self,
-            _req: StackTraceRequest,
-        ) -> Result<common::StackTraceResponse, Error> {
-            unreachable!()
-        }
+    #[test]
+    fn object_thread() {
+        let (mut adapter, _) = make_adapter(make_client, ClientConfig::new(), threads_mock());
+        adapter.supports_threads = true;
+        // The object is only known while the game is stopped.
+        assert_eq!(thread_names(&mut adapter), vec![(1, "main".to_string())]);
+        adapter.interface_state = InterfaceState::Stopped;
+        assert_eq!(
+            thread_names(&mut adapter),
+            vec![(1, "main".to_string()), (2, "MyGame.MyPawn_0".to_string())]
+        );
 
-        fn watch_count(
-            &mut self,
-            _kind: WatchKind,
-            _parent: VariableIndex,
-        ) -> Result<usize, Error> {
-            unreachable!()
+        // The object's thread has no stack.
+        match adapter.stack_trace(&StackTraceArguments {
+            thread_id: 2,
+            start_frame: None,
+            levels: None,
+        }) {
+            Ok(ResponseBody::StackTrace(body)) => {
+                assert!(body.stack_frames.is_empty());
+                assert_eq!(body.total_frames, Some(0));
+            }
+            other => panic!("Expected a stack trace response but got {other:?}"),
         }
+    }
 
-        fn evaluate(&mut self, _frame: FrameIndex, _expr: &str) -> Result<Vec<Variable>, Error> {
-            unreachable!()
-        }
+    #[test]
+    fn resume_thread_ids() {
+        let (mut adapter, _) = make_adapter(make_client, ClientConfig::new(), threads_mock());
+        adapter.supports_threads = true;
+        let mut resume = |command: &str, thread_id: i64| {
+            adapter.interface_state = InterfaceState::Stopped;
+            let request: Request = serde_json::from_value(serde_json::json!({
+                "type": "request",
+                "seq": 1,
+                "command": command,
+                "arguments": {"threadId": thread_id, "singleThread": true},
+            }))
+            .unwrap();
+            adapter.accept(&request)
+        };
 
-        fn variables(
-            &mut self,
-            _kind: WatchKind,
-            _frame: FrameIndex,
-            _variable: VariableIndex,
-            _start: usize,
-            _count: usize,
-        ) -> Result<(Vec<Variable>, bool), Error> {
-            unreachable!()
+        for command in ["continue", "next"] {
+            assert!(matches!(resume(command, UNREAL_THREAD_ID), Ok(None)));
+            // The object's thread is Unreal's thread too.
+            assert!(matches!(resume(command, 2), Ok(None)));
+            assert!(matches!(
+                resume(command, 3),
+                Err(UnrealscriptAdapterError::UnknownThread(3))
+            ));
         }
+    }
 
-        fn pause(&mut self) -> Result<(), Error> {
-            Ok(())
-        }
+    // A mock connection for an interface that may be able to restart frames and set variables.
+    fn capabilities_mock(supported: bool) -> MockConnection {
+        MockConnection::new(move |command| match command {
+            UnrealCommand::Initialize(init) => {
+                Some(Ok(UnrealResponse::Initialize(common::InitializeResponse {
+                    version: init.version.clone(),
+                    supports_list_breakpoints: false,
+                    supports_threads: false,
+                    supports_restart_frame: supported,
+                    supports_frame_line: false,
+                    supports_cancel_variable_request: false,
+                    supports_refresh_watches: false,
+                    supports_set_variable: supported,
+                })))
+            }
+            _ => None,
+        })
+    }
 
-        fn go(&mut self) -> Result<(), Error> {
-            Ok(())
-        }
+    #[test]
+    fn restart_frame() {
+        let version = Version {
+            major: 1,
+            minor: 0,
+            patch: 0,
+        };
+        let request: Request = serde_json::from_value(serde_json::json!({
+            "type": "request",
+            "seq": 1,
+            "command": "restartFrame",
+            "arguments": {"frameId": 1},
+        }))
+        .unwrap();
+        for supported in [false, true] {
+            let log = CommandLog::default();
+            let events = Arc::new(Mutex::new(vec![]));
+            let (mut adapter, _) = make_adapter(
+                |_| EventLogClient {
+                    events: events.clone(),
+                },
+                ClientConfig::new(),
+                capabilities_mock(supported).recording(&log),
+            );
+            adapter.handshake(&version).unwrap();
 
-        fn next(&mut self) -> Result<(), Error> {
-            Ok(())
+            // The client is only told about the capabilities if the interface has them.
+            let capabilities: Vec<(bool, bool, bool)> = events
+                .lock()
+                .unwrap()
+                .iter()
+                .filter_map(|e| match &e.body {
+                    EventBody::Capabilities(body) => Some((
+                        body.capabilities.supports_restart_frame,
+                        body.capabilities.supports_set_variable,
+                        body.capabilities.supports_set_expression,
+                    )),
+                    _ => None,
+                })
+                .collect();
+            let result = adapter.accept(&request);
+            if supported {
+                assert_eq!(capabilities, vec![(true, true, true)]);
+                assert!(matches!(result, Ok(None)));
+                assert!(adapter.stepping);
+                assert_eq!(log.of(&["restart"]), vec!["restart 1"]);
+            } else {
+                assert!(capabilities.is_empty());
+                assert!(matches!(
+                    result,
+                    Err(UnrealscriptAdapterError::UnhandledCommand(c)) if c == "restartFrame"
+                ));
+                assert!(log.of(&["restart"]).is_empty());
+            }
         }
+    }
+
+    // A client that records every response and event sent to it as JSON, for tests that drive
+    // a whole session through process_messages.
+    struct TranscriptClient {
+        messages: Arc<Mutex<Vec<serde_json::Value>>>,
+        cancellation: Cancellation,
+    }
 
-        fn step_in(&mut self) -> Result<(), Error> {
+    impl Client for TranscriptClient {
+        fn respond(&mut self, response: Response) -> Result<(), Error> {
+            self.messages
+                .lock()
+                .unwrap()
+                .push(serde_json::to_value(response).unwrap());
             Ok(())
         }
 
-        fn step_out(&mut self) -> Result<(), Error> {
+        fn send_event(&mut self, event: Event) -> Result<(), Error> {
+            self.messages
+                .lock()
+                .unwrap()
+                .push(serde_json::to_value(event).unwrap());
             Ok(())
         }
 
-        fn disconnect(&mut self) -> Result<(), Error> {
-            Ok(())
+        fn cancellation(&self) -> Cancellation {
+            self.cancellation.clone()
         }
     }
 
-    fn make_test_adapter() -> UnrealscriptAdapter<ClientImpl<Stdout>> {
-        let (tx, rx) = channel();
-        UnrealscriptAdapter::new(
-            make_client(tx),
-            rx,
-            ClientConfig::new(),
-            Box::new(MockConnection {}),
-            None,
-            None,
+    // A mock connection for a whole session: the game stops in SomeClass.Tick with a single
+    // local.
+    fn scripted_mock() -> MockConnection {
+        MockConnection::new(|command| match command {
+            UnrealCommand::StackTrace(_) => {
+                Some(Ok(UnrealResponse::StackTrace(common::StackTraceResponse {
+                    frames: vec![Frame {
+                        qualified_name: "MyPackage.SomeClass".to_string(),
+                        function_name: "Tick".to_string(),
+                        line: 10,
+                        state_name: None,
+                    }],
+                    total_frames: None,
+                })))
+            }
+            UnrealCommand::WatchCount(kind, _) => Some(Ok(UnrealResponse::WatchCount(
+                usize::from(matches!(kind, WatchKind::Local)),
+            ))),
+            UnrealCommand::Variables(kind, ..) => {
+                let variables = match kind {
+                    WatchKind::Local => vec![Variable {
+                        name: "Count".to_string(),
+                        ty: "int".to_string(),
+                        value: "3".to_string(),
+                        index: VariableIndex::create(1).unwrap(),
+                        has_children: false,
+                        is_array: false,
+                    }],
+                    _ => vec![],
+                };
+                Some(Ok(UnrealResponse::Variables(variables)))
+            }
+            _ => None,
+        })
+    }
+
+    // Make a request message from the client.
+    fn request(seq: i64, command: &str, arguments: serde_json::Value) -> AdapterMessage {
+        AdapterMessage::Request(
+            serde_json::from_value(serde_json::json!({
+                "type": "request",
+                "seq": seq,
+                "command": command,
+                "arguments": arguments,
+            }))
+            .unwrap(),
         )
     }
 
-    #[test]
-    fn can_split_source() {
-        let (package, class) = split_source(GOOD_PATH).unwrap();
-        assert_eq!(package, "MyPackage");
-        assert_eq!(class, "SomeClass");
+    // Run a whole session over the given connection. The messages are queued for the adapter
+    // as if they came from the client and the interface, followed by the end of the session.
+    // Returns everything sent to the client.
+    fn run_session(
+        connection: MockConnection,
+        messages: Vec<AdapterMessage>,
+    ) -> Vec<serde_json::Value> {
+        run_configured_session(ClientConfig::new(), connection, messages)
     }
 
-    #[test]
-    fn split_source_bad_classname() {
-        let path = if cfg!(windows) {
-            "C:\\MyMod\\BadClass.uc"
-        } else {
-            "/MyMod/BadClass.uc"
-        };
-        let info = split_source(path);
-        assert!(matches!(info, Err(BadFilenameError)));
+    // Run a whole session as with `run_session`, using the given configuration.
+    fn run_configured_session(
+        config: ClientConfig,
+        connection: MockConnection,
+        messages: Vec<AdapterMessage>,
+    ) -> Vec<serde_json::Value> {
+        let transcript = Arc::new(Mutex::new(vec![]));
+        let (mut adapter, tx) = make_adapter(
+            |_| TranscriptClient {
+                messages: transcript.clone(),
+                cancellation: Cancellation::default(),
+            },
+            config,
+            connection,
+        );
+        for message in messages {
+            tx.send(message).unwrap();
+        }
+        tx.send(AdapterMessage::Shutdown).unwrap();
+        adapter
+            .process_messages(Version {
+                major: 1,
+                minor: 0,
+                patch: 0,
+            })
+            .unwrap();
+        let messages = transcript.lock().unwrap().clone();
+        messages
     }
 
-    #[test]
-    fn split_source_forward_slashes() {
-        let (package, class) = split_source(GOOD_PATH).unwrap();
-        assert_eq!(package, "MyPackage");
-        assert_eq!(class, "SomeClass");
+    // Summarize a transcript message as the command or event it's for.
+    fn message_kind(message: &serde_json::Value) -> String {
+        match (message["command"].as_str(), message["event"].as_str()) {
+            (Some(command), _) => format!("response {command}"),
+            (_, Some(event)) => format!("event {event}"),
+            _ => panic!("Unexpected message {message}"),
+        }
     }
 
     #[test]
-    fn qualify_name() {
-        let class = ClassInfo::make(GOOD_PATH.to_string()).unwrap();
-        let qual = class.qualify();
-        assert_eq!(qual, "MyPackage.SomeClass")
+    fn stop_at_breakpoint_session() {
+        let log = CommandLog::default();
+        let locals = VariableReference::new(
+            WatchKind::Local,
+            FrameIndex::TOP_FRAME,
+            VariableIndex::SCOPE,
+        );
+        let transcript = run_session(
+            scripted_mock().recording(&log),
+            vec![
+                request(
+                    1,
+                    "setBreakpoints",
+                    serde_json::json!({
+                        "source": {"path": GOOD_PATH},
+                        "breakpoints": [{"line": 10}],
+                    }),
+                ),
+                AdapterMessage::Event(UnrealEvent::Stopped),
+                request(2, "stackTrace", serde_json::json!({"threadId": 1})),
+                request(3, "scopes", serde_json::json!({"frameId": 0})),
+                request(
+                    4,
+                    "variables",
+                    serde_json::json!({"variablesReference": locals.to_int()}),
+                ),
+                request(5, "continue", serde_json::json!({"threadId": 1})),
+                request(6, "disconnect", serde_json::json!({})),
+            ],
+        );
+
+        assert_eq!(
+            transcript.iter().map(message_kind).collect::<Vec<_>>(),
+            vec![
+                "event initialized",
+                "event thread",
+                "response setBreakpoints",
+                "event stopped",
+                "response stackTrace",
+                "response scopes",
+                "response variables",
+                "response continue",
+                "response disconnect",
+                "event thread",
+                "event terminated",
+            ]
+        );
+        assert!(transcript
+            .iter()
+            .filter(|message| message["command"].is_string())
+            .all(|response| response["success"] == true));
+        assert_eq!(transcript[2]["body"]["breakpoints"][0]["verified"], true);
+        assert_eq!(transcript[3]["body"]["reason"], "breakpoint");
+
+        let frame = &transcript[4]["body"]["stackFrames"][0];
+        assert_eq!(frame["name"], "Tick");
+        assert_eq!(frame["line"], 10);
+        assert_eq!(frame["source"]["path"], GOOD_PATH);
+
+        let scopes = &transcript[5]["body"]["scopes"];
+        assert_eq!(scopes[1]["name"], "locals");
+        assert_eq!(scopes[1]["variablesReference"], locals.to_int());
+
+        let variable = &transcript[6]["body"]["variables"][0];
+        assert_eq!(variable["name"], "Count");
+        assert_eq!(variable["value"], "3");
+
+        assert_eq!(
+            log.entries(),
+            vec![
+                "initialize",
+                "add 10",
+                "stack 0 0",
+                "count Local 0",
+                "count Global 0",
+                "variables Local 0 0 0 0",
+                "go",
+                "disconnect"
+            ]
+        );
     }
 
     #[test]
-    fn add_breakpoint_registers_class() {
-        let mut adapter = make_test_adapter();
-        let args = SetBreakpointsArguments {
-            source: Source {
-                name: None,
-                path: Some(GOOD_PATH.to_string()),
-            },
-            breakpoints: Some(vec![SourceBreakpoint { line: 10 }]),
-        };
-        let _response = adapter.set_breakpoints(&args).unwrap();
-        // Class cache should be keyed on UPCASED qualified names.
-        assert!(adapter.class_map.contains_key("MYPACKAGE.SOMECLASS"));
+    fn cancel_queued_variables_request() {
+        let log = CommandLog::default();
+        let locals = VariableReference::new(
+            WatchKind::Local,
+            FrameIndex::TOP_FRAME,
+            VariableIndex::SCOPE,
+        );
+        let transcript = run_session(
+            scripted_mock().recording(&log),
+            vec![
+                AdapterMessage::Event(UnrealEvent::Stopped),
+                request(
+                    2,
+                    "variables",
+                    serde_json::json!({"variablesReference": locals.to_int()}),
+                ),
+                request(
+                    3,
+                    "variables",
+                    serde_json::json!({"variablesReference": locals.to_int()}),
+                ),
+                request(4, "cancel", serde_json::json!({"requestId": 2})),
+                request(5, "disconnect", serde_json::json!({})),
+            ],
+        );
 
-        // The entry in this map should have 1 breakpoint
+        let responses = transcript
+            .iter()
+            .filter(|message| message["command"].is_string())
+            .collect::<Vec<_>>();
+        assert_eq!(responses[0]["request_seq"], 2);
+        assert_eq!(responses[0]["success"], false);
+        assert_eq!(responses[0]["message"], "cancelled");
+        assert_eq!(responses[1]["request_seq"], 3);
+        assert_eq!(responses[1]["success"], true);
+        assert_eq!(responses[1]["body"]["variables"][0]["name"], "Count");
+        assert_eq!(responses[2]["command"], "cancel");
+        assert_eq!(responses[2]["success"], true);
+
+        // Only the request that wasn't cancelled reached the interface.
         assert_eq!(
-            adapter.class_map["MYPACKAGE.SOMECLASS"].breakpoints,
-            vec![10]
+            log.entries(),
+            vec!["initialize", "variables Local 0 0 0 0", "disconnect"]
         );
     }
 
+    // A mock connection where the client cancels the first variables request while the
+    // interface is fetching it, and the interface gives up on it.
+    fn cancel_mock(cancellation: Cancellation) -> MockConnection {
+        let mut cancelled = false;
+        MockConnection::new(move |command| match command {
+            UnrealCommand::WatchCount(_, _) => Some(Ok(UnrealResponse::WatchCount(1))),
+            UnrealCommand::Variables(..) => {
+                if !std::mem::replace(&mut cancelled, true) {
+                    cancellation.cancel(2);
+                    return Some(Err(Error::new(
+                        std::io::ErrorKind::Interrupted,
+                        "Request cancelled",
+                    )));
+                }
+                Some(Ok(UnrealResponse::Variables(vec![Variable {
+                    name: "Count".to_string(),
+                    ty: "int".to_string(),
+                    value: "3".to_string(),
+                    index: VariableIndex::create(1).unwrap(),
+                    has_children: false,
+                    is_array: false,
+                }])))
+            }
+            _ => None,
+        })
+    }
+
     #[test]
-    fn add_multiple_breakpoints() {
-        let mut adapter = make_test_adapter();
-        let args = SetBreakpointsArguments {
-            source: Source {
-                name: None,
-                path: Some(GOOD_PATH.to_string()),
+    fn cancel_variables_request_in_flight() {
+        let transcript = Arc::new(Mutex::new(vec![]));
+        let cancellation = Cancellation::default();
+        let locals = VariableReference::new(
+            WatchKind::Local,
+            FrameIndex::TOP_FRAME,
+            VariableIndex::SCOPE,
+        );
+        let (mut adapter, tx) = make_adapter(
+            |_| TranscriptClient {
+                messages: transcript.clone(),
+                cancellation: cancellation.clone(),
             },
-            breakpoints: Some(vec![
-                SourceBreakpoint { line: 10 },
-                SourceBreakpoint { line: 105 },
-            ]),
-        };
-        let _response = adapter.set_breakpoints(&args).unwrap();
-        // The entry in this map should have 2 breakpoints
+            ClientConfig::new(),
+            cancel_mock(cancellation.clone()),
+        );
+        for message in [
+            AdapterMessage::Event(UnrealEvent::Stopped),
+            request(
+                2,
+                "variables",
+                serde_json::json!({"variablesReference": locals.to_int()}),
+            ),
+            request(
+                3,
+                "variables",
+                serde_json::json!({"variablesReference": locals.to_int()}),
+            ),
+            request(4, "disconnect", serde_json::json!({})),
+            AdapterMessage::Shutdown,
+        ] {
+            tx.send(message).unwrap();
+        }
+        adapter
+            .process_messages(Version {
+                major: 1,
+                minor: 0,
+                patch: 0,
+            })
+            .unwrap();
+
+        let transcript = transcript.lock().unwrap();
+        let responses = transcript
+            .iter()
+            .filter(|message| message["command"].is_string())
+            .collect::<Vec<_>>();
+        assert_eq!(responses[0]["request_seq"], 2);
+        assert_eq!(responses[0]["success"], false);
+        assert_eq!(responses[0]["message"], "cancelled");
+        assert_eq!(responses[1]["request_seq"], 3);
+        assert_eq!(responses[1]["success"], true);
+        assert_eq!(responses[1]["body"]["variables"][0]["name"], "Count");
+        assert!(!cancellation.is_cancelled());
+    }
+
+    #[test]
+    fn post_configuration_actions_run_in_order() {
+        let log = CommandLog::default();
+        let mut config = ClientConfig::new();
+        config.auto_resume = true;
+        config.startup_script =
+            Some(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/mute.txt").to_string());
+        let transcript = run_configured_session(
+            config,
+            scripted_mock().recording(&log),
+            vec![
+                AdapterMessage::Event(UnrealEvent::Stopped),
+                request(
+                    1,
+                    "setBreakpoints",
+                    serde_json::json!({
+                        "source": {"path": GOOD_PATH},
+                        "breakpoints": [{"line": 10}],
+                    }),
+                ),
+                request(2, "configurationDone", serde_json::Value::Null),
+                request(3, "disconnect", serde_json::json!({})),
+            ],
+        );
+
+        // The game was held at its initial breakpoint without telling the client, then
+        // released before the startup script muted the breakpoint.
+        assert!(!transcript
+            .iter()
+            .any(|message| message["event"] == "stopped"));
         assert_eq!(
-            adapter.class_map["MYPACKAGE.SOMECLASS"].breakpoints,
-            vec![10, 105]
+            log.entries(),
+            vec!["initialize", "add 10", "go", "remove 10", "disconnect"]
         );
     }
 
     #[test]
-    fn reset_breakpoints() {
-        let mut adapter = make_test_adapter();
-        let mut args = SetBreakpointsArguments {
-            source: Source {
-                name: None,
-                path: Some(GOOD_PATH.to_string()),
-            },
-            breakpoints: Some(vec![
-                SourceBreakpoint { line: 10 },
-                SourceBreakpoint { line: 105 },
-            ]),
+    fn restart_reuses_game_on_next_launch() {
+        let mut command = if cfg!(windows) {
+            let mut c = std::process::Command::new("cmd");
+            c.args(["/C", "ping -n 30 127.0.0.1 > nul"]);
+            c
+        } else {
+            let mut c = std::process::Command::new("sh");
+            c.args(["-c", "sleep 30"]);
+            c
         };
-        adapter.set_breakpoints(&args).unwrap();
-
-        // Set breakpoints in this class again.
-        args = SetBreakpointsArguments {
-            source: Source {
-                name: None,
-                path: Some(GOOD_PATH.to_string()),
+        let child = command.spawn().unwrap();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        // There's no Game.exe to spawn, so the launch can only succeed by reusing the game.
+        let launch_args = serde_json::json!({
+            "program": "Game.exe",
+            "args": ["-review"],
+            "port": listener.local_addr().unwrap().port(),
+            "connectAttempts": 1,
+        });
+        let messages = Arc::new(Mutex::new(vec![]));
+        let (tx, rx) = channel();
+        let mut adapter = UnrealscriptAdapter::new(
+            TranscriptClient {
+                messages: messages.clone(),
+                cancellation: Cancellation::default(),
             },
-            breakpoints: Some(vec![SourceBreakpoint { line: 26 }]),
+            rx,
+            ClientConfig::new(),
+            Box::new(MockConnection::default()),
+            Some(child),
+            None,
+        )
+        .with_launch_args(serde_json::from_value(launch_args.clone()).unwrap());
+        let disconnect = request(1, "disconnect", serde_json::json!({"restart": true}));
+        let AdapterMessage::Request(disconnect) = disconnect else {
+            unreachable!()
         };
-        // this should delete the two existing breakpoints and replace them
-        // with the new one.
-        adapter.set_breakpoints(&args).unwrap();
-        assert_eq!(
-            adapter.class_map["MYPACKAGE.SOMECLASS"].breakpoints,
-            vec![26]
-        );
+        adapter.accept(&disconnect).unwrap();
+
+        // Nothing is relaunched until the client asks.
+        let disconnected = adapter
+            .into_restart(tx.clone())
+            .expect("Expected a restart");
+        assert!(messages.lock().unwrap().is_empty());
+
+        tx.send(request(2, "launch", launch_args)).unwrap();
+        let connected = disconnected.connect();
+        assert!(connected.is_ok());
+        let messages = messages.lock().unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0]["command"], "launch");
+        assert_eq!(messages[0]["success"], true);
     }
 }
@@ -6,12 +6,15 @@
 //! manage the rest of the debugging session.
 
 use std::{
+    io::{BufRead, BufReader, Read},
     process::Child,
     sync::mpsc::{Receiver, Sender},
+    time::{Duration, Instant},
 };
 
 use common::{DEFAULT_PORT, PORT_VAR};
 use dap::{
+    events::{OutputEventBody, OutputEventCategory},
     requests::{AttachArguments, Command, InitializeArguments, LaunchArguments, Request},
     responses::{Response, ResponseBody},
     types::Capabilities,
@@ -19,10 +22,37 @@ use dap::{
 use flexi_logger::LogSpecification;
 
 use crate::{
-    client::Client, client_config::ClientConfig, comm::tcp::{TcpConnectTimeoutConfig, TcpConnection},
-    connected_adapter::UnrealscriptAdapter, AdapterMessage, UnrealscriptAdapterError, _LOGGER,
+    client::Client,
+    client_config::{ApplyToConfig, ClientConfig},
+    comm::{
+        tcp::{TcpConnectTimeoutConfig, TcpConnection},
+        Connection,
+    },
+    connected_adapter::{Reconnect, UnrealscriptAdapter},
+    output::{decode_output, send_limited_output, OutputLimiter},
+    AdapterMessage, UnrealscriptAdapterError, _LOGGER,
 };
 
+/// The capabilities the adapter reports to the client in response to an 'initialize' request.
+pub fn capabilities() -> Capabilities {
+    Capabilities {
+        supports_configuration_done_request: true,
+        supports_delayed_stack_trace_loading: true,
+        supports_evaluate_for_hovers: true,
+        supports_conditional_breakpoints: true,
+        supports_log_points: true,
+        supports_set_variable: false,
+        supports_set_expression: false,
+        supports_breakpoint_locations_request: true,
+        supports_function_breakpoints: true,
+        supports_single_thread_execution_requests: true,
+        supports_restart_frame: false,
+        // Queued requests can always be cancelled. A variables request already sent to the
+        // interface is only cancelled if the interface supports it, and otherwise finishes.
+        supports_cancel_request: true,
+    }
+}
+
 /// A representation of a disconnected adapter. This manages the portion of the
 /// protocol up to the point where a connection to the debuggee is established.
 pub struct DisconnectedAdapter<C: Client> {
@@ -30,6 +60,27 @@ pub struct DisconnectedAdapter<C: Client> {
     config: ClientConfig,
     sender: Sender<AdapterMessage>,
     receiver: Receiver<AdapterMessage>,
+    restart: Option<Box<Restart>>,
+    // Limits the output of a game launched without debugging, as in the connected adapter.
+    output_limiter: Option<OutputLimiter>,
+}
+
+/// A launched session the client disconnected from as part of a restart. The game is kept
+/// running for the client's next launch request, and closed if that doesn't reuse it.
+struct Restart {
+    args: LaunchArguments,
+    child: Option<Child>,
+}
+
+impl Drop for Restart {
+    fn drop(&mut self) {
+        if let Some(mut child) = self.child.take() {
+            log::info!("Closing the game kept for a restart.");
+            child.kill().unwrap_or_else(|e| {
+                log::error!("Failed to kill child process: {e:?}");
+            });
+        }
+    }
 }
 
 /// Error cases for a disconnected adapter.
@@ -53,7 +104,7 @@ pub enum DisconnectedAdapterError<C: Client> {
     /// We failed to connect, but still have valid communications with the client.
     /// We may be able to retry, so this error mode returns the same disconnected
     /// adapter so we can try again.
-    NoConnection(DisconnectedAdapter<C>),
+    NoConnection(Box<DisconnectedAdapter<C>>),
 }
 
 impl<C: Client> From<std::io::Error> for DisconnectedAdapterError<C> {
@@ -71,16 +122,36 @@ impl<C: Client> DisconnectedAdapter<C> {
     ) -> Self {
         DisconnectedAdapter {
             client,
-            config: ClientConfig {
-                one_based_lines: true,
-                supports_variable_type: false,
-                supports_invalidated_event: false,
-                source_roots: vec![],
-                enable_stack_hack: false,
-                auto_resume: false,
-            },
+            config: ClientConfig::new(),
+            sender,
+            receiver,
+            restart: None,
+            output_limiter: None,
+        }
+    }
+
+    /// Create a disconnected adapter for a launched session the client disconnected from to
+    /// restart it. The client restarts it with a new launch request, which reuses the game
+    /// launched for that session if it's still running.
+    pub fn restart(
+        client: C,
+        sender: Sender<AdapterMessage>,
+        receiver: Receiver<AdapterMessage>,
+        config: ClientConfig,
+        args: LaunchArguments,
+        child: Option<Child>,
+    ) -> Self {
+        let output_limiter = config
+            .max_output_per_second
+            .filter(|&rate| rate > 0)
+            .map(|rate| OutputLimiter::new(rate, Instant::now()));
+        DisconnectedAdapter {
+            client,
+            config,
             sender,
             receiver,
+            restart: Some(Box::new(Restart { args, child })),
+            output_limiter,
         }
     }
 
@@ -98,30 +169,63 @@ impl<C: Client> DisconnectedAdapter<C> {
                         Command::Launch(args) => return self.launch(&request, args),
                         Command::Disconnect(_) => {
                             log::info!("Received disconnect message during connection phase.");
-                            return Err(DisconnectedAdapterError::NoConnection(self));
+                            // The client isn't going to restart the session after all.
+                            self.restart = None;
+                            return Err(DisconnectedAdapterError::NoConnection(Box::new(self)));
+                        }
+                        Command::Malformed(name, reason) => {
+                            log::error!("Malformed {name} request in disconnected state.");
+                            self.client.respond(Response::make_error(
+                                &request,
+                                "Invalid Arguments".to_string(),
+                                UnrealscriptAdapterError::InvalidArguments(
+                                    name.clone(),
+                                    reason.clone(),
+                                )
+                                .to_error_message(),
+                            ))?;
                         }
                         // No other requests are expected in the disconnected state.
                         cmd => {
                             log::error!(
                                 "Unexpected command {} in disconnected state.",
-                                cmd.to_string()
+                                cmd.name()
                             );
                             //
                             self.client.respond(Response::make_error(
                                 &request,
                                 "Unhandled Command".to_string(),
-                                UnrealscriptAdapterError::UnhandledCommand(cmd.to_string())
+                                UnrealscriptAdapterError::UnhandledCommand(cmd.name())
                                     .to_error_message(),
                             ))?;
                         }
                     };
                 }
+                Ok(AdapterMessage::ProcessOutput(category, bytes)) => {
+                    // A game launched without debugging is still writing output.
+                    let output = decode_output(&bytes, self.config.normalize_output);
+                    send_limited_output(
+                        &mut self.client,
+                        self.output_limiter.as_mut(),
+                        OutputEventBody { category, output },
+                    )?;
+                }
+                Ok(AdapterMessage::InterfaceLog(line)) => {
+                    // Sent by an interface we were connected to before it closed.
+                    log::info!("Interface log after disconnecting: {}", line.trim_end());
+                }
                 Ok(AdapterMessage::Event(evt)) => {
                     return Err(DisconnectedAdapterError::IoError(std::io::Error::new(
                         std::io::ErrorKind::InvalidData,
                         format!("Received event {evt:?} in disconnected state."),
                     )));
                 }
+                Ok(AdapterMessage::ConnectionLost) => {
+                    return Err(DisconnectedAdapterError::IoError(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        "Lost interface connection in disconnected state.",
+                    )));
+                }
                 Ok(AdapterMessage::Shutdown) => {
                     log::info!("Adapter received shutdown message.");
                     return Err(DisconnectedAdapterError::IoError(std::io::Error::new(
@@ -150,21 +254,16 @@ impl<C: Client> DisconnectedAdapter<C> {
         // Build our client config.
         self.config = ClientConfig {
             one_based_lines: args.lines_start_at1.unwrap_or(true),
+            one_based_columns: args.columns_start_at1.unwrap_or(true),
             supports_variable_type: args.supports_variable_type.unwrap_or(false),
             supports_invalidated_event: args.supports_invalidated_event.unwrap_or(false),
-            source_roots: vec![],
-            enable_stack_hack: false,
-            auto_resume: false,
+            ..ClientConfig::new()
         };
 
         // Send the response.
         self.client.respond(Response::make_success(
             req,
-            ResponseBody::Initialize(Some(Capabilities {
-                supports_configuration_done_request: true,
-                supports_delayed_stack_trace_loading: true,
-                supports_evaluate_for_hovers:true,
-            })),
+            ResponseBody::Initialize(Some(capabilities())),
         ))?;
         Ok(())
     }
@@ -176,7 +275,27 @@ impl<C: Client> DisconnectedAdapter<C> {
 
         // Connect to the Unrealscript interface and set up the communications channel between
         // it and this adapter.
-        Ok(TcpConnection::connect(port, self.sender.clone(),timeout_config)?)
+        Ok(
+            TcpConnection::connect(port, self.sender.clone(), timeout_config)?
+                .with_cancellation(self.client.cancellation()),
+        )
+    }
+
+    /// Build the function the connected adapter uses to connect to the interface again if it
+    /// disconnects or the connection is lost.
+    fn reconnector(&self, port: u16) -> Reconnect {
+        let sender = self.sender.clone();
+        let cancellation = self.client.cancellation();
+        Box::new(move || {
+            // The adapter decides how often to try and how long to wait in between.
+            let timeout_config = TcpConnectTimeoutConfig {
+                connect_attempts: 1,
+                connect_timeout: Duration::ZERO,
+            };
+            let connection = TcpConnection::connect(port, sender.clone(), timeout_config)?
+                .with_cancellation(cancellation.clone());
+            Ok(Box::new(connection) as Box<dyn Connection>)
+        })
     }
 
     /// Attach to a running unreal process.
@@ -195,7 +314,7 @@ impl<C: Client> DisconnectedAdapter<C> {
     ) -> Result<UnrealscriptAdapter<C>, DisconnectedAdapterError<C>> {
         log::info!("Attach request");
 
-        if let Some(loglevel) = &args.log_level {
+        if let Some(loglevel) = &args.session.log_level {
             match LogSpecification::try_from(loglevel) {
                 Ok(newspec) => {
                     log::info!("Replacing log spec with {loglevel}");
@@ -213,13 +332,15 @@ impl<C: Client> DisconnectedAdapter<C> {
             }
         }
         let port = DEFAULT_PORT;
-        self.config.source_roots = args.source_roots.clone().unwrap_or_default();
-        self.config.enable_stack_hack = args.enable_stack_hack.unwrap_or(true);
+        args.session.apply_to(&mut self.config);
+        self.config.pause_on_disconnect = args.pause_on_disconnect.unwrap_or(false);
+        self.config.break_on_attach = args.break_on_attach.unwrap_or(false);
         match self.connect_to_interface(port,TcpConnectTimeoutConfig::default()) {
             Ok(connection) => {
                 // Connection succeeded: Respond with a success response and return
                 // the connected adapter.
                 self.client.respond(Response::make_ack(req))?;
+                let reconnect = self.reconnector(port);
 
                 Ok(UnrealscriptAdapter::new(
                     self.client,
@@ -227,8 +348,9 @@ impl<C: Client> DisconnectedAdapter<C> {
                     self.config,
                     Box::new(connection),
                     None,
-                    args.log_level.as_ref().cloned(),
-                ))
+                    args.session.log_level.as_ref().cloned(),
+                )
+                .with_reconnect(Some(reconnect)))
             }
             Err(e) => {
                 // Connection failed.
@@ -237,11 +359,34 @@ impl<C: Client> DisconnectedAdapter<C> {
                     "Connection Failed".to_string(),
                     e.to_error_message(),
                 ))?;
-                Err(DisconnectedAdapterError::NoConnection(self))
+                Err(DisconnectedAdapterError::NoConnection(Box::new(self)))
             }
         }
     }
 
+    /// Start the game for a launch request. If the client disconnected from the last session to
+    /// restart it, the game launched for that session is reused if it's still running and this
+    /// launch would start it the same way. Otherwise that game is closed and a new one started.
+    fn start_debuggee(
+        &mut self,
+        args: &LaunchArguments,
+        auto_debug: bool,
+    ) -> Result<Child, UnrealscriptAdapterError> {
+        if let Some(mut restart) = self.restart.take() {
+            let same_launch = auto_debug
+                && restart.args.program == args.program
+                && restart.args.args == args.args;
+            if let Some(mut child) = restart.child.take() {
+                if same_launch && matches!(child.try_wait(), Ok(None)) {
+                    log::info!("Reusing running game for restart.");
+                    return Ok(child);
+                }
+                restart.child = Some(child);
+            }
+        }
+        self.spawn_debuggee(args, auto_debug)
+    }
+
     /// Spawn the debuggee process according to the arguments given.
     fn spawn_debuggee(
         &self,
@@ -275,20 +420,28 @@ impl<C: Client> DisconnectedAdapter<C> {
 
         // Spawn the process.
         //
-        // Note we must disconnect all streams (or we could pipe them elsewhere...). By
-        // default in/out/err streams are inherited from the parent process, and we do _not_ want
-        // unreal writing to stdout or reading from stdin since those are our communication
-        // channel with the DAP client.
-        let child = command
+        // Note we must not let the process inherit our streams. By default in/out/err streams
+        // are inherited from the parent process, and we do _not_ want unreal writing to stdout
+        // or reading from stdin since those are our communication channel with the DAP client.
+        // Instead the output streams are piped and forwarded to the client as output events,
+        // since engine messages that don't go through the debugger log would otherwise be lost.
+        let mut child = command
             .stdin(std::process::Stdio::null())
-            .stdout(std::process::Stdio::null())
-            .stderr(std::process::Stdio::null())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
             .spawn()
             .or(Err(UnrealscriptAdapterError::InvalidProgram(format!(
                 "Failed to launch {0}",
                 program
             ))))?;
 
+        if let Some(stdout) = child.stdout.take() {
+            forward_output(stdout, OutputEventCategory::Stdout, self.sender.clone());
+        }
+        if let Some(stderr) = child.stderr.take() {
+            forward_output(stderr, OutputEventCategory::Stderr, self.sender.clone());
+        }
+
         Ok(child)
     }
 
@@ -344,7 +497,7 @@ impl<C: Client> DisconnectedAdapter<C> {
         args: &LaunchArguments,
     ) -> Result<UnrealscriptAdapter<C>, DisconnectedAdapterError<C>> {
         // Override the default log level if specified.
-        if let Some(loglevel) = &args.log_level {
+        if let Some(loglevel) = &args.session.log_level {
             match LogSpecification::try_from(loglevel) {
                 Ok(newspec) => {
                     log::info!("Replacing log spec with {loglevel}");
@@ -370,8 +523,15 @@ impl<C: Client> DisconnectedAdapter<C> {
         // attach, but that also requires the user to enable the debugger from the unreal side with
         // 'toggledebugger'.
         let auto_debug = !matches!(args.no_debug, Some(true));
+        args.session.apply_to(&mut self.config);
+        self.config.auto_resume = args.auto_resume.unwrap_or(false);
+        self.output_limiter = self
+            .config
+            .max_output_per_second
+            .filter(|&rate| rate > 0)
+            .map(|rate| OutputLimiter::new(rate, Instant::now()));
 
-        match self.spawn_debuggee(args, auto_debug) {
+        match self.start_debuggee(args, auto_debug) {
             Ok(child) => {
                 // If we're auto-debugging we can now connect to the interface.
                 if auto_debug {
@@ -379,10 +539,7 @@ impl<C: Client> DisconnectedAdapter<C> {
                         Ok(connection) => {
                             // Send a response ack for the launch request.
                             self.client.respond(Response::make_ack(req))?;
-                            self.config.source_roots =
-                                args.source_roots.clone().unwrap_or_default();
-                            self.config.auto_resume = args.auto_resume.unwrap_or(false);
-                            self.config.enable_stack_hack = args.enable_stack_hack.unwrap_or(true);
+                            let reconnect = self.reconnector(port);
 
                             Ok(UnrealscriptAdapter::new(
                                 self.client,
@@ -390,8 +547,10 @@ impl<C: Client> DisconnectedAdapter<C> {
                                 self.config,
                                 Box::new(connection),
                                 Some(child),
-                                args.log_level.as_ref().cloned(),
-                            ))
+                                args.session.log_level.as_ref().cloned(),
+                            )
+                            .with_launch_args(args.clone())
+                            .with_reconnect(Some(reconnect)))
                         }
                         Err(e) => {
                             // We launched, but failed to connect.
@@ -401,7 +560,7 @@ impl<C: Client> DisconnectedAdapter<C> {
                                 "Connection failed".to_string(),
                                 e.to_error_message(),
                             ))?;
-                            Err(DisconnectedAdapterError::NoConnection(self))
+                            Err(DisconnectedAdapterError::NoConnection(Box::new(self)))
                         }
                     }
                 } else {
@@ -409,7 +568,7 @@ impl<C: Client> DisconnectedAdapter<C> {
                     // client, but stay in the disconnected state.
                     log::info!("Launch request succeeded but autodebug is disabled. Remaining disconnected.");
                     self.client.respond(Response::make_ack(req))?;
-                    Err(DisconnectedAdapterError::NoConnection(self))
+                    Err(DisconnectedAdapterError::NoConnection(Box::new(self)))
                 }
             }
             Err(e) => {
@@ -419,8 +578,171 @@ impl<C: Client> DisconnectedAdapter<C> {
                     "Launch Failed".to_string(),
                     e.to_error_message(),
                 ))?;
-                Err(DisconnectedAdapterError::NoConnection(self))
+                Err(DisconnectedAdapterError::NoConnection(Box::new(self)))
+            }
+        }
+    }
+}
+
+/// Spawn a thread that reads lines from one of the debuggee's output streams and sends them to
+/// the adapter's message channel to be forwarded to the client as output events of the given
+/// category. The thread exits when the stream closes, i.e. when the debuggee exits, or when the
+/// adapter is no longer listening.
+fn forward_output<R: Read + Send + 'static>(
+    stream: R,
+    category: OutputEventCategory,
+    sender: Sender<AdapterMessage>,
+) {
+    std::thread::spawn(move || {
+        let mut reader = BufReader::new(stream);
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            match reader.read_until(b'\n', &mut buf) {
+                Ok(0) => return,
+                Ok(_) => {
+                    // The game is not guaranteed to write utf-8, so send the raw bytes and
+                    // let the adapter decode them according to the client configuration.
+                    if sender
+                        .send(AdapterMessage::ProcessOutput(category, buf.clone()))
+                        .is_err()
+                    {
+                        return;
+                    }
+                }
+                Err(e) => {
+                    log::error!("Error reading debuggee {category:?} stream: {e}");
+                    return;
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        process::Stdio,
+        sync::{mpsc::channel, Arc, Mutex},
+    };
+
+    use dap::events::{Event, EventBody};
+
+    use super::*;
+
+    // A client that records the events sent to it.
+    struct EventClient {
+        events: Arc<Mutex<Vec<Event>>>,
+    }
+
+    impl Client for EventClient {
+        fn respond(&mut self, _response: Response) -> Result<(), std::io::Error> {
+            Ok(())
+        }
+
+        fn send_event(&mut self, event: Event) -> Result<(), std::io::Error> {
+            self.events.lock().unwrap().push(event);
+            Ok(())
+        }
+    }
+
+    // Send five lines of game output to a disconnected adapter with the given output limit,
+    // returning the output shown to the client.
+    fn output_while_disconnected(max_output_per_second: Option<u32>) -> Vec<String> {
+        let events = Arc::new(Mutex::new(vec![]));
+        let (tx, rx) = channel();
+        let mut config = ClientConfig::new();
+        config.max_output_per_second = max_output_per_second;
+        // The game kept running for a restart is still writing output.
+        let adapter = DisconnectedAdapter::restart(
+            EventClient {
+                events: events.clone(),
+            },
+            tx.clone(),
+            rx,
+            config,
+            serde_json::from_value(serde_json::json!({})).unwrap(),
+            None,
+        );
+        for i in 0..5 {
+            tx.send(AdapterMessage::ProcessOutput(
+                OutputEventCategory::Stdout,
+                format!("line {i}\n").into_bytes(),
+            ))
+            .unwrap();
+        }
+        tx.send(AdapterMessage::Shutdown).unwrap();
+        assert!(matches!(
+            adapter.connect(),
+            Err(DisconnectedAdapterError::IoError(_))
+        ));
+
+        let events = events.lock().unwrap();
+        events
+            .iter()
+            .map(|event| match &event.body {
+                EventBody::Output(body) => body.output.clone(),
+                other => panic!("Expected an output event but got {other:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn limits_output_while_disconnected() {
+        assert_eq!(
+            output_while_disconnected(Some(2)),
+            vec!["line 0\n", "line 1\n"]
+        );
+    }
+
+    #[test]
+    fn zero_output_limit_is_unlimited() {
+        assert_eq!(output_while_disconnected(Some(0)).len(), 5);
+    }
+
+    #[test]
+    fn forwards_both_output_streams() {
+        let mut command = if cfg!(windows) {
+            let mut c = std::process::Command::new("cmd");
+            c.args(["/C", "echo to stdout & echo to stderr 1>&2"]);
+            c
+        } else {
+            let mut c = std::process::Command::new("sh");
+            c.args(["-c", "echo to stdout; echo to stderr 1>&2"]);
+            c
+        };
+        let mut child = command
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let (tx, rx) = channel();
+        forward_output(
+            child.stdout.take().unwrap(),
+            OutputEventCategory::Stdout,
+            tx.clone(),
+        );
+        forward_output(
+            child.stderr.take().unwrap(),
+            OutputEventCategory::Stderr,
+            tx,
+        );
+        child.wait().unwrap();
+
+        let mut stdout = None;
+        let mut stderr = None;
+        for msg in rx.iter() {
+            match msg {
+                AdapterMessage::ProcessOutput(OutputEventCategory::Stdout, s) => {
+                    stdout = Some(decode_output(&s, true))
+                }
+                AdapterMessage::ProcessOutput(OutputEventCategory::Stderr, s) => {
+                    stderr = Some(decode_output(&s, true))
+                }
+                other => panic!("Unexpected message {other:?}"),
             }
         }
+        assert_eq!(stdout.unwrap().trim_end(), "to stdout");
+        assert_eq!(stderr.unwrap().trim_end(), "to stderr");
     }
 }
@@ -5,10 +5,10 @@
 //! the Unrealscript Debugger Interface, which runs in the Unreal process.
 #![warn(missing_docs)]
 
-use std::sync::RwLock;
+use std::{collections::BTreeMap, fmt::Display, sync::RwLock};
 
 use common::UnrealEvent;
-use dap::{requests::Request, types::Message};
+use dap::{events::OutputEventCategory, requests::Request, types::Message};
 use flexi_logger::LoggerHandle;
 use thiserror::Error;
 pub mod client;
@@ -16,6 +16,10 @@ pub mod client_config;
 pub mod comm;
 pub mod connected_adapter;
 pub mod disconnected_adapter;
+pub mod output;
+pub mod repl;
+pub mod source;
+pub mod value_history;
 pub mod variable_reference;
 
 /// The logging instance for the adapter.
@@ -61,9 +65,62 @@ pub enum UnrealscriptAdapterError {
     /// This error does not mean that the expression could not be evaluated: that
     /// returns a watch variable with an error string in the "value". This can
     /// only occur when a new watch is registered but Unreal does not actually
-    /// give us any watch data, which should be impossible.
+    /// give us any watch data, which should be impossible, or when Unreal rejects
+    /// an assignment to a variable.
     #[error("Error setting watch for: {0}")]
     WatchError(String),
+
+    /// The text under the cursor for a hover request is not something we can evaluate,
+    /// e.g. a keyword or punctuation. This is expected and should not be shown to the user.
+    #[error("Not an evaluatable expression: {0}")]
+    NotEvaluatable(String),
+
+    /// A request referred to a stack frame that no longer exists, e.g. because execution has
+    /// resumed since the client fetched the stack.
+    #[error("Stack frame {0} no longer exists")]
+    StaleFrame(i64),
+
+    /// A location reference could not be resolved to a source location, e.g. because the
+    /// declaration is in a source file we can't find.
+    #[error("Location {0} could not be found")]
+    NotLocatable(i64),
+
+    /// The startup script could not be read, or contains a line that isn't an adapter command.
+    #[error("Invalid startup script: {0}")]
+    InvalidScript(String),
+
+    /// A request referred to a thread that doesn't exist.
+    #[error("Unknown thread {0}")]
+    UnknownThread(i64),
+
+    /// A request for a command we know had arguments we couldn't parse.
+    #[error("Invalid arguments for {0}: {1}")]
+    InvalidArguments(String, String),
+}
+
+/// Where an error came from, to tell the user whether to look at their configuration or at
+/// the game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorOrigin {
+    /// The adapter couldn't handle the request, e.g. because of a bad configuration or a
+    /// request that doesn't make sense.
+    Adapter,
+    /// The interface or the game failed, e.g. because the connection was lost or Unreal
+    /// didn't give us the information we asked for.
+    Interface,
+    /// The interface sent something the adapter didn't expect, which usually means the two are
+    /// different versions.
+    Protocol,
+}
+
+impl Display for ErrorOrigin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ErrorOrigin::Adapter => write!(f, "adapter"),
+            ErrorOrigin::Interface => write!(f, "interface"),
+            ErrorOrigin::Protocol => write!(f, "protocol"),
+        }
+    }
 }
 
 impl From<std::io::Error> for UnrealscriptAdapterError {
@@ -84,16 +141,49 @@ impl UnrealscriptAdapterError {
             UnrealscriptAdapterError::InvalidProgram(_) => 3,
             UnrealscriptAdapterError::LimitExceeded(_) => 4,
             UnrealscriptAdapterError::WatchError(_) => 5,
+            UnrealscriptAdapterError::NotEvaluatable(_) => 6,
+            UnrealscriptAdapterError::StaleFrame(_) => 7,
+            UnrealscriptAdapterError::NotLocatable(_) => 8,
+            UnrealscriptAdapterError::InvalidScript(_) => 9,
+            UnrealscriptAdapterError::UnknownThread(_) => 10,
+            UnrealscriptAdapterError::InvalidArguments(_, _) => 11,
+        }
+    }
+
+    /// Return where an error came from. I/O errors come from communicating with the interface,
+    /// and those with [`std::io::ErrorKind::InvalidData`] are responses we didn't expect.
+    pub fn origin(&self) -> ErrorOrigin {
+        match self {
+            UnrealscriptAdapterError::IoError(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+                ErrorOrigin::Protocol
+            }
+            UnrealscriptAdapterError::IoError(_) | UnrealscriptAdapterError::WatchError(_) => {
+                ErrorOrigin::Interface
+            }
+            UnrealscriptAdapterError::UnhandledCommand(_)
+            | UnrealscriptAdapterError::InvalidFilename(_)
+            | UnrealscriptAdapterError::NoProgram
+            | UnrealscriptAdapterError::InvalidProgram(_)
+            | UnrealscriptAdapterError::LimitExceeded(_)
+            | UnrealscriptAdapterError::NotEvaluatable(_)
+            | UnrealscriptAdapterError::StaleFrame(_)
+            | UnrealscriptAdapterError::NotLocatable(_)
+            | UnrealscriptAdapterError::InvalidScript(_)
+            | UnrealscriptAdapterError::UnknownThread(_)
+            | UnrealscriptAdapterError::InvalidArguments(_, _) => ErrorOrigin::Adapter,
         }
     }
 
     /// Convert an UnrealScriptAdapterError to a DAP error message suitable
-    /// for use as a body in an error response.
+    /// for use as a body in an error response. The message is tagged with the error's origin,
+    /// which is also sent as the `origin` variable for clients that want to inspect it.
     pub fn to_error_message(&self) -> Message {
+        let origin = self.origin();
         Message {
             id: self.id(),
-            format: self.to_string(),
-            show_user: true,
+            format: format!("[{origin}] {self}"),
+            show_user: !matches!(self, UnrealscriptAdapterError::NotEvaluatable(_)),
+            variables: Some(BTreeMap::from([("origin".to_string(), origin.to_string())])),
         }
     }
 }
@@ -106,6 +196,14 @@ pub enum AdapterMessage {
     Request(Request),
     /// An event from the interface
     Event(UnrealEvent),
+    /// Output written by a launched debuggee to its stdout or stderr streams. This is the raw
+    /// output which may not be utf-8, see [`output::decode_output`].
+    ProcessOutput(OutputEventCategory, Vec<u8>),
+    /// A line logged by the interface itself. See
+    /// [`client_config::ClientConfig::interface_log_level`].
+    InterfaceLog(String),
     /// The client has closed the connection.
     Shutdown,
+    /// The connection to the interface was lost unexpectedly, e.g. because the game crashed.
+    ConnectionLost,
 }
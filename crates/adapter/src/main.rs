@@ -5,7 +5,7 @@ use adapter::{
     disconnected_adapter::{DisconnectedAdapter, DisconnectedAdapterError},
     _LOGGER,
 };
-use common::{create_logger, Version};
+use common::{create_logger, new_session_id, set_session_id, Version};
 use pkg_version::{pkg_version_major, pkg_version_minor, pkg_version_patch};
 
 const ADAPTER_VERSION: Version = Version {
@@ -15,8 +15,13 @@ const ADAPTER_VERSION: Version = Version {
 };
 
 fn main() {
-    // Create the logging instance.
-    _LOGGER.write().unwrap().replace(create_logger("adapter"));
+    // Create the logging instance. Every line includes an id for this session, which is also
+    // sent to the interface so the two logs can be matched up.
+    set_session_id(Some(&new_session_id()));
+    _LOGGER
+        .write()
+        .unwrap()
+        .replace(create_logger("adapter", None));
 
     // Clients don't always connect stderr to anything so hook panics and write them to the log.
     std::panic::set_hook(Box::new(|p| {
@@ -25,7 +30,7 @@ fn main() {
 
     let (tx, rx) = channel();
     let client = ClientImpl::new(std::io::stdin(), std::io::stdout(), tx.clone());
-    let mut adapter = DisconnectedAdapter::new(client, tx, rx);
+    let mut adapter = DisconnectedAdapter::new(client, tx.clone(), rx);
 
     log::info!("Ready to start!");
     let return_code = loop {
@@ -33,10 +38,15 @@ fn main() {
             Ok(mut connected) => {
                 log::info!("Connection established!");
                 match connected.process_messages(ADAPTER_VERSION) {
-                    Ok(()) => {
-                        log::info!("Debugger session ended.");
-                        break 0;
-                    }
+                    Ok(()) => match connected.into_restart(tx.clone()) {
+                        // The client disconnected to restart the session: loop again to
+                        // wait for its launch request.
+                        Some(restarting) => adapter = restarting,
+                        None => {
+                            log::info!("Debugger session ended.");
+                            break 0;
+                        }
+                    },
                     Err(e) => {
                         log::error!("Adapter exiting with error {e}");
                         break 1;
@@ -47,7 +57,7 @@ fn main() {
                 // We failed to connect, or launched without attempting connection.
                 // If the former the client will just kill this process. If the
                 // latter then loop again and wait for an attach message.
-                adapter = a;
+                adapter = *a;
             }
             Err(DisconnectedAdapterError::IoError(e)) => {
                 log::error!("Received fatal error {e} while connecting. Aborting");
@@ -0,0 +1,301 @@
+//! Handling of text sent to the client in output events.
+//!
+//! Output events carry text from the Unreal log and from the output streams of a launched
+//! game, and optionally the interface's own log. This text is not guaranteed to be utf-8, since
+//! older games often write in their native Latin-1 encoding, and it usually uses Windows line
+//! endings. Some clients render this poorly, e.g. with garbled characters or double-spaced
+//! lines, so by default the text is normalized before it is sent.
+//!
+//! A runaway log can also flood the client with output events, so they may optionally be
+//! rate limited with an [`OutputLimiter`].
+
+use std::time::{Duration, Instant};
+
+use dap::events::{Event, EventBody, OutputEventBody, OutputEventCategory};
+
+use crate::client::Client;
+
+/// Convert raw output text to a string for an output event.
+///
+/// If `normalize` is true then text that is not valid utf-8 is decoded as Latin-1 and all
+/// line endings (CRLF or lone CR) are converted to LF. Otherwise the text is passed through
+/// unchanged except that invalid utf-8 sequences are replaced with the unicode replacement
+/// character, as DAP messages must be valid utf-8.
+pub fn decode_output(bytes: &[u8], normalize: bool) -> String {
+    if !normalize {
+        return String::from_utf8_lossy(bytes).into_owned();
+    }
+
+    let text = match std::str::from_utf8(bytes) {
+        Ok(s) => s.to_string(),
+        // Latin-1 maps each byte directly to the unicode code point of the same value.
+        Err(_) => bytes.iter().map(|&b| b as char).collect(),
+    };
+
+    text.replace("\r\n", "\n").replace('\r', "\n")
+}
+
+/// A token bucket rate limiter for output events.
+///
+/// The bucket holds up to one second's worth of events, so short bursts are passed through
+/// unchanged. Events beyond the rate are dropped and counted, and the count is reported
+/// either with the next event that is allowed through or at least once per second while the
+/// flood continues.
+#[derive(Debug)]
+pub struct OutputLimiter {
+    rate: u32,
+    tokens: f64,
+    last_refill: Instant,
+    suppressed: usize,
+    last_notice: Instant,
+}
+
+/// The result of checking an output event with [`OutputLimiter::admit`].
+#[derive(Debug, PartialEq, Eq)]
+pub struct Admission {
+    /// If true the event should be sent, otherwise it should be dropped.
+    pub send: bool,
+    /// The number of events dropped since the last notice, if a notice should be sent now.
+    pub notice: Option<usize>,
+}
+
+impl OutputLimiter {
+    /// Create a limiter allowing `rate` events per second.
+    pub fn new(rate: u32, now: Instant) -> Self {
+        OutputLimiter {
+            rate,
+            tokens: rate as f64,
+            last_refill: now,
+            suppressed: 0,
+            last_notice: now,
+        }
+    }
+
+    /// Check whether an output event arriving at time `now` may be sent.
+    pub fn admit(&mut self, now: Instant) -> Admission {
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate as f64).min(self.rate as f64);
+        self.last_refill = now;
+
+        let send = if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            self.suppressed += 1;
+            false
+        };
+
+        // Report suppressed events when the flood has let up, or periodically if it hasn't.
+        let notice = if self.suppressed > 0
+            && (send || now.saturating_duration_since(self.last_notice) >= Duration::from_secs(1))
+        {
+            self.last_notice = now;
+            Some(std::mem::take(&mut self.suppressed))
+        } else {
+            None
+        };
+
+        Admission { send, notice }
+    }
+}
+
+/// Choose the output category for a line of the Unreal log. Lines start with their log
+/// category, e.g. `ScriptWarning: Accessed None`, possibly after a timestamp. Warnings and
+/// errors are sent to stderr so the client can show them differently, and everything else,
+/// including lines without a category, to stdout.
+pub fn log_category(line: &str) -> OutputEventCategory {
+    let line = line.trim_start();
+    let line = match line.strip_prefix('[').and_then(|rest| rest.split_once(']')) {
+        Some((_, rest)) => rest.trim_start(),
+        None => line,
+    };
+    let Some((name, _)) = line.split_once(':') else {
+        return OutputEventCategory::Stdout;
+    };
+    if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        return OutputEventCategory::Stdout;
+    }
+    let name = name.to_ascii_lowercase();
+    if name.contains("warning") || name.contains("error") || name == "critical" {
+        OutputEventCategory::Stderr
+    } else {
+        OutputEventCategory::Stdout
+    }
+}
+
+/// The text of the notice sent in place of suppressed output events.
+pub fn suppressed_notice(count: usize) -> String {
+    format!("... suppressed {count} lines of output\n")
+}
+
+/// Send an output event to the client, subject to the given rate limiter if there is one. A
+/// notice of the events it dropped is sent first when it's due.
+///
+/// # Errors
+///
+/// Returns an i/o error if the client connection has closed.
+pub fn send_limited_output(
+    client: &mut impl Client,
+    limiter: Option<&mut OutputLimiter>,
+    body: OutputEventBody,
+) -> Result<(), std::io::Error> {
+    let admission = match limiter {
+        Some(limiter) => limiter.admit(Instant::now()),
+        None => Admission {
+            send: true,
+            notice: None,
+        },
+    };
+
+    if let Some(count) = admission.notice {
+        client.send_event(Event {
+            body: EventBody::Output(OutputEventBody {
+                category: OutputEventCategory::Console,
+                output: suppressed_notice(count),
+            }),
+        })?;
+    }
+    if admission.send {
+        client.send_event(Event {
+            body: EventBody::Output(body),
+        })?;
+    }
+    Ok(())
+}
+
+/// Build the output for a line logged by the interface. It is sent in the console category
+/// and marked as coming from the interface so it stands apart from the game's log.
+pub fn interface_log_output(line: String) -> OutputEventBody {
+    OutputEventBody {
+        category: OutputEventCategory::Console,
+        output: format!("[interface] {line}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_line_endings() {
+        assert_eq!(
+            decode_output(b"first\r\nsecond\rthird\n", true),
+            "first\nsecond\nthird\n"
+        );
+    }
+
+    #[test]
+    fn normalizes_latin1() {
+        assert_eq!(
+            decode_output(b"caf\xe9 na\xefve\r\n", true),
+            "caf\u{e9} na\u{ef}ve\n"
+        );
+    }
+
+    #[test]
+    fn preserves_when_not_normalizing() {
+        assert_eq!(decode_output(b"line\r\n", false), "line\r\n");
+        assert_eq!(decode_output(b"caf\xe9", false), "caf\u{fffd}");
+    }
+
+    #[test]
+    fn log_categories() {
+        for line in [
+            "ScriptWarning: Accessed None 'Pawn'\n",
+            "Error: Failed to load 'MyPackage'\n",
+            "[0012.34] ScriptError: Infinite script recursion\n",
+            "Critical: appError called\n",
+        ] {
+            assert!(
+                matches!(log_category(line), OutputEventCategory::Stderr),
+                "{line}"
+            );
+        }
+        for line in [
+            "ScriptLog: Health: 0\n",
+            "Log: Warning: not a category\n",
+            "[0012.34] Init: Engine initialized\n",
+            "No errors here\n",
+            "Said: an error occurred\n",
+        ] {
+            assert!(
+                matches!(log_category(line), OutputEventCategory::Stdout),
+                "{line}"
+            );
+        }
+    }
+
+    #[test]
+    fn interface_log_is_console_output() {
+        let body = interface_log_output("INFO interface::debugger: Connected\n".to_string());
+        assert!(matches!(body.category, OutputEventCategory::Console));
+        assert_eq!(
+            body.output,
+            "[interface] INFO interface::debugger: Connected\n"
+        );
+    }
+
+    #[test]
+    fn limits_bursts() {
+        let start = Instant::now();
+        let mut limiter = OutputLimiter::new(10, start);
+
+        // A burst of 25 events at once: the first 10 get through, the rest are suppressed.
+        let sent = (0..25).filter(|_| limiter.admit(start).send).count();
+        assert_eq!(sent, 10);
+
+        // Half a second later the bucket has refilled enough for another event, which carries
+        // the summary of what was dropped.
+        let later = start + Duration::from_millis(500);
+        assert_eq!(
+            limiter.admit(later),
+            Admission {
+                send: true,
+                notice: Some(15)
+            }
+        );
+        assert_eq!(
+            limiter.admit(later),
+            Admission {
+                send: true,
+                notice: None
+            }
+        );
+    }
+
+    #[test]
+    fn periodic_notice_during_flood() {
+        let start = Instant::now();
+        let mut limiter = OutputLimiter::new(1, start);
+        assert!(limiter.admit(start).send);
+        assert_eq!(
+            limiter.admit(start),
+            Admission {
+                send: false,
+                notice: None
+            }
+        );
+
+        // Still flooding a second later: the refilled token is used, and the notice sent.
+        let later = start + Duration::from_secs(1);
+        assert_eq!(
+            limiter.admit(later),
+            Admission {
+                send: true,
+                notice: Some(1)
+            }
+        );
+        let admission = limiter.admit(later + Duration::from_millis(100));
+        assert!(!admission.send);
+        assert_eq!(admission.notice, None);
+        assert_eq!(
+            limiter.admit(later + Duration::from_millis(1100)),
+            Admission {
+                send: true,
+                notice: Some(1)
+            }
+        );
+    }
+}
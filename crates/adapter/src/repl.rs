@@ -0,0 +1,244 @@
+//! Adapter commands entered in the debug console.
+//!
+//! Expressions evaluated from the client's REPL are normally sent to Unreal as watches, but
+//! those starting with a '.' are instead treated as commands to the adapter itself. These
+//! are mostly useful for inspecting the state of the debugger.
+//!
+//! The same commands can also be run from a startup script when a session connects, see
+//! [`read_script`].
+
+use crate::UnrealscriptAdapterError;
+
+/// The prefix marking a REPL expression as an adapter command.
+pub const COMMAND_PREFIX: char = '.';
+
+/// A command to the adapter entered in the REPL.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ReplCommand {
+    /// Show version and environment information for bug reports.
+    About,
+    /// Show the effective client configuration.
+    Caps,
+    /// Show the selected stack frame, or select the given one.
+    Frame(Option<i64>),
+    /// Navigate to the given object path, relative to the current one, or back to the top level
+    /// if there is none. `..` goes up a level, and levels are separated with `/`.
+    Cd(Option<String>),
+    /// List the children of the current object, or the locals at the top level.
+    Ls,
+    /// Pin the given expression so it is evaluated and shown each time execution stops, or
+    /// list the pinned expressions if there is none.
+    Watch(Option<String>),
+    /// Unpin the given expression, or all of them if there is none.
+    Unwatch(Option<String>),
+    /// Break briefly to show where the game is and the values of the pinned expressions, then
+    /// resume it.
+    Peek,
+    /// Remove all the breakpoints from Unreal while remembering them, so the game runs without
+    /// breaking.
+    Mute,
+    /// Set the breakpoints removed with `Mute` again.
+    Unmute,
+    /// Have the interface rebuild its watch lists and the client refetch its variables.
+    Refresh,
+}
+
+impl ReplCommand {
+    /// Parse a REPL command. The input is the text following the [`COMMAND_PREFIX`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`UnrealscriptAdapterError::UnhandledCommand`] if the command is not known.
+    pub fn parse(input: &str) -> Result<ReplCommand, UnrealscriptAdapterError> {
+        let mut words = input.split_whitespace();
+        // The rest of the input after the command, for commands taking an expression that may
+        // contain spaces.
+        let rest = input
+            .trim()
+            .split_once(char::is_whitespace)
+            .map(|(_, rest)| rest.trim().to_string());
+        match words.next().unwrap_or("") {
+            "about" => Ok(ReplCommand::About),
+            "caps" => Ok(ReplCommand::Caps),
+            "cd" => Ok(ReplCommand::Cd(words.next().map(str::to_string))),
+            "ls" => Ok(ReplCommand::Ls),
+            "watch" => Ok(ReplCommand::Watch(rest)),
+            "unwatch" => Ok(ReplCommand::Unwatch(rest)),
+            "peek" => Ok(ReplCommand::Peek),
+            "mute" => Ok(ReplCommand::Mute),
+            "unmute" => Ok(ReplCommand::Unmute),
+            "refresh" => Ok(ReplCommand::Refresh),
+            "frame" => match words.next() {
+                None => Ok(ReplCommand::Frame(None)),
+                Some(arg) => arg.parse().map(|n| ReplCommand::Frame(Some(n))).or(Err(
+                    UnrealscriptAdapterError::UnhandledCommand(format!(
+                        "{COMMAND_PREFIX}frame {arg}"
+                    )),
+                )),
+            },
+            other => Err(UnrealscriptAdapterError::UnhandledCommand(format!(
+                "{COMMAND_PREFIX}{other}"
+            ))),
+        }
+    }
+}
+
+/// Read a startup script of REPL commands, returning each command along with the line it was
+/// parsed from. Commands are one per line and the [`COMMAND_PREFIX`] is optional. Blank lines
+/// and lines starting with `#` are ignored.
+///
+/// # Errors
+///
+/// Returns [`UnrealscriptAdapterError::InvalidScript`] if the file can't be read or any line
+/// isn't a known command. In that case none of the script should be run.
+pub fn read_script(path: &str) -> Result<Vec<(String, ReplCommand)>, UnrealscriptAdapterError> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| UnrealscriptAdapterError::InvalidScript(format!("{path}: {e}")))?;
+    parse_script(&text).map_err(|e| UnrealscriptAdapterError::InvalidScript(format!("{path}, {e}")))
+}
+
+/// Parse the text of a startup script. On failure the error names the first bad line.
+fn parse_script(text: &str) -> Result<Vec<(String, ReplCommand)>, String> {
+    let mut commands = vec![];
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let input = line.strip_prefix(COMMAND_PREFIX).unwrap_or(line);
+        match ReplCommand::parse(input) {
+            Ok(command) => commands.push((line.to_string(), command)),
+            Err(e) => return Err(format!("line {}: {e}", number + 1)),
+        }
+    }
+    Ok(commands)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_about() {
+        assert_eq!(ReplCommand::parse("about").unwrap(), ReplCommand::About);
+    }
+
+    #[test]
+    fn parse_caps() {
+        assert_eq!(ReplCommand::parse("caps").unwrap(), ReplCommand::Caps);
+        assert_eq!(ReplCommand::parse(" caps ").unwrap(), ReplCommand::Caps);
+    }
+
+    #[test]
+    fn parse_cd() {
+        assert_eq!(ReplCommand::parse("cd").unwrap(), ReplCommand::Cd(None));
+        assert_eq!(
+            ReplCommand::parse("cd Pawn/Weapon").unwrap(),
+            ReplCommand::Cd(Some("Pawn/Weapon".to_string()))
+        );
+        assert_eq!(
+            ReplCommand::parse("cd ..").unwrap(),
+            ReplCommand::Cd(Some("..".to_string()))
+        );
+    }
+
+    #[test]
+    fn parse_ls() {
+        assert_eq!(ReplCommand::parse("ls").unwrap(), ReplCommand::Ls);
+    }
+
+    #[test]
+    fn parse_watch() {
+        assert_eq!(
+            ReplCommand::parse("watch").unwrap(),
+            ReplCommand::Watch(None)
+        );
+        assert_eq!(
+            ReplCommand::parse("watch  Pawn.Health * 2 ").unwrap(),
+            ReplCommand::Watch(Some("Pawn.Health * 2".to_string()))
+        );
+        assert_eq!(
+            ReplCommand::parse("unwatch").unwrap(),
+            ReplCommand::Unwatch(None)
+        );
+        assert_eq!(
+            ReplCommand::parse("unwatch Count").unwrap(),
+            ReplCommand::Unwatch(Some("Count".to_string()))
+        );
+    }
+
+    #[test]
+    fn parse_peek() {
+        assert_eq!(ReplCommand::parse("peek").unwrap(), ReplCommand::Peek);
+    }
+
+    #[test]
+    fn parse_mute() {
+        assert_eq!(ReplCommand::parse("mute").unwrap(), ReplCommand::Mute);
+        assert_eq!(ReplCommand::parse("unmute").unwrap(), ReplCommand::Unmute);
+    }
+
+    #[test]
+    fn parse_refresh() {
+        assert_eq!(ReplCommand::parse("refresh").unwrap(), ReplCommand::Refresh);
+    }
+
+    #[test]
+    fn parse_frame() {
+        assert_eq!(
+            ReplCommand::parse("frame").unwrap(),
+            ReplCommand::Frame(None)
+        );
+        assert_eq!(
+            ReplCommand::parse("frame 2").unwrap(),
+            ReplCommand::Frame(Some(2))
+        );
+        assert!(matches!(
+            ReplCommand::parse("frame top"),
+            Err(UnrealscriptAdapterError::UnhandledCommand(c)) if c == ".frame top"
+        ));
+    }
+
+    #[test]
+    fn parse_unknown() {
+        assert!(matches!(
+            ReplCommand::parse("bogus"),
+            Err(UnrealscriptAdapterError::UnhandledCommand(c)) if c == ".bogus"
+        ));
+    }
+
+    #[test]
+    fn parse_script_lines() {
+        let commands =
+            parse_script("# Pin some things\n.watch Count\n\n  watch Pawn.Health\n").unwrap();
+        assert_eq!(
+            commands,
+            vec![
+                (
+                    ".watch Count".to_string(),
+                    ReplCommand::Watch(Some("Count".to_string()))
+                ),
+                (
+                    "watch Pawn.Health".to_string(),
+                    ReplCommand::Watch(Some("Pawn.Health".to_string()))
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_script() {
+        assert_eq!(
+            parse_script(".about\n.bogus\n").unwrap_err(),
+            "line 2: Unhandled command: .bogus"
+        );
+    }
+
+    #[test]
+    fn missing_script() {
+        assert!(matches!(
+            read_script("no/such/script.txt"),
+            Err(UnrealscriptAdapterError::InvalidScript(e)) if e.starts_with("no/such/script.txt: ")
+        ));
+    }
+}
@@ -0,0 +1,603 @@
+//! Scanning of Unrealscript source files.
+//!
+//! Unreal attributes breakpoints and stops to the first line of a statement, so a
+//! breakpoint on any other line of a statement that spans several lines never fires.
+//! This module locates statement boundaries so such breakpoints can be moved, and so clients
+//! can be told which lines a breakpoint can be set on at all.
+//!
+//! It also scans source roots for the classes they contain, so source files can be found
+//! without probing the file system for each class.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+};
+
+/// Return the first line of the statement containing the given line. Lines are 1-based.
+///
+/// This is a heuristic line-based scan: a line continues a statement if the code on the
+/// line above doesn't end one, e.g. with `;` or a brace. Lines that are blank, outside the
+/// file, or aren't part of a multi-line statement are returned unchanged.
+pub fn statement_start(source: &str, line: i32) -> i32 {
+    let lines: Vec<&str> = source.lines().collect();
+    let index = match usize::try_from(line - 1) {
+        Ok(index) if index < lines.len() => index,
+        _ => return line,
+    };
+
+    if code_part(lines[index]).is_empty() {
+        return line;
+    }
+
+    let mut start = index;
+    while start > 0 && !ends_statement(code_part(lines[start - 1])) {
+        start -= 1;
+    }
+
+    // Lines are 1-based and the start can't be beyond the original line, so this fits.
+    start as i32 + 1
+}
+
+/// Return the lines a breakpoint can usefully be set on: the first line of each statement or
+/// declaration, skipping blank lines and comments. Lines are 1-based.
+///
+/// Like [`statement_start`] this is a heuristic line-based scan, and continuation lines of a
+/// statement are excluded in the same way.
+pub fn breakpoint_lines(source: &str) -> BTreeSet<i32> {
+    let mut lines = BTreeSet::new();
+    let mut in_comment = false;
+    let mut starts_statement = true;
+    for (index, line) in source.lines().enumerate() {
+        let mut code = code_part(line);
+
+        // Skip over block comments, but keep any code after one ends on a line.
+        if in_comment {
+            match code.find("*/") {
+                Some(pos) => {
+                    in_comment = false;
+                    code = code[pos + 2..].trim();
+                }
+                None => continue,
+            }
+        }
+        if let Some(comment) = code.strip_prefix("/*") {
+            match comment.find("*/") {
+                Some(pos) => code = comment[pos + 2..].trim(),
+                None => {
+                    in_comment = true;
+                    continue;
+                }
+            }
+        }
+
+        if code.is_empty() {
+            starts_statement = true;
+            continue;
+        }
+        if starts_statement {
+            lines.insert(index as i32 + 1);
+        }
+        starts_statement = ends_statement(code);
+    }
+    lines
+}
+
+/// Return the line of the first statement of each function defined in the given source, in
+/// order. Lines are 1-based.
+///
+/// Like [`statement_start`] this is a heuristic line-based scan. A function starts at a line
+/// with the `function` or `event` keyword, and its first statement is the first line in its
+/// body that isn't a `local` declaration. Declarations without a body, e.g. of native
+/// functions, and functions with no statements are skipped.
+pub fn function_entry_lines(source: &str) -> Vec<i32> {
+    function_entries(source)
+        .into_iter()
+        .map(|(_, line)| line)
+        .collect()
+}
+
+/// Return the line of the first statement of each function with the given name, compared
+/// case-insensitively. There may be several, e.g. if the function is overridden in a state.
+pub fn named_function_entry_lines(source: &str, function: &str) -> Vec<i32> {
+    function_entries(source)
+        .into_iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case(function))
+        .map(|(_, line)| line)
+        .collect()
+}
+
+// Return the name and first statement line of each function in the given source.
+fn function_entries(source: &str) -> Vec<(&str, i32)> {
+    #[derive(PartialEq)]
+    enum State {
+        // Looking for the next function.
+        Outside,
+        // In a function header, looking for the start of its body.
+        Header,
+        // In a function body, looking for its first statement.
+        Body,
+        // In a function body after its first statement, looking for its end.
+        Rest,
+    }
+
+    let mut entries = vec![];
+    let mut state = State::Outside;
+    let mut name = "";
+    // The depth of braces in the function body, counting the body's own.
+    let mut depth = 0;
+    for (index, line) in source.lines().enumerate() {
+        let mut code = code_part(line);
+        if state == State::Outside && is_function_header(code) {
+            state = State::Header;
+            name = function_name(code);
+        }
+        if state == State::Header {
+            match code.find('{') {
+                Some(pos) => {
+                    state = State::Body;
+                    depth = 1;
+                    code = code[pos + 1..].trim();
+                }
+                None => {
+                    if code.ends_with(';') {
+                        state = State::Outside;
+                    }
+                    continue;
+                }
+            }
+        }
+        if state == State::Body && !code.is_empty() && !is_local_declaration(code) {
+            state = State::Rest;
+            if !code.starts_with('}') {
+                // Lines are 1-based, and a file with more lines than fit in an i32 won't fit
+                // in memory either.
+                entries.push((name, index as i32 + 1));
+            }
+        }
+        if state == State::Body || state == State::Rest {
+            for c in code.chars() {
+                match c {
+                    '{' => depth += 1,
+                    '}' => depth -= 1,
+                    _ => (),
+                }
+            }
+            if depth <= 0 {
+                state = State::Outside;
+            }
+        }
+    }
+    entries
+}
+
+/// Find the declaration of the named local variable or parameter of the function containing
+/// the given line, returning the line and column of the name. Lines and columns are 1-based,
+/// and Unrealscript names are not case sensitive.
+///
+/// This scans back from the given line through the function's `local` declarations to its
+/// header. Returns None if the name isn't declared there.
+pub fn local_declaration(source: &str, line: i32, name: &str) -> Option<(i32, i32)> {
+    let lines: Vec<&str> = source.lines().collect();
+    let end = usize::try_from(line).ok()?.min(lines.len());
+    for index in (0..end).rev() {
+        let code = code_part(lines[index]);
+        if is_local_declaration(code) {
+            if let Some(column) = find_name(lines[index], name) {
+                return Some((index as i32 + 1, column));
+            }
+        } else if is_function_header(code) {
+            // Parameters may be declared on the lines following the header, up to the body.
+            for (offset, header) in lines[index..end].iter().enumerate() {
+                if let Some(column) = find_name(header, name) {
+                    return Some(((index + offset) as i32 + 1, column));
+                }
+                if code_part(header).contains('{') {
+                    break;
+                }
+            }
+            return None;
+        }
+    }
+    None
+}
+
+/// Find the declaration of the named member variable of the class in the given source,
+/// returning the line and column of the name. Lines and columns are 1-based. Members declared
+/// in a parent class are not found.
+pub fn member_declaration(source: &str, name: &str) -> Option<(i32, i32)> {
+    source.lines().enumerate().find_map(|(index, line)| {
+        let is_var = code_part(line)
+            .split(|c: char| !c.is_alphanumeric())
+            .next()
+            .is_some_and(|word| word.eq_ignore_ascii_case("var"));
+        let column = find_name(line, name).filter(|_| is_var)?;
+        Some((index as i32 + 1, column))
+    })
+}
+
+/// Find a call to the named function in a line of source, returning the 1-based column of the
+/// function's name. A call is the name as a whole word followed by its argument list, and
+/// names are not case sensitive. Returns None if the line doesn't call the function.
+pub fn line_call_column(text: &str, function: &str) -> Option<i32> {
+    let code = match text.find("//") {
+        Some(pos) => &text[..pos],
+        None => text,
+    };
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    // Lower-casing ASCII doesn't change byte offsets, so positions in one apply to the other.
+    let lower = code.to_ascii_lowercase();
+    let name = function.to_ascii_lowercase();
+    let (pos, _) = lower.match_indices(&name).find(|(pos, _)| {
+        !code[..*pos].ends_with(is_ident) && code[pos + name.len()..].trim_start().starts_with('(')
+    })?;
+    // Columns count characters, not bytes.
+    Some(code[..pos].chars().count() as i32 + 1)
+}
+
+/// Find all the classes in the given source roots, returning a map from each class's upper-case
+/// qualified name (`PACKAGE.CLASS`) to its source file. Source roots are laid out as
+/// `root/Package/Classes/Class.uc`. If a class is in several roots the first one wins, matching
+/// the order roots are searched in.
+///
+/// Only the classes directory of each package is scanned, and each is scanned once however it
+/// is reached, so symlinks (including ones that form a loop) and overlapping roots can't make
+/// the scan repeat itself.
+pub fn scan_classes(source_roots: &[String]) -> BTreeMap<String, PathBuf> {
+    let mut classes = BTreeMap::new();
+    let mut visited = BTreeSet::new();
+    for root in source_roots {
+        let packages = match std::fs::read_dir(root) {
+            Ok(packages) => packages,
+            Err(e) => {
+                log::error!("Failed to scan source root {root}: {e}");
+                continue;
+            }
+        };
+        // Sort the packages so that which of several links to the same package wins doesn't
+        // depend on the file system.
+        let mut packages: Vec<_> = packages.flatten().collect();
+        packages.sort_by_key(|package| package.file_name());
+        for package in packages {
+            let dir = package.path().join("Classes");
+            let Ok(canonical) = dir.canonicalize() else {
+                continue;
+            };
+            if !visited.insert(canonical) {
+                log::debug!("Skipping {dir:?}: already scanned");
+                continue;
+            }
+            let Ok(files) = std::fs::read_dir(&dir) else {
+                continue;
+            };
+            let package_name = package.file_name().to_string_lossy().to_uppercase();
+            for file in files.flatten() {
+                let path = file.path();
+                if !path
+                    .extension()
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case("uc"))
+                {
+                    continue;
+                }
+                if let Some(class) = path.file_stem() {
+                    let name = format!("{package_name}.{}", class.to_string_lossy().to_uppercase());
+                    classes.entry(name).or_insert(path);
+                }
+            }
+        }
+    }
+    classes
+}
+
+// The code on a line, without any trailing comment or surrounding whitespace.
+fn code_part(line: &str) -> &str {
+    match line.find("//") {
+        Some(pos) => &line[..pos],
+        None => line,
+    }
+    .trim()
+}
+
+// Return the name of the function declared by a function header line: the last word before
+// its parameter list, or on the line if the parameters start on a later line.
+fn function_name(code: &str) -> &str {
+    let head = code.split('(').next().unwrap_or(code);
+    head.split(|c: char| !c.is_alphanumeric() && c != '_')
+        .rfind(|word| !word.is_empty())
+        .unwrap_or("")
+}
+
+// Returns true if the given code starts the declaration of a function: the `function` or
+// `event` keyword, preceded only by specifiers like `simulated` or `native(256)`, and followed
+// by the return type and name before the parameter list. A keyword anywhere else, e.g. Actor's
+// `Event` property in `if (Event != '')`, doesn't start a declaration.
+fn is_function_header(code: &str) -> bool {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    let mut rest = code;
+    loop {
+        let len = rest.find(|c: char| !is_ident(c)).unwrap_or(rest.len());
+        if len == 0 {
+            return false;
+        }
+        let (word, after) = rest.split_at(len);
+        if word.eq_ignore_ascii_case("function") || word.eq_ignore_ascii_case("event") {
+            // The return type may be an array, e.g. 'array<int>'.
+            return after.split_once('(').is_some_and(|(head, _)| {
+                head.contains(is_ident)
+                    && head
+                        .chars()
+                        .all(|c| is_ident(c) || c.is_whitespace() || c == '<' || c == '>')
+            });
+        }
+        // Skip a specifier's arguments, e.g. the index in 'native(256)'.
+        rest = match after.strip_prefix('(') {
+            Some(args) => match args.split_once(')') {
+                Some((_, after)) => after,
+                None => return false,
+            },
+            None => after,
+        };
+        if !rest.starts_with(char::is_whitespace) {
+            return false;
+        }
+        rest = rest.trim_start();
+    }
+}
+
+// Return the 1-based column of the last occurrence of the given name as a whole word in the
+// code on a line. The last occurrence is used since a variable's type comes before its name
+// and may be spelled the same, e.g. 'var name Name;'.
+fn find_name(line: &str, name: &str) -> Option<i32> {
+    let code = match line.find("//") {
+        Some(pos) => &line[..pos],
+        None => line,
+    };
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    let mut found = None;
+    let mut start = 0;
+    for (pos, c) in code.char_indices() {
+        if is_ident(c) {
+            continue;
+        }
+        if code[start..pos].eq_ignore_ascii_case(name) {
+            found = Some(start);
+        }
+        start = pos + c.len_utf8();
+    }
+    if code[start..].eq_ignore_ascii_case(name) {
+        found = Some(start);
+    }
+    // Columns count characters, not bytes.
+    found.map(|pos| code[..pos].chars().count() as i32 + 1)
+}
+
+// Returns true if the given code declares local variables.
+fn is_local_declaration(code: &str) -> bool {
+    code.split_whitespace()
+        .next()
+        .is_some_and(|word| word.eq_ignore_ascii_case("local"))
+}
+
+// Returns true if a statement can't continue past a line with the given code.
+fn ends_statement(code: &str) -> bool {
+    if code.is_empty() || code.ends_with([';', '{', '}', ':']) {
+        return true;
+    }
+
+    // A control statement header without braces, e.g. 'if (x)' followed by the statement
+    // on the next line.
+    let keyword = code
+        .split(|c: char| !c.is_alphanumeric())
+        .next()
+        .unwrap_or("")
+        .to_lowercase();
+    match keyword.as_str() {
+        "if" | "for" | "foreach" | "while" => code.ends_with(')'),
+        "else" | "do" => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = include_str!("../testdata/MyPackage/Classes/Multiline.uc");
+
+    #[test]
+    fn single_line_statement() {
+        assert_eq!(statement_start(SOURCE, 15), 15);
+    }
+
+    #[test]
+    fn continuation_lines() {
+        assert_eq!(statement_start(SOURCE, 8), 8);
+        assert_eq!(statement_start(SOURCE, 9), 8);
+        assert_eq!(statement_start(SOURCE, 10), 8);
+    }
+
+    #[test]
+    fn control_statement_body() {
+        assert_eq!(statement_start(SOURCE, 12), 12);
+        assert_eq!(statement_start(SOURCE, 13), 13);
+    }
+
+    #[test]
+    fn breakpoint_lines_of_testdata() {
+        assert_eq!(
+            breakpoint_lines(SOURCE).into_iter().collect::<Vec<_>>(),
+            vec![1, 3, 5, 8, 12, 13, 15, 16, 18, 20, 21]
+        );
+    }
+
+    #[test]
+    fn breakpoint_lines_skip_block_comments() {
+        let source = "\
+/* A comment
+   over several lines */
+Foo();
+/* Inline */ Bar();
+/** Doc */";
+        assert_eq!(
+            breakpoint_lines(source).into_iter().collect::<Vec<_>>(),
+            vec![3, 4]
+        );
+    }
+
+    #[test]
+    fn entry_lines() {
+        assert_eq!(function_entry_lines(SOURCE), vec![8, 20]);
+    }
+
+    #[test]
+    fn entry_lines_of_other_forms() {
+        let source = "\
+native function Declared();
+simulated event Tick(float Delta) {
+    Update();
+}
+function Empty()
+{
+}
+state Waiting
+{
+    function Inner(
+        int A)
+    {
+        local int B;
+        B = A;
+    }
+}";
+        assert_eq!(function_entry_lines(source), vec![3, 14]);
+        assert_eq!(named_function_entry_lines(source, "tick"), vec![3]);
+        assert_eq!(named_function_entry_lines(source, "Inner"), vec![14]);
+        assert!(named_function_entry_lines(source, "Empty").is_empty());
+    }
+
+    #[test]
+    fn entry_lines_skip_bodies() {
+        let source = "\
+function Trigger(Actor Other)
+{
+    if (Event != '')
+    {
+        Event = 'Done';
+    }
+    if (Other != None) { Other.Trigger(self); }
+}
+simulated function array<int> Counts() { return Cached; }";
+        assert_eq!(function_entry_lines(source), vec![3, 9]);
+        assert_eq!(local_declaration(source, 5, "Other"), Some((1, 24)));
+    }
+
+    #[test]
+    fn function_headers() {
+        assert!(is_function_header("function Foo()"));
+        assert!(is_function_header("simulated event Tick(float Delta) {"));
+        assert!(is_function_header(
+            "native(256) final latent function Sleep(float Seconds);"
+        ));
+        assert!(is_function_header(
+            "static final function array<int> Counts("
+        ));
+        assert!(!is_function_header("if (Event != '')"));
+        assert!(!is_function_header("Event = 'Done';"));
+        assert!(!is_function_header("TriggerEvent(Event, self, None);"));
+        assert!(!is_function_header("// function Foo()"));
+        assert!(!is_function_header("var name Event;"));
+    }
+
+    #[test]
+    fn named_entry_lines() {
+        assert_eq!(named_function_entry_lines(SOURCE, "Foo"), vec![8]);
+        assert_eq!(named_function_entry_lines(SOURCE, "Bar"), vec![20]);
+        assert!(named_function_entry_lines(SOURCE, "Missing").is_empty());
+    }
+
+    #[test]
+    fn local_declarations() {
+        assert_eq!(local_declaration(SOURCE, 15, "Sum"), Some((5, 15)));
+        assert_eq!(local_declaration(SOURCE, 15, "sum"), Some((5, 15)));
+        assert_eq!(local_declaration(SOURCE, 15, "B"), Some((3, 25)));
+        // Declared in a different function.
+        assert_eq!(local_declaration(SOURCE, 20, "Sum"), None);
+        assert_eq!(local_declaration(SOURCE, 0, "Sum"), None);
+    }
+
+    #[test]
+    fn call_columns() {
+        let lines: Vec<&str> = SOURCE.lines().collect();
+        assert_eq!(line_call_column(lines[7], "Bar"), Some(11));
+        assert_eq!(line_call_column(lines[14], "bar"), Some(5));
+        assert_eq!(line_call_column(lines[14], "Foo"), None);
+        assert_eq!(line_call_column("", "Bar"), None);
+        // Only whole names followed by arguments are calls.
+        assert_eq!(line_call_column("    FooBar(Bar);", "Bar"), None);
+        assert_eq!(line_call_column("    x.Bar (1); // Bar()", "Bar"), Some(7));
+    }
+
+    #[test]
+    fn parameters_after_header_line() {
+        let source = "\
+function Foo(int A,
+    name Name)
+{
+    Bar(Name);
+}";
+        assert_eq!(local_declaration(source, 4, "Name"), Some((2, 10)));
+        assert_eq!(local_declaration(source, 4, "Missing"), None);
+    }
+
+    #[test]
+    fn member_declarations() {
+        let source = "\
+class Foo extends Object;
+
+var() int Count; // The Count of things.
+var name Name;
+
+function int Count2() { local int Count; }";
+        assert_eq!(member_declaration(source, "Count"), Some((3, 11)));
+        assert_eq!(member_declaration(source, "Name"), Some((4, 10)));
+        assert_eq!(member_declaration(source, "Things"), None);
+    }
+
+    #[test]
+    fn scan_testdata() {
+        let root = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata").to_string();
+        let classes = scan_classes(&[root]);
+        let names: Vec<&str> = classes.keys().map(|k| k.as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["MYPACKAGE.MULTILINE", "OTHERPACKAGE.OTHERCLASS"]
+        );
+        assert!(classes["OTHERPACKAGE.OTHERCLASS"].ends_with("OtherClass.uc"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn scan_symlink_loop() {
+        use std::os::unix::fs::symlink;
+
+        let root = std::env::temp_dir().join(format!("scan_symlink_loop_{}", std::process::id()));
+        let classes = root.join("Pkg").join("Classes");
+        std::fs::create_dir_all(&classes).unwrap();
+        std::fs::write(classes.join("A.uc"), "class A extends Object;").unwrap();
+        symlink(&root, classes.join("Loop")).unwrap();
+        symlink(root.join("Pkg"), root.join("SamePkg")).unwrap();
+
+        let found = scan_classes(&[
+            root.to_string_lossy().to_string(),
+            classes.join("Loop").to_string_lossy().to_string(),
+        ]);
+        std::fs::remove_dir_all(&root).unwrap();
+        let names: Vec<&str> = found.keys().map(|k| k.as_str()).collect();
+        assert_eq!(names, vec!["PKG.A"]);
+    }
+
+    #[test]
+    fn blank_and_out_of_range() {
+        assert_eq!(statement_start(SOURCE, 11), 11);
+        assert_eq!(statement_start(SOURCE, 0), 0);
+        assert_eq!(statement_start(SOURCE, 500), 500);
+    }
+}
@@ -0,0 +1,209 @@
+//! Tracking of variable values across stops.
+//!
+//! Clients can highlight variables whose value changed since execution last stopped, but only
+//! if the adapter tells them which ones did. Variable references aren't a reliable way to match
+//! a variable with its previous value: the interface numbers watches in the order Unreal sends
+//! them, so a variable's index shifts whenever something before it gains or loses children.
+//! Frame indices aren't reliable either, since the same function is at a different depth after
+//! a call returns. Instead each variable is identified by the function of its frame and its path
+//! from the scope it was found in, e.g. `Local:MyPackage.MyPawn.Tick.Pawn.Health`, and values are
+//! compared by path.
+
+use std::collections::BTreeMap;
+
+use common::{FrameIndex, Variable, VariableIndex, WatchKind};
+
+use crate::variable_reference::VariableReference;
+
+/// Remembers the values of the variables sent to the client so changes can be detected at
+/// the next stop.
+#[derive(Debug, Default)]
+pub struct ValueHistory {
+    // The qualified function of each frame sent since the last stop, by frame index.
+    functions: BTreeMap<usize, String>,
+    // The path of each structured variable sent since the last stop, by variable reference.
+    paths: BTreeMap<i64, String>,
+    // The values sent since the last stop, by path.
+    current: BTreeMap<String, String>,
+    // The values sent in the previous stop, by path.
+    previous: BTreeMap<String, String>,
+}
+
+impl ValueHistory {
+    /// Create an empty history.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Execution has stopped again. Values recorded since the last stop replace the previous
+    /// values that new ones are compared against, and frames and variable references from the
+    /// last stop are forgotten since they are no longer valid.
+    pub fn stopped(&mut self) {
+        self.functions.clear();
+        self.paths.clear();
+        self.previous = std::mem::take(&mut self.current);
+    }
+
+    /// Record the qualified name of the function running in a frame, e.g.
+    /// `MyPackage.MyPawn.Tick`.
+    pub fn frame_function(&mut self, frame: FrameIndex, function: String) {
+        self.functions.insert(frame.into(), function);
+    }
+
+    /// Record the value of a variable sent as a child of `parent`, returning true if it has
+    /// changed since it was last sent at an earlier stop. `reference` is the variable reference
+    /// sent for the variable itself, or 0 if it has no children.
+    ///
+    /// User watches are not tracked: they are re-evaluated from scratch each time. Nor are
+    /// variables of a frame whose function hasn't been recorded since the last stop.
+    pub fn record(&mut self, parent: &VariableReference, var: &Variable, reference: i64) -> bool {
+        let parent_path = match parent.kind() {
+            WatchKind::User => return false,
+            kind if parent.variable() == VariableIndex::SCOPE => {
+                match self.functions.get(&parent.frame().into()) {
+                    Some(function) => format!("{kind:?}:{function}"),
+                    None => return false,
+                }
+            }
+            _ => match self.paths.get(&parent.to_int()) {
+                Some(path) => path.clone(),
+                None => return false,
+            },
+        };
+        let path = format!("{parent_path}.{}", var.name);
+        if reference != 0 {
+            self.paths.insert(reference, path.clone());
+        }
+        let changed = self
+            .previous
+            .get(&path)
+            .is_some_and(|value| *value != var.value);
+        self.current.insert(path, var.value.clone());
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TICK: &str = "MyPackage.MyPawn.Tick";
+
+    fn var(name: &str, value: &str, index: u32) -> Variable {
+        Variable {
+            name: name.to_string(),
+            ty: "int".to_string(),
+            value: value.to_string(),
+            index: VariableIndex::create(index).unwrap(),
+            has_children: false,
+            is_array: false,
+        }
+    }
+
+    fn frame(index: u16) -> FrameIndex {
+        FrameIndex::create(index.into()).unwrap()
+    }
+
+    fn scope(kind: WatchKind) -> VariableReference {
+        VariableReference::new(kind, FrameIndex::TOP_FRAME, VariableIndex::SCOPE)
+    }
+
+    // Stop again with the given function in the top frame.
+    fn stop_in(history: &mut ValueHistory, function: &str) {
+        history.stopped();
+        history.frame_function(FrameIndex::TOP_FRAME, function.to_string());
+    }
+
+    #[test]
+    fn changed_between_stops() {
+        let mut history = ValueHistory::new();
+        stop_in(&mut history, TICK);
+        let locals = scope(WatchKind::Local);
+        assert!(!history.record(&locals, &var("Count", "1", 1), 0));
+        assert!(!history.record(&locals, &var("Name", "'Bob'", 2), 0));
+        stop_in(&mut history, TICK);
+        assert!(history.record(&locals, &var("Count", "2", 1), 0));
+        assert!(!history.record(&locals, &var("Name", "'Bob'", 2), 0));
+    }
+
+    #[test]
+    fn same_name_in_other_scope() {
+        let mut history = ValueHistory::new();
+        stop_in(&mut history, TICK);
+        history.record(&scope(WatchKind::Local), &var("Count", "1", 1), 0);
+        stop_in(&mut history, TICK);
+        assert!(!history.record(&scope(WatchKind::Global), &var("Count", "2", 1), 0));
+    }
+
+    #[test]
+    fn same_name_in_other_function() {
+        let mut history = ValueHistory::new();
+        stop_in(&mut history, TICK);
+        history.record(&scope(WatchKind::Local), &var("Count", "1", 1), 0);
+        stop_in(&mut history, "MyPackage.MyPawn.Touch");
+        assert!(!history.record(&scope(WatchKind::Local), &var("Count", "2", 1), 0));
+    }
+
+    #[test]
+    fn matched_by_function_not_frame() {
+        let mut history = ValueHistory::new();
+        stop_in(&mut history, "MyPackage.MyPawn.TakeDamage");
+        history.frame_function(frame(1), TICK.to_string());
+        let caller = VariableReference::new(WatchKind::Local, frame(1), VariableIndex::SCOPE);
+        history.record(&caller, &var("Count", "1", 1), 0);
+
+        // TakeDamage returned, so Tick is now the top frame.
+        stop_in(&mut history, TICK);
+        assert!(history.record(&scope(WatchKind::Local), &var("Count", "2", 1), 0));
+    }
+
+    #[test]
+    fn child_matched_by_path() {
+        let mut history = ValueHistory::new();
+        stop_in(&mut history, TICK);
+        let locals = scope(WatchKind::Local);
+        let first = VariableReference::new(
+            WatchKind::Local,
+            FrameIndex::TOP_FRAME,
+            VariableIndex::create(1).unwrap(),
+        );
+        history.record(&locals, &var("Pawn", "Pawn_0", 1), first.to_int());
+        history.record(&first, &var("Health", "100", 2), 0);
+        stop_in(&mut history, TICK);
+
+        // The parent has a different index at the next stop.
+        let second = VariableReference::new(
+            WatchKind::Local,
+            FrameIndex::TOP_FRAME,
+            VariableIndex::create(5).unwrap(),
+        );
+        history.record(&locals, &var("Pawn", "Pawn_0", 5), second.to_int());
+        assert!(history.record(&second, &var("Health", "90", 6), 0));
+    }
+
+    #[test]
+    fn unchanged_when_not_seen_before() {
+        let mut history = ValueHistory::new();
+        stop_in(&mut history, TICK);
+        assert!(!history.record(&scope(WatchKind::Local), &var("Count", "1", 1), 0));
+    }
+
+    #[test]
+    fn only_compared_with_last_stop() {
+        let mut history = ValueHistory::new();
+        stop_in(&mut history, TICK);
+        history.record(&scope(WatchKind::Local), &var("Count", "1", 1), 0);
+        // Nothing was shown at this stop.
+        stop_in(&mut history, TICK);
+        stop_in(&mut history, TICK);
+        assert!(!history.record(&scope(WatchKind::Local), &var("Count", "2", 1), 0));
+    }
+
+    #[test]
+    fn untracked_without_function() {
+        let mut history = ValueHistory::new();
+        history.record(&scope(WatchKind::Local), &var("Count", "1", 1), 0);
+        history.stopped();
+        assert!(!history.record(&scope(WatchKind::Local), &var("Count", "2", 1), 0));
+    }
+}
@@ -0,0 +1,21 @@
+class Multiline extends Object;
+
+function Foo(int A, int B)
+{
+    local int Sum;
+
+    // A call with its arguments split across lines.
+    Sum = Bar(A,
+        B,
+        A + B);
+
+    if (Sum > 0)
+        Sum = 0;
+
+    Bar(Sum, 0, 0);
+}
+
+function int Bar(int X, int Y, int Z)
+{
+    return X + Y + Z;
+}
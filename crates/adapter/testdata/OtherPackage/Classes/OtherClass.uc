@@ -0,0 +1,3 @@
+class OtherClass extends Object;
+
+var int Count;
@@ -0,0 +1,4 @@
+class Multiline extends Object;
+
+// A copy of the class in another root, for testing package roots.
+function Foo() {}
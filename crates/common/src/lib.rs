@@ -16,9 +16,20 @@
 
 #![warn(missing_docs)]
 
-use std::{fmt::Display, path::PathBuf, time::Duration};
-
-use flexi_logger::{Duplicate, FileSpec, FlexiLoggerError, LogSpecification, Logger, LoggerHandle};
+use std::{
+    collections::hash_map::RandomState,
+    fmt::Display,
+    hash::{BuildHasher, Hasher},
+    path::PathBuf,
+    sync::RwLock,
+    time::Duration,
+};
+
+use flexi_logger::{
+    writers::LogWriter, DeferredNow, Duplicate, FileSpec, FlexiLoggerError, LogSpecification,
+    Logger, LoggerHandle,
+};
+use log::Record;
 use serde::{Deserialize, Serialize};
 
 /// The default port to use for the TCP connection between the interface and
@@ -56,6 +67,23 @@ pub const DEFAULT_CONNECT_ATTEMPTS: u32 = 10;
 /// The default amount of time to wait between each connection attempt.
 pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
 
+/// The largest frame the interface sends to the adapter. Messages larger than this, e.g. a
+/// response with a huge watch value, are split across several frames.
+pub const MAX_FRAME_LEN: usize = 8 * 1024 * 1024;
+
+/// Set in the length prefix of a frame sent by the interface if the message continues in the
+/// next frame. The length itself is in the remaining bits.
+pub const MORE_FRAMES_FLAG: u32 = 1 << 31;
+
+/// The function name given to callstack frames for which Unreal did not provide a
+/// usable class name, e.g. engine-internal frames. These frames have an empty
+/// qualified name and no source.
+pub const NATIVE_FRAME_NAME: &str = "<native>";
+
+/// The type given to a watch whose name Unreal did not report in the usual "name ( type )"
+/// form. This is how a watch on an expression Unreal couldn't evaluate comes back.
+pub const UNKNOWN_TYPE: &str = "<unknown type>";
+
 /// An error indicating a particular value (such as a frame or variable index)
 /// is out of range.
 #[derive(Debug)]
@@ -78,9 +106,9 @@ impl FrameIndex {
     ///
     /// # Errors
     ///
-    /// Returns [`OutOfRangeError`] if the value is larger than [`FrameIndex::MAX`].
+    /// Returns [`OutOfRangeError`] if the value is negative or larger than [`FrameIndex::MAX`].
     pub fn create(val: i64) -> Result<Self, OutOfRangeError> {
-        if val > Self::MAX.into() {
+        if val < 0 || val > Self::MAX.into() {
             Err(OutOfRangeError)
         } else {
             Ok(FrameIndex(val.try_into().unwrap()))
@@ -94,6 +122,12 @@ impl From<FrameIndex> for usize {
     }
 }
 
+impl From<FrameIndex> for u32 {
+    fn from(val: FrameIndex) -> Self {
+        val.0.into()
+    }
+}
+
 impl From<FrameIndex> for u64 {
     fn from(val: FrameIndex) -> Self {
         val.0.into()
@@ -118,7 +152,7 @@ impl Display for FrameIndex {
 /// represent root variables. We don't expose the negative value outside of the
 /// interface so will use an unsigned value, but we do limit variable indices
 /// to only 20 bits.
-#[derive(Debug, Serialize, Deserialize, Copy, Clone)]
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq)]
 pub struct VariableIndex(u32);
 
 impl VariableIndex {
@@ -198,6 +232,12 @@ pub struct Version {
     pub patch: u32,
 }
 
+impl Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
 /// An initialization message from the adapter to the interface, sent when the
 /// adapter first connects to the interface. This will result in a [`InitializeResponse`].
 ///
@@ -216,6 +256,15 @@ pub struct InitializeRequest {
     pub enable_stack_hack: bool,
     /// If set, an overriding log level to use for the interface after connecting.
     pub overridden_log_level: Option<String>,
+    /// If set, the interface also sends its own log lines at or above this level to the
+    /// adapter as [`UnrealInterfaceMessage::InterfaceLog`] messages. Older adapters do not
+    /// send this field.
+    #[serde(default)]
+    pub forwarded_log_level: Option<String>,
+    /// The adapter's session id. The interface prefixes its log lines with this for the rest
+    /// of the session. Older adapters do not send this field.
+    #[serde(default)]
+    pub session_id: Option<String>,
 }
 
 /// An initialization response from the interface to the adapter. Tells the
@@ -224,6 +273,34 @@ pub struct InitializeRequest {
 pub struct InitializeResponse {
     /// The version of the interface.
     pub version: Version,
+    /// If true the interface answers [`UnrealCommand::ListBreakpoints`] commands. Older
+    /// interfaces do not send this field.
+    #[serde(default)]
+    pub supports_list_breakpoints: bool,
+    /// If true the interface answers [`UnrealCommand::Threads`] commands. Older interfaces do
+    /// not send this field.
+    #[serde(default)]
+    pub supports_threads: bool,
+    /// If true the interface can handle [`UnrealCommand::RestartFrame`] commands. Older
+    /// interfaces do not send this field.
+    #[serde(default)]
+    pub supports_restart_frame: bool,
+    /// If true the interface answers [`UnrealCommand::FrameLine`] commands. Older interfaces do
+    /// not send this field.
+    #[serde(default)]
+    pub supports_frame_line: bool,
+    /// If true the interface can handle [`UnrealCommand::CancelVariableRequest`] commands.
+    /// Older interfaces do not send this field.
+    #[serde(default)]
+    pub supports_cancel_variable_request: bool,
+    /// If true the interface can handle [`UnrealCommand::RefreshWatches`] commands. Older
+    /// interfaces do not send this field.
+    #[serde(default)]
+    pub supports_refresh_watches: bool,
+    /// If true the interface can handle [`UnrealCommand::SetVariable`] commands. Older
+    /// interfaces do not send this field.
+    #[serde(default)]
+    pub supports_set_variable: bool,
 }
 
 /// A message representing a request from the adapter to the interface to
@@ -242,6 +319,9 @@ pub struct StackTraceResponse {
     /// A vector of requested frames. The returned vector may be shorter
     /// than the initial request's `levels` field.
     pub frames: Vec<Frame>,
+    /// The total number of frames in the call stack. Older interfaces do not send this field.
+    #[serde(default)]
+    pub total_frames: Option<u32>,
 }
 
 /// A callstack frame.
@@ -254,6 +334,11 @@ pub struct Frame {
     /// A line number for this frame. Note that this may be '0', indicating
     /// the line is unknown.
     pub line: i32,
+    /// The name of the state the function belongs to, if it is declared in one. The same
+    /// function name may be declared in several states of a class. Older interfaces do not
+    /// send this field.
+    #[serde(default)]
+    pub state_name: Option<String>,
 }
 
 /// The kind of watch, e.g. scope or user-defined watches.
@@ -285,7 +370,7 @@ impl WatchKind {
 /// Structs, classes, static and dynamic arrays can all have children, with the
 /// last two being considered 'arrays'. This distinction can be important to
 /// some clients that differentiate between 'named' and 'indexed' children.
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Variable {
     /// The name of the variable
     pub name: String,
@@ -312,18 +397,47 @@ pub enum UnrealCommand {
     AddBreakpoint(Breakpoint),
     /// Remove a breakpoint
     RemoveBreakpoint(Breakpoint),
+    /// List all the breakpoints Unreal has set. Only sent to interfaces that report support for
+    /// it in the initialize response.
+    ListBreakpoints,
+    /// List the names of the logical threads of execution other than the main one. Only sent
+    /// to interfaces that report support for it in the initialize response.
+    Threads,
     /// Request the call stack - may request the full stack or only a subset.
     StackTrace(StackTraceRequest),
     /// Determine the number of watches of the given kind in the currently active
     /// frame.
     WatchCount(WatchKind, VariableIndex),
-    /// Retrieve variables. This returns all children of a particular parent (either a scope or
-    /// a structured variable).
+    /// Retrieve variables. This returns the children of a particular parent (either a scope or
+    /// a structured variable), starting at the child with the given start index and returning at
+    /// most the given count. A count of 0 returns every child from the start to the end.
     Variables(WatchKind, FrameIndex, VariableIndex, usize, usize),
 
     /// Evaluate a given variable expression in the context of the given frame.
     Evaluate(FrameIndex, String),
 
+    /// Determine the qualified class name and current line for the given frame. This may
+    /// require switching to that frame and back again if the line is not yet known. Only sent
+    /// to interfaces that report support for it in the initialize response.
+    FrameLine(FrameIndex),
+
+    /// Ask Unreal to rebuild the watch lists for the current frame, e.g. after some state
+    /// has been modified and the existing watch values may be stale. Only sent to interfaces
+    /// that report support for it in the initialize response.
+    RefreshWatches,
+
+    /// Assign a value to the variable named by the given expression in the current frame.
+    /// Unreal doesn't say whether the assignment worked, so the watch lists are rebuilt
+    /// afterwards as for [`UnrealCommand::RefreshWatches`] and the new value can be read back.
+    /// Only sent to interfaces that report support for it in the initialize response.
+    SetVariable(String, String),
+
+    /// The adapter no longer needs the response to the variable request the interface is
+    /// waiting on Unreal for. The interface answers [`UnrealResponse::Cancelled`] in place of
+    /// that response if one is still pending, and doesn't answer otherwise. Only sent to
+    /// interfaces that report support for it in the initialize response.
+    CancelVariableRequest,
+
     /// Break as soon as possible
     Pause,
 
@@ -339,6 +453,11 @@ pub enum UnrealCommand {
     /// Step out of the current function
     StepOut,
 
+    /// Run the function of the given frame again from its start, stopping there as though a
+    /// step had completed. Only sent to interfaces that report support for it in the
+    /// initialize response.
+    RestartFrame(FrameIndex),
+
     /// Stop debugging - the client has disconnected.
     Disconnect,
 }
@@ -353,6 +472,13 @@ pub enum UnrealResponse {
     BreakpointAdded(Breakpoint),
     /// A breakpoint has been removed.
     BreakpointRemoved(Breakpoint),
+    /// A response to a [`UnrealCommand::ListBreakpoints`] request with every breakpoint Unreal
+    /// has set. Class names are in upper case.
+    Breakpoints(Vec<Breakpoint>),
+    /// A response to a [`UnrealCommand::Threads`] request with the name of each logical thread
+    /// other than the main one. Unreal has only one real thread, so these are contexts such as
+    /// the object that is executing.
+    Threads(Vec<String>),
     /// A list of zero or more stack frames.
     StackTrace(StackTraceResponse),
     /// The number of watches found.
@@ -368,6 +494,15 @@ pub enum UnrealResponse {
     /// the frame information again. This is also used for [`UnrealRequest.Evaluate`]
     /// for the same scenario as [`UnrealRequest.Variables`].
     DeferredVariables(Vec<Variable>),
+    /// A response to a [`UnrealCommand::FrameLine`] request with the qualified class name
+    /// and line number for the requested frame.
+    FrameLine(String, i32),
+    /// A response to a [`UnrealCommand::RefreshWatches`] request sent once the watch lists
+    /// have been rebuilt.
+    WatchesRefreshed,
+    /// Sent in place of the response to a pending variable request after a
+    /// [`UnrealCommand::CancelVariableRequest`].
+    Cancelled,
 }
 
 /// Events that can be sent from the interface at any time.
@@ -393,6 +528,42 @@ pub enum UnrealInterfaceMessage {
     /// An event. These can occur at any time without any intervention from the
     /// adapter.
     Event(UnrealEvent),
+    /// A line logged by the interface itself. Only sent if the adapter asked for them with
+    /// [`InitializeRequest::forwarded_log_level`].
+    InterfaceLog(String),
+}
+
+/// The id of the current debugging session, if any, included in every log line.
+static SESSION_ID: RwLock<Option<String>> = RwLock::new(None);
+
+/// Generate a short id for a new debugging session. This is random so that logs from
+/// sessions sharing a log file can be told apart.
+pub fn new_session_id() -> String {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u32(std::process::id());
+    format!("{:08x}", hasher.finish() as u32)
+}
+
+/// Set the session id included in log lines, or stop including one if `None`.
+pub fn set_session_id(id: Option<&str>) {
+    *SESSION_ID.write().unwrap() = id.map(str::to_string);
+}
+
+/// Get the session id included in log lines, if any.
+pub fn session_id() -> Option<String> {
+    SESSION_ID.read().unwrap().clone()
+}
+
+// Format a log line, prefixed with the session id if there is one.
+fn format_line(
+    w: &mut dyn std::io::Write,
+    now: &mut DeferredNow,
+    record: &Record,
+) -> std::io::Result<()> {
+    if let Some(id) = SESSION_ID.read().unwrap().as_deref() {
+        write!(w, "[{id}] ")?;
+    }
+    flexi_logger::default_format(w, now, record)
 }
 
 // Return the log directory to use.
@@ -422,7 +593,10 @@ fn log_dir() -> Option<PathBuf> {
 }
 
 /// Create a logger instance using a common configuration from the environment
-fn create_custom_logger(basename: &str) -> Result<LoggerHandle, FlexiLoggerError> {
+fn create_custom_logger(
+    basename: &str,
+    writer: &mut Option<Box<dyn LogWriter>>,
+) -> Result<LoggerHandle, FlexiLoggerError> {
     let mut file_spec = FileSpec::default().basename(basename);
 
     // Try to read the default log level from an env var, or default to warn if there is none.
@@ -431,7 +605,7 @@ fn create_custom_logger(basename: &str) -> Result<LoggerHandle, FlexiLoggerError
         .unwrap_or("warn".to_string());
 
     // Try to create a logger with this level
-    let logger = Logger::try_with_env_or_str(level)?;
+    let logger = Logger::try_with_env_or_str(level)?.format(format_line);
 
     // If we have a custom log directory, try that.
     if let Some(d) = log_dir() {
@@ -439,23 +613,29 @@ fn create_custom_logger(basename: &str) -> Result<LoggerHandle, FlexiLoggerError
     }
 
     // Try to log to the specified file
-    logger
-        .log_to_file(file_spec)
-        .duplicate_to_stderr(Duplicate::All)
-        .start()
+    let logger = match writer.take() {
+        Some(writer) => logger.log_to_file_and_writer(file_spec, writer),
+        None => logger.log_to_file(file_spec),
+    };
+    logger.duplicate_to_stderr(Duplicate::All).start()
 }
 
 /// Create a logger instance. Will first attempt to respect the settings from various
 /// environment variables, but if that fails will fall back to a default implementation.
-pub fn create_logger(basename: &str) -> LoggerHandle {
-    match create_custom_logger(basename) {
+///
+/// If a writer is given log lines are written to it as well as to the log file.
+pub fn create_logger(basename: &str, mut writer: Option<Box<dyn LogWriter>>) -> LoggerHandle {
+    match create_custom_logger(basename, &mut writer) {
         Ok(logger) => logger,
         Err(e) => {
-            let logger = Logger::with(LogSpecification::env_or_parse("warn").unwrap())
-                .log_to_file(FileSpec::default().basename(basename))
-                .duplicate_to_stderr(Duplicate::All)
-                .start()
-                .unwrap();
+            let logger =
+                Logger::with(LogSpecification::env_or_parse("warn").unwrap()).format(format_line);
+            let file_spec = FileSpec::default().basename(basename);
+            let logger = match writer {
+                Some(writer) => logger.log_to_file_and_writer(file_spec, writer),
+                None => logger.log_to_file(file_spec),
+            };
+            let logger = logger.duplicate_to_stderr(Duplicate::All).start().unwrap();
             // Log the error we got from the custom settings before returning
             log::error!(
                 "Failed to create logger from environment, using default log settings: {e}"
@@ -464,3 +644,41 @@ pub fn create_logger(basename: &str) -> LoggerHandle {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use log::Level;
+
+    use super::*;
+
+    #[test]
+    fn session_ids_differ() {
+        let id = new_session_id();
+        assert_eq!(id.len(), 8);
+        assert!(id.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(id, new_session_id());
+    }
+
+    #[test]
+    fn log_line_has_session_id() {
+        let format = |text: &str| {
+            let mut line = vec![];
+            format_line(
+                &mut line,
+                &mut DeferredNow::new(),
+                &Record::builder()
+                    .level(Level::Info)
+                    .module_path(Some("adapter"))
+                    .args(format_args!("{text}"))
+                    .build(),
+            )
+            .unwrap();
+            String::from_utf8(line).unwrap()
+        };
+
+        set_session_id(Some("0123abcd"));
+        assert_eq!(format("Hello"), "[0123abcd] INFO [adapter] Hello");
+        set_session_id(None);
+        assert_eq!(format("Goodbye"), "INFO [adapter] Goodbye");
+    }
+}
@@ -4,6 +4,8 @@
 
 use serde::Serialize;
 
+use crate::types::Capabilities;
+
 /// A DAP event message
 ///
 /// This is typically used only by the client, the adapter uses the [`Event`] type.
@@ -31,6 +33,10 @@ pub struct Event {
 #[derive(Serialize, Debug)]
 #[serde(tag = "event", content = "body", rename_all = "camelCase")]
 pub enum EventBody {
+    /// Capabilities event. Sent when the capabilities change after the client was told them in
+    /// the initialize response, e.g. once we know what the interface supports.
+    Capabilities(CapabilitiesEventBody),
+
     /// Initialized event, sent by the adapter after initialization is complete.
     Initialized,
 
@@ -50,6 +56,18 @@ pub enum EventBody {
 
     /// Terminated event. Sent when we detect the debuggee has shut down.
     Terminated,
+
+    /// Thread event. Sent when the Unrealscript thread starts after initialization and when it
+    /// exits at the end of the session. Some clients won't show a stack for a thread until
+    /// they've been told it started.
+    Thread(ThreadEventBody),
+}
+
+/// Body for a capabilities event.
+#[derive(Serialize, Debug)]
+pub struct CapabilitiesEventBody {
+    /// The capabilities, including any that haven't changed.
+    pub capabilities: Capabilities,
 }
 
 /// Body for an invalidated event.
@@ -57,9 +75,9 @@ pub enum EventBody {
 pub struct InvalidatedEventBody {
     /// The areas that have been invalidated.
     pub areas: Vec<InvalidatedAreas>,
-    /// Which stack frame has been invalidated.
-    #[serde(rename = "stackFrameId")]
-    pub frame_id: i64,
+    /// Which stack frame has been invalidated, or None for all of them.
+    #[serde(rename = "stackFrameId", skip_serializing_if = "Option::is_none")]
+    pub frame_id: Option<i64>,
 }
 
 /// Areas that may be invalidated.
@@ -72,7 +90,7 @@ pub enum InvalidatedAreas {
     Stacks,
     /// Threads have been invalidated. Not used.
     Threads,
-    /// Variables have been invalidated. Not used.
+    /// Variables have been invalidated. Used when the watch lists have been rebuilt.
     Variables,
 }
 
@@ -86,7 +104,7 @@ pub struct OutputEventBody {
 }
 
 /// Categories for output events.
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Clone, Copy)]
 #[serde(rename_all = "camelCase")]
 pub enum OutputEventCategory {
     /// Console. Used for debugger information and is written to the debug console.
@@ -99,11 +117,12 @@ pub enum OutputEventCategory {
     /// expect the string to be in some non-textual format or some other fields to be present.
     Important,
 
-    /// Stdout output from the debuggee. Unreal logs are written here.
+    /// Stdout output from the debuggee. Unreal log lines other than warnings and errors are
+    /// written here, as is anything a launched game writes to its own stdout stream.
     Stdout,
 
-    /// Stderr output from the debuggee. Not used; Unreal does not log output to different
-    /// channels.
+    /// Stderr output from the debuggee. Used for warnings and errors in the Unreal log, and for
+    /// anything a launched game writes to its own stderr stream.
     Stderr,
 
     /// Telemetry. Not used.
@@ -121,6 +140,26 @@ pub struct StoppedEventBody {
     pub reason: StoppedEventReason,
 }
 
+/// A thread event body.
+#[derive(Serialize, Debug)]
+pub struct ThreadEventBody {
+    /// Whether the thread has started or exited.
+    pub reason: ThreadEventReason,
+    /// The thread the event is for. Unrealscript only has one thread.
+    #[serde(rename = "threadId")]
+    pub thread_id: i64,
+}
+
+/// The reason for a thread event.
+#[derive(Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ThreadEventReason {
+    /// The thread has started.
+    Started,
+    /// The thread has exited.
+    Exited,
+}
+
 /// The reason why the debugger stopped.
 ///
 /// Other useful reasons would be things like step, but Unreal doesn't give
@@ -3,10 +3,12 @@
 //! Requests are sent from the client (editor) to the adapter and expect a
 //! response.
 
+use std::collections::BTreeMap;
+
 use serde::Deserialize;
-use strum::Display;
+use strum::{Display, EnumVariantNames, VariantNames};
 
-use crate::types::{Source, SourceBreakpoint};
+use crate::types::{FunctionBreakpoint, Source, SourceBreakpoint};
 
 /// A request from the DAP client.
 #[derive(Deserialize, Debug)]
@@ -20,47 +22,89 @@ pub struct Request {
 }
 
 /// The DAP request 'command' or type.
-#[derive(Deserialize, Debug, Display)]
+#[derive(Deserialize, Debug, Display, EnumVariantNames)]
 #[serde(tag = "command", content = "arguments", rename_all = "camelCase")]
 #[strum(serialize_all = "camelCase")]
 pub enum Command {
     /// Attach to a running process.
     Attach(AttachArguments),
+    /// Find the lines in a range of a source file where breakpoints can be set.
+    BreakpointLocations(BreakpointLocationsArguments),
+    /// Cancel an earlier request the client no longer needs the result of.
+    Cancel(CancelArguments),
     /// The client has finished the configuration stage.
     ConfigurationDone,
     /// Continue execution.
-    Continue(IgnoredArguments),
+    Continue(ThreadArguments),
     /// Disconnect from the debuggee. We treat this as shutting down the
     /// debugging session. If we launched the debuggee it will close the process
-    /// too.
-    Disconnect(IgnoredArguments),
+    /// too, unless the disconnect is part of a restart.
+    Disconnect(DisconnectArguments),
     /// Evaluate a given watch expression.
     Evaluate(EvaluateArguments),
     /// Initialize the connection with the client. Contains configuration details
     /// about the client.
     Initialize(InitializeArguments),
-    /// Launch an application and optionally debug it.
-    Launch(LaunchArguments),
+    /// Launch an application and optionally debug it. The arguments are boxed as they're much
+    /// larger than any other request's.
+    Launch(Box<LaunchArguments>),
+    /// Find the source location for a location reference, e.g. where a variable is declared.
+    Locations(LocationsArguments),
     /// Step over the next statement.
-    Next(IgnoredArguments),
+    Next(ThreadArguments),
     /// Tell the debuggee to break.
     Pause(IgnoredArguments),
+    /// Run the function of a stack frame again from its start. Only supported by some
+    /// interfaces.
+    RestartFrame(RestartFrameArguments),
     /// Request for scope information. Unrealscript has only two real scopes: local scope
     /// and global (class) scope.
     Scopes(ScopesArguments),
     /// Set breakpoints for a given file. This completely replaces all previous breakpoints
     /// in the file.
     SetBreakpoints(SetBreakpointsArguments),
+    /// Assign a new value to the variable named by a watch expression.
+    SetExpression(SetExpressionArguments),
+    /// Set breakpoints on entry to the named functions. This completely replaces all previous
+    /// function breakpoints.
+    SetFunctionBreakpoints(SetFunctionBreakpointsArguments),
+    /// Assign a new value to a variable.
+    SetVariable(SetVariableArguments),
     /// Request stack trace information.
     StackTrace(StackTraceArguments),
     /// Step into the next statement.
-    StepIn(IgnoredArguments),
+    StepIn(ThreadArguments),
     /// Step out of the current function.
-    StepOut(IgnoredArguments),
+    StepOut(ThreadArguments),
     /// Request information about the currently running threads. Unreal has only a single thread.
     Threads,
     /// Request information about variables.
     Variables(VariablesArguments),
+    /// A command we don't know about, with its name. This is never deserialized from a request
+    /// directly: it is built by the client when it can't parse a request's command.
+    #[serde(skip)]
+    Unknown(String),
+    /// A command we know about but whose arguments couldn't be parsed, with its name and the
+    /// reason. Like `Unknown` this is built by the client rather than deserialized.
+    #[serde(skip)]
+    Malformed(String, String),
+}
+
+impl Command {
+    /// The name of the command as sent by the client.
+    pub fn name(&self) -> String {
+        match self {
+            Command::Unknown(name) | Command::Malformed(name, _) => name.clone(),
+            cmd => cmd.to_string(),
+        }
+    }
+
+    /// Returns true if the named command is one we can parse, even if a particular request for
+    /// it can't be.
+    pub fn is_known(name: &str) -> bool {
+        // The variants the client builds itself aren't real commands.
+        !matches!(name, "unknown" | "malformed") && Command::VARIANTS.contains(&name)
+    }
 }
 
 /// A dummy struct with no members.
@@ -71,6 +115,18 @@ pub enum Command {
 #[derive(Deserialize, Debug)]
 pub struct IgnoredArguments {}
 
+/// Arguments for the requests that resume or step a thread: [`Command::Continue`],
+/// [`Command::Next`], [`Command::StepIn`], and [`Command::StepOut`].
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ThreadArguments {
+    /// The thread to resume or step.
+    pub thread_id: i64,
+    /// If true only the given thread is resumed or stepped. Unreal has only one thread, so
+    /// this makes no difference.
+    pub single_thread: Option<bool>,
+}
+
 /// Arguments for an [`Command::Attach`] command.
 ///
 /// These are almost entirely implementation-defined, and are usually populated
@@ -78,6 +134,24 @@ pub struct IgnoredArguments {}
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct AttachArguments {
+    /// The options shared with [`LaunchArguments`].
+    #[serde(flatten)]
+    pub session: SessionArguments,
+
+    /// If true pause the game when the client disconnects instead of letting it run, and leave
+    /// the debugger running so a new session can attach to the game where it stopped.
+    pub pause_on_disconnect: Option<bool>,
+
+    /// If true break as soon as the debugger has attached to the game, rather than waiting
+    /// for a breakpoint.
+    pub break_on_attach: Option<bool>,
+}
+
+/// The implementation-defined options accepted by both [`Command::Attach`] and
+/// [`Command::Launch`] requests.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionArguments {
     /// An ordered list of directories in which to search for source files. This is required
     /// so that we can tell the editor what file to open when the debugger breaks in some
     /// Unreal class. It relies on the naming and directory layout convention of Unreal so
@@ -92,6 +166,61 @@ pub struct AttachArguments {
     /// Override the log level with the given log spec. Can be one of 'trace', 'debug', 'info',
     /// 'warn', or 'error'; or a more complex log spec.
     pub log_level: Option<String>,
+
+    /// If true (the default) normalize the encoding and line endings of game output sent to
+    /// the client. If false the output is passed through as-is.
+    pub normalize_output: Option<bool>,
+
+    /// The maximum number of output events to send to the client per second. Excess output
+    /// is dropped and summarized. If not set output is not limited.
+    pub max_output_per_second: Option<u32>,
+
+    /// A delay in milliseconds to wait after a step before reporting the stop to the client,
+    /// giving Unreal time to finish sending the new state.
+    pub step_settle_delay_ms: Option<u64>,
+
+    /// If true keep the session alive when the game disconnects, and wait for it to connect
+    /// again instead of ending the session.
+    pub wait_for_reconnect: Option<bool>,
+
+    /// If true respond to commands we don't know with an empty success response rather than
+    /// an error, so clients quietly do without optional features we don't support.
+    pub acknowledge_unknown_commands: Option<bool>,
+
+    /// If true scan the source roots for classes when the session starts, rather than looking
+    /// for each class's source file as it is needed. This can be slow for large source trees.
+    pub scan_source_roots: Option<bool>,
+
+    /// If set the interface sends its own log lines at or above this level to be shown as
+    /// console output, giving a single timeline of the interface's and the game's activity.
+    /// Can be one of 'trace', 'debug', 'info', 'warn', or 'error'.
+    pub interface_log_level: Option<String>,
+
+    /// Qualified names of classes, e.g. 'MyPackage.MyClass', to break on entry to any of their
+    /// functions. A breakpoint is set on the first statement of each function found in the
+    /// class's source file.
+    pub break_on_enter_classes: Option<Vec<String>>,
+
+    /// If true (the default) name stack frames for functions declared in a state with the
+    /// state, e.g. `Idle.Tick`. If false use just the function name.
+    pub show_frame_states: Option<bool>,
+
+    /// The path of a file of debug console commands, one per line, to run once the debugger
+    /// has connected to the game.
+    pub startup_script: Option<String>,
+
+    /// A map from package names to the directory holding each one, for workspaces where
+    /// different packages live in different source roots. A package listed here is looked for
+    /// in its own root first, so a same-named class in another root can't be picked instead.
+    pub package_roots: Option<BTreeMap<String, String>>,
+
+    /// If true leave the 'self' scope out when the game is stopped somewhere with no current
+    /// object, such as a static function, rather than showing it empty.
+    pub hide_self_without_object: Option<bool>,
+
+    /// The most local variables of the top frame to fetch each time the game stops, so they
+    /// can be shown without waiting. If not set, or 0, they're only fetched when the client asks.
+    pub prefetch_locals: Option<usize>,
 }
 
 /// Arguments for a [`Command::Evaluate`] command.
@@ -104,6 +233,27 @@ pub struct EvaluateArguments {
     #[serde(rename = "frameId")]
     /// The id of the frame in which this expression should be evaluated.
     pub frame_id: Option<i64>,
+    /// The context in which the evaluate request is used.
+    pub context: Option<EvaluateContext>,
+}
+
+/// The context of an [`EvaluateArguments`] request.
+#[derive(Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum EvaluateContext {
+    /// Evaluate is run in a watch.
+    Watch,
+    /// Evaluate is run from the REPL console.
+    Repl,
+    /// Evaluate is run from a data hover.
+    Hover,
+    /// Evaluate is run to copy a value to the clipboard.
+    Clipboard,
+    /// Evaluate is run from the variables view.
+    Variables,
+    /// Any other context not known to the adapter.
+    #[serde(other)]
+    Unknown,
 }
 
 /// Arguments for a [`Command::Initialize`] command.
@@ -115,6 +265,9 @@ pub struct InitializeArguments {
     /// Lines start at 1 (true or unset) or 0 (false).
     pub lines_start_at1: Option<bool>,
 
+    /// Columns start at 1 (true or unset) or 0 (false).
+    pub columns_start_at1: Option<bool>,
+
     /// If true the client supports 'type' fields in variables. If this is not
     /// set to true we will not send type info as part of variables responses.
     pub supports_variable_type: Option<bool>,
@@ -126,28 +279,36 @@ pub struct InitializeArguments {
     pub supports_invalidated_event: Option<bool>,
 }
 
+/// Arguments for a [`Command::Disconnect`] request.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DisconnectArguments {
+    /// If `true` this disconnect is part of a restart sequence and the session should be
+    /// started again with the same configuration.
+    pub restart: Option<bool>,
+    /// Whether the debuggee should be terminated when disconnecting. Not currently used:
+    /// a launched debuggee is always terminated unless restarting.
+    pub terminate_debuggee: Option<bool>,
+}
+
 /// Arguments for a [`Command::Launch`] request.
 ///
 /// This is sent as part of initialization when the client wants us to launch
 /// a process to debug.
 ///
 /// All arguments are implementation-defined.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct LaunchArguments {
+    /// The options shared with [`AttachArguments`].
+    #[serde(flatten)]
+    pub session: SessionArguments,
     /// If `true` we will launch but not debug the program.
     pub no_debug: Option<bool>,
-    /// The list of source roots. See [`AttachArguments::source_roots`].
-    pub source_roots: Option<Vec<String>>,
-    /// Enable the stack hack. See [`AttachArguments::enable_stack_hack`].
-    pub enable_stack_hack: Option<bool>,
     /// Full path to the program to launch.
     pub program: Option<String>,
     /// An array of arguments to pass to the program.
     pub args: Option<Vec<String>>,
-    /// Override the log level with the given log spec. Can be one of 'trace', 'debug', 'info',
-    /// 'warn', or 'error'; or a more complex log spec.
-    pub log_level: Option<String>,
     /// Specify the port number to use for communications with the interface.
     pub port: Option<i64>,
     /// If true, auto-resume after the first implicit breakpoint is hit.
@@ -155,7 +316,48 @@ pub struct LaunchArguments {
     /// The connection attempts to make.
     pub connect_attempts: Option<u32>,
     /// The connection timeout in seconds.
-    pub connect_timeout_seconds:Option<f32>
+    pub connect_timeout_seconds: Option<f32>,
+}
+
+/// Arguments for a [`Command::BreakpointLocations`] request.
+///
+/// Columns may also be sent, but Unreal breakpoints are on whole lines so these are ignored.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct BreakpointLocationsArguments {
+    /// The source file to find breakpoint locations in.
+    pub source: Source,
+    /// The first line of the range.
+    pub line: i64,
+    /// The last line of the range. If not set the range is just `line`.
+    pub end_line: Option<i64>,
+}
+
+/// Arguments for a [`Command::Cancel`] request.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CancelArguments {
+    /// The sequence number of the request to cancel.
+    pub request_id: Option<i64>,
+    /// The progress to cancel. We never report progress, so this is ignored.
+    pub progress_id: Option<String>,
+}
+
+/// Arguments for a [`Command::Locations`] request.
+#[derive(Deserialize, Debug)]
+pub struct LocationsArguments {
+    /// A location reference given to the client, e.g. in
+    /// [`crate::types::Variable::declaration_location_reference`].
+    #[serde(rename = "locationReference")]
+    pub location_reference: i64,
+}
+
+/// Arguments for a [`Command::RestartFrame`] request.
+#[derive(Deserialize, Debug)]
+pub struct RestartFrameArguments {
+    /// The frame to restart.
+    #[serde(rename = "frameId")]
+    pub frame_id: i64,
 }
 
 /// Arguments for a [`Command::Scopes`] request.
@@ -187,6 +389,43 @@ pub struct SetBreakpointsArguments {
     pub breakpoints: Option<Vec<SourceBreakpoint>>,
 }
 
+/// Arguments for a [`Command::SetExpression`] request.
+///
+/// Sent from a watch rather than the variables view, so the variable is identified by an
+/// expression instead of a variable reference.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SetExpressionArguments {
+    /// The expression naming the variable to assign to.
+    pub expression: String,
+    /// The value to assign.
+    pub value: String,
+    /// The id of the frame in which the expression should be evaluated.
+    pub frame_id: Option<i64>,
+}
+
+/// Arguments for a [`Command::SetFunctionBreakpoints`] request.
+#[derive(Deserialize, Debug)]
+pub struct SetFunctionBreakpointsArguments {
+    /// The complete list of function breakpoints.
+    pub breakpoints: Vec<FunctionBreakpoint>,
+}
+
+/// Arguments for a [`Command::SetVariable`] request.
+///
+/// The variable is identified by its name and the variable reference of its parent, as
+/// given to the client in a [`Command::Variables`] response.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SetVariableArguments {
+    /// The reference of the variable's parent: a scope or a structured variable.
+    pub variables_reference: i64,
+    /// The name of the variable in its parent.
+    pub name: String,
+    /// The value to assign.
+    pub value: String,
+}
+
 /// Arguments for a [`Command::StackTrace`] request.
 ///
 /// This requests stack information and is usually requested each time the
@@ -8,8 +8,8 @@ use serde::Serialize;
 use crate::{
     requests::Request,
     types::{
-        Breakpoint, Capabilities, Message, Scope, StackFrame, Thread, Variable,
-        VariableReferenceInfo,
+        Breakpoint, BreakpointLocation, Capabilities, Message, Scope, Source, StackFrame, Thread,
+        Variable, VariableReferenceInfo,
     },
 };
 
@@ -52,7 +52,7 @@ impl Response {
             success: true,
             message: None,
             body: Some(body),
-            command: request.command.to_string(),
+            command: request.command.name(),
         }
     }
 
@@ -64,7 +64,18 @@ impl Response {
             success: true,
             message: None,
             body: None,
-            command: request.command.to_string(),
+            command: request.command.name(),
+        }
+    }
+
+    /// Helper to make the response to a request that was cancelled before it was run.
+    pub fn make_cancelled(request: &Request) -> Self {
+        Self {
+            request_seq: request.seq,
+            success: false,
+            message: Some("cancelled".to_string()),
+            body: None,
+            command: request.command.name(),
         }
     }
 
@@ -76,7 +87,7 @@ impl Response {
             success: false,
             message: Some(title),
             body: Some(ResponseBody::Error(ErrorResponseBody { error: message })),
-            command: request.command.to_string(),
+            command: request.command.name(),
         }
     }
 }
@@ -89,6 +100,10 @@ pub enum ResponseBody {
     Initialize(Option<Capabilities>),
     /// The response to an [`crate::requests::Command::SetBreakpoints`] request.
     SetBreakpoints(SetBreakpointsResponseBody),
+    /// The response to an [`crate::requests::Command::BreakpointLocations`] request.
+    BreakpointLocations(BreakpointLocationsResponseBody),
+    /// The response to an [`crate::requests::Command::SetFunctionBreakpoints`] request.
+    SetFunctionBreakpoints(SetFunctionBreakpointsResponseBody),
     /// The response to an [`crate::requests::Command::Continue`] request.
     Continue(ContinueResponseBody),
     /// The response to an [`crate::requests::Command::StackTrace`] request.
@@ -101,6 +116,12 @@ pub enum ResponseBody {
     Threads(ThreadsResponseBody),
     /// The response to an [`crate::requests::Command::Evaluate`] request.
     Evaluate(EvaluateResponseBody),
+    /// The response to an [`crate::requests::Command::Locations`] request.
+    Locations(LocationsResponseBody),
+    /// The response to an [`crate::requests::Command::SetVariable`] request.
+    SetVariable(SetVariableResponseBody),
+    /// The response to an [`crate::requests::Command::SetExpression`] request.
+    SetExpression(SetExpressionResponseBody),
     /// The response body for an error response.
     Error(ErrorResponseBody),
 }
@@ -120,6 +141,24 @@ pub struct SetBreakpointsResponseBody {
     pub breakpoints: Vec<Breakpoint>,
 }
 
+/// A [`ResponseBody::SetFunctionBreakpoints`] response. Contains a breakpoint for each
+/// function in the request, which is unverified if the function couldn't be found.
+#[derive(Serialize, Debug)]
+#[serde(rename = "setFunctionBreakpoints")]
+pub struct SetFunctionBreakpointsResponseBody {
+    /// The breakpoints, in the order of the functions in the request.
+    pub breakpoints: Vec<Breakpoint>,
+}
+
+/// A [`ResponseBody::BreakpointLocations`] response. Contains the lines in the requested range
+/// where breakpoints can be set.
+#[derive(Serialize, Debug)]
+#[serde(rename = "breakpointLocations")]
+pub struct BreakpointLocationsResponseBody {
+    /// The breakpoint locations, in order.
+    pub breakpoints: Vec<BreakpointLocation>,
+}
+
 /// A [`ResponseBody::Continue`] response. Indicates whether all threads were
 /// continued or not. Since Unrealscript only has one thread this is always true.
 #[derive(Serialize, Debug)]
@@ -143,6 +182,22 @@ pub struct StackTraceResponseBody {
     /// The list of stack frames.
     #[serde(rename = "stackFrames")]
     pub stack_frames: Vec<StackFrame>,
+    /// The total number of frames available, if known.
+    #[serde(rename = "totalFrames", skip_serializing_if = "Option::is_none")]
+    pub total_frames: Option<i64>,
+}
+
+/// A [`ResponseBody::Locations`] response. Gives the source location for a location reference.
+#[derive(Serialize, Debug)]
+#[serde(rename = "locations")]
+pub struct LocationsResponseBody {
+    /// The source file containing the location.
+    pub source: Source,
+    /// The line of the location.
+    pub line: i64,
+    /// The column of the location, if known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<i64>,
 }
 
 /// A [`ResponseBody::Scopes`] response.
@@ -199,6 +254,40 @@ pub struct EvaluateResponseBody {
     pub variable_info: VariableReferenceInfo,
 }
 
+/// A [`ResponseBody::SetVariable`] response. Has the variable's value as read back after
+/// the assignment.
+#[derive(Serialize, Debug)]
+#[serde(rename = "setVariable")]
+pub struct SetVariableResponseBody {
+    /// The new value of the variable.
+    pub value: String,
+    /// The type of the variable. Only sent if
+    /// [`crate::requests::InitializeArguments::supports_variable_type`] was sent by the client in the
+    /// initialize request.
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub ty: Option<String>,
+    /// Variable reference info for the new value.
+    #[serde(flatten)]
+    pub variable_info: VariableReferenceInfo,
+}
+
+/// A [`ResponseBody::SetExpression`] response. Has the value of the expression as read back
+/// after the assignment.
+#[derive(Serialize, Debug)]
+#[serde(rename = "setExpression")]
+pub struct SetExpressionResponseBody {
+    /// The new value of the expression.
+    pub value: String,
+    /// The type of the expression. Only sent if
+    /// [`crate::requests::InitializeArguments::supports_variable_type`] was sent by the client in the
+    /// initialize request.
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub ty: Option<String>,
+    /// Variable reference info for the new value.
+    #[serde(flatten)]
+    pub variable_info: VariableReferenceInfo,
+}
+
 /// A response body for an error response
 #[derive(Serialize, Debug)]
 #[serde(rename = "error")]
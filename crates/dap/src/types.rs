@@ -1,5 +1,7 @@
 //! DAP Types. Data structures used as part of the protocol.
 
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 
 /// Capabilities are sent as part of the [`crate::responses::ResponseBody::Initialize`]
@@ -16,6 +18,25 @@ pub struct Capabilities {
     pub supports_delayed_stack_trace_loading: bool,
     /// make VS Code use 'evaluate' when hovering over source.
     pub supports_evaluate_for_hovers: bool,
+    /// The client may send a condition with a breakpoint.
+    pub supports_conditional_breakpoints: bool,
+    /// The client may send a log message with a breakpoint, making it a logpoint.
+    pub supports_log_points: bool,
+    /// The client may send a [`crate::requests::Command::SetVariable`] request.
+    pub supports_set_variable: bool,
+    /// The client may send a [`crate::requests::Command::SetExpression`] request.
+    pub supports_set_expression: bool,
+    /// The client may send a [`crate::requests::Command::BreakpointLocations`] request.
+    pub supports_breakpoint_locations_request: bool,
+    /// The client may send a [`crate::requests::Command::SetFunctionBreakpoints`] request.
+    pub supports_function_breakpoints: bool,
+    /// The client may send `singleThread` with requests that resume or step a thread.
+    pub supports_single_thread_execution_requests: bool,
+    /// The client may send a [`crate::requests::Command::RestartFrame`] request. This is only
+    /// true if the interface can restart a frame.
+    pub supports_restart_frame: bool,
+    /// The client may send a [`crate::requests::Command::Cancel`] request.
+    pub supports_cancel_request: bool,
 }
 
 /// Breakpoints are sent as part of the [`crate::responses::ResponseBody::SetBreakpoints`] response.
@@ -26,13 +47,25 @@ pub struct Capabilities {
 #[derive(Serialize, Debug)]
 #[serde(rename = "breakpoint")]
 pub struct Breakpoint {
-    /// If true the breakpoint was successfully set. Unreal doesn't tell us if a breakpoint
-    /// was successfully set or not so we just have to assume true always.
+    /// If true the breakpoint was successfully set. Unreal only tells us if it refused the line
+    /// outright, so this is true unless it did or the breakpoint itself is invalid, e.g. a
+    /// logpoint with a malformed message or a function that couldn't be found.
     pub verified: bool,
     /// The source file for the breakpoint.
     pub source: Source,
     /// The line number the breakpoint is on.
     pub line: i64,
+    /// Why the breakpoint couldn't be verified, shown to the user.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// A place a breakpoint can be set, sent as part of a
+/// [`crate::responses::ResponseBody::BreakpointLocations`] response.
+#[derive(Serialize, Debug)]
+pub struct BreakpointLocation {
+    /// The line of the location.
+    pub line: i64,
 }
 
 /// A source file.
@@ -46,6 +79,14 @@ pub struct Source {
     pub name: Option<String>,
     /// The full path to the file
     pub path: Option<String>,
+    /// A reference to the source for sources without a path, e.g. classes whose file
+    /// wasn't found on disk.
+    #[serde(
+        rename = "sourceReference",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
+    pub source_reference: Option<i64>,
 }
 
 /// A stack frame, sent as part of a `[crate::responses::ResponseBody::StackTrace`] response.
@@ -61,8 +102,9 @@ pub struct StackFrame {
     /// 0 for all stack frames other than the top-most because Unreal does not provide
     /// this info.
     pub line: i64,
-    /// The column number for this frame. Unreal does not support column info so this
-    /// is always 0.
+    /// The column number for this frame. Unreal does not support column info, so this is
+    /// the column of the call the frame is making if it can be found in the source, otherwise
+    /// the first column of the line, or 0 if there is no source.
     pub column: i64,
 }
 
@@ -97,12 +139,34 @@ pub struct Variable {
     /// client in the initialize request.
     #[serde(rename = "type")]
     pub ty: Option<String>,
+    /// An expression that evaluates to this variable, used by the client to add it to the
+    /// watch list.
+    #[serde(rename = "evaluateName", skip_serializing_if = "Option::is_none")]
+    pub evaluate_name: Option<String>,
+    /// Hints for how the client should show this variable.
+    #[serde(rename = "presentationHint", skip_serializing_if = "Option::is_none")]
+    pub presentation_hint: Option<VariablePresentationHint>,
+    /// A reference the client can use in a [`crate::requests::Command::Locations`] request to
+    /// find where this variable is declared.
+    #[serde(
+        rename = "declarationLocationReference",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub declaration_location_reference: Option<i64>,
     /// Variable reference info. This is not part of DAP and is a wrapper struct to hold
     /// common info for this implementation.
     #[serde(flatten)]
     pub variable_info: VariableReferenceInfo,
 }
 
+/// Hints for how a [`Variable`] should be shown.
+#[derive(Serialize, Debug)]
+pub struct VariablePresentationHint {
+    /// Attributes of the variable. Besides the attributes defined by DAP we send `changed` for
+    /// variables whose value changed since execution last stopped.
+    pub attributes: Vec<String>,
+}
+
 /// A thread, sent as part of a [`crate::responses::ResponseBody::Threads`] response.
 ///
 /// Unrealscript has only one thread, so this is always the same.
@@ -120,7 +184,31 @@ pub struct Thread {
 pub struct SourceBreakpoint {
     /// The line for this breakpoint.
     pub line: i64,
-    // TODO implement log points
+    /// An expression that must evaluate to true for the debugger to stop at this breakpoint.
+    pub condition: Option<String>,
+    /// If set, this is a logpoint: instead of stopping, the debugger logs this message.
+    /// Expressions within `{}` are evaluated and replaced with their values.
+    #[serde(rename = "logMessage")]
+    pub log_message: Option<String>,
+}
+
+impl SourceBreakpoint {
+    /// Create a breakpoint on the given line without a condition or log message.
+    pub fn new(line: i64) -> SourceBreakpoint {
+        SourceBreakpoint {
+            line,
+            condition: None,
+            log_message: None,
+        }
+    }
+}
+
+/// A function breakpoint, sent by the client as part of a
+/// [`crate::requests::Command::SetFunctionBreakpoints`] request.
+#[derive(Deserialize, Debug)]
+pub struct FunctionBreakpoint {
+    /// The name of the function, e.g. `MyPackage.MyClass:PostBeginPlay`.
+    pub name: String,
 }
 
 /// A type to abstract some common parts of DAP responses relating to variables.
@@ -155,6 +243,9 @@ pub struct Message {
     /// If true this error should be displayed to the user.
     #[serde(rename = "showUser")]
     pub show_user: bool,
+    /// Values for the `{name}` placeholders in the format, or extra details about the error.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub variables: Option<BTreeMap<String, String>>,
 }
 
 impl VariableReferenceInfo {
@@ -4,6 +4,10 @@
 //!
 //! This contains all the publicly exported functions defined by the Unrealscript
 //! debugger interface.
+//!
+//! Some engine builds call an entry point by a different name than others. These are
+//! supported by exporting an alias for the entry point with the `alias!` macro rather than
+//! duplicating its body.
 
 /// The unreal callback type. Note that the debugger specification defines
 /// it as accepting a 'const char*' parameter but we use u8 here. This is
@@ -13,10 +17,58 @@ pub type UnrealCallback = extern "C" fn(*const u8) -> ();
 use std::ffi::c_char;
 
 use crate::lifetime::initialize;
-use common::WatchKind;
 use log;
 
-use crate::DEBUGGER;
+use crate::{
+    debugger::{Debugger, InputError},
+    DEBUGGER,
+};
+
+/// Export an entry point under another name, forwarding to the named entry point. The
+/// documentation comment should say which engine builds use the alias.
+macro_rules! alias {
+    ($(#[$attr:meta])* $alias:ident => $name:ident($($arg:ident: $ty:ty),*) $(-> $ret:ty)?) => {
+        $(#[$attr])*
+        #[no_mangle]
+        pub extern "C" fn $alias($($arg: $ty),*) $(-> $ret)? {
+            log::trace!(concat!(stringify!($alias), " -> ", stringify!($name)));
+            $name($($arg),*)
+        }
+    };
+}
+
+/// Run the given function with the debugger state.
+///
+/// Some engine builds call entry points such as `ShowDllForm` before `SetCallback`, when there
+/// is no debugger yet. These calls are ignored with a warning rather than panicking, which would
+/// crash the game.
+fn with_debugger(entry_point: &str, f: impl FnOnce(&mut Debugger)) {
+    let mut hnd = DEBUGGER.lock().unwrap();
+    match hnd.as_mut() {
+        Some(dbg) => f(dbg),
+        None => log::warn!("{entry_point} called before the debugger was initialized"),
+    }
+}
+
+/// Run the given fallible function with the debugger state, returning its result.
+///
+/// If there is no debugger yet, or Unreal gave us arguments the debugger can't use, the error
+/// is logged and the default value returned: panicking here would unwind across the FFI
+/// boundary.
+fn try_with_debugger<T: Default>(
+    entry_point: &str,
+    f: impl FnOnce(&mut Debugger) -> Result<T, InputError>,
+) -> T {
+    let mut hnd = DEBUGGER.lock().unwrap();
+    let Some(dbg) = hnd.as_mut() else {
+        log::warn!("{entry_point} called before the debugger was initialized");
+        return T::default();
+    };
+    f(dbg).unwrap_or_else(|e| {
+        log::error!("{entry_point}: {e}");
+        T::default()
+    })
+}
 
 /// Called once from Unreal when the debugger interface is initialized, passing the callback
 /// function to use.
@@ -37,9 +89,9 @@ pub extern "C" fn SetCallback(callback: Option<UnrealCallback>) {
 #[no_mangle]
 pub extern "C" fn ShowDllForm() {
     log::trace!("ShowDllForm");
-    let mut hnd = DEBUGGER.lock().unwrap();
-    let dbg = hnd.as_mut().unwrap();
-    dbg.show_dll_form();
+    with_debugger("ShowDllForm", |dbg| {
+        dbg.show_dll_form();
+    });
 }
 
 /// Add the given class to the class hierarchy.
@@ -48,18 +100,18 @@ pub extern "C" fn ShowDllForm() {
 #[no_mangle]
 pub extern "C" fn AddClassToHierarchy(class_name: *const c_char) {
     log::trace!("AddClassToHierarchy");
-    let mut hnd = DEBUGGER.lock().unwrap();
-    let dbg = hnd.as_mut().unwrap();
-    dbg.add_class_to_hierarchy(class_name);
+    with_debugger("AddClassToHierarchy", |dbg| {
+        dbg.add_class_to_hierarchy(class_name);
+    });
 }
 
 /// Clear the class hierarchy in the debugger state.
 #[no_mangle]
 pub extern "C" fn ClearClassHierarchy() {
     log::trace!("ClearClassHierarchy");
-    let mut hnd = DEBUGGER.lock().unwrap();
-    let dbg = hnd.as_mut().unwrap();
-    dbg.clear_class_hierarchy();
+    with_debugger("ClearClassHierarchy", |dbg| {
+        dbg.clear_class_hierarchy();
+    });
 }
 
 /// ???
@@ -68,17 +120,6 @@ pub extern "C" fn BuildClassHierarchy() {
     log::trace!("BuildClassHierarchy");
 }
 
-/// Legacy version of ClearAWatch.
-#[no_mangle]
-pub extern "C" fn ClearWatch(kind: i32) {
-    log::trace!("ClearWatch {kind}");
-    let mut hnd = DEBUGGER.lock().unwrap();
-    let dbg = hnd.as_mut().unwrap();
-    dbg.clear_watch(
-        WatchKind::from_int(kind).expect("Unreal should never give us a bad watch kind."),
-    );
-}
-
 /// Removes all watches of the given kind.
 ///
 /// Used when rebuilding the watch list.
@@ -86,17 +127,19 @@ pub extern "C" fn ClearWatch(kind: i32) {
 #[no_mangle]
 pub extern "C" fn ClearAWatch(kind: i32) {
     log::trace!("ClearAWatch {kind}");
-    let mut hnd = DEBUGGER.lock().unwrap();
-    let dbg = hnd.as_mut().unwrap();
-    dbg.clear_watch(
-        WatchKind::from_int(kind).expect("Unreal should never give us a bad watch kind."),
-    );
+    try_with_debugger("ClearAWatch", |dbg| dbg.try_clear_watch(kind));
 }
 
+alias!(
+    /// Legacy version of ClearAWatch, called by older engine builds.
+    ClearWatch => ClearAWatch(kind: i32)
+);
+
 /// Adds a watch to the watch list for the given kind.
 ///
 /// This is the only Unreal
-/// debugger API that returns a value.
+/// debugger API that returns a value. If the watch can't be added this returns 0, which
+/// Unreal will treat as the root if it uses it as the parent of another watch.
 #[no_mangle]
 pub extern "C" fn AddAWatch(
     kind: i32,
@@ -104,14 +147,9 @@ pub extern "C" fn AddAWatch(
     name: *const c_char,
     value: *const c_char,
 ) -> i32 {
-    let mut hnd = DEBUGGER.lock().unwrap();
-    let dbg = hnd.as_mut().unwrap();
-    dbg.add_watch(
-        WatchKind::from_int(kind).expect("Unreal should never give us a bad watch kind."),
-        parent,
-        name,
-        value,
-    )
+    try_with_debugger("AddAWatch", |dbg| {
+        dbg.try_add_watch(kind, parent, name, value)
+    })
 }
 
 /// Locks the given watch list.
@@ -121,9 +159,9 @@ pub extern "C" fn AddAWatch(
 #[no_mangle]
 pub extern "C" fn LockList(_kind: i32) {
     log::trace!("LockList {_kind}");
-    let mut hnd = DEBUGGER.lock().unwrap();
-    let dbg = hnd.as_mut().unwrap();
-    dbg.lock_watchlist()
+    with_debugger("LockList", |dbg| {
+        dbg.lock_watchlist();
+    });
 }
 
 /// Unlocks the given watch list.
@@ -132,11 +170,7 @@ pub extern "C" fn LockList(_kind: i32) {
 #[no_mangle]
 pub extern "C" fn UnlockList(kind: i32) {
     log::trace!("UnlockList {kind}");
-    let mut hnd = DEBUGGER.lock().unwrap();
-    let dbg = hnd.as_mut().unwrap();
-    dbg.unlock_watchlist(
-        WatchKind::from_int(kind).expect("Unreal should never give us a bad watch kind."),
-    );
+    try_with_debugger("UnlockList", |dbg| dbg.try_unlock_watchlist(kind));
 }
 
 /// Adds a breakpoint.
@@ -144,9 +178,9 @@ pub extern "C" fn UnlockList(kind: i32) {
 /// Called in response to an 'addbreakpoint' command.
 #[no_mangle]
 pub extern "C" fn AddBreakpoint(class_name: *const c_char, line: i32) {
-    let mut hnd = DEBUGGER.lock().unwrap();
-    let dbg = hnd.as_mut().unwrap();
-    dbg.add_breakpoint(class_name, line);
+    with_debugger("AddBreakpoint", |dbg| {
+        dbg.add_breakpoint(class_name, line);
+    });
 }
 
 /// Remove a breakpoint.
@@ -154,9 +188,9 @@ pub extern "C" fn AddBreakpoint(class_name: *const c_char, line: i32) {
 /// Called in response to a 'removebreakpoint' command.
 #[no_mangle]
 pub extern "C" fn RemoveBreakpoint(class_name: *const c_char, line: i32) {
-    let mut hnd = DEBUGGER.lock().unwrap();
-    let dbg = hnd.as_mut().unwrap();
-    dbg.remove_breakpoint(class_name, line);
+    with_debugger("RemoveBreakpoint", |dbg| {
+        dbg.remove_breakpoint(class_name, line);
+    });
 }
 
 /// Focus the given class name in the editor.
@@ -172,17 +206,17 @@ pub extern "C" fn EditorLoadClass(_class_name: *const c_char) {}
 /// Jump to the given line in the editor.
 #[no_mangle]
 pub extern "C" fn EditorGotoLine(line: i32, _highlight: i32) {
-    let mut hnd = DEBUGGER.lock().unwrap();
-    let dbg = hnd.as_mut().unwrap();
-    dbg.goto_line(line);
+    with_debugger("EditorGotoLine", |dbg| {
+        dbg.goto_line(line);
+    });
 }
 
 /// A line has been added to the log.
 #[no_mangle]
 pub extern "C" fn AddLineToLog(text: *const c_char) {
-    let mut hnd = DEBUGGER.lock().unwrap();
-    let dbg = hnd.as_mut().unwrap();
-    dbg.add_line_to_log(text);
+    with_debugger("AddLineToLog", |dbg| {
+        dbg.add_line_to_log(text);
+    });
 }
 
 /// Clear the call stack.
@@ -192,9 +226,9 @@ pub extern "C" fn AddLineToLog(text: *const c_char) {
 #[no_mangle]
 pub extern "C" fn CallStackClear() {
     log::trace!("CallStackClear");
-    let mut hnd = DEBUGGER.lock().unwrap();
-    let dbg = hnd.as_mut().unwrap();
-    dbg.clear_callstack();
+    with_debugger("CallStackClear", |dbg| {
+        dbg.clear_callstack();
+    });
 }
 
 /// Add the given class name to the call stack. Call stacks are built bottom-up
@@ -202,18 +236,18 @@ pub extern "C" fn CallStackClear() {
 #[no_mangle]
 pub extern "C" fn CallStackAdd(class_name: *const c_char) {
     log::trace!("CallStackAdd");
-    let mut hnd = DEBUGGER.lock().unwrap();
-    let dbg = hnd.as_mut().unwrap();
-    dbg.add_frame(class_name);
+    with_debugger("CallStackAdd", |dbg| {
+        dbg.add_frame(class_name);
+    });
 }
 
 /// Record the object name for the current object (this).
 #[no_mangle]
 pub extern "C" fn SetCurrentObjectName(obj_name: *const c_char) {
     log::trace!("SetCurrentObjectName");
-    let mut hnd = DEBUGGER.lock().unwrap();
-    let dbg = hnd.as_mut().unwrap();
-    dbg.current_object_name(obj_name);
+    with_debugger("SetCurrentObjectName", |dbg| {
+        dbg.current_object_name(obj_name);
+    });
 }
 
 /// Unused.
@@ -221,3 +255,68 @@ pub extern "C" fn SetCurrentObjectName(obj_name: *const c_char) {
 pub extern "C" fn DebugWindowState(code: i32) {
     log::trace!("DebugWindowState {code}");
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use common::WatchKind;
+    use tokio::sync::mpsc::unbounded_channel;
+
+    use super::*;
+
+    // Entry points use the global debugger state, so tests that call them can't run at the
+    // same time.
+    static STATE_LOCK: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn show_dll_form_before_init() {
+        let _lock = STATE_LOCK.lock().unwrap();
+        assert!(DEBUGGER.lock().unwrap().is_none());
+        ShowDllForm();
+        assert!(DEBUGGER.lock().unwrap().is_none());
+    }
+
+    // Add a local and a global watch, then clear the locals with the given entry point.
+    // Returns the number of local and global watches left.
+    fn clear_locals_with(clear: extern "C" fn(i32)) -> (usize, usize) {
+        let name = c"SomeVar".as_ptr();
+        let val = c"10".as_ptr();
+        let (ctx, _) = unbounded_channel();
+        DEBUGGER.lock().unwrap().replace(Debugger::new(ctx, None));
+        AddAWatch(0, -1, name, val);
+        AddAWatch(1, -1, name, val);
+        clear(0);
+        let mut dbg = DEBUGGER.lock().unwrap().take().unwrap();
+        (
+            dbg.watch_count(WatchKind::Local, 0),
+            dbg.watch_count(WatchKind::Global, 0),
+        )
+    }
+
+    #[test]
+    fn alias_dispatches_like_entry_point() {
+        let _lock = STATE_LOCK.lock().unwrap();
+        assert_eq!(clear_locals_with(ClearAWatch), (0, 1));
+        assert_eq!(clear_locals_with(ClearWatch), (0, 1));
+    }
+
+    #[test]
+    fn bad_watch_input_is_ignored() {
+        let _lock = STATE_LOCK.lock().unwrap();
+        let name = c"SomeVar".as_ptr();
+        let val = c"10".as_ptr();
+        assert_eq!(AddAWatch(0, -1, name, val), 0);
+
+        let (ctx, _) = unbounded_channel();
+        DEBUGGER.lock().unwrap().replace(Debugger::new(ctx, None));
+        assert_eq!(AddAWatch(0, -1, name, val), 1);
+        assert_eq!(AddAWatch(3, -1, name, val), 0);
+        assert_eq!(AddAWatch(0, 5, name, val), 0);
+        assert_eq!(AddAWatch(0, -1, std::ptr::null(), val), 0);
+        ClearAWatch(-1);
+        UnlockList(7);
+        let mut dbg = DEBUGGER.lock().unwrap().take().unwrap();
+        assert_eq!(dbg.watch_count(WatchKind::Local, 0), 1);
+    }
+}
@@ -0,0 +1,308 @@
+//! The channel carrying messages from the debugger to the adapter connection.
+//!
+//! Messages are sent from Unreal's thread and written to the adapter from the interface's own
+//! thread. If the adapter falls behind, e.g. while the game floods the log, an unbounded
+//! channel would grow without limit inside the game process. This channel instead holds at
+//! most [`MAX_QUEUED_LOGS`] log events or interface log lines, dropping the oldest ones to
+//! make room for new ones. Responses and other events are never dropped since the adapter
+//! depends on them.
+//!
+//! Dropping log events is the first sign that the adapter isn't keeping up, so the interface
+//! logs a warning when it starts and again once the adapter has caught up.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use common::{UnrealEvent, UnrealInterfaceMessage};
+use thiserror::Error;
+use tokio::sync::Notify;
+
+/// The maximum number of log events waiting to be sent to the adapter.
+pub const MAX_QUEUED_LOGS: usize = 1000;
+
+/// The error returned when sending a message to a receiver that has been dropped.
+#[derive(Error, Debug)]
+#[error("Channel closed")]
+pub struct ChannelClosed;
+
+/// Create a new channel that holds at most `max_logs` log events.
+pub fn message_channel(max_logs: usize) -> (MessageSender, MessageReceiver) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            queue: VecDeque::new(),
+            logs: 0,
+            dropped: 0,
+            lagging: false,
+            sender_closed: false,
+            receiver_closed: false,
+        }),
+        notify: Notify::new(),
+        max_logs,
+    });
+    (
+        MessageSender {
+            shared: shared.clone(),
+        },
+        MessageReceiver { shared },
+    )
+}
+
+struct Shared {
+    state: Mutex<State>,
+    notify: Notify,
+    max_logs: usize,
+}
+
+struct State {
+    queue: VecDeque<UnrealInterfaceMessage>,
+    // The number of log events and interface log lines in the queue.
+    logs: usize,
+    // The number of log events dropped since the receiver was last told about it.
+    dropped: usize,
+    // True from when log events are first dropped until the receiver empties the queue.
+    lagging: bool,
+    sender_closed: bool,
+    receiver_closed: bool,
+}
+
+fn is_log(msg: &UnrealInterfaceMessage) -> bool {
+    matches!(
+        msg,
+        UnrealInterfaceMessage::Event(UnrealEvent::Log(_))
+            | UnrealInterfaceMessage::InterfaceLog(_)
+    )
+}
+
+impl Shared {
+    fn push(&self, msg: UnrealInterfaceMessage) -> Result<(), ChannelClosed> {
+        let mut state = self.state.lock().unwrap();
+        if state.receiver_closed || state.sender_closed {
+            return Err(ChannelClosed);
+        }
+        let mut started_lagging = false;
+        if is_log(&msg) {
+            if state.logs >= self.max_logs {
+                if let Some(pos) = state.queue.iter().position(is_log) {
+                    state.queue.remove(pos);
+                    state.logs -= 1;
+                    state.dropped += 1;
+                    started_lagging = !std::mem::replace(&mut state.lagging, true);
+                }
+            }
+            state.logs += 1;
+        }
+        state.queue.push_back(msg);
+        drop(state);
+        self.notify.notify_one();
+
+        // Our own log lines may be sent on this channel, so only log once it's unlocked.
+        if started_lagging {
+            log::warn!("The adapter isn't keeping up with the game, dropping log lines");
+        }
+        Ok(())
+    }
+}
+
+/// The sending half of a message channel.
+pub struct MessageSender {
+    shared: Arc<Shared>,
+}
+
+impl MessageSender {
+    /// Queue a message for the adapter. If this is a log event and the queue already holds the
+    /// maximum number of them the oldest queued log event is dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ChannelClosed`] if the receiver has been dropped.
+    pub fn send(&self, msg: UnrealInterfaceMessage) -> Result<(), ChannelClosed> {
+        self.shared.push(msg)
+    }
+
+    /// Create a sender for the interface's own log lines on this channel. Unlike a second
+    /// message sender it doesn't keep the channel open: it stops working once this sender is
+    /// dropped.
+    pub fn log_sender(&self) -> LogSender {
+        LogSender {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl Drop for MessageSender {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().sender_closed = true;
+        self.shared.notify.notify_one();
+    }
+}
+
+/// A sender for interface log lines, see [`MessageSender::log_sender`].
+#[derive(Clone)]
+pub struct LogSender {
+    shared: Arc<Shared>,
+}
+
+impl LogSender {
+    /// Queue an interface log line for the adapter. Like log events, the oldest queued line
+    /// is dropped if the queue is full.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ChannelClosed`] if either the message sender or the receiver has been dropped.
+    pub fn send(&self, line: String) -> Result<(), ChannelClosed> {
+        self.shared.push(UnrealInterfaceMessage::InterfaceLog(line))
+    }
+
+    /// Returns true if both senders send on the same channel.
+    pub fn same_channel(&self, other: &LogSender) -> bool {
+        Arc::ptr_eq(&self.shared, &other.shared)
+    }
+}
+
+/// The receiving half of a message channel.
+pub struct MessageReceiver {
+    shared: Arc<Shared>,
+}
+
+impl MessageReceiver {
+    /// Take the next message from the queue, if there is one. If log events were dropped a log
+    /// event saying how many is returned before the next queued log event.
+    pub fn try_recv(&mut self) -> Option<UnrealInterfaceMessage> {
+        let mut state = self.shared.state.lock().unwrap();
+        if state.dropped > 0 && state.queue.front().is_some_and(is_log) {
+            let dropped = std::mem::take(&mut state.dropped);
+            return Some(UnrealInterfaceMessage::Event(UnrealEvent::Log(format!(
+                "[{dropped} log lines dropped]\r\n"
+            ))));
+        }
+        let msg = state.queue.pop_front()?;
+        if is_log(&msg) {
+            state.logs -= 1;
+        }
+        let caught_up = state.lagging && state.queue.is_empty();
+        if caught_up {
+            state.lagging = false;
+        }
+        drop(state);
+        if caught_up {
+            log::info!("The adapter has caught up with the game");
+        }
+        Some(msg)
+    }
+
+    /// Wait for the next message. Returns `None` once the sender has been dropped and all
+    /// queued messages have been received.
+    pub async fn recv(&mut self) -> Option<UnrealInterfaceMessage> {
+        loop {
+            if let Some(msg) = self.try_recv() {
+                return Some(msg);
+            }
+            if self.shared.state.lock().unwrap().sender_closed {
+                return None;
+            }
+            self.shared.notify.notified().await;
+        }
+    }
+
+    /// Wait for the next message, blocking the current thread.
+    pub fn blocking_recv(&mut self) -> Option<UnrealInterfaceMessage> {
+        futures::executor::block_on(self.recv())
+    }
+}
+
+impl Drop for MessageReceiver {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().receiver_closed = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(text: &str) -> UnrealInterfaceMessage {
+        UnrealInterfaceMessage::Event(UnrealEvent::Log(text.to_string()))
+    }
+
+    fn received(rx: &mut MessageReceiver) -> Vec<String> {
+        std::iter::from_fn(|| rx.try_recv())
+            .map(|msg| match msg {
+                UnrealInterfaceMessage::Event(UnrealEvent::Log(text)) => text,
+                other => format!("{other:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn logs_dropped_under_pressure() {
+        let (tx, mut rx) = message_channel(2);
+        tx.send(log("one")).unwrap();
+        tx.send(UnrealInterfaceMessage::Event(UnrealEvent::Stopped))
+            .unwrap();
+        tx.send(log("two")).unwrap();
+        tx.send(log("three")).unwrap();
+        tx.send(UnrealInterfaceMessage::Event(UnrealEvent::Disconnect))
+            .unwrap();
+        tx.send(log("four")).unwrap();
+        assert_eq!(
+            received(&mut rx),
+            vec![
+                "Event(Stopped)",
+                "[2 log lines dropped]\r\n",
+                "three",
+                "Event(Disconnect)",
+                "four"
+            ]
+        );
+    }
+
+    #[test]
+    fn lagging_until_drained() {
+        let (tx, mut rx) = message_channel(2);
+        let lagging = |tx: &MessageSender| tx.shared.state.lock().unwrap().lagging;
+        tx.send(log("one")).unwrap();
+        tx.send(log("two")).unwrap();
+        assert!(!lagging(&tx));
+        tx.send(log("three")).unwrap();
+        assert!(lagging(&tx));
+
+        // Still lagging until every queued message has been received.
+        rx.try_recv().unwrap();
+        rx.try_recv().unwrap();
+        assert!(lagging(&tx));
+        rx.try_recv().unwrap();
+        assert!(!lagging(&tx));
+    }
+
+    #[test]
+    fn log_sender_closed_with_sender() {
+        let (tx, mut rx) = message_channel(2);
+        let log_tx = tx.log_sender();
+        log_tx.send("one".to_string()).unwrap();
+        drop(tx);
+        assert!(log_tx.send("two".to_string()).is_err());
+        assert!(matches!(
+            rx.blocking_recv(),
+            Some(UnrealInterfaceMessage::InterfaceLog(line)) if line == "one"
+        ));
+        assert!(rx.blocking_recv().is_none());
+    }
+
+    #[test]
+    fn send_after_receiver_dropped() {
+        let (tx, rx) = message_channel(2);
+        drop(rx);
+        assert!(tx.send(log("one")).is_err());
+    }
+
+    #[test]
+    fn recv_after_sender_dropped() {
+        let (tx, mut rx) = message_channel(2);
+        tx.send(log("one")).unwrap();
+        drop(tx);
+        assert!(rx.blocking_recv().is_some());
+        assert!(rx.blocking_recv().is_none());
+    }
+}
@@ -4,10 +4,14 @@
 //! by Unreal and all the associated handler functions for managing calls from the
 //! Unreal API and calls from the connected adapter.
 use flexi_logger::LogSpecification;
+use log::LevelFilter;
+use std::borrow::Cow;
+use std::collections::BTreeSet;
 use std::ffi::{c_char, CStr};
+use std::str::FromStr;
 use std::thread::JoinHandle;
 use thiserror::Error;
-use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio::sync::mpsc::UnboundedSender;
 use winapi::um::stringapiset::{MultiByteToWideChar, WideCharToMultiByte};
 use winapi::um::winnls::{CP_ACP, CP_UTF8};
 
@@ -15,8 +19,10 @@ use common::{
     Breakpoint, FrameIndex, InitializeResponse, StackTraceRequest, StackTraceResponse,
     UnrealCommand, UnrealEvent, UnrealInterfaceMessage, UnrealResponse, Variable, VariableIndex,
 };
-use common::{Frame, WatchKind};
+use common::{Frame, WatchKind, NATIVE_FRAME_NAME, UNKNOWN_TYPE};
 
+use crate::channel::MessageSender;
+use crate::log_forwarder;
 use crate::stackhack::{StackHack, DEFAULT_MODEL};
 use crate::{INTERFACE_VERSION, LOGGER, VARIABLE_REQUST_CONDVAR};
 
@@ -35,11 +41,15 @@ pub struct Debugger {
     user_watches: Vec<Watch>,
     callstack: Vec<Frame>,
     current_object_name: Option<String>,
-    response_channel: Option<tokio::sync::mpsc::UnboundedSender<UnrealInterfaceMessage>>,
+    response_channel: Option<MessageSender>,
     saw_show_dll: bool,
     pending_break_event: bool,
     current_line: i32,
 
+    // The breakpoints Unreal has reported adding and not yet removed, by upper-case qualified
+    // class name and line. Used to list them for an adapter that connects again.
+    breakpoints: BTreeSet<(String, i32)>,
+
     // The frame index for which we have received watch info. This is stored
     // in DAP format, with 0 being the top-most frame, which is the _last_
     // frame unreal gives us when building the call stack, but is the only frame
@@ -56,6 +66,11 @@ pub struct Debugger {
     // could result in more variable requests.
     pending_variable_request: Option<PendingVariableRequest>,
 
+    // Set when the adapter cancels the pending variable request. Unreal still has to finish
+    // switching frames, so the request stays pending until it does, but its response is
+    // dropped: the adapter has already been told it was cancelled.
+    request_cancelled: bool,
+
     // The optional stack hack implementation to use. If none then we will not have
     // line numbers for any stack frame other than the top-most.
     stack_hack: Option<StackHack>,
@@ -72,6 +87,9 @@ enum PendingVariableRequest {
     Variables(WatchKind, FrameIndex, VariableIndex, usize, usize),
     UserWatch,
     CrossFrameUserWatch,
+    FrameLine(FrameIndex),
+    RestoreFrame(String, i32),
+    RefreshWatches,
 }
 
 /// A variable watch.
@@ -107,6 +125,20 @@ pub enum DebuggerError {
     NotConnected,
 }
 
+/// Arguments Unreal gave an entry point that we can't use.
+#[derive(Error, Debug)]
+pub enum InputError {
+    /// Unreal gave us a watch kind we don't know.
+    #[error("Invalid watch kind {0}")]
+    InvalidWatchKind(i32),
+    /// Unreal gave us a watch parent that isn't in the watch list.
+    #[error("Invalid watch parent {0}")]
+    InvalidWatchParent(i32),
+    /// Unreal gave us a null pointer where it should have given us a string.
+    #[error("Null {0}")]
+    NullPointer(&'static str),
+}
+
 /// The action the debugger processing loop should take after resolving a command.
 ///
 /// Some commands from the adapter require us to dispatch the command to unreal through
@@ -180,8 +212,10 @@ impl Debugger {
             saw_show_dll: false,
             pending_break_event: false,
             current_line: 0,
+            breakpoints: BTreeSet::new(),
             current_frame: FrameIndex::TOP_FRAME,
             pending_variable_request: None,
+            request_cancelled: false,
             stack_hack: None,
             widechar_buffer: Vec::with_capacity(DEFAULT_WIDECHAR_CAPACITY),
             narrow_buffer: Vec::with_capacity(DEFAULT_NARROW_CAPACITY),
@@ -211,6 +245,10 @@ impl Debugger {
     ) -> Result<CommandAction, DebuggerError> {
         match command {
             UnrealCommand::Initialize(init) => {
+                // Tag our log lines with the adapter's session id so they can be matched up
+                // with its log.
+                common::set_session_id(init.session_id.as_deref());
+
                 // Override the default log level if specified.
                 if let Some(loglevel) = &init.overridden_log_level {
                     match LogSpecification::try_from(loglevel) {
@@ -227,6 +265,19 @@ impl Debugger {
                     }
                 }
 
+                // Send our own log lines to the adapter too if it wants them.
+                if let Some(level) = &init.forwarded_log_level {
+                    match (LevelFilter::from_str(level), &self.response_channel) {
+                        (Ok(level), Some(channel)) => {
+                            log_forwarder::start(channel.log_sender(), level)
+                        }
+                        (Ok(_), None) => (),
+                        (Err(e), _) => {
+                            log::error!("Failed to forward logs at level {level}: {e}")
+                        }
+                    }
+                }
+
                 log::info!(
                     "Connected to adapter version {}.{}.{}; interface version {}.{}.{}",
                     init.version.major,
@@ -250,6 +301,15 @@ impl Debugger {
                 }
                 self.send_response(UnrealResponse::Initialize(InitializeResponse {
                     version: INTERFACE_VERSION.clone(),
+                    supports_list_breakpoints: true,
+                    supports_threads: true,
+                    // Unreal's debugger has no way to move execution back to the start of a
+                    // function.
+                    supports_restart_frame: false,
+                    supports_frame_line: true,
+                    supports_cancel_variable_request: true,
+                    supports_refresh_watches: true,
+                    supports_set_variable: true,
                 }))?;
                 // This doesn't require any action by Unreal
                 Ok(CommandAction::Nothing)
@@ -264,6 +324,22 @@ impl Debugger {
                 log::trace!("handle_command: {str}");
                 Ok(CommandAction::Callback(self.encode_string(&str)))
             }
+            UnrealCommand::ListBreakpoints => {
+                let breakpoints = self
+                    .breakpoints
+                    .iter()
+                    .map(|(name, line)| Breakpoint::new(name, *line))
+                    .collect();
+                self.send_response(UnrealResponse::Breakpoints(breakpoints))?;
+                Ok(CommandAction::Nothing)
+            }
+            UnrealCommand::Threads => {
+                // Unreal runs all script on one thread, but it tells us which object it stopped
+                // in and that's shown as a thread of its own.
+                let threads = self.current_object_name.iter().cloned().collect();
+                self.send_response(UnrealResponse::Threads(threads))?;
+                Ok(CommandAction::Nothing)
+            }
             UnrealCommand::StackTrace(stack) => {
                 // A stack trace request can be handled without talking to unreal: we
                 // just return the current call stack state.
@@ -359,6 +435,65 @@ impl Debugger {
                     Ok(CommandAction::Callback(self.encode_string(&str)))
                 }
             }
+            UnrealCommand::FrameLine(frame) => {
+                let frame_id: usize = frame.into();
+                if frame_id >= self.callstack.len() {
+                    log::error!("Frame line request stack frame {frame_id} is out of range.");
+                    self.send_response(UnrealResponse::FrameLine(String::new(), 0))?;
+                    return Ok(CommandAction::Nothing);
+                }
+
+                // If we already know the line for this frame we can respond immediately. This is
+                // always the case for the current frame, and for every frame with the stack hack.
+                let entry = &self.callstack[self.callstack.len() - 1 - frame_id];
+                if frame == self.current_frame || entry.line != 0 {
+                    let response =
+                        UnrealResponse::FrameLine(entry.qualified_name.clone(), entry.line);
+                    self.send_response(response)?;
+                    return Ok(CommandAction::Nothing);
+                }
+
+                // Otherwise switch to the frame to learn the line, then switch back to the
+                // current frame so that subsequent requests see the same state as before.
+                log::trace!("Registering pending request for line of frame {frame}");
+                self.pending_variable_request = Some(PendingVariableRequest::FrameLine(frame));
+                let change_stack = format!("changestack {frame}");
+                let restore_stack = format!("changestack {}", self.current_frame);
+                Ok(CommandAction::MultiStepCallback(vec![
+                    self.encode_string(&change_stack),
+                    self.encode_string(&restore_stack),
+                ]))
+            }
+            UnrealCommand::RefreshWatches => {
+                // Unreal rebuilds all the watch lists whenever it switches stack frames, so
+                // "switch" to the frame we're already in and wait for the lists to be unlocked.
+                log::trace!("Registering pending request to refresh watches");
+                self.pending_variable_request = Some(PendingVariableRequest::RefreshWatches);
+                let str = format!("changestack {}", self.current_frame);
+                Ok(CommandAction::Callback(self.encode_string(&str)))
+            }
+            UnrealCommand::SetVariable(expr, value) => {
+                // Unreal doesn't tell us whether the assignment worked, so rebuild the watch
+                // lists afterwards as for a refresh. The adapter reads back the new value.
+                log::trace!("Registering pending request to set {expr}");
+                self.pending_variable_request = Some(PendingVariableRequest::RefreshWatches);
+                let set = format!("set {expr} {value}");
+                let change_stack = format!("changestack {}", self.current_frame);
+                Ok(CommandAction::MultiStepCallback(vec![
+                    self.encode_string(&set),
+                    self.encode_string(&change_stack),
+                ]))
+            }
+            UnrealCommand::CancelVariableRequest => {
+                // The adapter sends this while waiting for a response, which may have been sent
+                // already. Only answer if there's still a response to replace.
+                if self.pending_variable_request.is_some() && !self.request_cancelled {
+                    log::trace!("Cancelling pending variable request");
+                    self.request_cancelled = true;
+                    self.send_response(UnrealResponse::Cancelled)?;
+                }
+                Ok(CommandAction::Nothing)
+            }
             UnrealCommand::Pause => {
                 log::trace!("Pause");
                 let str = "break";
@@ -384,6 +519,11 @@ impl Debugger {
                 let str = "stepoutof";
                 Ok(CommandAction::Callback(self.encode_string(str)))
             }
+            UnrealCommand::RestartFrame(frame) => {
+                // We don't claim to support this, so the adapter shouldn't send it.
+                log::error!("Can't restart frame {frame}: Unreal has no command for it");
+                Ok(CommandAction::Nothing)
+            }
             UnrealCommand::Disconnect => {
                 log::trace!("Disconnect");
                 self.disconnect();
@@ -398,13 +538,32 @@ impl Debugger {
     /// until a new session is established.
     fn disconnect(&mut self) {
         // Drop our references to the communications channels.
+        log_forwarder::stop();
         self.response_channel.take();
+        common::set_session_id(None);
+
+        // Unreal clears its breakpoints when debugging stops.
+        self.breakpoints.clear();
 
         // Tell the thread to stop itself. This is typically called from this same thread,
         // so it won't be processed until we return back to the main loop.
         _ = self.shutdown_sender.send(());
     }
 
+    /// The connection to the adapter was lost without it asking us to disconnect, e.g. because
+    /// the adapter crashed. Unlike [`Self::disconnect`] the debugging session carries on:
+    /// Unreal keeps its breakpoints, so we keep tracking them for the next adapter to connect
+    /// to list and verify. A stop while no adapter is connected is held until one does.
+    pub fn connection_lost(&mut self) {
+        log_forwarder::stop();
+        self.response_channel.take();
+        common::set_session_id(None);
+        log::info!(
+            "Keeping {} breakpoints for the next connection",
+            self.breakpoints.len()
+        );
+    }
+
     /// Collect watch info and send a variable response with the variable data. This can be invoked
     /// either directly in response to a variables command (if the current stack frame is the same
     /// as the requested frame) or as a deferred response after Unreal switches frames if the
@@ -419,7 +578,7 @@ impl Debugger {
     ) -> Result<(), DebuggerError> {
         let list = self.get_watches(kind);
 
-        // A count of 0 means all elements.
+        // A count of 0 means all elements from the start to the end.
         let count = if count == 0 { usize::MAX } else { count };
 
         let idx: usize = parent.into();
@@ -566,7 +725,7 @@ impl Debugger {
 
         let watch = Watch {
             name,
-            ty: ty.unwrap_or("<unknown type>".to_string()),
+            ty: ty.unwrap_or(UNKNOWN_TYPE.to_string()),
             value: self.decode_string(value),
             children: vec![],
             is_array: is_array.unwrap_or(false),
@@ -606,19 +765,23 @@ impl Debugger {
         // this kind.
         if let WatchKind::User = kind {
             if let Some(req) = self.pending_variable_request.take() {
+                let cancelled = self.request_cancelled;
                 match req {
                     PendingVariableRequest::Variables(kind, frame, parent, start, count) => {
                         // Update the current stack frame to represent the new state.
                         self.current_frame = frame;
 
                         // Send the response to the adapter so it can proceed.
-                        self.send_variable_response(kind, parent, start, count, true)
-                            .unwrap_or_else(|_| {
-                                log::error!(
-                                    "Failed to send response for deferred variable request"
-                                );
-                            });
+                        if !cancelled {
+                            self.send_variable_response(kind, parent, start, count, true)
+                                .unwrap_or_else(|_| {
+                                    log::error!(
+                                        "Failed to send response for deferred variable request"
+                                    );
+                                });
+                        }
                     }
+                    PendingVariableRequest::UserWatch if cancelled => {}
                     PendingVariableRequest::UserWatch => {
                         // The new user watch will be the last one added to the user watchlist,
                         // so it's the last child of the root.
@@ -645,6 +808,35 @@ impl Debugger {
                         // replace the state with a UserWatch, we'll get another unlock after that.
                         self.pending_variable_request = Some(PendingVariableRequest::UserWatch);
                     }
+                    PendingVariableRequest::FrameLine(frame) => {
+                        // We've switched to the requested frame and goto_line has recorded its
+                        // line. Hold on to the result until we've switched back.
+                        let index =
+                            self.callstack.len() - 1 - <FrameIndex as Into<usize>>::into(frame);
+                        let entry = &self.callstack[index];
+                        self.pending_variable_request = Some(PendingVariableRequest::RestoreFrame(
+                            entry.qualified_name.clone(),
+                            entry.line,
+                        ));
+                    }
+                    PendingVariableRequest::RestoreFrame(..)
+                    | PendingVariableRequest::RefreshWatches
+                        if cancelled => {}
+                    PendingVariableRequest::RestoreFrame(class, line) => {
+                        self.send_response(UnrealResponse::FrameLine(class, line))
+                            .unwrap_or_else(|_| {
+                                log::error!("Failed to send response for frame line request");
+                            });
+                    }
+                    PendingVariableRequest::RefreshWatches => {
+                        self.send_response(UnrealResponse::WatchesRefreshed)
+                            .unwrap_or_else(|_| {
+                                log::error!("Failed to send response for watch refresh");
+                            });
+                    }
+                }
+                if self.pending_variable_request.is_none() {
+                    self.request_cancelled = false;
                 }
 
                 // Signal the variable request condvar so we can unblock the command processing thread.
@@ -653,6 +845,38 @@ impl Debugger {
         }
     }
 
+    /// Clear the watch list of the given kind, checking the kind first. This and the other
+    /// `try_` methods are for the entry points, which must not panic on bad input from Unreal.
+    pub fn try_clear_watch(&mut self, kind: i32) -> Result<(), InputError> {
+        self.clear_watch(watch_kind(kind)?);
+        Ok(())
+    }
+
+    /// Add a watch entry, checking the kind, parent, and name first. See [`Self::add_watch`].
+    pub fn try_add_watch(
+        &mut self,
+        kind: i32,
+        parent: i32,
+        name: *const c_char,
+        value: *const c_char,
+    ) -> Result<i32, InputError> {
+        let watch_kind = watch_kind(kind)?;
+        if name.is_null() {
+            return Err(InputError::NullPointer("watch name"));
+        }
+        if parent > 0 && parent as usize >= self.get_watches(watch_kind).len() {
+            return Err(InputError::InvalidWatchParent(parent));
+        }
+        Ok(self.add_watch(watch_kind, parent, name, value))
+    }
+
+    /// Unlock the watch list of the given kind, checking the kind first. See
+    /// [`Self::unlock_watchlist`].
+    pub fn try_unlock_watchlist(&mut self, kind: i32) -> Result<(), InputError> {
+        self.unlock_watchlist(watch_kind(kind)?);
+        Ok(())
+    }
+
     /// A breakpoint has been added.
     pub fn add_breakpoint(&mut self, name: *const c_char, line: i32) {
         let bp = Breakpoint {
@@ -660,6 +884,8 @@ impl Debugger {
             line,
         };
         log::trace!("Added breakpoint at {}:{}", bp.qualified_name, bp.line);
+        self.breakpoints
+            .insert((bp.qualified_name.to_uppercase(), bp.line));
         if let Err(e) = self.send_response(UnrealResponse::BreakpointAdded(bp)) {
             log::error!("Sending BreakpointAdded response failed: {e}");
         }
@@ -672,6 +898,8 @@ impl Debugger {
             line,
         };
         log::trace!("Removed breakpoint at {}:{}", bp.qualified_name, bp.line);
+        self.breakpoints
+            .remove(&(bp.qualified_name.to_uppercase(), bp.line));
         if let Err(e) = self.send_response(UnrealResponse::BreakpointRemoved(bp)) {
             log::error!("Sending BreakpointRemoved response failed: {e}");
         }
@@ -684,13 +912,28 @@ impl Debugger {
 
     /// Add a frame to the callstack.
     pub fn add_frame(&mut self, class_name: *const c_char) {
-        // The "name" provided by Unreal is of the form 'Function ClassName:FunctionName'.
-        //
+        // The "name" provided by Unreal is of the form 'Function ClassName:FunctionName', or
+        // 'Function ClassName:StateName.FunctionName' for a function declared in a state.
         let name = self.decode_string(class_name);
         let mut it = name.split(&[' ', ':']);
         it.next();
-        let class_name = it.next().unwrap_or("");
-        let function_name = it.next().unwrap_or("");
+        let mut class_name = it.next().unwrap_or("");
+        let function = it.next().unwrap_or("");
+        let (state_name, mut function_name) = match function.rsplit_once('.') {
+            Some((state, function)) => (Some(state.to_string()), function),
+            None => (None, function),
+        };
+
+        // Unreal occasionally sends an empty or malformed name for engine-internal frames. Keep
+        // the frame so the stack remains intact, but don't give it a class name the adapter would
+        // try to resolve to a source file.
+        if !class_name.contains('.') {
+            log::debug!("Unqualifiable callstack entry '{name}'");
+            class_name = "";
+            if function_name.is_empty() {
+                function_name = NATIVE_FRAME_NAME;
+            }
+        }
 
         let frame = match &self.stack_hack {
             Some(hack) => {
@@ -699,6 +942,7 @@ impl Debugger {
                     qualified_name: class_name.to_string(),
                     function_name: function_name.to_string(),
                     line,
+                    state_name,
                 }
             }
             None => {
@@ -709,6 +953,7 @@ impl Debugger {
                     qualified_name: class_name.to_string(),
                     function_name: function_name.to_string(),
                     line: self.current_line,
+                    state_name,
                 };
 
                 // If we previously added an entry clear the line since it wasn't the top-most
@@ -752,6 +997,7 @@ impl Debugger {
                 .take(levels)
                 .cloned()
                 .collect(),
+            total_frames: self.callstack.len().try_into().ok(),
         }
     }
 
@@ -760,9 +1006,15 @@ impl Debugger {
         self.get_watches(kind)[parent].children.len()
     }
 
-    /// Record the current object name. This is updated each time unreal stops.
+    /// Record the current object name. This is updated each time unreal stops. Unreal gives an
+    /// empty name or `None` when there is no current object, e.g. in a static function.
     pub fn current_object_name(&mut self, obj_name: *const c_char) {
-        self.current_object_name = Some(self.decode_string(obj_name));
+        let name = self.decode_string(obj_name);
+        self.current_object_name = if name.is_empty() || name.eq_ignore_ascii_case("none") {
+            None
+        } else {
+            Some(name)
+        };
     }
 
     /// A line has been added to the log. Send directly to the adapter (if connected).
@@ -860,19 +1112,26 @@ impl Debugger {
         // now know the line number for the stack frame we're switching to.
         if self.stack_hack.is_none() {
             // If we have a pending variable request then this is the line for our frame.
-            if let Some(PendingVariableRequest::Variables(_, frame, _, _, _)) =
-                &self.pending_variable_request
-            {
-                // Set the line number in the frame we are moving to.
-                let mut index: usize = self.callstack.len() - 1;
-                index -= <FrameIndex as Into<usize>>::into(*frame);
-                log::trace!("Setting line number for frame {} to {}", index, line);
-                self.callstack[index].line = line;
-            } else {
-                // No pending variable request. This goto line is due to the debugger stopping,
-                // and the line is associated with whatever the last frame will be. Record this
-                // in the debugger object and the add stack frame calls will use it.
-                self.current_line = line;
+            match &self.pending_variable_request {
+                Some(PendingVariableRequest::Variables(_, frame, _, _, _))
+                | Some(PendingVariableRequest::FrameLine(frame)) => {
+                    // Set the line number in the frame we are moving to.
+                    let mut index: usize = self.callstack.len() - 1;
+                    index -= <FrameIndex as Into<usize>>::into(*frame);
+                    log::trace!("Setting line number for frame {} to {}", index, line);
+                    self.callstack[index].line = line;
+                }
+                // We're switching back to the original frame after a frame line request, or
+                // re-entering the current frame to refresh watches. We already know this line.
+                Some(PendingVariableRequest::RestoreFrame(..))
+                | Some(PendingVariableRequest::RefreshWatches) => (),
+                _ => {
+                    // No pending variable request. This goto line is due to the debugger
+                    // stopping, and the line is associated with whatever the last frame will be.
+                    // Record this in the debugger object and the add stack frame calls will use
+                    // it.
+                    self.current_line = line;
+                }
             }
         }
     }
@@ -889,12 +1148,7 @@ impl Debugger {
     /// Decompose an Unreal variable watch name into a name, type, and whether this
     /// type is an array.
     fn decompose_name(&mut self, ptr: *const c_char) -> (String, Option<String>, Option<bool>) {
-        let str = std::str::from_utf8(make_cstr(ptr).to_bytes())
-            .unwrap_or_else(|_| {
-                log::error!("Unreal variable name and type must be ascii");
-                "<unknown>"
-            })
-            .to_string();
+        let str = lossy_string(make_cstr(ptr).to_bytes(), "watch name");
 
         // The name string is of the form "Name ( Ty,addr1,addr2 )".
         // If the type is a dynamic array the type will be "Array". If it's
@@ -1006,14 +1260,15 @@ impl Debugger {
             );
 
             // The sizes reported from Windows APIs include the trailing null byte,
-            // so skip that.
-            if utf_size > 0 {
-                self.narrow_buffer.set_len((utf_size - 1) as usize);
+            // so skip that. If the conversion failed fall back to the raw bytes.
+            if utf_size <= 0 {
+                log::warn!("Failed to convert string from the system codepage");
+                return lossy_string(str.to_bytes(), "string");
             }
+            self.narrow_buffer.set_len((utf_size - 1) as usize);
 
             // Construct a new string from the bytes of this buffer.
-            let str = std::str::from_utf8_unchecked(&self.narrow_buffer).to_string();
-            str
+            lossy_string(&self.narrow_buffer, "string")
         }
     }
 
@@ -1031,7 +1286,7 @@ impl Debugger {
 
     /// A new connection has been established from the adapter. Record the tcp stream used to send
     /// events.
-    pub fn new_connection(&mut self, tx: mpsc::UnboundedSender<UnrealInterfaceMessage>) {
+    pub fn new_connection(&mut self, tx: MessageSender) {
         self.response_channel = Some(tx);
 
         // The debugger stopped before we connected (e.g. due to -autoDebug). Send a stopped
@@ -1044,6 +1299,24 @@ impl Debugger {
     }
 }
 
+/// Convert bytes from Unreal to a string, replacing any invalid UTF-8 with the replacement
+/// character. Unreal's strings should always be valid, so a warning is logged if any
+/// replacement was needed.
+fn lossy_string(bytes: &[u8], what: &str) -> String {
+    match String::from_utf8_lossy(bytes) {
+        Cow::Borrowed(str) => str.to_string(),
+        Cow::Owned(str) => {
+            log::warn!("Replaced invalid UTF-8 in {what} '{str}'");
+            str
+        }
+    }
+}
+
+/// Convert a watch kind from Unreal, which may not be one we know.
+fn watch_kind(kind: i32) -> Result<WatchKind, InputError> {
+    WatchKind::from_int(kind).ok_or(InputError::InvalidWatchKind(kind))
+}
+
 /// Convert an unreal C string pointer to a CStr.
 fn make_cstr<'a>(raw: *const c_char) -> &'a CStr {
     if !raw.is_null() {
@@ -1057,6 +1330,8 @@ fn make_cstr<'a>(raw: *const c_char) -> &'a CStr {
 mod tests {
     use tokio::sync::mpsc::unbounded_channel;
 
+    use crate::channel::{message_channel, MAX_QUEUED_LOGS};
+
     use super::*;
 
     #[test]
@@ -1114,6 +1389,20 @@ mod tests {
         assert_eq!(dbg.user_watches.len(), 2);
     }
 
+    #[test]
+    fn add_watch_invalid_utf8() {
+        let (ctx, _) = unbounded_channel();
+        let mut dbg = Debugger::new(ctx, None);
+        let name = c"Bad\xff ( Int,0,0 )".as_ptr();
+        let val = c"\xff".as_ptr();
+        assert_eq!(dbg.add_watch(WatchKind::Local, -1, name, val), 1);
+        assert_eq!(dbg.local_watches[1].name, "Bad\u{FFFD}");
+        assert_eq!(dbg.local_watches[1].ty, "Int");
+        // Values are decoded from the system codepage first, which may map the byte to a
+        // character of its own.
+        assert!(!dbg.local_watches[1].value.is_empty());
+    }
+
     #[test]
     #[should_panic]
     fn add_watch_invalid_parent() {
@@ -1128,7 +1417,7 @@ mod tests {
     fn log_sends_line() {
         let (ctx, _) = unbounded_channel();
         let mut dbg = Debugger::new(ctx, None);
-        let (tx, mut rx) = mpsc::unbounded_channel();
+        let (tx, mut rx) = message_channel(MAX_QUEUED_LOGS);
         dbg.response_channel = Some(tx);
         let str = "This is a log line\0";
         dbg.add_line_to_log(str.as_ptr() as *const i8);
@@ -1150,6 +1439,311 @@ mod tests {
         dbg.add_frame("Function MyPackage.Class:MyFunction\0".as_ptr() as *const i8);
         assert_eq!(dbg.callstack[0].qualified_name, "MyPackage.Class");
         assert_eq!(dbg.callstack[0].function_name, "MyFunction");
+        assert_eq!(dbg.callstack[0].state_name, None);
+    }
+
+    #[test]
+    fn add_frame_in_state() {
+        let (ctx, _) = unbounded_channel();
+        let mut dbg = Debugger::new(ctx, None);
+        dbg.add_frame(c"Function MyPackage.Class:Idle.MyFunction".as_ptr());
+        assert_eq!(dbg.callstack[0].qualified_name, "MyPackage.Class");
+        assert_eq!(dbg.callstack[0].function_name, "MyFunction");
+        assert_eq!(dbg.callstack[0].state_name.as_deref(), Some("Idle"));
+    }
+
+    #[test]
+    fn add_empty_frame() {
+        let (ctx, _) = unbounded_channel();
+        let mut dbg = Debugger::new(ctx, None);
+        dbg.add_frame("Function MyPackage.Class:Outer\0".as_ptr() as *const i8);
+        dbg.add_frame("\0".as_ptr() as *const i8);
+        dbg.add_frame("Function MyPackage.Class:Inner\0".as_ptr() as *const i8);
+        assert_eq!(dbg.callstack.len(), 3);
+        assert_eq!(dbg.callstack[1].qualified_name, "");
+        assert_eq!(dbg.callstack[1].function_name, NATIVE_FRAME_NAME);
+        assert_eq!(dbg.callstack[2].qualified_name, "MyPackage.Class");
+        assert_eq!(dbg.callstack[2].function_name, "Inner");
+    }
+
+    #[test]
+    fn list_breakpoints() {
+        let (ctx, _) = unbounded_channel();
+        let mut dbg = Debugger::new(ctx, None);
+        dbg.add_breakpoint(c"MyPackage.Class".as_ptr(), 20);
+        dbg.add_breakpoint(c"MyPackage.Class".as_ptr(), 10);
+        dbg.add_breakpoint(c"MyPackage.Other".as_ptr(), 5);
+        dbg.remove_breakpoint(c"MyPackage.Other".as_ptr(), 5);
+
+        let (tx, mut rx) = message_channel(MAX_QUEUED_LOGS);
+        dbg.response_channel = Some(tx);
+        let action = dbg.handle_command(UnrealCommand::ListBreakpoints).unwrap();
+        assert!(matches!(action, CommandAction::Nothing));
+        match rx.blocking_recv().unwrap() {
+            UnrealInterfaceMessage::Response(UnrealResponse::Breakpoints(breakpoints)) => {
+                let listed: Vec<_> = breakpoints
+                    .iter()
+                    .map(|bp| (bp.qualified_name.as_str(), bp.line))
+                    .collect();
+                assert_eq!(
+                    listed,
+                    vec![("MYPACKAGE.CLASS", 10), ("MYPACKAGE.CLASS", 20)]
+                );
+            }
+            _ => panic!("Expected a breakpoints response"),
+        }
+    }
+
+    #[test]
+    fn breakpoints_survive_reconnect() {
+        let (ctx, _) = unbounded_channel();
+        let mut dbg = Debugger::new(ctx, None);
+        // Skip the spurious startup call.
+        dbg.show_dll_form();
+
+        let (tx, rx) = message_channel(MAX_QUEUED_LOGS);
+        dbg.new_connection(tx);
+        dbg.add_breakpoint(c"MyPackage.Class".as_ptr(), 10);
+
+        // The adapter goes away without disconnecting, and the game stops while it's gone.
+        drop(rx);
+        dbg.connection_lost();
+        dbg.show_dll_form();
+
+        // The next adapter to connect hears about the stop and finds the breakpoint still set.
+        let (tx, mut rx) = message_channel(MAX_QUEUED_LOGS);
+        dbg.new_connection(tx);
+        assert!(matches!(
+            rx.blocking_recv().unwrap(),
+            UnrealInterfaceMessage::Event(UnrealEvent::Stopped)
+        ));
+        dbg.handle_command(UnrealCommand::ListBreakpoints).unwrap();
+        match rx.blocking_recv().unwrap() {
+            UnrealInterfaceMessage::Response(UnrealResponse::Breakpoints(breakpoints)) => {
+                let listed: Vec<_> = breakpoints
+                    .iter()
+                    .map(|bp| (bp.qualified_name.as_str(), bp.line))
+                    .collect();
+                assert_eq!(listed, vec![("MYPACKAGE.CLASS", 10)]);
+            }
+            _ => panic!("Expected a breakpoints response"),
+        }
+    }
+
+    #[test]
+    fn threads() {
+        let (ctx, _) = unbounded_channel();
+        let mut dbg = Debugger::new(ctx, None);
+        let (tx, mut rx) = message_channel(MAX_QUEUED_LOGS);
+        dbg.response_channel = Some(tx);
+        dbg.handle_command(UnrealCommand::Threads).unwrap();
+        dbg.current_object_name(c"MyGame.MyPawn_0".as_ptr());
+        dbg.handle_command(UnrealCommand::Threads).unwrap();
+        dbg.current_object_name(c"None".as_ptr());
+        dbg.handle_command(UnrealCommand::Threads).unwrap();
+
+        assert!(matches!(
+            rx.blocking_recv().unwrap(),
+            UnrealInterfaceMessage::Response(UnrealResponse::Threads(threads)) if threads.is_empty()
+        ));
+        assert!(matches!(
+            rx.blocking_recv().unwrap(),
+            UnrealInterfaceMessage::Response(UnrealResponse::Threads(threads))
+                if threads == vec!["MyGame.MyPawn_0"]
+        ));
+        // Without a current object there's no thread for it.
+        assert!(matches!(
+            rx.blocking_recv().unwrap(),
+            UnrealInterfaceMessage::Response(UnrealResponse::Threads(threads)) if threads.is_empty()
+        ));
+    }
+
+    #[test]
+    fn frame_line_known() {
+        let (ctx, _) = unbounded_channel();
+        let mut dbg = Debugger::new(ctx, None);
+        let (tx, mut rx) = message_channel(MAX_QUEUED_LOGS);
+        dbg.response_channel = Some(tx);
+        dbg.goto_line(10);
+        dbg.add_frame("Function MyPackage.Class:MyFunction\0".as_ptr() as *const i8);
+        let action = dbg
+            .handle_command(UnrealCommand::FrameLine(FrameIndex::TOP_FRAME))
+            .unwrap();
+        assert!(matches!(action, CommandAction::Nothing));
+        match rx.blocking_recv().unwrap() {
+            UnrealInterfaceMessage::Response(UnrealResponse::FrameLine(class, line)) => {
+                assert_eq!(class, "MyPackage.Class");
+                assert_eq!(line, 10);
+            }
+            _ => panic!("Expected a frame line response"),
+        };
+    }
+
+    #[test]
+    fn frame_line_switches_frames() {
+        let (ctx, _) = unbounded_channel();
+        let mut dbg = Debugger::new(ctx, None);
+        let (tx, mut rx) = message_channel(MAX_QUEUED_LOGS);
+        dbg.response_channel = Some(tx);
+        dbg.goto_line(10);
+        dbg.add_frame("Function MyPackage.Outer:Caller\0".as_ptr() as *const i8);
+        dbg.add_frame("Function MyPackage.Inner:Callee\0".as_ptr() as *const i8);
+        let action = dbg
+            .handle_command(UnrealCommand::FrameLine(FrameIndex::create(1).unwrap()))
+            .unwrap();
+        assert!(matches!(action, CommandAction::MultiStepCallback(ref v) if v.len() == 2));
+
+        // Unreal switches to the requested frame...
+        dbg.goto_line(33);
+        dbg.unlock_watchlist(WatchKind::User);
+        assert!(dbg.pending_variable_request());
+
+        // ...and back again.
+        dbg.goto_line(10);
+        dbg.unlock_watchlist(WatchKind::User);
+        assert!(!dbg.pending_variable_request());
+
+        match rx.blocking_recv().unwrap() {
+            UnrealInterfaceMessage::Response(UnrealResponse::FrameLine(class, line)) => {
+                assert_eq!(class, "MyPackage.Outer");
+                assert_eq!(line, 33);
+            }
+            _ => panic!("Expected a frame line response"),
+        };
+        assert_eq!(dbg.callstack[0].line, 33);
+        assert_eq!(dbg.callstack[1].line, 10);
+        assert_eq!(dbg.current_frame, FrameIndex::TOP_FRAME);
+    }
+
+    #[test]
+    fn cancel_pending_variable_request() {
+        let (ctx, _) = unbounded_channel();
+        let mut dbg = Debugger::new(ctx, None);
+        let (tx, mut rx) = message_channel(MAX_QUEUED_LOGS);
+        dbg.response_channel = Some(tx);
+        dbg.goto_line(10);
+        dbg.add_frame(c"Function MyPackage.Outer:Caller".as_ptr());
+        dbg.add_frame(c"Function MyPackage.Inner:Callee".as_ptr());
+
+        // Nothing is pending, so there's nothing to cancel and no response.
+        let action = dbg
+            .handle_command(UnrealCommand::CancelVariableRequest)
+            .unwrap();
+        assert!(matches!(action, CommandAction::Nothing));
+        assert!(rx.try_recv().is_none());
+
+        let frame = FrameIndex::create(1).unwrap();
+        let action = dbg
+            .handle_command(UnrealCommand::Variables(
+                WatchKind::Local,
+                frame,
+                VariableIndex::SCOPE,
+                0,
+                0,
+            ))
+            .unwrap();
+        assert!(matches!(action, CommandAction::Callback(_)));
+
+        // The cancel is answered at once, and only once.
+        dbg.handle_command(UnrealCommand::CancelVariableRequest)
+            .unwrap();
+        dbg.handle_command(UnrealCommand::CancelVariableRequest)
+            .unwrap();
+        assert!(matches!(
+            rx.try_recv().unwrap(),
+            UnrealInterfaceMessage::Response(UnrealResponse::Cancelled)
+        ));
+        assert!(rx.try_recv().is_none());
+
+        // Unreal still switches frames, but the variables aren't sent.
+        assert!(dbg.pending_variable_request());
+        dbg.unlock_watchlist(WatchKind::User);
+        assert!(!dbg.pending_variable_request());
+        assert_eq!(dbg.current_frame, frame);
+        assert!(rx.try_recv().is_none());
+
+        // The next request is answered as usual.
+        dbg.handle_command(UnrealCommand::RefreshWatches).unwrap();
+        dbg.unlock_watchlist(WatchKind::User);
+        assert!(matches!(
+            rx.try_recv().unwrap(),
+            UnrealInterfaceMessage::Response(UnrealResponse::WatchesRefreshed)
+        ));
+    }
+
+    #[test]
+    fn set_variable_refreshes_watches() {
+        let (ctx, _) = unbounded_channel();
+        let mut dbg = Debugger::new(ctx, None);
+        let (tx, mut rx) = message_channel(MAX_QUEUED_LOGS);
+        dbg.response_channel = Some(tx);
+
+        let action = dbg
+            .handle_command(UnrealCommand::SetVariable(
+                "Count".to_string(),
+                "5".to_string(),
+            ))
+            .unwrap();
+        match action {
+            CommandAction::MultiStepCallback(cmds) => {
+                assert_eq!(cmds[0], b"set Count 5\0");
+                assert_eq!(cmds[1], b"changestack 0\0");
+            }
+            _ => panic!("Expected set and changestack callbacks"),
+        }
+
+        // The watch lists are rebuilt after the assignment.
+        dbg.clear_watch(WatchKind::User);
+        dbg.unlock_watchlist(WatchKind::User);
+        assert!(!dbg.pending_variable_request());
+        assert!(matches!(
+            rx.blocking_recv().unwrap(),
+            UnrealInterfaceMessage::Response(UnrealResponse::WatchesRefreshed)
+        ));
+    }
+
+    #[test]
+    fn refresh_watches_rebuilds_lists() {
+        let (ctx, _) = unbounded_channel();
+        let mut dbg = Debugger::new(ctx, None);
+        let (tx, mut rx) = message_channel(MAX_QUEUED_LOGS);
+        dbg.response_channel = Some(tx);
+        dbg.clear_watch(WatchKind::Local);
+        dbg.add_watch(
+            WatchKind::Local,
+            -1,
+            "Stale ( Int,0 )\0".as_ptr() as *const i8,
+            "1\0".as_ptr() as *const i8,
+        );
+
+        let action = dbg.handle_command(UnrealCommand::RefreshWatches).unwrap();
+        match action {
+            CommandAction::Callback(cmd) => assert_eq!(cmd, b"changestack 0\0"),
+            _ => panic!("Expected a changestack callback"),
+        }
+        assert!(dbg.pending_variable_request());
+
+        // Unreal rebuilds the lists.
+        dbg.clear_watch(WatchKind::Local);
+        dbg.add_watch(
+            WatchKind::Local,
+            -1,
+            "Fresh ( Int,0 )\0".as_ptr() as *const i8,
+            "2\0".as_ptr() as *const i8,
+        );
+        dbg.unlock_watchlist(WatchKind::Local);
+        assert!(dbg.pending_variable_request());
+        dbg.clear_watch(WatchKind::User);
+        dbg.unlock_watchlist(WatchKind::User);
+        assert!(!dbg.pending_variable_request());
+
+        assert!(matches!(
+            rx.blocking_recv().unwrap(),
+            UnrealInterfaceMessage::Response(UnrealResponse::WatchesRefreshed)
+        ));
+        assert_eq!(dbg.local_watches.len(), 2);
+        assert_eq!(dbg.local_watches[1].name, "Fresh");
+        assert_eq!(dbg.local_watches[1].value, "2");
     }
 
     #[test]
@@ -1171,11 +1765,13 @@ mod tests {
             qualified_name: "Class1".to_string(),
             function_name: "foo".to_string(),
             line: 20,
+            state_name: None,
         });
         dbg.callstack.push(Frame {
             qualified_name: "Class2".to_string(),
             function_name: "bar".to_string(),
             line: 84,
+            state_name: None,
         });
         let response = dbg.handle_stacktrace_request(&StackTraceRequest {
             start_frame: 0,
@@ -1187,12 +1783,14 @@ mod tests {
                 Frame {
                     qualified_name: "Class2".to_string(),
                     function_name: "bar".to_string(),
-                    line: 84
+                    line: 84,
+                    state_name: None,
                 },
                 Frame {
                     qualified_name: "Class1".to_string(),
                     function_name: "foo".to_string(),
-                    line: 20
+                    line: 20,
+                    state_name: None,
                 },
             ]
         );
@@ -1206,11 +1804,13 @@ mod tests {
             qualified_name: "Class1".to_string(),
             function_name: "foo".to_string(),
             line: 20,
+            state_name: None,
         });
         dbg.callstack.push(Frame {
             qualified_name: "Class2".to_string(),
             function_name: "bar".to_string(),
             line: 84,
+            state_name: None,
         });
         let response = dbg.handle_stacktrace_request(&StackTraceRequest {
             start_frame: 0,
@@ -1221,7 +1821,8 @@ mod tests {
             vec![Frame {
                 qualified_name: "Class2".to_string(),
                 function_name: "bar".to_string(),
-                line: 84
+                line: 84,
+                state_name: None,
             },]
         );
     }
@@ -1234,11 +1835,13 @@ mod tests {
             qualified_name: "Class1".to_string(),
             function_name: "foo".to_string(),
             line: 20,
+            state_name: None,
         });
         dbg.callstack.push(Frame {
             qualified_name: "Class2".to_string(),
             function_name: "bar".to_string(),
             line: 84,
+            state_name: None,
         });
         let response = dbg.handle_stacktrace_request(&StackTraceRequest {
             start_frame: 1,
@@ -1249,7 +1852,8 @@ mod tests {
             vec![Frame {
                 qualified_name: "Class1".to_string(),
                 function_name: "foo".to_string(),
-                line: 20
+                line: 20,
+                state_name: None,
             },]
         );
     }
@@ -1262,17 +1866,20 @@ mod tests {
             qualified_name: "Class1".to_string(),
             function_name: "foo".to_string(),
             line: 20,
+            state_name: None,
         });
         dbg.callstack.push(Frame {
             qualified_name: "Class2".to_string(),
             function_name: "bar".to_string(),
             line: 84,
+            state_name: None,
         });
         let response = dbg.handle_stacktrace_request(&StackTraceRequest {
             start_frame: 2,
             levels: 1,
         });
         assert!(response.frames.is_empty());
+        assert_eq!(response.total_frames, Some(2));
     }
 
     #[test]
@@ -1418,4 +2025,86 @@ mod tests {
         assert_eq!(ty.unwrap(), "array element");
         assert!(!is_array.unwrap());
     }
+
+    // Add a dynamic array of ints with the given length to the locals. Returns its index.
+    fn add_array(dbg: &mut Debugger, len: usize) -> VariableIndex {
+        let array = dbg.add_watch(
+            WatchKind::Local,
+            -1,
+            c"Counts ( Array,00000000,00000000 )".as_ptr(),
+            c"".as_ptr(),
+        );
+        for i in 0..len {
+            let name = std::ffi::CString::new(format!("Counts[{i}]")).unwrap();
+            dbg.add_watch(WatchKind::Local, array, name.as_ptr(), c"0".as_ptr());
+        }
+        VariableIndex::create(array.try_into().unwrap()).unwrap()
+    }
+
+    fn names(vars: &[Variable]) -> Vec<&str> {
+        vars.iter().map(|var| var.name.as_str()).collect()
+    }
+
+    #[test]
+    fn variables_window() {
+        let (ctx, _) = unbounded_channel();
+        let mut dbg = Debugger::new(ctx, None);
+        let (tx, mut rx) = message_channel(MAX_QUEUED_LOGS);
+        dbg.response_channel = Some(tx);
+        let array = add_array(&mut dbg, 1000);
+        dbg.handle_command(UnrealCommand::Variables(
+            WatchKind::Local,
+            FrameIndex::TOP_FRAME,
+            array,
+            500,
+            3,
+        ))
+        .unwrap();
+        // A count of 0 is everything to the end.
+        dbg.handle_command(UnrealCommand::Variables(
+            WatchKind::Local,
+            FrameIndex::TOP_FRAME,
+            array,
+            998,
+            0,
+        ))
+        .unwrap();
+
+        match rx.blocking_recv().unwrap() {
+            UnrealInterfaceMessage::Response(UnrealResponse::Variables(vars)) => {
+                assert_eq!(
+                    names(&vars),
+                    vec!["Counts[500]", "Counts[501]", "Counts[502]"]
+                );
+            }
+            other => panic!("Expected a variables response but got {other:?}"),
+        }
+        match rx.blocking_recv().unwrap() {
+            UnrealInterfaceMessage::Response(UnrealResponse::Variables(vars)) => {
+                assert_eq!(names(&vars), vec!["Counts[998]", "Counts[999]"]);
+            }
+            other => panic!("Expected a variables response but got {other:?}"),
+        }
+
+        // A window in another frame is sent once Unreal has switched to that frame.
+        dbg.add_frame(c"Function MyPackage.Class:Outer".as_ptr());
+        dbg.add_frame(c"Function MyPackage.Class:Inner".as_ptr());
+        let action = dbg
+            .handle_command(UnrealCommand::Variables(
+                WatchKind::Local,
+                FrameIndex::create(1).unwrap(),
+                array,
+                10,
+                2,
+            ))
+            .unwrap();
+        assert!(matches!(action, CommandAction::Callback(_)));
+        dbg.unlock_watchlist(WatchKind::User);
+        match rx.blocking_recv().unwrap() {
+            UnrealInterfaceMessage::Response(UnrealResponse::DeferredVariables(vars)) => {
+                assert_eq!(names(&vars), vec!["Counts[10]", "Counts[11]"]);
+            }
+            other => panic!("Expected a deferred variables response but got {other:?}"),
+        }
+    }
 }
@@ -0,0 +1,92 @@
+//! Framing of messages sent to the adapter.
+//!
+//! Each message is sent as a frame with a 4-byte big-endian length prefix. A message larger
+//! than the maximum frame length, e.g. a response containing a huge watch value, is split
+//! across several frames. Every frame but the last has [`MORE_FRAMES_FLAG`] set in its
+//! prefix, and the adapter joins them back together before decoding the message.
+//!
+//! Messages that fit in a single frame are framed exactly as by a length-delimited codec, so
+//! older adapters can still read them.
+
+use common::MORE_FRAMES_FLAG;
+use tokio_util::{
+    bytes::{BufMut, Bytes, BytesMut},
+    codec::Encoder,
+};
+
+/// An encoder splitting messages into frames of at most a given length.
+pub struct FrameEncoder {
+    max_frame_len: usize,
+}
+
+impl FrameEncoder {
+    /// Create an encoder sending frames of at most `max_frame_len` bytes, not counting the
+    /// length prefix.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length is zero or doesn't leave room for [`MORE_FRAMES_FLAG`].
+    pub fn new(max_frame_len: usize) -> FrameEncoder {
+        assert!(max_frame_len > 0 && max_frame_len < MORE_FRAMES_FLAG as usize);
+        FrameEncoder { max_frame_len }
+    }
+}
+
+impl Encoder<Bytes> for FrameEncoder {
+    type Error = std::io::Error;
+
+    fn encode(&mut self, message: Bytes, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        if message.len() > self.max_frame_len {
+            log::info!(
+                "Splitting a {} byte message across several frames.",
+                message.len()
+            );
+        }
+
+        dst.reserve(message.len() + 4 * (message.len() / self.max_frame_len + 1));
+        let mut rest = &message[..];
+        loop {
+            let len = rest.len().min(self.max_frame_len);
+            let (frame, remaining) = rest.split_at(len);
+            let more = if remaining.is_empty() {
+                0
+            } else {
+                MORE_FRAMES_FLAG
+            };
+            dst.put_u32(len as u32 | more);
+            dst.extend_from_slice(frame);
+            if remaining.is_empty() {
+                return Ok(());
+            }
+            rest = remaining;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(message: &[u8], max_frame_len: usize) -> Vec<u8> {
+        let mut dst = BytesMut::new();
+        FrameEncoder::new(max_frame_len)
+            .encode(Bytes::copy_from_slice(message), &mut dst)
+            .unwrap();
+        dst.to_vec()
+    }
+
+    #[test]
+    fn small_message_is_one_frame() {
+        assert_eq!(encode(b"abc", 4), b"\0\0\0\x03abc");
+        assert_eq!(encode(b"abcd", 4), b"\0\0\0\x04abcd");
+        assert_eq!(encode(b"", 4), b"\0\0\0\0");
+    }
+
+    #[test]
+    fn large_message_is_split() {
+        assert_eq!(
+            encode(b"abcdefghij", 4),
+            b"\x80\0\0\x04abcd\x80\0\0\x04efgh\0\0\0\x02ij"
+        );
+    }
+}
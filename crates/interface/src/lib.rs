@@ -4,7 +4,9 @@
 //!
 //! Unreal controls the lifetime this library, and does not provide much of
 //! any error handling or recovery mechanisms. If any of the expected invariants
-//! of this interface are violated we will simply panic.
+//! of this interface are violated we will simply panic, except where an entry
+//! point can check its arguments: bad watch kinds and the like are logged and
+//! ignored instead, since a panic would unwind across the FFI boundary.
 //!
 //! The functions in this interface are thin wrappers that simply pass their
 //! arguments on to corresponding methods on the debugger state instance.
@@ -17,8 +19,11 @@ use debugger::Debugger;
 use flexi_logger::LoggerHandle;
 use pkg_version::{pkg_version_major, pkg_version_minor, pkg_version_patch};
 pub mod api;
+pub mod channel;
 pub mod debugger;
+pub mod framing;
 pub mod lifetime;
+pub mod log_forwarder;
 pub mod stackhack;
 
 /// The debugger state. Calls from Unreal are dispatched into this instance.
@@ -38,20 +38,26 @@
 
 use std::{net::SocketAddr, thread};
 
-use common::{create_logger, UnrealCommand, UnrealInterfaceMessage, DEFAULT_PORT, DEFAULT_PORT_TRY_NUM, PORT_TRY_NUM_VAR, PORT_VAR};
+use common::{
+    create_logger, UnrealCommand, UnrealInterfaceMessage, DEFAULT_PORT, DEFAULT_PORT_TRY_NUM,
+    MAX_FRAME_LEN, PORT_TRY_NUM_VAR, PORT_VAR,
+};
 use futures::prelude::*;
 use tokio::{
     net::{TcpListener, TcpStream},
     runtime::Builder,
     select,
-    sync::mpsc::{self, unbounded_channel, UnboundedReceiver},
+    sync::mpsc::{unbounded_channel, UnboundedReceiver},
 };
 use tokio_serde::formats::SymmetricalJson;
 use tokio_util::codec::{FramedRead, FramedWrite, LengthDelimitedCodec};
 
 use crate::{
     api::UnrealCallback,
+    channel::{message_channel, MAX_QUEUED_LOGS},
     debugger::{CommandAction, Debugger, DebuggerError},
+    framing::FrameEncoder,
+    log_forwarder::ForwardingWriter,
     DEBUGGER, LOGGER, VARIABLE_REQUST_CONDVAR,
 };
 
@@ -109,7 +115,7 @@ pub fn initialize(cb: UnrealCallback) {
 fn init_logger() {
     let mut logger = LOGGER.lock().unwrap();
     assert!(logger.is_none(), "Already have a logger. Multiple inits?");
-    let new_logger = create_logger("interface");
+    let new_logger = create_logger("interface", Some(Box::new(ForwardingWriter)));
     logger.replace(new_logger);
 }
 
@@ -233,7 +239,7 @@ async fn handle_connection(
     // Create a new message passing channel and send the sender to the debugger.
     // It's convenient to have a per-connection message channel as it also serves
     // as an indicator within the debugger to tell if the interface is connected.
-    let (etx, mut erx) = mpsc::unbounded_channel();
+    let (etx, mut erx) = message_channel(MAX_QUEUED_LOGS);
 
     {
         let mut hnd = DEBUGGER.lock().unwrap();
@@ -249,7 +255,7 @@ async fn handle_connection(
         SymmetricalJson::<UnrealCommand>::default(),
     );
 
-    let delimiter = FramedWrite::new(writer, LengthDelimitedCodec::new());
+    let delimiter = FramedWrite::new(writer, FrameEncoder::new(MAX_FRAME_LEN));
     let mut serializer = tokio_serde::SymmetricallyFramed::new(
         delimiter,
         SymmetricalJson::<UnrealInterfaceMessage>::default(),
@@ -292,14 +298,21 @@ async fn handle_connection(
     }
 
     log::info!("Client disconnected.");
+    {
+        let mut hnd = DEBUGGER.lock().unwrap();
+        let dbg = hnd.as_mut().unwrap();
+        dbg.connection_lost();
+    }
     Ok(ConnectionResult::Disconnected)
 }
 
 fn dispatch_command(command: UnrealCommand) -> CommandAction {
+    // A cancel is for the pending request itself, so it must not wait for that to complete.
+    let cancelling = matches!(command, UnrealCommand::CancelVariableRequest);
     let mut hnd = DEBUGGER.lock().unwrap();
     loop {
         let dbg = hnd.as_mut().unwrap();
-        if dbg.pending_variable_request() {
+        if dbg.pending_variable_request() && !cancelling {
             // There is still an outstanding variable request. We can't do anything until
             // this is finished.
             log::info!("Waiting for variable request to complete...");
@@ -0,0 +1,199 @@
+//! Forwarding of the interface's own log lines to the adapter.
+//!
+//! The interface and adapter log to separate files, which makes it hard to line up what each
+//! side was doing when something goes wrong. If the adapter asks for it the interface also
+//! sends its log lines over the connection so they can be shown alongside the game's output.
+//!
+//! The writer is installed when the logger is created but only sends anything between
+//! [`start`] and [`stop`]. Lines are still subject to the interface's log spec: a line that
+//! isn't logged at all is never forwarded.
+//!
+//! The logger calls the writer synchronously on whichever thread logged the line, and sending
+//! a line can itself log (e.g. when the channel starts dropping lines). Forwarding is
+//! therefore skipped for lines logged while this thread is already forwarding one, and the
+//! lock on the target is never held while sending.
+
+use std::{cell::Cell, sync::Mutex};
+
+use flexi_logger::{writers::LogWriter, DeferredNow};
+use log::{LevelFilter, Record};
+
+use crate::channel::LogSender;
+
+#[derive(Clone)]
+struct Forwarding {
+    sender: LogSender,
+    level: LevelFilter,
+}
+
+/// The current forwarding target, if any.
+static FORWARDING: Mutex<Option<Forwarding>> = Mutex::new(None);
+
+thread_local! {
+    /// True while this thread is forwarding a line.
+    static WRITING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Start forwarding log lines at or above the given level to the adapter.
+pub fn start(sender: LogSender, level: LevelFilter) {
+    FORWARDING
+        .lock()
+        .unwrap()
+        .replace(Forwarding { sender, level });
+}
+
+/// Stop forwarding log lines.
+pub fn stop() {
+    FORWARDING.lock().unwrap().take();
+}
+
+/// A log writer that sends log lines to the adapter while forwarding is enabled.
+pub struct ForwardingWriter;
+
+impl LogWriter for ForwardingWriter {
+    fn write(&self, _now: &mut DeferredNow, record: &Record) -> std::io::Result<()> {
+        if WRITING.with(|writing| writing.replace(true)) {
+            return Ok(());
+        }
+        let target = FORWARDING.lock().unwrap().clone();
+        if let Some(target) = target {
+            if record.level() <= target.level {
+                let line = format!(
+                    "{} {}: {}\n",
+                    record.level(),
+                    record.target(),
+                    record.args()
+                );
+                // The connection has gone away, so there's nowhere to send lines until the
+                // next one asks for them. Forwarding may have been restarted for a new
+                // connection in the meantime, so only stop it if it's still this one.
+                if target.sender.send(line).is_err() {
+                    let mut forwarding = FORWARDING.lock().unwrap();
+                    if forwarding
+                        .as_ref()
+                        .is_some_and(|f| f.sender.same_channel(&target.sender))
+                    {
+                        forwarding.take();
+                    }
+                }
+            }
+        }
+        WRITING.with(|writing| writing.set(false));
+        Ok(())
+    }
+
+    fn flush(&self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::mpsc, time::Duration};
+
+    use common::{UnrealEvent, UnrealInterfaceMessage};
+    use log::{Level, Log, Metadata};
+
+    use super::*;
+    use crate::channel::message_channel;
+
+    // Forwarding is global, so tests that use it can't run at the same time.
+    static TEST_LOCK: Mutex<()> = Mutex::new(());
+
+    thread_local! {
+        static CAPTURE: Cell<bool> = const { Cell::new(false) };
+    }
+
+    /// A logger that, like the real one, writes lines to the forwarding writer on the thread
+    /// that logged them. Only lines from threads that ask for it are written so that other
+    /// tests' logging is left alone.
+    struct TestLogger;
+
+    impl Log for TestLogger {
+        fn enabled(&self, _metadata: &Metadata) -> bool {
+            CAPTURE.with(Cell::get)
+        }
+
+        fn log(&self, record: &Record) {
+            if self.enabled(record.metadata()) {
+                ForwardingWriter
+                    .write(&mut DeferredNow::new(), record)
+                    .unwrap();
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    fn write(level: Level, text: &str) {
+        ForwardingWriter
+            .write(
+                &mut DeferredNow::new(),
+                &Record::builder()
+                    .level(level)
+                    .target("interface")
+                    .args(format_args!("{text}"))
+                    .build(),
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn forwards_at_level() {
+        let _guard = TEST_LOCK.lock().unwrap();
+        let (tx, mut rx) = message_channel(10);
+        start(tx.log_sender(), LevelFilter::Info);
+        write(Level::Debug, "hidden");
+        write(Level::Warn, "shown");
+        drop(tx);
+        write(Level::Error, "after close");
+        assert!(FORWARDING.lock().unwrap().is_none());
+
+        assert!(matches!(
+            rx.blocking_recv(),
+            Some(UnrealInterfaceMessage::InterfaceLog(line)) if line == "WARN interface: shown\n"
+        ));
+        assert!(rx.blocking_recv().is_none());
+    }
+
+    #[test]
+    fn overflow_while_forwarding() {
+        let _guard = TEST_LOCK.lock().unwrap();
+        let _ = log::set_logger(&TestLogger);
+        log::set_max_level(LevelFilter::Trace);
+
+        let (tx, mut rx) = message_channel(2);
+        start(tx.log_sender(), LevelFilter::Info);
+
+        // Filling the queue makes the channel log a warning on the same thread while the
+        // first line is still being forwarded.
+        let (done_tx, done_rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            CAPTURE.with(|capture| capture.set(true));
+            for i in 0..5 {
+                log::info!("line {i}");
+            }
+            done_tx.send(()).unwrap();
+        });
+        done_rx
+            .recv_timeout(Duration::from_secs(5))
+            .expect("forwarding deadlocked");
+        stop();
+
+        let received: Vec<_> = std::iter::from_fn(|| rx.try_recv())
+            .map(|msg| match msg {
+                UnrealInterfaceMessage::Event(UnrealEvent::Log(text)) => text,
+                UnrealInterfaceMessage::InterfaceLog(line) => line,
+                other => format!("{other:?}"),
+            })
+            .collect();
+        assert_eq!(
+            received,
+            vec![
+                "[3 log lines dropped]\r\n".to_string(),
+                "INFO interface::log_forwarder::tests: line 3\n".to_string(),
+                "INFO interface::log_forwarder::tests: line 4\n".to_string(),
+            ]
+        );
+    }
+}
@@ -41,8 +41,9 @@ async fn hit_breakpoint() {
                 source: Source {
                     name: None,
                     path: Some(PACKAGE_CLASSNAME.to_string()),
+                    source_reference: None,
                 },
-                breakpoints: Some(vec![SourceBreakpoint { line: 10 }]),
+                breakpoints: Some(vec![SourceBreakpoint::new(10)]),
             }),
         })
         .unwrap();
@@ -89,8 +90,9 @@ async fn remove_breakpoint() {
                 source: Source {
                     name: None,
                     path: Some(PACKAGE_CLASSNAME.to_string()),
+                    source_reference: None,
                 },
-                breakpoints: Some(vec![SourceBreakpoint { line: 10 }]),
+                breakpoints: Some(vec![SourceBreakpoint::new(10)]),
             }),
         })
         .unwrap();
@@ -108,6 +110,7 @@ async fn remove_breakpoint() {
                 source: Source {
                     name: None,
                     path: Some(PACKAGE_CLASSNAME.to_string()),
+                    source_reference: None,
                 },
                 breakpoints: None,
             }),
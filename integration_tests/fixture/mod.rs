@@ -6,9 +6,12 @@ use adapter::{
     AdapterMessage,
 };
 use common::{UnrealCommand, UnrealInterfaceMessage};
-use dap::events::Event;
+use dap::events::{Event, EventBody, ThreadEventBody, ThreadEventReason};
 use futures::{stream::SplitStream, SinkExt, StreamExt};
-use interface::debugger::Debugger;
+use interface::{
+    channel::{message_channel, MAX_QUEUED_LOGS},
+    debugger::Debugger,
+};
 use tokio::{
     net::{TcpListener, TcpStream},
     sync::mpsc::unbounded_channel,
@@ -54,12 +57,8 @@ pub async fn setup_with_client<C: Client>(
         client,
         receiver,
         ClientConfig {
-            one_based_lines: true,
             supports_variable_type: true,
-            supports_invalidated_event: false,
-            source_roots: vec![],
-            enable_stack_hack: false,
-            auto_resume: false,
+            ..ClientConfig::new()
         },
         Box::new(TcpConnection::connect(port, sender,TcpConnectTimeoutConfig::default()).unwrap()),
         None,
@@ -81,7 +80,7 @@ pub async fn setup_with_client<C: Client>(
     let tcp_stream = tokio_serde::Framed::new(frame, format);
     let (mut tcp_tx, tcp_rx) = tcp_stream.split();
 
-    let (tx, mut rx) = unbounded_channel();
+    let (tx, mut rx) = message_channel(MAX_QUEUED_LOGS);
     dbg.new_connection(tx);
 
     // Spawn a task to monitor the receiving side of events and push them through the TCP
@@ -138,3 +137,16 @@ pub fn make_test_client() -> (TestClient, std::sync::mpsc::Receiver<Event>) {
     let client = TestClient::new(etx);
     (client, erx)
 }
+
+/// Receive the next event from a test client and check that it's a thread event for the
+/// Unrealscript thread with the given reason.
+#[allow(dead_code)]
+pub fn expect_thread_event(erx: &std::sync::mpsc::Receiver<Event>, reason: ThreadEventReason) {
+    match erx.recv().unwrap().body {
+        EventBody::Thread(ThreadEventBody {
+            reason: r,
+            thread_id: 1,
+        }) if r == reason => (),
+        b => panic!("Expected a thread {reason:?} event but got {b:?}"),
+    }
+}
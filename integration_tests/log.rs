@@ -1,7 +1,7 @@
 //! Integration tests for communications between the adapter and interface: logging.
 
 use common::{InitializeResponse, UnrealCommand, UnrealResponse, Version};
-use dap::{events::EventBody, events::OutputEventCategory};
+use dap::events::{EventBody, OutputEventCategory, ThreadEventReason};
 use tokio_stream::StreamExt;
 mod fixture;
 
@@ -22,6 +22,13 @@ async fn simple_log() {
                 minor: 0,
                 patch: 0,
             },
+            supports_list_breakpoints: false,
+            supports_threads: false,
+            supports_restart_frame: false,
+            supports_frame_line: false,
+            supports_cancel_variable_request: false,
+            supports_refresh_watches: false,
+            supports_set_variable: false,
         }))
         .unwrap();
         // Send a log event
@@ -35,6 +42,9 @@ async fn simple_log() {
         let evt = erx.recv().unwrap();
         assert!(matches!(evt.body, EventBody::Initialized));
 
+        // Then the Unrealscript thread starts.
+        fixture::expect_thread_event(&erx, ThreadEventReason::Started);
+
         // The adapter should receive the log event and dispatch it to the event sender.
         let evt = erx.recv().unwrap();
         match &evt.body {
@@ -45,7 +55,9 @@ async fn simple_log() {
             b => panic!("Expected an output event but got {b:?}"),
         }
 
-        // Finally we'll get a terminated event because we closed the interface connection.
+        // Finally the thread exits and we'll get a terminated event because we closed the
+        // interface connection.
+        fixture::expect_thread_event(&erx, ThreadEventReason::Exited);
         let evt = erx.recv().unwrap();
         assert!(matches!(evt.body, EventBody::Terminated));
     });
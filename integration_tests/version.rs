@@ -1,5 +1,5 @@
 use common::{InitializeResponse, UnrealCommand, UnrealResponse, Version};
-use dap::{events::EventBody, events::OutputEventCategory};
+use dap::events::{EventBody, OutputEventCategory, ThreadEventReason};
 use tokio_stream::StreamExt;
 
 mod fixture;
@@ -17,6 +17,13 @@ async fn equal_version() {
             // Send a response with the same version we were given.
             dbg.send_response(UnrealResponse::Initialize(InitializeResponse {
                 version: init.version,
+                supports_list_breakpoints: false,
+                supports_threads: false,
+                supports_restart_frame: false,
+                supports_frame_line: false,
+                supports_cancel_variable_request: false,
+                supports_refresh_watches: false,
+                supports_set_variable: false,
             }))
             .unwrap();
         } else {
@@ -26,12 +33,17 @@ async fn equal_version() {
         drop(comm);
     });
 
-    std::thread::spawn(move || {
+    let events = std::thread::spawn(move || {
         // We should get an initialized event first from construction of the adapter.
         let evt = erx.recv().unwrap();
         assert!(matches!(evt.body, EventBody::Initialized));
 
-        // Then we'll get a terminated event because we closed the interface connection.
+        // Then the Unrealscript thread starts.
+        fixture::expect_thread_event(&erx, ThreadEventReason::Started);
+
+        // Then the thread exits and we'll get a terminated event because we closed the
+        // interface connection.
+        fixture::expect_thread_event(&erx, ThreadEventReason::Exited);
         let evt = erx.recv().unwrap();
         assert!(matches!(evt.body, EventBody::Terminated));
     });
@@ -43,6 +55,7 @@ async fn equal_version() {
             patch: 0,
         })
         .unwrap();
+    events.join().unwrap();
 }
 
 #[tokio::test(flavor = "multi_thread")]
@@ -62,6 +75,13 @@ async fn larger_major() {
                     minor: init.version.minor,
                     patch: init.version.patch,
                 },
+                supports_list_breakpoints: false,
+                supports_threads: false,
+                supports_restart_frame: false,
+                supports_frame_line: false,
+                supports_cancel_variable_request: false,
+                supports_refresh_watches: false,
+                supports_set_variable: false,
             }))
             .unwrap();
         } else {
@@ -71,7 +91,7 @@ async fn larger_major() {
         drop(comm);
     });
 
-    std::thread::spawn(move || {
+    let events = std::thread::spawn(move || {
         // We should get an output event telling us there is a version mismatch
         let evt = erx.recv().unwrap();
         match evt.body {
@@ -85,7 +105,12 @@ async fn larger_major() {
         let evt = erx.recv().unwrap();
         assert!(matches!(evt.body, EventBody::Initialized));
 
-        // Then we'll get a terminated event because we closed the interface connection.
+        // Then the Unrealscript thread starts.
+        fixture::expect_thread_event(&erx, ThreadEventReason::Started);
+
+        // Then the thread exits and we'll get a terminated event because we closed the
+        // interface connection.
+        fixture::expect_thread_event(&erx, ThreadEventReason::Exited);
         let evt = erx.recv().unwrap();
         assert!(matches!(evt.body, EventBody::Terminated));
     });
@@ -97,6 +122,7 @@ async fn larger_major() {
             patch: 0,
         })
         .unwrap();
+    events.join().unwrap();
 }
 
 #[tokio::test(flavor = "multi_thread")]
@@ -116,6 +142,13 @@ async fn larger_minor() {
                     minor: init.version.minor + 1,
                     patch: init.version.patch,
                 },
+                supports_list_breakpoints: false,
+                supports_threads: false,
+                supports_restart_frame: false,
+                supports_frame_line: false,
+                supports_cancel_variable_request: false,
+                supports_refresh_watches: false,
+                supports_set_variable: false,
             }))
             .unwrap();
         } else {
@@ -125,7 +158,7 @@ async fn larger_minor() {
         drop(comm);
     });
 
-    std::thread::spawn(move || {
+    let events = std::thread::spawn(move || {
         // We should get an output event telling us there is a version mismatch
         let evt = erx.recv().unwrap();
         match evt.body {
@@ -139,7 +172,12 @@ async fn larger_minor() {
         let evt = erx.recv().unwrap();
         assert!(matches!(evt.body, EventBody::Initialized));
 
-        // Then we'll get a terminated event because we closed the interface connection.
+        // Then the Unrealscript thread starts.
+        fixture::expect_thread_event(&erx, ThreadEventReason::Started);
+
+        // Then the thread exits and we'll get a terminated event because we closed the
+        // interface connection.
+        fixture::expect_thread_event(&erx, ThreadEventReason::Exited);
         let evt = erx.recv().unwrap();
         assert!(matches!(evt.body, EventBody::Terminated));
     });
@@ -151,6 +189,7 @@ async fn larger_minor() {
             patch: 0,
         })
         .unwrap();
+    events.join().unwrap();
 }
 
 #[tokio::test(flavor = "multi_thread")]
@@ -170,6 +209,13 @@ async fn larger_patch() {
                     minor: init.version.minor,
                     patch: init.version.patch + 1,
                 },
+                supports_list_breakpoints: false,
+                supports_threads: false,
+                supports_restart_frame: false,
+                supports_frame_line: false,
+                supports_cancel_variable_request: false,
+                supports_refresh_watches: false,
+                supports_set_variable: false,
             }))
             .unwrap();
         } else {
@@ -179,7 +225,7 @@ async fn larger_patch() {
         drop(comm);
     });
 
-    std::thread::spawn(move || {
+    let events = std::thread::spawn(move || {
         // We should get an output event telling us there is a version mismatch
         let evt = erx.recv().unwrap();
         match evt.body {
@@ -193,7 +239,12 @@ async fn larger_patch() {
         let evt = erx.recv().unwrap();
         assert!(matches!(evt.body, EventBody::Initialized));
 
-        // Then we'll get a terminated event because we closed the interface connection.
+        // Then the Unrealscript thread starts.
+        fixture::expect_thread_event(&erx, ThreadEventReason::Started);
+
+        // Then the thread exits and we'll get a terminated event because we closed the
+        // interface connection.
+        fixture::expect_thread_event(&erx, ThreadEventReason::Exited);
         let evt = erx.recv().unwrap();
         assert!(matches!(evt.body, EventBody::Terminated));
     });
@@ -205,6 +256,7 @@ async fn larger_patch() {
             patch: 0,
         })
         .unwrap();
+    events.join().unwrap();
 }
 
 #[tokio::test(flavor = "multi_thread")]
@@ -224,6 +276,13 @@ async fn smaller_major() {
                     minor: init.version.minor,
                     patch: init.version.patch,
                 },
+                supports_list_breakpoints: false,
+                supports_threads: false,
+                supports_restart_frame: false,
+                supports_frame_line: false,
+                supports_cancel_variable_request: false,
+                supports_refresh_watches: false,
+                supports_set_variable: false,
             }))
             .unwrap();
         } else {
@@ -233,7 +292,7 @@ async fn smaller_major() {
         drop(comm);
     });
 
-    std::thread::spawn(move || {
+    let events = std::thread::spawn(move || {
         // We should get an output event telling us there is a version mismatch
         let evt = erx.recv().unwrap();
         match evt.body {
@@ -247,7 +306,12 @@ async fn smaller_major() {
         let evt = erx.recv().unwrap();
         assert!(matches!(evt.body, EventBody::Initialized));
 
-        // Then we'll get a terminated event because we closed the interface connection.
+        // Then the Unrealscript thread starts.
+        fixture::expect_thread_event(&erx, ThreadEventReason::Started);
+
+        // Then the thread exits and we'll get a terminated event because we closed the
+        // interface connection.
+        fixture::expect_thread_event(&erx, ThreadEventReason::Exited);
         let evt = erx.recv().unwrap();
         assert!(matches!(evt.body, EventBody::Terminated));
     });
@@ -259,6 +323,7 @@ async fn smaller_major() {
             patch: 0,
         })
         .unwrap();
+    events.join().unwrap();
 }
 
 #[tokio::test(flavor = "multi_thread")]
@@ -278,6 +343,13 @@ async fn smaller_minor() {
                     minor: init.version.minor - 1,
                     patch: init.version.patch,
                 },
+                supports_list_breakpoints: false,
+                supports_threads: false,
+                supports_restart_frame: false,
+                supports_frame_line: false,
+                supports_cancel_variable_request: false,
+                supports_refresh_watches: false,
+                supports_set_variable: false,
             }))
             .unwrap();
         } else {
@@ -287,7 +359,7 @@ async fn smaller_minor() {
         drop(comm);
     });
 
-    std::thread::spawn(move || {
+    let events = std::thread::spawn(move || {
         // We should get an output event telling us there is a version mismatch
         let evt = erx.recv().unwrap();
         match evt.body {
@@ -301,7 +373,12 @@ async fn smaller_minor() {
         let evt = erx.recv().unwrap();
         assert!(matches!(evt.body, EventBody::Initialized));
 
-        // Then we'll get a terminated event because we closed the interface connection.
+        // Then the Unrealscript thread starts.
+        fixture::expect_thread_event(&erx, ThreadEventReason::Started);
+
+        // Then the thread exits and we'll get a terminated event because we closed the
+        // interface connection.
+        fixture::expect_thread_event(&erx, ThreadEventReason::Exited);
         let evt = erx.recv().unwrap();
         assert!(matches!(evt.body, EventBody::Terminated));
     });
@@ -313,6 +390,7 @@ async fn smaller_minor() {
             patch: 0,
         })
         .unwrap();
+    events.join().unwrap();
 }
 
 #[tokio::test(flavor = "multi_thread")]
@@ -332,6 +410,13 @@ async fn smaller_patch() {
                     minor: init.version.minor,
                     patch: init.version.patch - 1,
                 },
+                supports_list_breakpoints: false,
+                supports_threads: false,
+                supports_restart_frame: false,
+                supports_frame_line: false,
+                supports_cancel_variable_request: false,
+                supports_refresh_watches: false,
+                supports_set_variable: false,
             }))
             .unwrap();
         } else {
@@ -341,7 +426,7 @@ async fn smaller_patch() {
         drop(comm);
     });
 
-    std::thread::spawn(move || {
+    let events = std::thread::spawn(move || {
         // We should get an output event telling us there is a version mismatch
         let evt = erx.recv().unwrap();
         match evt.body {
@@ -355,7 +440,12 @@ async fn smaller_patch() {
         let evt = erx.recv().unwrap();
         assert!(matches!(evt.body, EventBody::Initialized));
 
-        // Then we'll get a terminated event because we closed the interface connection.
+        // Then the Unrealscript thread starts.
+        fixture::expect_thread_event(&erx, ThreadEventReason::Started);
+
+        // Then the thread exits and we'll get a terminated event because we closed the
+        // interface connection.
+        fixture::expect_thread_event(&erx, ThreadEventReason::Exited);
         let evt = erx.recv().unwrap();
         assert!(matches!(evt.body, EventBody::Terminated));
     });
@@ -367,4 +457,5 @@ async fn smaller_patch() {
             patch: 3,
         })
         .unwrap();
+    events.join().unwrap();
 }